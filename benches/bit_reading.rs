@@ -0,0 +1,102 @@
+// Throughput of the Motorola (big endian) and Intel (little endian) bit readers
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use elpis::elpis::{read_bits_intel_le, read_bits_motorola_be, MessageDefinition, SignalDefinition};
+
+// A complete 8-byte CAN frame
+const FRAME: [u8; 8] = [0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 0xDE, 0xF0];
+
+// Builds a deterministic set of 100 signals of mixed sizes that all fit within an 8-byte frame,
+// as (start, length) pairs in Intel bit numbering
+fn mixed_signals() -> Vec<(i32, i32)> {
+    const LENGTHS: [i32; 6] = [1, 2, 4, 8, 12, 16];
+
+    let mut state: u32 = 0x2545_F491;
+    (0..100)
+        .map(|_| {
+            // xorshift keeps the layout identical between runs
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+
+            let length = LENGTHS[(state % LENGTHS.len() as u32) as usize];
+            let start = ((state >> 8) % (64 - length as u32 + 1)) as i32;
+            (start, length)
+        })
+        .collect()
+}
+
+// Convert an Intel start bit to the Motorola start bit (MSB) covering the same byte
+fn motorola_start(start: i32) -> i32 {
+    (start / 8) * 8 + 7
+}
+
+fn single_signals(c: &mut Criterion) {
+    let mut group = c.benchmark_group("single_signal");
+
+    for length in [1, 8, 16, 32, 64] {
+        group.bench_with_input(BenchmarkId::new("motorola_be", length), &length, |b, &length| {
+            b.iter(|| read_bits_motorola_be(black_box(&FRAME), black_box(7), black_box(length)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("intel_le", length), &length, |b, &length| {
+            b.iter(|| read_bits_intel_le(black_box(&FRAME), black_box(0), black_box(length)).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+fn mixed_frame(c: &mut Criterion) {
+    let signals = mixed_signals();
+    let mut group = c.benchmark_group("mixed_100_signals");
+    group.throughput(Throughput::Elements(signals.len() as u64));
+
+    group.bench_function("motorola_be", |b| {
+        b.iter(|| {
+            for (start, length) in signals.iter() {
+                // Motorola signals grow towards later bytes, keep them inside the frame
+                let start = motorola_start(*start).min(63 - ((*length - 1) / 8) * 8);
+                black_box(read_bits_motorola_be(black_box(&FRAME), start, *length).unwrap());
+            }
+        })
+    });
+
+    group.bench_function("intel_le", |b| {
+        b.iter(|| {
+            for (start, length) in signals.iter() {
+                black_box(read_bits_intel_le(black_box(&FRAME), *start, *length).unwrap());
+            }
+        })
+    });
+
+    group.finish();
+}
+
+// Decode a complete frame through the message definition, the way the dissector does
+fn frame_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame_decode");
+
+    for is_big_endian in [true, false] {
+        let mut message = MessageDefinition::new("Bench", 1, 8);
+        for (idx, (start, length)) in mixed_signals().into_iter().enumerate() {
+            let start = if is_big_endian {
+                motorola_start(start).min(63 - ((length - 1) / 8) * 8)
+            } else {
+                start
+            };
+
+            let mut signal = SignalDefinition::new(&format!("Signal_{}", idx), Some(start), length);
+            signal.is_big_endian = is_big_endian;
+            signal.scale = Some(0.5);
+            message.signals.push(signal);
+        }
+
+        let name = if is_big_endian { "motorola_be" } else { "intel_le" };
+        group.bench_function(name, |b| b.iter(|| message.decode(black_box(&FRAME))));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, single_signals, mixed_frame, frame_decode);
+criterion_main!(benches);
@@ -0,0 +1,38 @@
+// Cost of a debug message on the dissection path, which must stay the same however slow the sink
+// the messages end up in is
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use elpis::debug_log::DebugLog;
+use std::io::{self, Write};
+use std::time::Duration;
+
+// A sink that takes a millisecond for every write, like a terminal that can't keep up
+struct SlowSink;
+
+impl Write for SlowSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        std::thread::sleep(Duration::from_millis(1));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn log_message(c: &mut Criterion) {
+    let mut group = c.benchmark_group("debug_log");
+
+    let sinks: [(&str, Box<dyn Write + Send>); 2] = [("fast_sink", Box::new(io::sink())), ("slow_sink", Box::new(SlowSink))];
+    for (name, sink) in sinks {
+        let log = DebugLog::new(1024, sink);
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| log.log(format!("packet {} frame {}: id {:#x}, {} payload bytes", black_box(1), 0, 0x120, 8)))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, log_message);
+criterion_main!(benches);
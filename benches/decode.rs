@@ -0,0 +1,109 @@
+// Throughput of the offline decode paths: loading a database, decoding a single frame and walking a
+// whole datagram the way elpis-decode does. Nothing here needs Wireshark.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use elpis::elpis::{ElpisMessages, FrameIter, MessageDefinition, SignalDefinition};
+
+// Build a message of the given payload length with one 8-bit signal per byte, alternating between
+// byte orders
+fn byte_signals_message(id: i32, length: i32) -> MessageDefinition {
+    let mut message = MessageDefinition::new(&format!("Bench_{}", length), id, length);
+    for byte in 0..length {
+        let is_big_endian = byte % 2 == 1;
+        let start = if is_big_endian { byte * 8 + 7 } else { byte * 8 };
+
+        let mut signal = SignalDefinition::new(&format!("Byte_{}", byte), Some(start), 8);
+        signal.is_big_endian = is_big_endian;
+        signal.scale = Some(0.5);
+        signal.offset = -10.0;
+        message.signals.push(signal);
+    }
+    message
+}
+
+// Build a datagram of the given number of frames, each with an 8-byte payload
+fn datagram(id: i32, frames: usize) -> Vec<u8> {
+    let mut datagram = Vec::new();
+    for frame in 0..frames {
+        datagram.extend_from_slice(&id.to_be_bytes());
+        datagram.extend_from_slice(&8i32.to_be_bytes());
+        datagram.extend((0..8).map(|byte| (frame * 8 + byte) as u8));
+    }
+    datagram
+}
+
+fn database_load(c: &mut Criterion) {
+    let mut group = c.benchmark_group("database_load");
+    group.sample_size(10);
+
+    let fixtures = [
+        ("small", concat!(env!("CARGO_MANIFEST_DIR"), "/tests/wide_signals.json")),
+        ("large", concat!(env!("CARGO_MANIFEST_DIR"), "/messages.json")),
+    ];
+    for (name, path) in fixtures {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &path, |b, path| {
+            b.iter(|| ElpisMessages::load_from_json(black_box(path)).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+fn frame_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame_decode_bytes");
+
+    for length in [8, 64] {
+        let message = byte_signals_message(1, length);
+        let payload: Vec<u8> = (0..length as u8).collect();
+
+        group.throughput(Throughput::Bytes(length as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(length), &payload, |b, payload| {
+            b.iter(|| message.decode(black_box(payload)))
+        });
+    }
+
+    group.finish();
+}
+
+// The same frames as frame_decode, through a codec built once up front
+fn codec_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("codec_decode_bytes");
+
+    for length in [8, 64] {
+        let codec = ElpisMessages::from_definitions(vec![byte_signals_message(1, length)]).build_codec();
+        let payload: Vec<u8> = (0..length as u8).collect();
+
+        group.throughput(Throughput::Bytes(length as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(length), &payload, |b, payload| {
+            b.iter(|| codec.decode(1, black_box(payload)).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+// Walk every frame of a datagram, look up its definition and decode it
+fn datagram_decode(c: &mut Criterion) {
+    let messages = ElpisMessages::from_definitions(vec![byte_signals_message(0x120, 8)]);
+    let mut group = c.benchmark_group("datagram_decode_frames");
+
+    for frames in [1, 50] {
+        let datagram = datagram(0x120, frames);
+
+        group.throughput(Throughput::Elements(frames as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(frames), &datagram, |b, datagram| {
+            b.iter(|| {
+                for frame in FrameIter::new(black_box(datagram)) {
+                    let frame = frame.unwrap();
+                    let message_def = messages.get_def_by_id(frame.id).unwrap();
+                    black_box(message_def.decode(frame.payload));
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, database_load, frame_decode, codec_decode, datagram_decode);
+criterion_main!(benches);
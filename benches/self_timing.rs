@@ -0,0 +1,83 @@
+// Overhead of timing the phases of dissection, which is on by default and must cost no more than a
+// few percent. The timed walk reads the clock where the plugin does: around each frame's lookup, its
+// signal reads and its part of the tree, and around the Info column. Decoding the frame stands in for
+// building its items, which costs Wireshark more still. Every packet is timed as a whole and one in
+// PHASE_SAMPLING has its phases timed, as in the plugin. Compare the two ids of the same datagram.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use elpis::elpis::{ElpisMessages, FrameIter, MessageDefinition, SignalDefinition};
+use elpis::self_timing::{Phase, SelfTiming};
+
+// A message with one 8-bit signal per byte of its 8-byte payload
+fn byte_signals_message(id: i32) -> MessageDefinition {
+    let mut message = MessageDefinition::new("Bench", id, 8);
+    for byte in 0..8 {
+        let mut signal = SignalDefinition::new(&format!("Byte_{}", byte), Some(byte * 8), 8);
+        signal.is_big_endian = false;
+        signal.scale = Some(0.5);
+        message.signals.push(signal);
+    }
+    message
+}
+
+fn datagram(id: i32, frames: usize) -> Vec<u8> {
+    let mut datagram = Vec::new();
+    for frame in 0..frames {
+        datagram.extend_from_slice(&id.to_be_bytes());
+        datagram.extend_from_slice(&8i32.to_be_bytes());
+        datagram.extend((0..8).map(|byte| (frame * 8 + byte) as u8));
+    }
+    datagram
+}
+
+// Walk a datagram, reading every signal of every frame, timed like the plugin times a packet
+fn walk(messages: &ElpisMessages, datagram: &[u8], totals: &SelfTiming, enabled: bool) {
+    let mut timer = totals.start_packet(enabled);
+    for frame in FrameIter::new(datagram) {
+        let frame = frame.unwrap();
+
+        let started = timer.start();
+        let message_def = messages.get_def_by_id(frame.id).unwrap();
+        timer.stop(Phase::DatabaseLookup, started);
+
+        let tree_started = timer.start();
+        let started = timer.start();
+        let raw_values: Vec<anyhow::Result<u128>> =
+            message_def.signals.iter().map(|signal| signal.read_raw(frame.payload)).collect();
+        timer.stop(Phase::SignalExtraction, started);
+        black_box(raw_values);
+        black_box(message_def.decode(frame.payload));
+        timer.stop(Phase::TreeConstruction, tree_started);
+    }
+
+    let started = timer.start();
+    black_box(&info_column(messages));
+    timer.stop(Phase::InfoColumn, started);
+
+    if let Some(elapsed) = timer.elapsed() {
+        totals.add(&timer, elapsed);
+    }
+}
+
+fn info_column(messages: &ElpisMessages) -> String {
+    messages.iter_sorted().map(|message| message.name.as_str()).collect::<Vec<&str>>().join(" / ")
+}
+
+fn timed_walk(c: &mut Criterion) {
+    let messages = ElpisMessages::from_definitions(vec![byte_signals_message(0x120)]);
+    let datagram = datagram(0x120, 50);
+    let totals = SelfTiming::default();
+    let mut group = c.benchmark_group("self_timing_overhead");
+
+    group.throughput(Throughput::Elements(50));
+    for (name, enabled) in [("untimed", false), ("timed", true)] {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &datagram, |b, datagram| {
+            b.iter(|| walk(&messages, black_box(datagram), &totals, enabled))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, timed_walk);
+criterion_main!(benches);
@@ -0,0 +1,80 @@
+// Getting the values of two plotted signals for every frame of a capture, as IO Graph does on each
+// redraw: decoded from the payloads again, as before the watched signal cache, or read back from the
+// cache the first pass filled. The frames are split over packets of 4, like the datagrams of a logger.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use elpis::elpis::{MessageDefinition, SignalDefinition};
+use elpis::series::SeriesCache;
+
+const FRAMES_PER_PACKET: u32 = 4;
+
+// Indexes of the plotted signals in plotted_message
+const PLOTTED: [usize; 2] = [1, 2];
+
+// A message with a 16-bit speed and a 16-bit rpm signal among two status bytes
+fn plotted_message() -> MessageDefinition {
+    let mut message = MessageDefinition::new("Powertrain", 0x120, 8);
+    for (name, start, length, scale) in [("Status", 0, 8, 1.0), ("VehicleSpeed", 8, 16, 0.01), ("EngineRpm", 24, 16, 0.25), ("Mode", 40, 8, 1.0)] {
+        let mut signal = SignalDefinition::new(name, Some(start), length);
+        signal.is_big_endian = false;
+        signal.scale = Some(scale);
+        message.signals.push(signal);
+    }
+    message
+}
+
+fn payloads(frames: u32) -> Vec<Vec<u8>> {
+    (0..frames).map(|frame| frame.to_le_bytes().iter().chain(frame.to_be_bytes().iter()).copied().collect()).collect()
+}
+
+// Where each frame is in the capture, as (packet number, frame index)
+fn locations(frames: u32) -> impl Iterator<Item = (u32, u32)> {
+    (0..frames).map(|frame| (1 + frame / FRAMES_PER_PACKET, frame % FRAMES_PER_PACKET))
+}
+
+fn plotted_values(c: &mut Criterion) {
+    let message = plotted_message();
+    let mut group = c.benchmark_group("plotted_values_frames");
+    group.sample_size(20);
+
+    for frames in [10_000, 1_000_000] {
+        let payloads = payloads(frames);
+
+        // Filled the way the first pass fills it
+        let mut cache = SeriesCache::default();
+        for ((packet, frame), payload) in locations(frames).zip(payloads.iter()) {
+            for index in PLOTTED {
+                let signal = &message.signals[index];
+                let physical = signal.to_physical(signal.read_traced(payload, None).unwrap());
+                cache.observe(packet, frame, message.id, index as u32, physical);
+            }
+        }
+
+        group.throughput(Throughput::Elements(frames as u64));
+        group.bench_with_input(BenchmarkId::new("decoded", frames), &payloads, |b, payloads| {
+            b.iter(|| {
+                for payload in payloads.iter() {
+                    for index in PLOTTED {
+                        let signal = &message.signals[index];
+                        black_box(signal.to_physical(signal.read_traced(black_box(payload), None).unwrap()));
+                    }
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("cached", frames), &frames, |b, frames| {
+            b.iter(|| {
+                for (packet, frame) in locations(*frames) {
+                    for index in PLOTTED {
+                        black_box(cache.observe(black_box(packet), frame, message.id, index as u32, 0.0));
+                    }
+                }
+            })
+        });
+        println!("{}", cache.describe());
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, plotted_values);
+criterion_main!(benches);
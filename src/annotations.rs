@@ -0,0 +1,135 @@
+// Free-text notes from analysts, shown next to the messages and signals they're about. Notes are
+// loaded from a JSON file keyed by "Message" or "Message.Signal":
+//
+//     { "MF_InverterStatus.MF_InverterStatus_01_Counter": "Freezes while the ECU reboots" }
+//
+// Notes are display only, they never change how anything is decoded or which filter fields are set.
+
+use crate::elpis::ElpisMessages;
+use anyhow::Context;
+use std::collections::HashMap;
+
+// Longest note shown in full, longer ones are cut off with an ellipsis
+const MAX_NOTE_LENGTH: usize = 200;
+
+// Shorten a note to the maximum length, counting characters rather than bytes
+fn truncate_note(note: &str) -> String {
+    if note.chars().count() <= MAX_NOTE_LENGTH {
+        return note.to_string();
+    }
+
+    let mut truncated: String = note.chars().take(MAX_NOTE_LENGTH - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+#[derive(Debug, Default)]
+pub struct Annotations {
+    // Notes on whole messages, keyed by message name
+    messages: HashMap<String, String>,
+    // Notes on signals, keyed by message and signal name
+    signals: HashMap<(String, String), String>,
+}
+
+impl Annotations {
+    // Load notes from the given path
+    pub fn load_from_json(json_path: &str) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(json_path)
+            .with_context(|| format!("Could not open file {}", json_path))?;
+        let notes: HashMap<String, String> =
+            serde_json::from_str(&contents).with_context(|| format!("Could not parse JSON file {}", json_path))?;
+
+        Ok(Self::from_notes(notes))
+    }
+
+    // Build annotations from notes keyed by "Message" or "Message.Signal"
+    pub fn from_notes(notes: HashMap<String, String>) -> Self {
+        let mut annotations = Self::default();
+        for (target, note) in notes {
+            let note = truncate_note(note.trim());
+            match target.split_once('.') {
+                Some((message, signal)) => {
+                    annotations.signals.insert((message.to_string(), signal.to_string()), note);
+                }
+                None => {
+                    annotations.messages.insert(target, note);
+                }
+            }
+        }
+
+        annotations
+    }
+
+    pub fn message_note(&self, message: &str) -> Option<&str> {
+        self.messages.get(message).map(|note| note.as_str())
+    }
+
+    pub fn signal_note(&self, message: &str, signal: &str) -> Option<&str> {
+        self.signals
+            .get(&(message.to_string(), signal.to_string()))
+            .map(|note| note.as_str())
+    }
+
+    // Get the targets of notes that don't name a message or signal in the database, sorted
+    pub fn unknown_targets(&self, messages: &ElpisMessages) -> Vec<String> {
+        let find_message = |name: &str| messages.iter_sorted().find(|message| message.name == name);
+
+        let mut unknown: Vec<String> = self
+            .messages
+            .keys()
+            .filter(|message| find_message(message).is_none())
+            .cloned()
+            .chain(
+                self.signals
+                    .keys()
+                    .filter(|(message, signal)| {
+                        find_message(message).is_none_or(|message| !message.signals.iter().any(|s| &s.name == signal))
+                    })
+                    .map(|(message, signal)| format!("{}.{}", message, signal)),
+            )
+            .collect();
+        unknown.sort();
+        unknown
+    }
+}
+
+#[test]
+fn annotate_messages_and_signals() {
+    use crate::elpis::{MessageDefinition, SignalDefinition};
+
+    let notes: HashMap<String, String> = serde_json::from_str(
+        r#"{
+            "Wheels": "Sent by the prototype ABS unit",
+            "Wheels.Counter": "  Freezes while the ECU reboots  ",
+            "Wheels.Missing": "Signal that was removed",
+            "Gone": "Message that was removed",
+            "Gone.Speed": "Signal of a message that was removed"
+        }"#,
+    )
+    .unwrap();
+    let annotations = Annotations::from_notes(notes);
+
+    assert_eq!(annotations.message_note("Wheels"), Some("Sent by the prototype ABS unit"));
+    assert_eq!(annotations.signal_note("Wheels", "Counter"), Some("Freezes while the ECU reboots"));
+    assert_eq!(annotations.signal_note("Wheels", "Speed"), None);
+    assert_eq!(annotations.message_note("Status"), None);
+
+    let mut wheels = MessageDefinition::new("Wheels", 0x120, 2);
+    wheels.signals.push(SignalDefinition::new("Counter", Some(7), 8));
+    let messages = ElpisMessages::from_definitions(vec![wheels]);
+    assert_eq!(annotations.unknown_targets(&messages), vec!["Gone", "Gone.Speed", "Wheels.Missing"]);
+}
+
+#[test]
+fn truncate_long_notes() {
+    assert_eq!(truncate_note("short"), "short");
+
+    let exact = "a".repeat(MAX_NOTE_LENGTH);
+    assert_eq!(truncate_note(&exact), exact);
+
+    // Multi-byte characters are counted as one
+    let long = "ü".repeat(MAX_NOTE_LENGTH + 10);
+    let truncated = truncate_note(&long);
+    assert_eq!(truncated.chars().count(), MAX_NOTE_LENGTH);
+    assert!(truncated.ends_with("ü…"));
+}
@@ -0,0 +1,478 @@
+// Command line decoder for ELPIS captures, for triaging traffic without Wireshark
+
+use anyhow::Context;
+use clap::{Parser, Subcommand, ValueEnum};
+use elpis::{
+    capture::{CaptureReader, Datagram},
+    dbdiff::DbDiff,
+    elpis::{ElpisMessages, FrameIter, LoadMode},
+    live::{LiveDecoder, StreamFormat},
+    patch::ElpisPatch,
+    schema,
+    simlog::{self, SimlogFrame},
+    summary::{self, WindowAggregator},
+    trace::{self, DecodeTrace},
+};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    net::UdpSocket,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Parser)]
+#[command(name = "elpis-decode", about = "Decode ELPIS traffic without Wireshark")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Summarize a capture in fixed time windows
+    Summarize {
+        /// Path to a pcap capture or simulator log
+        capture: String,
+
+        #[arg(long, value_enum, default_value_t = InputFormat::Pcap)]
+        input_format: InputFormat,
+
+        /// Path to the messages.json database
+        #[arg(long)]
+        db: String,
+
+        /// Path to a JSON merge patch applied on top of the database
+        #[arg(long)]
+        patch: Option<String>,
+
+        /// Length of each window, e.g. 1s or 250ms
+        #[arg(long, default_value = "1s")]
+        window: String,
+
+        /// Signals to report the minimum and maximum of in each window
+        #[arg(long, value_delimiter = ',')]
+        signals: Vec<String>,
+
+        #[arg(long, value_enum, default_value_t = TableFormat::Text)]
+        format: TableFormat,
+
+        /// UDP port carrying ELPIS traffic
+        #[arg(long, default_value_t = 20000)]
+        port: u16,
+    },
+
+    /// Decode every frame of a capture
+    Decode {
+        /// Path to a pcap capture or simulator log
+        capture: String,
+
+        #[arg(long, value_enum, default_value_t = InputFormat::Pcap)]
+        input_format: InputFormat,
+
+        /// Path to the messages.json database
+        #[arg(long)]
+        db: String,
+
+        /// Path to a JSON merge patch applied on top of the database
+        #[arg(long)]
+        patch: Option<String>,
+
+        /// Signals to output, all of them when not given
+        #[arg(long, value_delimiter = ',')]
+        signals: Vec<String>,
+
+        #[arg(long, value_enum, default_value_t = LiveFormat::Pretty)]
+        format: LiveFormat,
+
+        /// UDP port carrying ELPIS traffic
+        #[arg(long, default_value_t = 20000)]
+        port: u16,
+    },
+
+    /// Decode ELPIS datagrams from a UDP socket as they arrive
+    Live {
+        /// Address to listen on
+        #[arg(long, default_value = "0.0.0.0:20000")]
+        listen: String,
+
+        /// Path to the messages.json database
+        #[arg(long)]
+        db: String,
+
+        /// Path to a JSON merge patch applied on top of the database
+        #[arg(long)]
+        patch: Option<String>,
+
+        /// Signals to output, all of them when not given
+        #[arg(long, value_delimiter = ',')]
+        signals: Vec<String>,
+
+        #[arg(long, value_enum, default_value_t = LiveFormat::Pretty)]
+        format: LiveFormat,
+    },
+
+    /// Report semantic differences between two message databases
+    DbDiff {
+        /// Path to the old messages.json database
+        old: String,
+
+        /// Path to the new messages.json database
+        new: String,
+
+        /// Exit with an error when these changes are found
+        #[arg(long, value_enum)]
+        fail_on: Option<FailOn>,
+    },
+
+    /// Show every step of decoding a single payload as JSON
+    Trace {
+        /// Payload bytes in hex, e.g. "12 34 56 78"
+        #[arg(long)]
+        payload: String,
+
+        /// Id of the message, e.g. 0x123 or 291, or its name
+        #[arg(long)]
+        message: String,
+
+        /// Path to the messages.json database
+        #[arg(long)]
+        db: String,
+
+        /// Path to a JSON merge patch applied on top of the database
+        #[arg(long)]
+        patch: Option<String>,
+    },
+
+    /// Upgrade a message database to the current schema
+    Migrate {
+        /// Path to the messages.json database
+        db: String,
+
+        /// Where to write the upgraded database, standard output when not given
+        #[arg(long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum InputFormat {
+    /// ELPIS datagrams in a pcap capture
+    Pcap,
+    /// Frames logged one per line by the HIL simulator, e.g. "12.3456 TX 0x123 8 DE AD BE EF 00 11 22 33"
+    Simlog,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TableFormat {
+    Text,
+    Csv,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FailOn {
+    /// Changes that alter how existing signals decode
+    Breaking,
+    /// Any change at all
+    Any,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LiveFormat {
+    Jsonl,
+    Csv,
+    Pretty,
+}
+
+impl From<LiveFormat> for StreamFormat {
+    fn from(format: LiveFormat) -> Self {
+        match format {
+            LiveFormat::Jsonl => StreamFormat::Jsonl,
+            LiveFormat::Csv => StreamFormat::Csv,
+            LiveFormat::Pretty => StreamFormat::Pretty,
+        }
+    }
+}
+
+// A capture to read, and how
+struct Input<'a> {
+    path: &'a str,
+    format: InputFormat,
+    // UDP port carrying ELPIS traffic in pcaps
+    port: u16,
+}
+
+// Calls back with every ELPIS datagram in a capture sent to or from the given port, stopping at the
+// first error the callback returns
+fn for_each_datagram(path: &str, port: u16, mut callback: impl FnMut(&Datagram) -> anyhow::Result<()>) -> anyhow::Result<()> {
+    let mut capture = CaptureReader::open(path)?;
+    while let Some(datagram) = capture.next_datagram()? {
+        if datagram.src_port == port || datagram.dst_port == port {
+            callback(&datagram)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Calls back with every frame in a simulator log, warning about malformed lines and skipping them,
+// and stopping at the first error the callback returns
+fn for_each_simlog_frame(path: &str, mut callback: impl FnMut(&SimlogFrame) -> anyhow::Result<()>) -> anyhow::Result<()> {
+    let file = File::open(path).with_context(|| format!("Could not open simulator log {}", path))?;
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("Could not read simulator log {}", path))?;
+        match simlog::parse_line(&line) {
+            Ok(Some(frame)) => callback(&frame)?,
+            Ok(None) => {}
+            Err(e) => eprintln!("warning: {} line {}: {:#}, skipped", path, index + 1, e),
+        }
+    }
+
+    Ok(())
+}
+
+// Load the message database and apply a patch to it, warning about any problems that were worked around
+fn load_messages(db: &str, patch: Option<&str>) -> anyhow::Result<ElpisMessages> {
+    let mut messages = ElpisMessages::load_from_json_with_mode(db, LoadMode::Permissive)?;
+    for issue in messages.issues() {
+        eprintln!("warning: {}", issue);
+    }
+
+    if let Some(patch) = patch {
+        messages.apply_patch(ElpisPatch::load_from_json(patch)?)?;
+    }
+
+    Ok(messages)
+}
+
+// Resolve requested signal names, which may be aliases from older databases, to their canonical names
+fn resolve_signal_names(messages: &ElpisMessages, names: Vec<String>) -> anyhow::Result<Vec<String>> {
+    names
+        .iter()
+        .map(|name| {
+            messages
+                .resolve_signal_name(name)
+                .map(|canonical| canonical.to_string())
+                .ok_or_else(|| anyhow::anyhow!("Unknown signal {}", name))
+        })
+        .collect()
+}
+
+fn summarize(
+    input: &Input,
+    db: &str,
+    patch: Option<&str>,
+    window: &str,
+    signals: Vec<String>,
+    format: TableFormat,
+) -> anyhow::Result<()> {
+    let messages = load_messages(db, patch)?;
+    let signals = resolve_signal_names(&messages, signals)?;
+    let mut aggregator = WindowAggregator::new(summary::parse_window(window)?, signals);
+    let codec = messages.build_codec();
+
+    match input.format {
+        InputFormat::Pcap => for_each_datagram(input.path, input.port, |datagram| {
+            aggregator.add_datagram(datagram.timestamp);
+
+            for frame in FrameIter::new(&datagram.payload) {
+                match frame {
+                    Ok(frame) => {
+                        // Only unknown ids fail to decode
+                        let decoded = codec.decode(frame.id, frame.payload).ok();
+                        aggregator.add_frame(datagram.timestamp, frame.id, decoded.as_ref());
+                    }
+                    Err(_) => aggregator.add_decode_error(datagram.timestamp),
+                }
+            }
+            Ok(())
+        })?,
+        // Each logged frame counts as a datagram of its own
+        InputFormat::Simlog => for_each_simlog_frame(input.path, |frame| {
+            aggregator.add_datagram(frame.timestamp);
+            let decoded = codec.decode(frame.id, &frame.payload).ok();
+            aggregator.add_frame(frame.timestamp, frame.id, decoded.as_ref());
+            Ok(())
+        })?,
+    }
+
+    let signals = aggregator.tracked_signals().to_vec();
+    let windows = aggregator.finish();
+    let output = match format {
+        TableFormat::Text => summary::render_text(&windows, &signals),
+        TableFormat::Csv => summary::render_csv(&windows, &signals),
+    };
+    print!("{}", output);
+
+    Ok(())
+}
+
+fn decode(input: &Input, db: &str, patch: Option<&str>, signals: Vec<String>, format: LiveFormat) -> anyhow::Result<()> {
+    let messages = load_messages(db, patch)?;
+    let signals = resolve_signal_names(&messages, signals)?;
+    let mut decoder = LiveDecoder::new(&messages, signals, format.into());
+
+    // Lines are written as each datagram is decoded, so a large capture is never held in memory
+    let mut stdout = io::stdout().lock();
+    if let Some(header) = decoder.header() {
+        writeln!(stdout, "{}", header)?;
+    }
+    match input.format {
+        InputFormat::Pcap => for_each_datagram(input.path, input.port, |datagram| {
+            write!(stdout, "{}", decoder.decode_datagram(datagram.timestamp, &datagram.payload))?;
+            Ok(())
+        })?,
+        InputFormat::Simlog => for_each_simlog_frame(input.path, |frame| {
+            write!(stdout, "{}", decoder.decode_frame(frame.timestamp, frame.id, &frame.payload))?;
+            Ok(())
+        })?,
+    }
+    stdout.flush()?;
+    eprint!("{}", decoder.stats().render());
+
+    Ok(())
+}
+
+fn live(listen: &str, db: &str, patch: Option<&str>, signals: Vec<String>, format: LiveFormat) -> anyhow::Result<()> {
+    let messages = load_messages(db, patch)?;
+    let signals = resolve_signal_names(&messages, signals)?;
+    let mut decoder = LiveDecoder::new(&messages, signals, format.into());
+
+    // Stop receiving on Ctrl+C so the summary still gets printed
+    let running = Arc::new(AtomicBool::new(true));
+    let handler_running = running.clone();
+    ctrlc::set_handler(move || handler_running.store(false, Ordering::SeqCst))?;
+
+    let socket = UdpSocket::bind(listen).with_context(|| format!("Could not listen on {}", listen))?;
+    // Wake up regularly to check whether we were interrupted
+    socket.set_read_timeout(Some(Duration::from_millis(200)))?;
+
+    let mut stdout = io::stdout().lock();
+    if let Some(header) = decoder.header() {
+        writeln!(stdout, "{}", header)?;
+        stdout.flush()?;
+    }
+
+    let mut buffer = vec![0u8; 65536];
+    while running.load(Ordering::SeqCst) {
+        let length = match socket.recv(&mut buffer) {
+            Ok(length) => length,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted) => {
+                continue
+            }
+            Err(e) => return Err(e).context("Could not receive datagram"),
+        };
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64();
+        write!(stdout, "{}", decoder.decode_datagram(timestamp, &buffer[..length]))?;
+        stdout.flush()?;
+    }
+
+    eprint!("{}", decoder.stats().render());
+
+    Ok(())
+}
+
+fn db_diff(old: &str, new: &str, fail_on: Option<FailOn>) -> anyhow::Result<()> {
+    let diff = DbDiff::compare(&load_messages(old, None)?, &load_messages(new, None)?);
+    print!("{}", diff.render());
+
+    match fail_on {
+        Some(FailOn::Breaking) if diff.has_breaking_changes() => Err(anyhow::anyhow!("Found breaking changes")),
+        Some(FailOn::Any) if !diff.changes.is_empty() => Err(anyhow::anyhow!("Found changes")),
+        _ => Ok(()),
+    }
+}
+
+fn trace_payload(payload: &str, message: &str, db: &str, patch: Option<&str>) -> anyhow::Result<()> {
+    let messages = load_messages(db, patch)?;
+    let payload = trace::parse_hex(payload)?;
+
+    let id = match message.strip_prefix("0x") {
+        Some(hex) => i32::from_str_radix(hex, 16).ok(),
+        None => message.parse::<i32>().ok(),
+    };
+    let message_def = match id {
+        Some(id) => messages.get_def_by_id(id),
+        None => messages.iter_sorted().find(|message_def| message_def.name == message),
+    }
+    .ok_or_else(|| anyhow::anyhow!("Unknown message {}", message))?;
+
+    let mut trace = DecodeTrace::default();
+    message_def.decode_with_trace(&payload, Some(&mut trace));
+    println!("{}", trace.to_json());
+
+    Ok(())
+}
+
+fn migrate(db: &str, output: Option<&str>) -> anyhow::Result<()> {
+    let contents = std::fs::read_to_string(db).with_context(|| format!("Could not open file {}", db))?;
+    let (migrated, changes) = schema::migrate(&contents).with_context(|| format!("Could not migrate {}", db))?;
+    for change in changes.iter() {
+        eprintln!("{}", change);
+    }
+
+    let migrated = serde_json::to_string_pretty(&migrated)? + "\n";
+    match output {
+        Some(output) => std::fs::write(output, migrated).with_context(|| format!("Could not write file {}", output))?,
+        None => print!("{}", migrated),
+    }
+
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    match Cli::parse().command {
+        Command::Summarize {
+            capture,
+            input_format,
+            db,
+            patch,
+            window,
+            signals,
+            format,
+            port,
+        } => {
+            let input = Input {
+                path: &capture,
+                format: input_format,
+                port,
+            };
+            summarize(&input, &db, patch.as_deref(), &window, signals, format)
+        }
+        Command::Decode {
+            capture,
+            input_format,
+            db,
+            patch,
+            signals,
+            format,
+            port,
+        } => {
+            let input = Input {
+                path: &capture,
+                format: input_format,
+                port,
+            };
+            decode(&input, &db, patch.as_deref(), signals, format)
+        }
+        Command::Live {
+            listen,
+            db,
+            patch,
+            signals,
+            format,
+        } => live(&listen, &db, patch.as_deref(), signals, format),
+        Command::DbDiff { old, new, fail_on } => db_diff(&old, &new, fail_on),
+        Command::Trace {
+            payload,
+            message,
+            db,
+            patch,
+        } => trace_payload(&payload, &message, &db, patch.as_deref()),
+        Command::Migrate { db, output } => migrate(&db, output.as_deref()),
+    }
+}
@@ -0,0 +1,213 @@
+// Limits on the work done dissecting a single packet. A crafted datagram can hold thousands of
+// frames, each of a message with hundreds of signals, and every one of them becomes a subtree. The
+// budget is charged before each frame is decoded, and once a limit would be passed the rest of the
+// datagram is left undecoded.
+
+use crate::elpis::MessageDefinition;
+
+// Default limits, far above anything a real datagram of at most 64 KiB needs
+const DEFAULT_MAX_FRAMES: usize = 4096;
+const DEFAULT_MAX_SIGNALS: usize = 16384;
+const DEFAULT_MAX_BYTES: usize = 1 << 20;
+
+// The work a packet may take
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BudgetLimits {
+    pub frames: usize,
+    pub signals: usize,
+    // Frame bytes plus the payload bytes each signal is read from
+    pub bytes: usize,
+}
+
+impl BudgetLimits {
+    // The default limits multiplied by scale, a scale of 0 counts as 1
+    pub fn scaled(scale: u32) -> Self {
+        let scale = scale.max(1) as usize;
+        Self {
+            frames: DEFAULT_MAX_FRAMES.saturating_mul(scale),
+            signals: DEFAULT_MAX_SIGNALS.saturating_mul(scale),
+            bytes: DEFAULT_MAX_BYTES.saturating_mul(scale),
+        }
+    }
+}
+
+impl Default for BudgetLimits {
+    fn default() -> Self {
+        Self::scaled(1)
+    }
+}
+
+// The limit a frame would have passed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BudgetExceeded {
+    Frames(usize),
+    Signals(usize),
+    Bytes(usize),
+}
+
+impl std::fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BudgetExceeded::Frames(limit) => write!(f, "more than {} frames", limit),
+            BudgetExceeded::Signals(limit) => write!(f, "more than {} signals", limit),
+            BudgetExceeded::Bytes(limit) => write!(f, "more than {} bytes touched", limit),
+        }
+    }
+}
+
+// The work done on a packet so far
+#[derive(Debug)]
+pub struct DecodeBudget {
+    limits: BudgetLimits,
+    frames: usize,
+    signals: usize,
+    bytes: usize,
+}
+
+impl DecodeBudget {
+    pub fn new(limits: BudgetLimits) -> Self {
+        Self {
+            limits,
+            frames: 0,
+            signals: 0,
+            bytes: 0,
+        }
+    }
+
+    // Charge a frame of payload_length bytes and the signals of its message, if known. Nothing is
+    // charged if that would pass a limit.
+    pub fn charge_frame(&mut self, payload_length: usize, message: Option<&MessageDefinition>) -> Result<(), BudgetExceeded> {
+        let (signals, signal_bytes) = message.map_or((0, 0), signal_work);
+
+        let frames = self.frames + 1;
+        let signals = self.signals + signals;
+        let bytes = self.bytes + 8 + payload_length + signal_bytes;
+
+        if frames > self.limits.frames {
+            return Err(BudgetExceeded::Frames(self.limits.frames));
+        }
+        if signals > self.limits.signals {
+            return Err(BudgetExceeded::Signals(self.limits.signals));
+        }
+        if bytes > self.limits.bytes {
+            return Err(BudgetExceeded::Bytes(self.limits.bytes));
+        }
+
+        self.frames = frames;
+        self.signals = signals;
+        self.bytes = bytes;
+        Ok(())
+    }
+}
+
+// Get the number of signals decoded for a message, derived ones included, and the payload bytes
+// they're read from
+fn signal_work(message: &MessageDefinition) -> (usize, usize) {
+    let bytes = message
+        .signals
+        .iter()
+        .filter_map(|signal| signal.byte_offsets())
+        .map(|(lsb, msb)| (lsb - msb).unsigned_abs() as usize + 1)
+        .sum();
+    (message.signals.len() + message.derived.len(), bytes)
+}
+
+#[cfg(test)]
+use crate::elpis::{DecodedFrame, ElpisMessages, FrameIter, SignalDefinition};
+
+// Decode a datagram the way the dissector does, stopping where the budget runs out
+#[cfg(test)]
+fn decode_with_budget(messages: &ElpisMessages, datagram: &[u8], limits: BudgetLimits) -> (Vec<DecodedFrame>, Option<BudgetExceeded>) {
+    let mut budget = DecodeBudget::new(limits);
+    let mut frames = Vec::new();
+    for frame in FrameIter::new(datagram) {
+        let Ok(frame) = frame else { break };
+        let message = messages.get_def_by_id(frame.id);
+        if let Err(exceeded) = budget.charge_frame(frame.payload.len(), message) {
+            return (frames, Some(exceeded));
+        }
+        if let Some(message) = message {
+            frames.push(message.decode(frame.payload));
+        }
+    }
+    (frames, None)
+}
+
+// A database with one message of 512 single bit signals, the most subtrees per payload byte
+#[cfg(test)]
+fn dense_messages() -> ElpisMessages {
+    let mut message = MessageDefinition::new("Dense", 0x10, 64);
+    message.signals = (0..512).map(|bit| SignalDefinition::new(&format!("Bit{}", bit), Some(bit), 1)).collect();
+    ElpisMessages::from_definitions(vec![message])
+}
+
+#[cfg(test)]
+fn datagram(frames: impl Iterator<Item = (i32, Vec<u8>)>) -> Vec<u8> {
+    let mut datagram = Vec::new();
+    for (id, payload) in frames {
+        datagram.extend_from_slice(&id.to_be_bytes());
+        datagram.extend_from_slice(&(payload.len() as i32).to_be_bytes());
+        datagram.extend_from_slice(&payload);
+    }
+    datagram
+}
+
+#[test]
+fn budget_limits() {
+    let messages = dense_messages();
+    let dense = messages.get_def_by_id(0x10);
+
+    let mut budget = DecodeBudget::new(BudgetLimits { frames: 2, signals: 1024, bytes: 1 << 20 });
+    assert!(budget.charge_frame(64, dense).is_ok());
+    assert!(budget.charge_frame(64, dense).is_ok());
+    assert_eq!(budget.charge_frame(0, None), Err(BudgetExceeded::Frames(2)));
+
+    // A frame that doesn't fit isn't charged, so a smaller one still can be
+    let mut budget = DecodeBudget::new(BudgetLimits { frames: 10, signals: 600, bytes: 1 << 20 });
+    assert!(budget.charge_frame(64, dense).is_ok());
+    assert_eq!(budget.charge_frame(64, dense), Err(BudgetExceeded::Signals(600)));
+    assert!(budget.charge_frame(64, None).is_ok());
+
+    // Every single bit signal reads a byte of its own
+    let mut budget = DecodeBudget::new(BudgetLimits { frames: 10, signals: 1024, bytes: 8 + 64 + 512 });
+    assert!(budget.charge_frame(64, dense).is_ok());
+    assert_eq!(budget.charge_frame(0, None), Err(BudgetExceeded::Bytes(8 + 64 + 512)));
+
+    assert_eq!(BudgetLimits::scaled(0), BudgetLimits::default());
+    assert_eq!(BudgetLimits::scaled(4).frames, 4 * DEFAULT_MAX_FRAMES);
+}
+
+#[test]
+fn worst_case_datagrams_stay_within_budget() {
+    let messages = dense_messages();
+
+    // Corpus of the most expensive datagrams that fit in 64 KiB
+    let corpus = [
+        // As many empty frames of an unknown id as fit
+        datagram((0..8191).map(|_| (0x20, Vec::new()))),
+        // As many frames of the densest message as fit
+        datagram((0..910).map(|_| (0x10, vec![0xa5; 64]))),
+        // Frames of the densest message padded out to the largest payload
+        datagram((0..7).map(|_| (0x10, vec![0xff; 8184]))),
+        // Unknown ids, so only the frame walk costs anything
+        datagram((0..1000).map(|id| (0x1000 + id, vec![0; 57]))),
+    ];
+
+    let started = std::time::Instant::now();
+    for datagram in corpus.iter() {
+        assert!(datagram.len() <= 65535);
+        let (frames, _) = decode_with_budget(&messages, datagram, BudgetLimits::default());
+        let signals: usize = frames.iter().map(|frame| frame.signals.len()).sum();
+        assert!(frames.len() <= DEFAULT_MAX_FRAMES && signals <= DEFAULT_MAX_SIGNALS);
+    }
+    let elapsed = started.elapsed();
+    assert!(elapsed < std::time::Duration::from_secs(2), "Worst case datagrams took {:?}", elapsed);
+
+    // The frame and signal limits are what stopped them
+    let (_, exceeded) = decode_with_budget(&messages, &corpus[0], BudgetLimits::default());
+    assert_eq!(exceeded, Some(BudgetExceeded::Frames(DEFAULT_MAX_FRAMES)));
+    let (_, exceeded) = decode_with_budget(&messages, &corpus[1], BudgetLimits::default());
+    assert_eq!(exceeded, Some(BudgetExceeded::Signals(DEFAULT_MAX_SIGNALS)));
+    let (frames, exceeded) = decode_with_budget(&messages, &corpus[1], BudgetLimits::scaled(64));
+    assert_eq!((frames.len(), exceeded), (910, None));
+}
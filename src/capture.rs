@@ -0,0 +1,175 @@
+// Reads UDP datagrams out of classic libpcap capture files, so ELPIS traffic can be
+// decoded without Wireshark.
+
+use anyhow::Context;
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+};
+
+// Link types supported by the reader
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+
+// A single UDP datagram found in a capture
+pub struct Datagram {
+    // Packet number in the capture, starting at 1 like Wireshark
+    pub number: u64,
+    // Seconds since the epoch
+    pub timestamp: f64,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub payload: Vec<u8>,
+}
+
+pub struct CaptureReader<R: Read> {
+    reader: R,
+    big_endian: bool,
+    nanosecond: bool,
+    link_type: u32,
+    packet_number: u64,
+}
+
+impl CaptureReader<BufReader<File>> {
+    // Open a pcap file from disk
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let file = File::open(path).with_context(|| format!("Could not open capture {}", path))?;
+        Self::new(BufReader::new(file)).with_context(|| format!("Could not read capture {}", path))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    // Read the pcap global header from the given reader
+    pub fn new(mut reader: R) -> anyhow::Result<Self> {
+        let mut header = [0u8; 24];
+        reader.read_exact(&mut header).context("Truncated pcap header")?;
+
+        let (big_endian, nanosecond) = match header[0..4] {
+            [0xd4, 0xc3, 0xb2, 0xa1] => (false, false),
+            [0xa1, 0xb2, 0xc3, 0xd4] => (true, false),
+            [0x4d, 0x3c, 0xb2, 0xa1] => (false, true),
+            [0xa1, 0xb2, 0x3c, 0x4d] => (true, true),
+            _ => return Err(anyhow::anyhow!("Not a pcap file (pcapng is not supported)")),
+        };
+
+        let mut capture = Self {
+            reader,
+            big_endian,
+            nanosecond,
+            link_type: 0,
+            packet_number: 0,
+        };
+        capture.link_type = capture.read_u32(&header[20..24]);
+
+        match capture.link_type {
+            LINKTYPE_ETHERNET | LINKTYPE_RAW | LINKTYPE_LINUX_SLL => Ok(capture),
+            other => Err(anyhow::anyhow!("Unsupported link type {}", other)),
+        }
+    }
+
+    fn read_u32(&self, bytes: &[u8]) -> u32 {
+        let bytes: [u8; 4] = bytes.try_into().unwrap();
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    // Read the next UDP datagram in the capture, skipping any packets that aren't UDP
+    pub fn next_datagram(&mut self) -> anyhow::Result<Option<Datagram>> {
+        loop {
+            let mut header = [0u8; 16];
+            match self.reader.read_exact(&mut header) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+
+            let seconds = self.read_u32(&header[0..4]) as f64;
+            let fraction = self.read_u32(&header[4..8]) as f64;
+            let captured_length = self.read_u32(&header[8..12]) as usize;
+
+            let mut data = vec![0u8; captured_length];
+            self.reader
+                .read_exact(&mut data)
+                .context("Truncated packet in capture")?;
+            self.packet_number += 1;
+
+            let timestamp = if self.nanosecond {
+                seconds + fraction / 1e9
+            } else {
+                seconds + fraction / 1e6
+            };
+
+            if let Some((src_port, dst_port, payload)) = parse_udp(self.link_type, &data) {
+                return Ok(Some(Datagram {
+                    number: self.packet_number,
+                    timestamp,
+                    src_port,
+                    dst_port,
+                    payload: payload.to_vec(),
+                }));
+            }
+        }
+    }
+}
+
+// Strip the link, IP and UDP headers from a packet, returning the ports and UDP payload
+fn parse_udp(link_type: u32, data: &[u8]) -> Option<(u16, u16, &[u8])> {
+    let (mut ethertype, mut ip) = match link_type {
+        LINKTYPE_ETHERNET => (u16::from_be_bytes([*data.get(12)?, *data.get(13)?]), data.get(14..)?),
+        LINKTYPE_LINUX_SLL => (u16::from_be_bytes([*data.get(14)?, *data.get(15)?]), data.get(16..)?),
+        _ => (0, data),
+    };
+
+    // Skip any VLAN tags
+    while ethertype == 0x8100 {
+        ethertype = u16::from_be_bytes([*ip.get(2)?, *ip.get(3)?]);
+        ip = ip.get(4..)?;
+    }
+
+    let version = ip.first()? >> 4;
+    let udp = match version {
+        4 => {
+            let header_length = ((ip[0] & 0x0f) as usize) * 4;
+            let fragment_offset = u16::from_be_bytes([*ip.get(6)?, *ip.get(7)?]) & 0x1fff;
+            if *ip.get(9)? != 17 || fragment_offset != 0 {
+                return None;
+            }
+            ip.get(header_length..)?
+        }
+        6 => {
+            if *ip.get(6)? != 17 {
+                return None;
+            }
+            ip.get(40..)?
+        }
+        _ => return None,
+    };
+
+    let src_port = u16::from_be_bytes([*udp.first()?, *udp.get(1)?]);
+    let dst_port = u16::from_be_bytes([*udp.get(2)?, *udp.get(3)?]);
+    let udp_length = u16::from_be_bytes([*udp.get(4)?, *udp.get(5)?]) as usize;
+    let payload = udp.get(8..udp_length.clamp(8, udp.len()))?;
+
+    Some((src_port, dst_port, payload))
+}
+
+#[test]
+fn read_sample_capture() {
+    let mut capture = CaptureReader::open(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/test1.pcap")).unwrap();
+
+    let datagram = capture.next_datagram().unwrap().unwrap();
+    assert_eq!(datagram.number, 1);
+    assert_eq!(datagram.dst_port, 20000);
+    assert_eq!(datagram.payload.len(), 42);
+    assert_eq!(&datagram.payload[0..4], &[0x00, 0x00, 0x01, 0x5e]);
+
+    let mut count = 1;
+    while capture.next_datagram().unwrap().is_some() {
+        count += 1;
+    }
+    assert!(count > 1);
+}
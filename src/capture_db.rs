@@ -0,0 +1,154 @@
+// Message databases named by the capture itself. Loggers can record the database they were
+// configured with as a comment, e.g. "elpis-db=vehicle42_v7.json", and with the
+// auto_db_from_capture preference that database is used for the capture instead of messages.json.
+//
+// Dissectors aren't given the capture's section header, so the comment is read from the first
+// ELPIS packet of the capture. Only that packet counts, so that every packet of a capture is
+// dissected with the same database however often it's revisited. Opening another capture goes
+// back to messages.json unless that capture names a database too.
+
+use crate::elpis::ElpisMessages;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+// Prefix of the comment naming a database
+const DB_COMMENT_PREFIX: &str = "elpis-db=";
+
+// Get the database file named by a packet comment. Only a plain file name is accepted, so a
+// comment can't point outside the search directory.
+pub fn parse_db_comment(comment: &str) -> Option<&str> {
+    let name = comment
+        .lines()
+        .find_map(|line| line.trim().strip_prefix(DB_COMMENT_PREFIX))?
+        .trim();
+
+    let is_plain = !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\']);
+    is_plain.then_some(name)
+}
+
+// What became of the database named by a capture
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaptureDatabaseOutcome {
+    Loaded(String),
+    NotFound(String),
+    Failed(String, String),
+}
+
+impl std::fmt::Display for CaptureDatabaseOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureDatabaseOutcome::Loaded(name) => write!(f, "Using database {} named by the capture", name),
+            CaptureDatabaseOutcome::NotFound(name) => {
+                write!(f, "Database {} named by the capture was not found, using messages.json", name)
+            }
+            CaptureDatabaseOutcome::Failed(name, error) => {
+                write!(f, "Database {} named by the capture could not be loaded, using messages.json: {}", name, error)
+            }
+        }
+    }
+}
+
+// The database chosen for the capture being dissected
+#[derive(Default)]
+pub struct CaptureDatabase {
+    // Timestamp of every packet seen so far. A packet seen with another timestamp, or not seen at
+    // all despite an earlier number, means a new capture was opened.
+    seen: HashMap<u32, f64>,
+    // Highest packet number seen
+    seen_through: u32,
+    // Database named by the capture, None to use messages.json
+    messages: Option<Arc<ElpisMessages>>,
+    // The packet the database was chosen on, and what happened
+    outcome: Option<(u32, CaptureDatabaseOutcome)>,
+}
+
+impl CaptureDatabase {
+    // Record a packet and its comment, loading the database it names if it's the first packet of a
+    // capture. Returns the outcome if this is the packet the database was chosen on.
+    pub fn observe(
+        &mut self,
+        packet_number: u32,
+        timestamp: f64,
+        comment: Option<&str>,
+        search_dir: &Path,
+        load: impl FnOnce(&Path) -> anyhow::Result<ElpisMessages>,
+    ) -> Option<CaptureDatabaseOutcome> {
+        match self.seen.get(&packet_number) {
+            Some(seen_timestamp) if *seen_timestamp == timestamp => {}
+            _ if packet_number <= self.seen_through => *self = Self::default(),
+            _ => {}
+        }
+
+        if self.seen.is_empty() {
+            if let Some(name) = comment.and_then(parse_db_comment) {
+                let path = search_dir.join(name);
+                let outcome = if !path.exists() {
+                    CaptureDatabaseOutcome::NotFound(name.to_string())
+                } else {
+                    match load(&path) {
+                        Ok(messages) => {
+                            self.messages = Some(Arc::new(messages));
+                            CaptureDatabaseOutcome::Loaded(name.to_string())
+                        }
+                        Err(e) => CaptureDatabaseOutcome::Failed(name.to_string(), format!("{:#}", e)),
+                    }
+                };
+                self.outcome = Some((packet_number, outcome));
+            }
+        }
+
+        self.seen.insert(packet_number, timestamp);
+        self.seen_through = self.seen_through.max(packet_number);
+
+        self.outcome
+            .as_ref()
+            .filter(|(chosen_on, _)| *chosen_on == packet_number)
+            .map(|(_, outcome)| outcome.clone())
+    }
+
+    // Get the database named by the capture, if it was loaded. A packet takes it once and keeps it
+    // for all of its frames.
+    pub fn messages(&self) -> Option<Arc<ElpisMessages>> {
+        self.messages.clone()
+    }
+}
+
+#[test]
+fn parse_db_comments() {
+    assert_eq!(parse_db_comment("elpis-db=vehicle42_v7.json"), Some("vehicle42_v7.json"));
+    assert_eq!(parse_db_comment("Logger 3.1\n elpis-db= vehicle42_v7.json \n"), Some("vehicle42_v7.json"));
+    assert_eq!(parse_db_comment("vehicle42_v7.json"), None);
+    assert_eq!(parse_db_comment("elpis-db="), None);
+    assert_eq!(parse_db_comment("elpis-db=../messages.json"), None);
+    assert_eq!(parse_db_comment("elpis-db=/etc/passwd"), None);
+    assert_eq!(parse_db_comment("elpis-db=.."), None);
+}
+
+#[test]
+fn capture_database_per_capture() {
+    let fixtures = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests"));
+    let load = |path: &Path| ElpisMessages::load_from_json(path.to_str().unwrap());
+    let mut database = CaptureDatabase::default();
+
+    // The first packet names the database, later packets and revisits keep it
+    let outcome = database.observe(3, 10.0, Some("elpis-db=wide_signals.json"), fixtures, load);
+    assert_eq!(outcome, Some(CaptureDatabaseOutcome::Loaded(String::from("wide_signals.json"))));
+    assert!(database.messages().unwrap().get_def_by_id(0x300).is_some());
+    assert_eq!(database.observe(4, 11.0, Some("elpis-db=missing.json"), fixtures, load), None);
+    assert!(database.messages().is_some());
+    assert!(database.observe(3, 10.0, Some("elpis-db=wide_signals.json"), fixtures, load).is_some());
+
+    // Another capture without the comment goes back to messages.json
+    assert_eq!(database.observe(1, 20.0, None, fixtures, load), None);
+    assert!(database.messages().is_none());
+
+    let mut database = CaptureDatabase::default();
+    let outcome = database.observe(1, 10.0, Some("elpis-db=missing.json"), fixtures, load);
+    assert_eq!(outcome, Some(CaptureDatabaseOutcome::NotFound(String::from("missing.json"))));
+    assert!(database.messages().is_none());
+
+    let mut database = CaptureDatabase::default();
+    let outcome = database.observe(1, 10.0, Some("elpis-db=test1.pcap"), fixtures, load);
+    assert!(matches!(outcome, Some(CaptureDatabaseOutcome::Failed(..))));
+}
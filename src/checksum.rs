@@ -0,0 +1,214 @@
+// Checksum algorithms a message's checksum signal can be verified with, chosen per message:
+//
+//     "checksum": { "signal": "Wheels_CRC", "algorithm": "e2e_p1", "data_id": 288 }
+//
+// Every algorithm covers the payload bytes other than the ones the checksum signal spans, in order.
+//
+// - xor: XOR of the bytes, as checksum_signal
+// - crc8: CRC-8/SAE-J1850, polynomial 0x1D, initial value and final XOR 0xFF
+// - crc16_ccitt: CRC-16/CCITT-FALSE, polynomial 0x1021, initial value 0xFFFF, no final XOR
+// - crc32: CRC-32 as used by Ethernet and zip, reflected polynomial 0xEDB88320
+// - e2e_p1: AUTOSAR E2E profile 1 with data id mode BOTH, CRC-8/SAE-J1850 with initial value and
+//   final XOR 0x00, over the low and high byte of data_id and then the bytes
+// - e2e_p2: AUTOSAR E2E profile 2, CRC-8H2F (polynomial 0x2F, initial value and final XOR 0xFF)
+//   over the bytes and then the entry of data_id_list picked by the counter in the low nibble of
+//   payload byte 1
+// - e2e_p4: AUTOSAR E2E profile 4, CRC-32P4 (reflected polynomial 0xC8DF352F, initial value and
+//   final XOR 0xFFFFFFFF)
+
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+// The checksum of a message: the signal holding it and how it's computed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChecksumSpec {
+    pub signal: String,
+    pub algorithm: String,
+    // Data id of E2E profile 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_id: Option<u16>,
+    // Data id of each counter value of E2E profile 2
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub data_id_list: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Xor,
+    Crc8,
+    Crc16Ccitt,
+    Crc32,
+    E2eProfile1,
+    E2eProfile2,
+    E2eProfile4,
+}
+
+impl ChecksumAlgorithm {
+    const ALL: [ChecksumAlgorithm; 7] = [
+        ChecksumAlgorithm::Xor,
+        ChecksumAlgorithm::Crc8,
+        ChecksumAlgorithm::Crc16Ccitt,
+        ChecksumAlgorithm::Crc32,
+        ChecksumAlgorithm::E2eProfile1,
+        ChecksumAlgorithm::E2eProfile2,
+        ChecksumAlgorithm::E2eProfile4,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Xor => "xor",
+            ChecksumAlgorithm::Crc8 => "crc8",
+            ChecksumAlgorithm::Crc16Ccitt => "crc16_ccitt",
+            ChecksumAlgorithm::Crc32 => "crc32",
+            ChecksumAlgorithm::E2eProfile1 => "e2e_p1",
+            ChecksumAlgorithm::E2eProfile2 => "e2e_p2",
+            ChecksumAlgorithm::E2eProfile4 => "e2e_p4",
+        }
+    }
+
+    pub fn from_name(name: &str) -> anyhow::Result<Self> {
+        Self::ALL.into_iter().find(|algorithm| algorithm.as_str() == name).ok_or_else(|| {
+            let names: Vec<&str> = Self::ALL.iter().map(|algorithm| algorithm.as_str()).collect();
+            anyhow::anyhow!("Unknown checksum algorithm \"{}\", expected one of {}", name, names.join(", "))
+        })
+    }
+
+    // Get the length in bits of the checksums the algorithm computes
+    pub fn width(&self) -> i32 {
+        match self {
+            ChecksumAlgorithm::Xor | ChecksumAlgorithm::Crc8 | ChecksumAlgorithm::E2eProfile1 | ChecksumAlgorithm::E2eProfile2 => 8,
+            ChecksumAlgorithm::Crc16Ccitt => 16,
+            ChecksumAlgorithm::Crc32 | ChecksumAlgorithm::E2eProfile4 => 32,
+        }
+    }
+}
+
+impl ChecksumSpec {
+    // Create the XOR checksum of a message's checksum_signal
+    pub fn xor(signal: &str) -> Self {
+        Self {
+            signal: signal.to_string(),
+            algorithm: ChecksumAlgorithm::Xor.as_str().to_string(),
+            data_id: None,
+            data_id_list: Vec::new(),
+        }
+    }
+
+    // Check the algorithm is known and has the data ids it needs, returning it
+    pub fn algorithm(&self) -> anyhow::Result<ChecksumAlgorithm> {
+        let algorithm = ChecksumAlgorithm::from_name(&self.algorithm)?;
+        if algorithm == ChecksumAlgorithm::E2eProfile1 && self.data_id.is_none() {
+            return Err(anyhow::anyhow!("Checksum algorithm e2e_p1 needs a \"data_id\""));
+        }
+        if algorithm == ChecksumAlgorithm::E2eProfile2 && self.data_id_list.len() != 16 {
+            return Err(anyhow::anyhow!(
+                "Checksum algorithm e2e_p2 needs a \"data_id_list\" of 16 data ids, not {}",
+                self.data_id_list.len()
+            ));
+        }
+        Ok(algorithm)
+    }
+
+    // Compute the checksum of a payload, leaving out the bytes the checksum signal spans
+    pub fn compute(&self, payload: &[u8], checksum_bytes: Range<usize>) -> anyhow::Result<u32> {
+        let bytes = payload
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !checksum_bytes.contains(index))
+            .map(|(_, byte)| *byte);
+
+        Ok(match self.algorithm()? {
+            ChecksumAlgorithm::Xor => bytes.fold(0u8, |acc, byte| acc ^ byte) as u32,
+            ChecksumAlgorithm::Crc8 => crc8(bytes, 0x1d, 0xff) as u32 ^ 0xff,
+            ChecksumAlgorithm::Crc16Ccitt => crc16_ccitt(bytes) as u32,
+            ChecksumAlgorithm::Crc32 => crc32_reflected(bytes, 0xedb88320),
+            ChecksumAlgorithm::E2eProfile1 => {
+                let data_id = self.data_id.unwrap_or_default().to_le_bytes();
+                crc8(data_id.into_iter().chain(bytes), 0x1d, 0x00) as u32
+            }
+            ChecksumAlgorithm::E2eProfile2 => {
+                let counter = payload.get(1).map(|byte| byte & 0x0f).unwrap_or_default();
+                let data_id = self.data_id_list[counter as usize];
+                crc8(bytes.chain(std::iter::once(data_id)), 0x2f, 0xff) as u32 ^ 0xff
+            }
+            ChecksumAlgorithm::E2eProfile4 => crc32_reflected(bytes, 0xc8df352f),
+        })
+    }
+}
+
+// CRC-8 with the given polynomial and initial value, most significant bit first and no final XOR
+fn crc8(bytes: impl Iterator<Item = u8>, polynomial: u8, initial: u8) -> u8 {
+    bytes.fold(initial, |mut crc, byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ polynomial } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+fn crc16_ccitt(bytes: impl Iterator<Item = u8>) -> u16 {
+    bytes.fold(0xffff, |mut crc: u16, byte| {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+// CRC-32 with the given reflected polynomial, initial value and final XOR 0xFFFFFFFF
+fn crc32_reflected(bytes: impl Iterator<Item = u8>, polynomial: u32) -> u32 {
+    !bytes.fold(0xffffffff, |mut crc: u32, byte| {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ polynomial } else { crc >> 1 };
+        }
+        crc
+    })
+}
+
+#[cfg(test)]
+fn test_spec(algorithm: &str) -> ChecksumSpec {
+    ChecksumSpec {
+        algorithm: algorithm.to_string(),
+        ..ChecksumSpec::xor("Checksum")
+    }
+}
+
+#[test]
+fn checksum_check_values() {
+    // The check values of each CRC over "123456789", with the checksum bytes past the end
+    let check = |algorithm: &str| test_spec(algorithm).compute(b"123456789", 9..10).unwrap();
+    assert_eq!(check("xor"), 0x31);
+    assert_eq!(check("crc8"), 0x4b);
+    assert_eq!(check("crc16_ccitt"), 0x29b1);
+    assert_eq!(check("crc32"), 0xcbf43926);
+    assert_eq!(check("e2e_p4"), 0x1697d06a);
+    assert_eq!(crc8(b"123456789".iter().copied(), 0x2f, 0xff) ^ 0xff, 0xdf);
+
+    // The checksum bytes are left out wherever they are
+    let spec = test_spec("crc32");
+    assert_eq!(spec.compute(b"1234\x00\x00\x00\x0056789", 4..8).unwrap(), 0xcbf43926);
+}
+
+#[test]
+fn e2e_checksums() {
+    // Profile 1 covers the data id before the payload
+    let mut spec = test_spec("e2e_p1");
+    assert!(spec.algorithm().is_err());
+    spec.data_id = Some(0x1234);
+    let payload = [0x00, 0x05, 0xaa, 0xbb];
+    assert_eq!(spec.compute(&payload, 0..1).unwrap(), crc8([0x34, 0x12, 0x05, 0xaa, 0xbb].into_iter(), 0x1d, 0x00) as u32);
+
+    // Profile 2 picks the data id by the counter, here 5
+    let mut spec = test_spec("e2e_p2");
+    spec.data_id_list = vec![0; 15];
+    assert!(spec.algorithm().is_err());
+    spec.data_id_list = (0x10..0x20).collect();
+    let expected = crc8([0x05, 0xaa, 0xbb, 0x15].into_iter(), 0x2f, 0xff) ^ 0xff;
+    assert_eq!(spec.compute(&payload, 0..1).unwrap(), expected as u32);
+
+    let error = test_spec("crc64").algorithm().err().unwrap();
+    assert!(error.to_string().starts_with("Unknown checksum algorithm \"crc64\""));
+}
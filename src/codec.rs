@@ -0,0 +1,526 @@
+// Decoding and encoding of whole messages with everything about their signals worked out up front.
+// MessageDefinition::decode checks each signal's start, length and byte order on every call, which
+// adds up when elpis-decode goes through a capture of millions of frames. A MessageCodec does that
+// once, when it's built, and decodes the same values.
+
+use crate::elpis::{DecodedDerived, DecodedFrame, ElpisMessages, MessageDefinition, SignalDefinition, ValueRange};
+use std::collections::HashMap;
+
+// How a signal's raw bits turn into a number, see SignalDefinition::to_physical
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ValueKind {
+    Unsigned,
+    Signed,
+    Float32,
+    Float64,
+}
+
+// A signal with its layout checked and its bit position worked out
+#[derive(Debug, Clone)]
+struct CompiledSignal {
+    name: String,
+    // Position of the signal in its message definition
+    index: usize,
+    is_big_endian: bool,
+    // First bit read, counted from the start of the payload. For big endian signals this is a
+    // position in the payload read most significant bit first.
+    first_bit: usize,
+    length: usize,
+    // Payload bytes needed to read the signal
+    required_bytes: usize,
+    kind: ValueKind,
+    scale: f64,
+    offset: f64,
+    ranges: RangeTable,
+}
+
+// A signal's value ranges as sorted ranges that don't overlap, so a value is looked up with a
+// binary search. Where ranges overlap in the database the first one wins, as it does for
+// SignalDefinition::range_label.
+#[derive(Debug, Clone, Default)]
+struct RangeTable {
+    // (from, to, label), inclusive, scaled ranges have no label
+    entries: Vec<(u128, u128, Option<String>)>,
+}
+
+impl RangeTable {
+    fn new(ranges: &[ValueRange]) -> Self {
+        let mut entries: Vec<(u128, u128, Option<String>)> = Vec::new();
+        for range in ranges.iter().filter(|range| range.from <= range.to) {
+            let (from, to) = (range.from as u128, range.to as u128);
+
+            // Only the parts of the range no earlier range holds
+            let mut parts = Vec::new();
+            let mut next = from;
+            for &(taken_from, taken_to, _) in entries.iter().filter(|entry| entry.1 >= from && entry.0 <= to) {
+                if taken_from > next {
+                    parts.push((next, taken_from - 1, range.label.clone()));
+                }
+                next = next.max(taken_to + 1);
+            }
+            if next <= to {
+                parts.push((next, to, range.label.clone()));
+            }
+
+            entries.extend(parts);
+            entries.sort_by_key(|entry| entry.0);
+        }
+        Self { entries }
+    }
+
+    // Get the label of the range holding a raw value, None when it's scaled or in no range
+    fn label(&self, raw: u128) -> Option<&str> {
+        let index = self.entries.partition_point(|entry| entry.1 < raw);
+        self.entries
+            .get(index)
+            .filter(|entry| entry.0 <= raw)
+            .and_then(|entry| entry.2.as_deref())
+    }
+}
+
+// Mask of the low bits of a value
+fn low_bits(count: usize) -> u128 {
+    if count >= 128 {
+        u128::MAX
+    } else {
+        (1u128 << count) - 1
+    }
+}
+
+impl CompiledSignal {
+    // Compile a signal, or give the reason it can never be decoded
+    fn new(signal: &SignalDefinition, index: usize) -> Result<Self, String> {
+        if signal.length > 128 {
+            return Err(format!("Signal {} is too large to fit in a u128", signal.name));
+        }
+        if signal.length < 0 {
+            return Err(format!("Signal {} has a negative length", signal.name));
+        }
+        let start = signal
+            .start_bit()
+            .ok_or_else(|| format!("Big-endian signal {} has no start bit", signal.name))?;
+        let start = usize::try_from(start).map_err(|_| format!("Signal {} has a negative start bit", signal.name))?;
+        let length = signal.length as usize;
+
+        // Motorola numbering starts each byte at bit 7, flip it to count from the most significant bit
+        let first_bit = if signal.is_big_endian {
+            (start / 8) * 8 + (7 - start % 8)
+        } else {
+            start
+        };
+
+        let is_float = signal.is_float.unwrap_or(false);
+        let kind = if is_float && length == 32 {
+            ValueKind::Float32
+        } else if is_float && length == 64 {
+            ValueKind::Float64
+        } else if signal.is_signed.unwrap_or(false) {
+            ValueKind::Signed
+        } else {
+            ValueKind::Unsigned
+        };
+
+        Ok(Self {
+            name: signal.name.clone(),
+            index,
+            is_big_endian: signal.is_big_endian,
+            first_bit,
+            length,
+            required_bytes: (first_bit + length).div_ceil(8),
+            kind,
+            scale: signal.scale.unwrap_or(1.0),
+            offset: signal.offset,
+            ranges: RangeTable::new(&signal.ranges),
+        })
+    }
+
+    // Read the raw value from a payload that's long enough
+    fn read(&self, payload: &[u8]) -> u128 {
+        let mut raw: u128 = 0;
+        let mut done = 0;
+        let mut position = self.first_bit;
+        while done < self.length {
+            let byte = payload[position / 8] as u128;
+            let bit = position % 8;
+            let take = (8 - bit).min(self.length - done);
+
+            if self.is_big_endian {
+                // Bits come most significant first, from the top of each byte down
+                let bits = (byte >> (8 - bit - take)) & low_bits(take);
+                raw = (raw << take) | bits;
+            } else {
+                // Bits come least significant first, from the bottom of each byte up
+                let bits = (byte >> bit) & low_bits(take);
+                raw |= bits << done;
+            }
+
+            done += take;
+            position += take;
+        }
+        raw
+    }
+
+    // Write a raw value into a payload that's long enough, leaving the other bits alone
+    fn write(&self, payload: &mut [u8], raw: u128) {
+        let mut done = 0;
+        let mut position = self.first_bit;
+        while done < self.length {
+            let bit = position % 8;
+            let take = (8 - bit).min(self.length - done);
+
+            let (bits, shift) = if self.is_big_endian {
+                ((raw >> (self.length - done - take)) & low_bits(take), 8 - bit - take)
+            } else {
+                ((raw >> done) & low_bits(take), bit)
+            };
+            let mask = (low_bits(take) as u8) << shift;
+            payload[position / 8] = (payload[position / 8] & !mask) | ((bits as u8) << shift);
+
+            done += take;
+            position += take;
+        }
+    }
+
+    fn to_physical(&self, raw: u128) -> f64 {
+        let value = match self.kind {
+            ValueKind::Float32 => f32::from_bits(raw as u32) as f64,
+            ValueKind::Float64 => f64::from_bits(raw as u64),
+            ValueKind::Signed => {
+                let shift = 128 - self.length.max(1);
+                (((raw << shift) as i128) >> shift) as f64
+            }
+            ValueKind::Unsigned => raw as f64,
+        };
+        value * self.scale + self.offset
+    }
+
+    // Turn a physical value back into raw bits, rounding to the nearest raw value
+    fn to_raw(&self, physical: f64) -> anyhow::Result<u128> {
+        let value = (physical - self.offset) / self.scale;
+        if !value.is_finite() {
+            return Err(anyhow::anyhow!(
+                "Value {} of signal {} is not a finite number",
+                physical,
+                self.name
+            ));
+        }
+
+        let out_of_range = || {
+            anyhow::anyhow!(
+                "Value {} doesn't fit the {} bits of signal {}",
+                physical,
+                self.length,
+                self.name
+            )
+        };
+        match self.kind {
+            ValueKind::Float32 => Ok((value as f32).to_bits() as u128),
+            ValueKind::Float64 => Ok(value.to_bits() as u128),
+            ValueKind::Signed => {
+                let value = value.round();
+                let limit = 2f64.powi(self.length.max(1) as i32 - 1);
+                if value < -limit || value >= limit {
+                    return Err(out_of_range());
+                }
+                Ok((value as i128 as u128) & low_bits(self.length))
+            }
+            ValueKind::Unsigned => {
+                let value = value.round();
+                if value < 0.0 || value >= 2f64.powi(self.length as i32) {
+                    return Err(out_of_range());
+                }
+                Ok(value as u128)
+            }
+        }
+    }
+}
+
+// A message with all of its signals compiled
+#[derive(Debug, Clone)]
+struct CompiledMessage {
+    definition: MessageDefinition,
+    signals: Vec<CompiledSignal>,
+    // Signals that can never be decoded, reported with every decoded frame
+    errors: Vec<String>,
+    // Index of each signal in signals by its name and aliases
+    by_name: HashMap<String, usize>,
+    // Length of the payload encode produces
+    encoded_length: usize,
+}
+
+impl CompiledMessage {
+    fn new(definition: &MessageDefinition) -> Self {
+        let mut signals = Vec::new();
+        let mut errors = Vec::new();
+        let mut by_name = HashMap::new();
+
+        // Signals without any bits carry no value
+        for (index, signal) in definition.signals.iter().enumerate().filter(|(_, signal)| signal.length != 0) {
+            match CompiledSignal::new(signal, index) {
+                Ok(compiled) => {
+                    for name in std::iter::once(&signal.name).chain(signal.aliases.iter()) {
+                        by_name.entry(name.clone()).or_insert(signals.len());
+                    }
+                    signals.push(compiled);
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+
+        let needed = signals.iter().map(|signal| signal.required_bytes).max().unwrap_or(0);
+        Self {
+            definition: definition.clone(),
+            encoded_length: needed.max(definition.length.max(0) as usize),
+            signals,
+            errors,
+            by_name,
+        }
+    }
+
+    // Encode physical values, keyed by signal name or alias, leaving signals without a value as
+    // zero bits
+    fn encode(&self, values: &HashMap<String, f64>) -> anyhow::Result<Vec<u8>> {
+        let mut payload = vec![0u8; self.encoded_length];
+        for (name, physical) in values.iter() {
+            let index = *self
+                .by_name
+                .get(name)
+                .ok_or_else(|| anyhow::anyhow!("Message {} has no signal {}", self.definition.name, name))?;
+            let signal = &self.signals[index];
+            signal.write(&mut payload, signal.to_raw(*physical)?);
+        }
+        Ok(payload)
+    }
+}
+
+// Decodes and encodes the messages of a database, built with ElpisMessages::build_codec
+#[derive(Debug, Clone)]
+pub struct MessageCodec {
+    messages: HashMap<i32, CompiledMessage>,
+}
+
+impl MessageCodec {
+    // Decode a payload of the message with the given id, the same way MessageDefinition::decode
+    // does, falling back to a message whose id family holds the id. Signals that don't fit the
+    // payload are reported in the frame's errors.
+    pub fn decode(&self, id: i32, data: &[u8]) -> anyhow::Result<DecodedFrame> {
+        let message = self
+            .messages
+            .get(&id)
+            .or_else(|| self.messages.values().find(|message| message.definition.matches_id(id)))
+            .ok_or_else(|| anyhow::anyhow!("Unknown message id {:#x}", id))?;
+
+        let mut frame = DecodedFrame {
+            id,
+            name: message.definition.name.clone(),
+            signals: Vec::with_capacity(message.signals.len()),
+            derived: Vec::new(),
+            errors: message.errors.clone(),
+        };
+
+        for signal in message.signals.iter() {
+            if signal.required_bytes > data.len() {
+                frame.errors.push(format!(
+                    "Could not read signal {}: Cannot read {} bits from a {} byte payload",
+                    signal.name,
+                    signal.length,
+                    data.len()
+                ));
+                continue;
+            }
+
+            let raw = signal.read(data);
+            let label = signal.ranges.label(raw).map(String::from);
+            let definition = &message.definition.signals[signal.index];
+            frame.signals.push(message.definition.decoded_signal(definition, raw, signal.to_physical(raw), label, data));
+        }
+
+        // Wide signals can't be used as operands, their physical values are approximate
+        for derived in message.definition.derived.iter() {
+            let value = message.definition.eval_derived(derived, &|name| {
+                frame
+                    .signals
+                    .iter()
+                    .find(|signal| signal.name == name && !signal.wide)
+                    .map(|signal| signal.physical)
+            });
+            frame.derived.push(DecodedDerived {
+                name: derived.name.clone(),
+                value,
+            });
+        }
+
+        Ok(frame)
+    }
+
+    // Encode physical values, keyed by signal name or alias, into a payload of the message with the
+    // given id. Signals without a value are left as zero bits.
+    pub fn encode(&self, id: i32, values: &HashMap<String, f64>) -> anyhow::Result<Vec<u8>> {
+        self.messages
+            .get(&id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown message id {:#x}", id))?
+            .encode(values)
+    }
+}
+
+impl MessageDefinition {
+    // Encode physical values into a payload of this message, the same way MessageCodec::encode
+    // does. The message is compiled on every call, a codec is quicker for encoding many payloads.
+    pub fn encode_signals(&self, values: &HashMap<String, f64>) -> anyhow::Result<Vec<u8>> {
+        CompiledMessage::new(self).encode(values)
+    }
+
+    // Whether a signal's physical value, encoded and read back, gives the raw value it was decoded
+    // from. Catches scales and offsets that can't be inverted exactly. Values with nothing to
+    // encode, wide signals and floats that aren't a number, always pass.
+    pub fn encode_roundtrip_ok(&self, signal: &SignalDefinition, raw: u128) -> bool {
+        let physical = signal.to_physical(raw);
+        if signal.is_wide() || !physical.is_finite() {
+            return true;
+        }
+        let values = HashMap::from([(signal.name.clone(), physical)]);
+        self.encode_signals(&values)
+            .and_then(|payload| signal.read_raw(&payload))
+            .is_ok_and(|encoded| encoded == raw)
+    }
+}
+
+impl ElpisMessages {
+    // Compile every message for repeated decoding and encoding
+    pub fn build_codec(&self) -> MessageCodec {
+        MessageCodec {
+            messages: self
+                .iter_sorted()
+                .map(|message| (message.id, CompiledMessage::new(message)))
+                .collect(),
+        }
+    }
+}
+
+#[test]
+fn codec_decodes_like_definitions() {
+    let messages = ElpisMessages::load_from_json(concat!(env!("CARGO_MANIFEST_DIR"), "/messages.json")).unwrap();
+    let codec = messages.build_codec();
+
+    // Every message of the bundled database with a few payloads, including one that's too short
+    for message in messages.iter_sorted() {
+        let length = message.length.max(1) as usize;
+        let payloads = [
+            vec![0u8; length],
+            vec![0xffu8; length],
+            (0..length as u8).map(|byte| byte.wrapping_mul(37)).collect(),
+            vec![0xa5],
+        ];
+        for payload in payloads.iter() {
+            let expected = message.decode(payload);
+            let decoded = codec.decode(message.id, payload).unwrap();
+
+            let values = |frame: &DecodedFrame| -> Vec<(String, u128, u64)> {
+                frame
+                    .signals
+                    .iter()
+                    .map(|signal| (signal.name.clone(), signal.raw, signal.physical.to_bits()))
+                    .collect()
+            };
+            assert_eq!(values(&decoded), values(&expected), "{} {:02x?}", message.name, payload);
+            assert_eq!(
+                decoded.errors.len(),
+                expected.errors.len(),
+                "{} {:02x?}",
+                message.name,
+                payload
+            );
+        }
+    }
+
+    assert!(codec.decode(-1, &[]).is_err());
+}
+
+#[test]
+fn codec_range_table() {
+    let mut signal = crate::elpis::ranged_test_signal();
+
+    // Overlapping ranges, the earlier one wins wherever they overlap
+    signal.ranges.insert(0, ValueRange { from: 100, to: 252, label: Some(String::from("Fast")), scale: false });
+    signal.ranges.push(ValueRange { from: 240, to: 260, label: Some(String::from("Unreachable")), scale: false });
+    let table = RangeTable::new(&signal.ranges);
+    assert_eq!(table.entries.len(), 6);
+
+    // Every range edge, and a step either side of it
+    for raw in [0, 1, 99, 100, 101, 251, 252, 253, 254, 255, 256, 260, 261, u64::MAX as u128] {
+        assert_eq!(table.label(raw), signal.range_label(raw), "raw value {}", raw);
+    }
+    assert_eq!(table.label(99), None);
+    assert_eq!(table.label(252), Some("Fast"));
+    assert_eq!(table.label(253), Some("Error"));
+    assert_eq!(table.label(256), Some("Unreachable"));
+    assert!(RangeTable::new(&[]).label(0).is_none());
+
+    let mut message = MessageDefinition::new("Speed", 0x100, 1);
+    message.signals.push(signal);
+    let codec = ElpisMessages::from_definitions(vec![message]).build_codec();
+    assert_eq!(codec.decode(0x100, &[255]).unwrap().signals[0].label.as_deref(), Some("SNA"));
+}
+
+#[test]
+fn codec_round_trip() {
+    let mut message = MessageDefinition::new("Wheels", 0x120, 8);
+    let layouts = [
+        ("Speed_FL", 0, 16, false, false),
+        ("Torque", 23, 12, true, true),
+        ("Temp", 44, 7, false, true),
+    ];
+    for (name, start, length, is_big_endian, is_signed) in layouts {
+        let mut signal = SignalDefinition::new(name, Some(start), length);
+        signal.is_big_endian = is_big_endian;
+        signal.is_signed = Some(is_signed);
+        signal.scale = Some(0.5);
+        signal.offset = -10.0;
+        message.signals.push(signal);
+    }
+    message.signals[0].aliases.push(String::from("WhlSpd_FL"));
+
+    let messages = ElpisMessages::from_definitions(vec![message]);
+    let codec = messages.build_codec();
+
+    let values: HashMap<String, f64> = [("WhlSpd_FL", 1234.5), ("Torque", -200.0), ("Temp", 5.5)]
+        .iter()
+        .map(|(name, value)| (name.to_string(), *value))
+        .collect();
+    let payload = codec.encode(0x120, &values).unwrap();
+    assert_eq!(payload.len(), 8);
+
+    // The definition decodes what the codec encoded
+    let decoded = messages.get_def_by_id(0x120).unwrap().decode(&payload);
+    let physical: Vec<(&str, f64)> = decoded
+        .signals
+        .iter()
+        .map(|signal| (signal.name.as_str(), signal.physical))
+        .collect();
+    assert_eq!(physical, vec![("Speed_FL", 1234.5), ("Torque", -200.0), ("Temp", 5.5)]);
+
+    let too_large: HashMap<String, f64> = [(String::from("Temp"), 100.0)].into_iter().collect();
+    assert!(codec.encode(0x120, &too_large).is_err());
+    let unknown: HashMap<String, f64> = [(String::from("Speed_RR"), 1.0)].into_iter().collect();
+    assert!(codec.encode(0x120, &unknown).is_err());
+}
+
+#[test]
+fn encode_roundtrip_check() {
+    let mut message = MessageDefinition::new("Odometer", 0x130, 8);
+    let mut signal = SignalDefinition::new("Distance", Some(0), 32);
+    signal.scale = Some(0.1);
+    message.signals.push(signal.clone());
+
+    // Every raw value of a decimal scale reads back
+    for raw in [0, 1, 3, 7, 12345, u32::MAX as u128] {
+        assert!(message.encode_roundtrip_ok(&message.signals[0], raw), "raw value {}", raw);
+    }
+
+    // An offset so large the physical value loses the raw value's low bits
+    signal.scale = None;
+    signal.offset = 1e17;
+    message.signals[0] = signal;
+    assert!(message.encode_roundtrip_ok(&message.signals[0], 0));
+    assert!(!message.encode_roundtrip_ok(&message.signals[0], 1));
+}
@@ -0,0 +1,181 @@
+// Which payload bits each signal of a message covers, and signals that claim the same bits. Two
+// signals reading the same bits is a database error, unless they're never in the same frame: a
+// multiplexed signal is only present for its multiplexer values, so signals of the same multiplexer
+// with no value in common can share bits. Multiplexers can be multiplexed themselves, and signals
+// are exclusive when any multiplexer above both of them picks a different value for each.
+//
+// Bits are numbered as byte * 8 + bit, with bit 0 the least significant bit of its byte, the same
+// numbering for both byte orders.
+
+use crate::elpis::{MessageDefinition, SignalDefinition};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Values of each multiplexer above a signal that it's present for, keyed by multiplexer name. None
+// when the values can't be read.
+type Conditions<'a> = BTreeMap<&'a str, Option<BTreeSet<i64>>>;
+
+// Two signals of a message that read some of the same bits
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignalOverlap {
+    pub message: String,
+    pub first: String,
+    pub second: String,
+    // Lowest and highest bit both signals read
+    pub bits: (u32, u32),
+}
+
+impl std::fmt::Display for SignalOverlap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Signals {} and {} overlap at bits {}..{}, database error",
+            self.first, self.second, self.bits.0, self.bits.1
+        )
+    }
+}
+
+// Get the payload bits a signal reads, None when it has no start bit
+pub fn signal_bits(signal: &SignalDefinition) -> Option<BTreeSet<u32>> {
+    let start = u32::try_from(signal.start_bit()?).ok()?;
+    let length = signal.length.clamp(0, 128) as u32;
+    if !signal.is_big_endian {
+        return Some((start..start + length).collect());
+    }
+
+    // Motorola bits run from the start down to bit 0 of its byte, then on from bit 7 of the next
+    let mut bits = BTreeSet::new();
+    let mut position = start;
+    for _ in 0..length {
+        bits.insert(position);
+        position = if position % 8 == 0 { position + 15 } else { position - 1 };
+    }
+    Some(bits)
+}
+
+// Get the values of each multiplexer above a signal that it's present for
+fn multiplexer_conditions<'a>(message: &'a MessageDefinition, signal: &'a SignalDefinition) -> Conditions<'a> {
+    let mut conditions = BTreeMap::new();
+    let mut current = signal;
+    while let Some(multiplexer) = current.multiplexer_signal.as_deref() {
+        // A multiplexer already seen means the database loops, stop rather than follow it forever
+        if conditions.insert(multiplexer, current.multiplexer_values()).is_some() {
+            break;
+        }
+        match message.signals.iter().find(|signal| signal.name == multiplexer) {
+            Some(parent) => current = parent,
+            None => break,
+        }
+    }
+    conditions
+}
+
+// Check whether two signals are never in the same frame. Signals of a multiplexer whose values
+// can't be read are taken to be exclusive, rather than flagged on a guess.
+fn mutually_exclusive(first: &Conditions, second: &Conditions) -> bool {
+    first.iter().any(|(multiplexer, values)| match (values, second.get(multiplexer)) {
+        (Some(values), Some(Some(other))) => values.is_disjoint(other),
+        (None, Some(_)) | (Some(_), Some(None)) => true,
+        (_, None) => false,
+    })
+}
+
+// Find every pair of signals of a message that can be in the same frame and read the same bits
+pub fn signal_overlaps(message: &MessageDefinition) -> Vec<SignalOverlap> {
+    let signals: Vec<(&SignalDefinition, BTreeSet<u32>, Conditions)> = message
+        .signals
+        .iter()
+        .filter_map(|signal| Some((signal, signal_bits(signal)?, multiplexer_conditions(message, signal))))
+        .collect();
+
+    let mut overlaps = Vec::new();
+    for (index, (first, first_bits, first_conditions)) in signals.iter().enumerate() {
+        for (second, second_bits, second_conditions) in signals[index + 1..].iter() {
+            let mut shared = first_bits.intersection(second_bits);
+            let Some(low) = shared.next().copied() else {
+                continue;
+            };
+            if mutually_exclusive(first_conditions, second_conditions) {
+                continue;
+            }
+            overlaps.push(SignalOverlap {
+                message: message.name.clone(),
+                first: first.name.clone(),
+                second: second.name.clone(),
+                bits: (low, shared.last().copied().unwrap_or(low)),
+            });
+        }
+    }
+    overlaps
+}
+
+#[cfg(test)]
+fn overlap_test_message(signals: &str) -> MessageDefinition {
+    serde_json::from_str(&format!(r#"{{ "name": "Status", "length": 8, "id": 1, "comment": null, "signals": {} }}"#, signals)).unwrap()
+}
+
+#[test]
+fn signal_bit_coverage() {
+    let message = overlap_test_message(
+        r#"[
+            { "name": "Intel", "start": 4, "length": 8, "is_big_endian": false },
+            { "name": "Motorola", "start": 3, "length": 8 }
+        ]"#,
+    );
+    assert_eq!(signal_bits(&message.signals[0]).unwrap(), (4..12).collect());
+    assert_eq!(signal_bits(&message.signals[1]).unwrap(), BTreeSet::from([0, 1, 2, 3, 12, 13, 14, 15]));
+}
+
+#[test]
+fn overlapping_signals() {
+    let message = overlap_test_message(
+        r#"[
+            { "name": "Foo", "start": 8, "length": 8, "is_big_endian": false },
+            { "name": "Bar", "start": 12, "length": 8, "is_big_endian": false },
+            { "name": "Baz", "start": 20, "length": 4, "is_big_endian": false }
+        ]"#,
+    );
+    let overlaps = signal_overlaps(&message);
+    assert_eq!(overlaps.len(), 1);
+    assert_eq!(overlaps[0].to_string(), "Signals Foo and Bar overlap at bits 12..15, database error");
+
+    // A multiplexed signal still overlaps signals that are always present
+    let message = overlap_test_message(
+        r#"[
+            { "name": "Mode", "start": 0, "length": 8, "is_big_endian": false, "is_multiplexer": true },
+            { "name": "Counter", "start": 8, "length": 4, "is_big_endian": false },
+            { "name": "Level", "start": 8, "length": 16, "is_big_endian": false,
+              "multiplexer_signal": "Mode", "multiplexer_ids": [1] }
+        ]"#,
+    );
+    let pairs: Vec<(String, String)> = signal_overlaps(&message).into_iter().map(|overlap| (overlap.first, overlap.second)).collect();
+    assert_eq!(pairs, vec![("Counter".to_string(), "Level".to_string())]);
+}
+
+#[test]
+fn multiplexed_signals_share_bits() {
+    // Signals of different multiplexer values are never in the same frame, nor are those of nested
+    // multiplexers below different values
+    let message = overlap_test_message(
+        r#"[
+            { "name": "Mode", "start": 0, "length": 8, "is_big_endian": false, "is_multiplexer": true },
+            { "name": "Temp", "start": 8, "length": 16, "is_big_endian": false,
+              "multiplexer_signal": "Mode", "multiplexer_ids": [1, 2] },
+            { "name": "Pressure", "start": 8, "length": 16, "is_big_endian": false,
+              "multiplexer_signal": "Mode", "multiplexer_ids": 3 },
+            { "name": "Page", "start": 24, "length": 8, "is_big_endian": false, "is_multiplexer": true,
+              "multiplexer_signal": "Mode", "multiplexer_ids": [4] },
+            { "name": "Cell", "start": 8, "length": 16, "is_big_endian": false,
+              "multiplexer_signal": "Page", "multiplexer_ids": [0] },
+            { "name": "Voltage", "start": 8, "length": 16, "is_big_endian": false,
+              "multiplexer_signal": "Page", "multiplexer_ids": [1] }
+        ]"#,
+    );
+    assert!(signal_overlaps(&message).is_empty());
+
+    // Sharing a multiplexer value they do overlap
+    let mut message = message;
+    message.signals[2].multiplexer_ids = Some(serde_json::json!([2, 3]));
+    let overlaps = signal_overlaps(&message);
+    assert_eq!(overlaps.len(), 1);
+    assert_eq!((overlaps[0].first.as_str(), overlaps[0].second.as_str(), overlaps[0].bits), ("Temp", "Pressure", (8, 23)));
+}
@@ -0,0 +1,470 @@
+// Semantic comparison of two message databases, for reviewing a new messages.json before deploying
+// it. Signals are compared by the layout they decode with, so reordering signals or spelling a
+// default out (e.g. `"scale": 1.0` instead of leaving it out) isn't reported as a change. A signal
+// that's gone under one name and new under another with the same layout is reported as renamed, as
+// happens when a vendor database and an internal one name the same bits differently.
+
+use crate::elpis::{ElpisMessages, MessageDefinition, SignalDefinition};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+    Renamed,
+}
+
+// A single difference between the databases
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    // What changed, e.g. "message 0x120 Wheels" or "signal Wheels.Speed"
+    pub subject: String,
+    // What about it changed, for modifications, e.g. "scale 0.01 -> 0.05"
+    pub detail: Option<String>,
+    // True when existing signals decode differently because of this change
+    pub breaking: bool,
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let marker = match self.kind {
+            ChangeKind::Added => "+",
+            ChangeKind::Removed => "-",
+            ChangeKind::Modified => "~",
+            ChangeKind::Renamed => ">",
+        };
+        write!(f, "{} {}", marker, self.subject)?;
+        if let Some(detail) = &self.detail {
+            write!(f, ": {}", detail)?;
+        }
+        if self.breaking {
+            write!(f, " (breaking)")?;
+        }
+        Ok(())
+    }
+}
+
+// The properties of a signal that affect how it's decoded, with defaults filled in
+#[derive(PartialEq)]
+struct SignalLayout {
+    start: Option<i32>,
+    length: i32,
+    byte_order: &'static str,
+    signed: bool,
+    float: bool,
+    scale: f64,
+    offset: f64,
+    unit: String,
+    choices: BTreeMap<String, i32>,
+}
+
+impl SignalLayout {
+    fn new(signal: &SignalDefinition) -> Self {
+        Self {
+            start: signal.start_bit(),
+            length: signal.length,
+            byte_order: if signal.is_big_endian { "big endian" } else { "little endian" },
+            signed: signal.is_signed.unwrap_or(false),
+            float: signal.is_float.unwrap_or(false),
+            scale: signal.scale.unwrap_or(1.0),
+            offset: signal.offset,
+            unit: signal.unit.clone().unwrap_or_default(),
+            choices: signal.choices.clone().unwrap_or_default().into_iter().collect(),
+        }
+    }
+}
+
+// Where a message that's only in one of the databases was defined
+fn describe_source(message: &MessageDefinition) -> Option<String> {
+    message.source.as_ref().map(|source| format!("from {}", source))
+}
+
+fn describe_start(start: Option<i32>) -> String {
+    start.map(|start| start.to_string()).unwrap_or_else(|| String::from("none"))
+}
+
+fn describe_optional(value: &Option<String>) -> String {
+    value.clone().unwrap_or_else(|| String::from("none"))
+}
+
+fn message_subject(message: &MessageDefinition) -> String {
+    format!("message {:#x} {}", message.id, message.name)
+}
+
+pub struct DbDiff {
+    pub changes: Vec<Change>,
+}
+
+impl DbDiff {
+    // Compare two databases, matching messages by id and signals by name, or by layout for renamed
+    // signals
+    pub fn compare(old: &ElpisMessages, new: &ElpisMessages) -> Self {
+        let mut changes = Vec::new();
+
+        let ids: BTreeSet<i32> = old
+            .iter_sorted()
+            .chain(new.iter_sorted())
+            .map(|message| message.id)
+            .collect();
+        for id in ids {
+            match (old.get_def_by_id(id), new.get_def_by_id(id)) {
+                (Some(old), Some(new)) => compare_messages(old, new, &mut changes),
+                (Some(old), None) => changes.push(Change {
+                    kind: ChangeKind::Removed,
+                    subject: message_subject(old),
+                    detail: describe_source(old),
+                    breaking: false,
+                }),
+                (None, Some(new)) => changes.push(Change {
+                    kind: ChangeKind::Added,
+                    subject: message_subject(new),
+                    detail: describe_source(new),
+                    breaking: false,
+                }),
+                (None, None) => {}
+            }
+        }
+
+        Self { changes }
+    }
+
+    pub fn has_breaking_changes(&self) -> bool {
+        self.changes.iter().any(|change| change.breaking)
+    }
+
+    fn count(&self, kind: ChangeKind, signals: bool) -> usize {
+        self.changes
+            .iter()
+            .filter(|change| change.kind == kind && change.subject.starts_with("signal") == signals)
+            .count()
+    }
+
+    // Render every change followed by a summary count
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        for change in self.changes.iter() {
+            output.push_str(format!("{}\n", change).as_str());
+        }
+
+        // Several changes to one signal count as a single modified signal
+        let modified = |signals: bool| {
+            self.changes
+                .iter()
+                .filter(|change| change.kind == ChangeKind::Modified && change.subject.starts_with("signal") == signals)
+                .map(|change| change.subject.as_str())
+                .collect::<BTreeSet<&str>>()
+                .len()
+        };
+
+        output.push_str(
+            format!(
+                "messages: {} added, {} removed, {} modified; signals: {} added, {} removed, {} modified, {} renamed; {} breaking\n",
+                self.count(ChangeKind::Added, false),
+                self.count(ChangeKind::Removed, false),
+                modified(false),
+                self.count(ChangeKind::Added, true),
+                self.count(ChangeKind::Removed, true),
+                modified(true),
+                self.count(ChangeKind::Renamed, true),
+                self.changes.iter().filter(|change| change.breaking).count()
+            )
+            .as_str(),
+        );
+
+        output
+    }
+}
+
+fn compare_messages(old: &MessageDefinition, new: &MessageDefinition, changes: &mut Vec<Change>) {
+    let subject = message_subject(new);
+    let mut modified = |detail: String| {
+        changes.push(Change {
+            kind: ChangeKind::Modified,
+            subject: subject.clone(),
+            detail: Some(detail),
+            breaking: false,
+        })
+    };
+
+    if old.name != new.name {
+        modified(format!("name {} -> {}", old.name, new.name));
+    }
+    if old.length != new.length {
+        modified(format!("length {} -> {}", old.length, new.length));
+    }
+    let old_frame_type = old.frame_type.map(|frame_type| frame_type.as_str().to_string());
+    let new_frame_type = new.frame_type.map(|frame_type| frame_type.as_str().to_string());
+    if old_frame_type != new_frame_type {
+        modified(format!(
+            "frame type {} -> {}",
+            describe_optional(&old_frame_type),
+            describe_optional(&new_frame_type)
+        ));
+    }
+    if old.checksum_signal != new.checksum_signal {
+        modified(format!(
+            "checksum signal {} -> {}",
+            describe_optional(&old.checksum_signal),
+            describe_optional(&new.checksum_signal)
+        ));
+    }
+    let old_checksum = old.checksum.as_ref().map(|checksum| format!("{} {}", checksum.algorithm, checksum.signal));
+    let new_checksum = new.checksum.as_ref().map(|checksum| format!("{} {}", checksum.algorithm, checksum.signal));
+    if old_checksum != new_checksum {
+        modified(format!(
+            "checksum {} -> {}",
+            describe_optional(&old_checksum),
+            describe_optional(&new_checksum)
+        ));
+    }
+
+    let names: BTreeSet<&str> = old
+        .signals
+        .iter()
+        .chain(new.signals.iter())
+        .map(|signal| signal.name.as_str())
+        .collect();
+    let renamed = renamed_signals(old, new);
+    for name in names {
+        let old_signal = old.signals.iter().find(|signal| signal.name == name);
+        let new_signal = new.signals.iter().find(|signal| signal.name == name);
+        let subject = format!("signal {}.{}", new.name, name);
+
+        match (old_signal, new_signal) {
+            (Some(old_signal), Some(new_signal)) => compare_signals(&subject, old_signal, new_signal, changes),
+            (Some(_), None) if renamed.values().any(|old_signal| old_signal.name == name) => {}
+            (None, Some(new_signal)) if renamed.contains_key(name) => {
+                let old_signal = renamed[name];
+                let alias = if new_signal.aliases.contains(&old_signal.name) { ", old name kept as an alias" } else { "" };
+                changes.push(Change {
+                    kind: ChangeKind::Renamed,
+                    subject: subject.clone(),
+                    detail: Some(format!("renamed from {}, same layout{}", old_signal.name, alias)),
+                    breaking: false,
+                });
+                compare_signals(&subject, old_signal, new_signal, changes);
+            }
+            (Some(_), None) => changes.push(Change {
+                kind: ChangeKind::Removed,
+                subject,
+                detail: None,
+                breaking: false,
+            }),
+            (None, Some(_)) => changes.push(Change {
+                kind: ChangeKind::Added,
+                subject,
+                detail: None,
+                breaking: false,
+            }),
+            (None, None) => {}
+        }
+    }
+}
+
+// Pair signals only in the old message with signals only in the new one decoding the same bits, by
+// the new name
+fn renamed_signals<'a>(old: &'a MessageDefinition, new: &'a MessageDefinition) -> BTreeMap<&'a str, &'a SignalDefinition> {
+    let only_in = |message: &'a MessageDefinition, other: &MessageDefinition| {
+        let mut signals: Vec<&'a SignalDefinition> = message
+            .signals
+            .iter()
+            .filter(|signal| !other.signals.iter().any(|other| other.name == signal.name))
+            .collect();
+        signals.sort_by(|a, b| a.name.cmp(&b.name));
+        signals
+    };
+
+    let mut added = only_in(new, old);
+    let mut renamed = BTreeMap::new();
+    for old_signal in only_in(old, new) {
+        if let Some(index) = added.iter().position(|new_signal| new.signal_decodes_like(new_signal, old, old_signal)) {
+            renamed.insert(added.remove(index).name.as_str(), old_signal);
+        }
+    }
+    renamed
+}
+
+fn compare_signals(subject: &str, old: &SignalDefinition, new: &SignalDefinition, changes: &mut Vec<Change>) {
+    let old = SignalLayout::new(old);
+    let new = SignalLayout::new(new);
+    if old == new {
+        return;
+    }
+
+    let mut modified = |detail: String, breaking: bool| {
+        changes.push(Change {
+            kind: ChangeKind::Modified,
+            subject: subject.to_string(),
+            detail: Some(detail),
+            breaking,
+        })
+    };
+
+    // Anything that changes the physical value read from the same payload is breaking
+    if old.start != new.start {
+        modified(format!("start {} -> {}", describe_start(old.start), describe_start(new.start)), true);
+    }
+    if old.length != new.length {
+        modified(format!("length {} -> {}", old.length, new.length), true);
+    }
+    if old.byte_order != new.byte_order {
+        modified(format!("byte order {} -> {}", old.byte_order, new.byte_order), true);
+    }
+    if old.signed != new.signed {
+        modified(format!("signed {} -> {}", old.signed, new.signed), true);
+    }
+    if old.float != new.float {
+        modified(format!("float {} -> {}", old.float, new.float), true);
+    }
+    if old.scale != new.scale {
+        modified(format!("scale {} -> {}", old.scale, new.scale), true);
+    }
+    if old.offset != new.offset {
+        modified(format!("offset {} -> {}", old.offset, new.offset), true);
+    }
+    if old.unit != new.unit {
+        modified(format!("unit \"{}\" -> \"{}\"", old.unit, new.unit), false);
+    }
+
+    let choice_names: BTreeSet<&String> = old.choices.keys().chain(new.choices.keys()).collect();
+    for choice in choice_names {
+        match (old.choices.get(choice), new.choices.get(choice)) {
+            (Some(old_value), Some(new_value)) if old_value != new_value => {
+                modified(format!("choice {} {} -> {}", choice, old_value, new_value), false)
+            }
+            (Some(value), None) => modified(format!("choice {} ({}) removed", choice, value), false),
+            (None, Some(value)) => modified(format!("choice {} ({}) added", choice, value), false),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+fn diff_messages(json: &str) -> ElpisMessages {
+    ElpisMessages::from_definitions(serde_json::from_str(json).unwrap())
+}
+
+#[test]
+fn diff_databases() {
+    let old = diff_messages(
+        r#"[{
+            "name": "Wheels", "length": 4, "id": 288, "comment": null,
+            "signals": [
+                { "name": "FL", "start": 7, "length": 16, "scale": 0.01 },
+                { "name": "FR", "start": 23, "length": 16, "scale": 0.01, "choices": { "Invalid": 65535 } },
+                { "name": "Counter", "length": 4, "is_big_endian": false }
+            ]
+        }, {
+            "name": "Old", "length": 1, "id": 1, "comment": null, "signals": []
+        }]"#,
+    );
+    let new = diff_messages(
+        r#"[{
+            "name": "Wheels", "length": 4, "id": 288, "comment": "reordered and defaults spelled out",
+            "signals": [
+                { "name": "Counter", "start": 0, "length": 4, "is_big_endian": false, "is_signed": false, "scale": 1.0 },
+                { "name": "FR", "start": 23, "length": 16, "scale": 0.05, "choices": { "Invalid": 65535, "Init": 0 } },
+                { "name": "FL", "start": 7, "length": 16, "scale": 0.01, "unit": "km/h" },
+                { "name": "RL", "start": 39, "length": 8 }
+            ]
+        }, {
+            "name": "New", "length": 1, "id": 2, "comment": null, "signals": []
+        }]"#,
+    );
+
+    let diff = DbDiff::compare(&old, &new);
+    let lines: Vec<String> = diff.changes.iter().map(|change| change.to_string()).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "- message 0x1 Old",
+            "+ message 0x2 New",
+            "~ signal Wheels.FL: unit \"\" -> \"km/h\"",
+            "~ signal Wheels.FR: scale 0.01 -> 0.05 (breaking)",
+            "~ signal Wheels.FR: choice Init (0) added",
+            "+ signal Wheels.RL",
+        ]
+    );
+    assert!(diff.has_breaking_changes());
+    assert!(diff
+        .render()
+        .ends_with("messages: 1 added, 1 removed, 0 modified; signals: 1 added, 0 removed, 2 modified, 0 renamed; 1 breaking\n"));
+
+    // Messages only in one database say which file they're from
+    let (mut old, mut new) = (old, new);
+    old.set_source("chassis_v11.json");
+    new.set_source("chassis_v12.json");
+    let diff = DbDiff::compare(&old, &new);
+    assert_eq!(diff.changes[0].to_string(), "- message 0x1 Old: from chassis_v11.json");
+    assert_eq!(diff.changes[1].to_string(), "+ message 0x2 New: from chassis_v12.json");
+
+    // Comparing a database with itself finds nothing
+    let diff = DbDiff::compare(&new, &new);
+    assert!(diff.changes.is_empty());
+    assert!(!diff.has_breaking_changes());
+}
+
+#[test]
+fn diff_breaking_layout_changes() {
+    let old = diff_messages(
+        r#"[{ "name": "M", "length": 2, "id": 5, "comment": null,
+              "signals": [{ "name": "S", "start": 7, "length": 8 }] }]"#,
+    );
+    let new = diff_messages(
+        r#"[{ "name": "M", "length": 2, "id": 5, "comment": null,
+              "signals": [{ "name": "S", "start": 0, "length": 12, "is_big_endian": false, "offset": -40 }] }]"#,
+    );
+
+    let details: Vec<String> = DbDiff::compare(&old, &new)
+        .changes
+        .iter()
+        .filter(|change| change.breaking)
+        .map(|change| change.detail.clone().unwrap())
+        .collect();
+    assert_eq!(
+        details,
+        vec![
+            "start 7 -> 0",
+            "length 8 -> 12",
+            "byte order big endian -> little endian",
+            "offset 0 -> -40",
+        ]
+    );
+}
+
+#[test]
+fn diff_renamed_signals() {
+    let old = diff_messages(
+        r#"[{ "name": "Wheels", "length": 3, "id": 288, "comment": null,
+              "signals": [
+                  { "name": "WhlSpd_FL", "start": 0, "length": 16, "is_big_endian": false, "scale": 0.01 },
+                  { "name": "CRC", "start": 16, "length": 8, "is_big_endian": false }
+              ] }]"#,
+    );
+    let new = diff_messages(
+        r#"[{ "name": "Wheels", "length": 3, "id": 288, "comment": null,
+              "signals": [
+                  { "name": "Wheel_Speed_Front_Left", "start": 0, "length": 16, "is_big_endian": false, "scale": 0.01,
+                    "unit": "km/h", "aliases": ["WhlSpd_FL"] },
+                  { "name": "Checksum", "start": 16, "length": 8, "is_big_endian": true }
+              ] }]"#,
+    );
+
+    // A changed byte order is a different signal, not a rename
+    let diff = DbDiff::compare(&old, &new);
+    let lines: Vec<String> = diff.changes.iter().map(|change| change.to_string()).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "- signal Wheels.CRC",
+            "+ signal Wheels.Checksum",
+            "> signal Wheels.Wheel_Speed_Front_Left: renamed from WhlSpd_FL, same layout, old name kept as an alias",
+            "~ signal Wheels.Wheel_Speed_Front_Left: unit \"\" -> \"km/h\"",
+        ]
+    );
+    assert!(diff
+        .render()
+        .ends_with("signals: 1 added, 1 removed, 1 modified, 1 renamed; 0 breaking\n"));
+}
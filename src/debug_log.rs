@@ -0,0 +1,113 @@
+// Debug output that never holds up dissection. Messages go through a bounded channel to a thread
+// that writes them out, and when the writer can't keep up, new messages are dropped and counted
+// instead of waiting. The count is written as a marker before the next message that gets through.
+//
+// Only debug output goes through here. Warnings and errors are rare and stay synchronous.
+
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+pub struct DebugLog {
+    sender: SyncSender<String>,
+    // Messages dropped since the last marker was written
+    dropped: Arc<AtomicU64>,
+    writer: JoinHandle<()>,
+}
+
+impl DebugLog {
+    // Start a log holding up to the given number of messages that haven't been written yet
+    pub fn new<W: Write + Send + 'static>(capacity: usize, mut sink: W) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<String>(capacity);
+        let dropped = Arc::new(AtomicU64::new(0));
+
+        let writer_dropped = dropped.clone();
+        let writer = std::thread::spawn(move || {
+            for message in receiver {
+                let dropped = writer_dropped.swap(0, Ordering::Relaxed);
+                if dropped > 0 {
+                    let _ = writeln!(sink, "DEBUG: {} debug messages dropped", dropped);
+                }
+                let _ = writeln!(sink, "DEBUG: {}", message);
+            }
+            let _ = sink.flush();
+        });
+
+        Self { sender, dropped, writer }
+    }
+
+    // Queue a message, dropping it if the queue is full
+    pub fn log(&self, message: String) {
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(message) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // Get the number of messages dropped since the last marker was written
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    // Write out every queued message and stop the writer
+    pub fn finish(self) {
+        drop(self.sender);
+        let _ = self.writer.join();
+    }
+}
+
+// A sink that blocks every write until it's opened, and keeps what was written
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct GatedSink {
+    open: Arc<std::sync::atomic::AtomicBool>,
+    written: Arc<std::sync::Mutex<Vec<u8>>>,
+}
+
+#[cfg(test)]
+impl Write for GatedSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        while !self.open.load(Ordering::Relaxed) {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        self.written.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn drop_messages_when_the_sink_is_slow() {
+    let sink = GatedSink::default();
+    let log = DebugLog::new(4, sink.clone());
+
+    // At most the queue and the message being written are held, the rest are dropped without waiting
+    let start = std::time::Instant::now();
+    for index in 0..20 {
+        log.log(format!("message {}", index));
+    }
+    assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    assert!(log.dropped() >= 15, "{}", log.dropped());
+
+    sink.open.store(true, Ordering::Relaxed);
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    log.log(String::from("after"));
+    log.finish();
+
+    // Every message is either written or counted in a marker
+    let written = String::from_utf8(sink.written.lock().unwrap().clone()).unwrap();
+    let dropped: u64 = written
+        .lines()
+        .filter_map(|line| line.strip_suffix(" debug messages dropped"))
+        .map(|count| count.trim_start_matches("DEBUG: ").parse::<u64>().unwrap())
+        .sum();
+    let messages = written.lines().filter(|line| !line.ends_with("dropped")).count() as u64;
+    assert!(dropped >= 15, "{}", written);
+    assert_eq!(dropped + messages, 21, "{}", written);
+    assert!(written.contains("DEBUG: message 0\n"), "{}", written);
+    assert!(written.ends_with("DEBUG: after\n"), "{}", written);
+}
@@ -1,6 +1,11 @@
+use crate::checksum::ChecksumSpec;
+use crate::expr::Expression;
+use crate::ports::HeaderVariant;
+use crate::trace::{DecodeTrace, DerivedTrace, SignalTrace};
+use crate::transform::{PayloadTransform, WordSwap};
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, io::{Cursor, SeekFrom}};
+use std::{collections::{BTreeMap, BTreeSet, HashMap, HashSet}, io::{Cursor, SeekFrom}, sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex, RwLock, Weak}};
 use bitstream_io::{BigEndian, BitRead, BitReader, LittleEndian};
 
 fn default_as_true() -> bool {
@@ -13,7 +18,7 @@ fn default_as_max_f64() -> f64 {
 
 // Defines all signals in a message. This can use *either* Intel or Motorola endianness
 //
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SignalDefinition {
     pub name: String,
     pub start: Option<i32>,
@@ -43,55 +48,1727 @@ pub struct SignalDefinition {
     pub is_multiplexer: Option<bool>,
     pub is_float: Option<bool>,
     pub multiplexer_ids: Option<serde_json::Value>, // Can be any type
+
+    // Previous names of this signal from older database revisions, still accepted in lookups
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+
+    // Decimal places physical values are displayed with, 2 when not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_precision: Option<u8>,
+
+    // Whether the signal is safety critical, such signals are marked with ⚠ in the tree
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub affects_safety: Option<bool>,
+
+    // Raw value ranges with their own meaning, the first range holding a value decides how it's shown
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ranges: Vec<ValueRange>,
+
+    // Section of the message the signal is shown under in the tree, e.g. "Position"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+
+    // Kind of quantity the signal holds across messages, e.g. "temperature", shown as elpis.signal_category
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+}
+
+// A range of raw values, inclusive, either shown as a label or as the scaled physical value. J1939
+// style signals use these for values past the measured range, e.g. 254 for "Not installed".
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ValueRange {
+    pub from: u64,
+    pub to: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub scale: bool,
+}
+
+impl SignalDefinition {
+    // Create a signal with the same defaults used when parsing it from JSON
+    pub fn new(name: &str, start: Option<i32>, length: i32) -> Self {
+        Self {
+            name: name.to_string(),
+            start,
+            length,
+            is_big_endian: default_as_true(),
+            default: None,
+            minimum: 0.0,
+            maximum: default_as_max_f64(),
+            offset: 0.0,
+            multiplexer_signal: None,
+            spn: None,
+            choices: None,
+            scale: None,
+            unit: None,
+            comment: None,
+            is_signed: None,
+            is_multiplexer: None,
+            is_float: None,
+            multiplexer_ids: None,
+            aliases: Vec::new(),
+            display_precision: None,
+            affects_safety: None,
+            ranges: Vec::new(),
+            group: None,
+            category: None,
+        }
+    }
+
+    // Check whether a name refers to this signal, either by its name or one of its aliases
+    pub fn matches_name(&self, name: &str) -> bool {
+        self.name == name || self.aliases.iter().any(|alias| alias == name)
+    }
+
+    // Check whether two signals decode the same bits to the same physical values for the same
+    // multiplexer values, whatever they're named or however they're shown. Which multiplexer they're
+    // under is left to MessageDefinition::signal_decodes_like, the name may differ between databases.
+    pub fn decodes_like(&self, other: &SignalDefinition) -> bool {
+        self.start_bit() == other.start_bit()
+            && self.length == other.length
+            && self.is_big_endian == other.is_big_endian
+            && self.is_signed.unwrap_or(false) == other.is_signed.unwrap_or(false)
+            && self.is_float.unwrap_or(false) == other.is_float.unwrap_or(false)
+            && self.scale.unwrap_or(1.0) == other.scale.unwrap_or(1.0)
+            && self.offset == other.offset
+            && self.is_multiplexer.unwrap_or(false) == other.is_multiplexer.unwrap_or(false)
+            && self.multiplexer_signal.is_some() == other.multiplexer_signal.is_some()
+            && self.multiplexer_values() == other.multiplexer_values()
+    }
+
+    // Get the starting bit of the signal. Little endian signals start at bit 0 when no start is
+    // given, but big endian signals have no sensible default and must give their start explicitly.
+    pub fn start_bit(&self) -> Option<i32> {
+        if self.is_big_endian {
+            self.start
+        } else {
+            Some(self.start.unwrap_or(0))
+        }
+    }
+
+    // Format a physical value for display with the signal's decimal places
+    pub fn format_physical(&self, physical: f64) -> String {
+        format!("{:.prec$}", physical, prec = self.display_precision.unwrap_or(2) as usize)
+    }
+
+    // Get a physical value mapped onto the signal's range as 0 to 1, or None when the database gives
+    // no maximum. Values outside the range give fractions outside 0 to 1.
+    pub fn range_fraction(&self, physical: f64) -> Option<f64> {
+        if self.maximum == f64::MAX || self.maximum <= self.minimum {
+            return None;
+        }
+        Some((physical - self.minimum) / (self.maximum - self.minimum))
+    }
+
+    // Get a physical value as a percentage of the signal's range, or None when the database gives
+    // no maximum. Values outside the range give percentages outside 0 to 100.
+    pub fn range_percent(&self, physical: f64) -> Option<f64> {
+        self.range_fraction(physical).map(|fraction| fraction * 100.0)
+    }
+
+    // Get the label of the first range holding a raw value, None when that range is scaled or no
+    // range holds it
+    pub fn range_label(&self, raw: u128) -> Option<&str> {
+        self.ranges
+            .iter()
+            .find(|range| (range.from as u128..=range.to as u128).contains(&raw))?
+            .label
+            .as_deref()
+    }
+
+    // Get the label of a raw value in the signal's choices. Of several labels for the same value the
+    // first in alphabetical order is used, choices have no order of their own.
+    pub fn choice_label(&self, raw: u128) -> Option<&str> {
+        let value = self.to_unscaled(raw);
+        self.choices
+            .as_ref()?
+            .iter()
+            .filter(|(_, choice)| **choice as f64 == value)
+            .map(|(label, _)| label.as_str())
+            .min()
+    }
+
+    // Get the multiplexer values the signal is present for, None when they can't be read. The values
+    // are a number or a list of numbers.
+    pub fn multiplexer_values(&self) -> Option<BTreeSet<i64>> {
+        match self.multiplexer_ids.as_ref()? {
+            serde_json::Value::Number(value) => Some(BTreeSet::from([value.as_i64()?])),
+            serde_json::Value::Array(values) => values.iter().map(|value| value.as_i64()).collect(),
+            _ => None,
+        }
+    }
+
+    // Whether the database marks the signal as safety critical, signals without the flag aren't
+    pub fn is_safety_critical(&self) -> bool {
+        self.affects_safety == Some(true)
+    }
+
+    // Signals longer than 64 bits are read as u128 but don't fit in an f64 without losing precision.
+    // Their physical value is only an approximation, so anything comparing or aggregating values
+    // works on the raw value instead, and displays show it in full as hex.
+    pub fn is_wide(&self) -> bool {
+        self.length > 64
+    }
+
+    // Get the raw value as big endian bytes, as many as the signal's length needs
+    pub fn raw_bytes(&self, raw: u128) -> Vec<u8> {
+        let count = (self.length.clamp(1, 128) as usize).div_ceil(8);
+        raw.to_be_bytes()[16 - count..].to_vec()
+    }
+
+    // Read the raw value of this signal from a message payload
+    pub fn read_raw(&self, payload: &[u8]) -> anyhow::Result<u128> {
+        debug_assert!(
+            (0..=MAX_SIGNAL_BITS).contains(&self.length),
+            "Signal {} with length {} reached extraction, loading should have refused it",
+            self.name,
+            self.length
+        );
+        let start = self
+            .start_bit()
+            .with_context(|| format!("Big-endian signal {} has no start bit", self.name))?;
+
+        if self.is_big_endian {
+            read_bits_motorola_be(payload, start, self.length)
+        } else {
+            read_bits_intel_le(payload, start, self.length)
+        }
+    }
+
+    // Read the raw value of this signal to decode it, recording it in the trace of its frame when
+    // given one. The dissector and decode both read signals through this, so a trace shows the same
+    // whichever of them made it.
+    pub fn read_traced(&self, payload: &[u8], trace: Option<&mut DecodeTrace>) -> Result<u128, SignalSkip> {
+        let read = if self.length == 0 {
+            Err(SignalSkip::NoBits)
+        } else if self.length > 128 {
+            Err(SignalSkip::TooWide(self.name.clone()))
+        } else if self.start_bit().is_none() {
+            Err(SignalSkip::NoStartBit(self.name.clone()))
+        } else {
+            self.read_raw(payload)
+                .map_err(|e| SignalSkip::Unreadable(self.name.clone(), e.to_string()))
+        };
+
+        if let Some(trace) = trace {
+            let mut signal_trace = SignalTrace::new(self);
+            match &read {
+                Ok(raw) => signal_trace.decoded(self, *raw),
+                Err(skip) => signal_trace.error = Some(skip.to_string()),
+            }
+            trace.signals.push(signal_trace);
+        }
+        read
+    }
+
+    // Get the payload byte offsets of the signal's least and most significant bits, as (lsb, msb).
+    // Uses the same bit numbering as read_raw.
+    // None when the signal runs past the end of the i32 range.
+    pub fn byte_offsets(&self) -> Option<(i32, i32)> {
+        let start = self.start_bit()?;
+        let last = self.length.max(1) - 1;
+
+        if self.is_big_endian {
+            // Motorola bits run from bit 7 down to bit 0 of each byte, the LSB is the last one read
+            let msb_position = (start / 8) * 8 + (7 - start % 8);
+            Some((msb_position.checked_add(last)? / 8, start / 8))
+        } else {
+            Some((start / 8, start.checked_add(last)? / 8))
+        }
+    }
+
+    // Get the payload bytes the signal spans, from its first byte to its last whichever the byte order.
+    // None when it starts before the payload.
+    pub fn byte_range(&self) -> Option<std::ops::Range<usize>> {
+        let (lsb, msb) = self.byte_offsets()?;
+        let first = usize::try_from(lsb.min(msb)).ok()?;
+        let last = usize::try_from(lsb.max(msb)).ok()?;
+        Some(first..last + 1)
+    }
+
+    // Describe what's wrong with the signal's length or start bit, None when both are within bounds
+    fn bounds_problem(&self) -> Option<String> {
+        if !(0..=MAX_SIGNAL_BITS).contains(&self.length) {
+            return Some(format!("length {} is not between 0 and {} bits", self.length, MAX_SIGNAL_BITS));
+        }
+        match self.start {
+            Some(start) if !(0..MAX_MESSAGE_BYTES * 8).contains(&start) => Some(format!(
+                "start {} is not between 0 and {}",
+                start,
+                MAX_MESSAGE_BYTES * 8 - 1
+            )),
+            _ => None,
+        }
+    }
+
+    // Convert a raw value into a number by applying sign and float, but not scale and offset
+    pub fn to_unscaled(&self, raw: u128) -> f64 {
+        let length = self.length.clamp(1, 128) as u32;
+
+        if self.is_float.unwrap_or(false) && length == 32 {
+            f32::from_bits(raw as u32) as f64
+        } else if self.is_float.unwrap_or(false) && length == 64 {
+            f64::from_bits(raw as u64)
+        } else if self.is_signed.unwrap_or(false) {
+            // Sign extend from the signal's length
+            let shift = 128 - length;
+            (((raw << shift) as i128) >> shift) as f64
+        } else {
+            raw as f64
+        }
+    }
+
+    // Convert a raw value into its physical value by applying sign, float, scale and offset
+    pub fn to_physical(&self, raw: u128) -> f64 {
+        self.to_unscaled(raw) * self.scale.unwrap_or(1.0) + self.offset
+    }
+}
+
+// A signal computed from the physical values of other signals in the same message
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DerivedSignal {
+    pub name: String,
+    pub expression: Expression,
+}
+
+// The kind of bus frame a message is carried in on the vehicle side of the gateway
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    #[serde(rename = "CAN")]
+    Can,
+    #[serde(rename = "CANFD")]
+    CanFd,
+    #[serde(rename = "LIN")]
+    Lin,
+    Ethernet,
+}
+
+impl FrameType {
+    // Get the name of the frame type as it's written in the JSON file
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FrameType::Can => "CAN",
+            FrameType::CanFd => "CANFD",
+            FrameType::Lin => "LIN",
+            FrameType::Ethernet => "Ethernet",
+        }
+    }
+
+    // Get the largest payload the bus can carry in a single frame, if it's limited
+    pub fn max_payload_length(&self) -> Option<usize> {
+        match self {
+            FrameType::Can | FrameType::Lin => Some(8),
+            FrameType::CanFd => Some(64),
+            FrameType::Ethernet => None,
+        }
+    }
+}
+
+// Defines a top level message definition, and underneath that are all the signals
+// and their definitions.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MessageDefinition {
+    pub name: String,
+    pub  length: i32,
+    pub id: i32,
+    pub comment: Option<String>,
+    pub signals: Vec<SignalDefinition>,
+
+    // Name of a signal in this message carrying an XOR checksum of the other payload bytes
+    pub checksum_signal: Option<String>,
+
+    // Signal carrying a checksum of the other payload bytes and the algorithm computing it, instead
+    // of checksum_signal's XOR
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<ChecksumSpec>,
+
+    pub frame_type: Option<FrameType>,
+
+    // Whether the message is sent as CAN FD, with up to 64 payload bytes rather than 8. Taken from
+    // frame_type when not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_can_fd: Option<bool>,
+
+    // Whether the id is a 29-bit extended CAN id rather than an 11-bit standard one, standard when
+    // not given
+    #[serde(default, alias = "is_extended_frame", skip_serializing_if = "Option::is_none")]
+    pub is_extended_id: Option<bool>,
+
+    // Bits of the id shared by a family of messages with this layout, the other bits are the
+    // instance, e.g. 0x7f0 for ids 0x120 to 0x12f
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_mask: Option<i32>,
+
+    // Longest expected time between two frames of this message, longer gaps mean frames went missing
+    pub receive_timeout_ms: Option<u32>,
+
+    // Name of the ECU sending the message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<String>,
+
+    // Signals computed from other signals after decoding
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub derived: Vec<DerivedSignal>,
+
+    // File the definition was loaded from, and any patch applied to it. Not part of the database
+    // itself, it's filled in while loading.
+    #[serde(skip)]
+    pub source: Option<String>,
+}
+
+// Result of comparing a checksum signal against the checksum computed over the payload
+pub struct ChecksumCheck {
+    pub received: u128,
+    pub computed: u32,
+}
+
+impl ChecksumCheck {
+    pub fn is_valid(&self) -> bool {
+        self.received == self.computed as u128
+    }
+}
+
+impl MessageDefinition {
+    // Create a message without any signals
+    pub fn new(name: &str, id: i32, length: i32) -> Self {
+        Self {
+            name: name.to_string(),
+            length,
+            id,
+            comment: None,
+            signals: Vec::new(),
+            checksum_signal: None,
+            checksum: None,
+            frame_type: None,
+            is_can_fd: None,
+            is_extended_id: None,
+            id_mask: None,
+            receive_timeout_ms: None,
+            sender: None,
+            derived: Vec::new(),
+            source: None,
+        }
+    }
+
+    // Check whether frames with the given id are of this message, either by its id or its id family
+    pub fn matches_id(&self, id: i32) -> bool {
+        self.id == id || self.id_mask.is_some_and(|mask| id & mask == self.id & mask)
+    }
+
+    // Get the instance a frame id stands for in this message's id family, None without an id_mask
+    pub fn instance(&self, id: i32) -> Option<i32> {
+        self.id_mask.map(|mask| id & !mask)
+    }
+
+    // Get the largest payload a single bus frame of this message can carry and the name of that kind
+    // of frame, if it's limited. Without allow_can_fd, CAN FD messages are held to classic CAN's 8 bytes.
+    pub fn payload_limit(&self, allow_can_fd: bool) -> Option<(usize, &'static str)> {
+        let can_fd = self.is_can_fd.unwrap_or(self.frame_type == Some(FrameType::CanFd));
+        match (self.is_can_fd, self.frame_type) {
+            _ if can_fd && allow_can_fd => Some((64, FrameType::CanFd.as_str())),
+            _ if can_fd => Some((8, FrameType::Can.as_str())),
+            (Some(false), _) => Some((8, FrameType::Can.as_str())),
+            (_, frame_type) => frame_type.and_then(|frame_type| Some((frame_type.max_payload_length()?, frame_type.as_str()))),
+        }
+    }
+
+    // Describe how a signal reaches past the payload of a single bus frame of this message, None
+    // when it fits or the payload isn't limited
+    pub fn signal_past_frame(&self, signal: &SignalDefinition) -> Option<String> {
+        let (max_length, frame_type) = self.payload_limit(true)?;
+        let (lsb, msb) = signal.byte_offsets()?;
+        let last_byte = lsb.max(msb);
+        (last_byte >= max_length as i32).then(|| {
+            format!("reaches payload byte {}, past the {} bytes of a {} frame", last_byte + 1, max_length, frame_type)
+        })
+    }
+
+    // Find a signal definition in this message by its name or one of its aliases
+    pub fn get_signal_by_name(&self, name: &str) -> Option<&SignalDefinition> {
+        self.signals
+            .iter()
+            .find(|signal| signal.name == name)
+            .or_else(|| self.signals.iter().find(|signal| signal.matches_name(name)))
+    }
+
+    // Check if the message has at most the given number of signals, counting derived signals but
+    // not signals of zero length, which are never shown
+    pub fn is_small(&self, max_signals: usize) -> bool {
+        let shown = self.signals.iter().filter(|signal| signal.length != 0).count() + self.derived.len();
+        shown <= max_signals
+    }
+
+    // Get the signals in order of their start bit, then their name. Signals without a start bit
+    // come last.
+    pub fn signals_sorted(&self) -> Vec<&SignalDefinition> {
+        let mut signals: Vec<&SignalDefinition> = self.signals.iter().collect();
+        signals.sort_by(|a, b| {
+            let key = |signal: &SignalDefinition| (signal.start_bit().is_none(), signal.start_bit());
+            key(a).cmp(&key(b)).then_with(|| a.name.cmp(&b.name))
+        });
+        signals
+    }
+
+    // Get the checksum of the message, checksum_signal being an XOR checksum
+    pub fn checksum_spec(&self) -> Option<ChecksumSpec> {
+        self.checksum.clone().or_else(|| self.checksum_signal.as_deref().map(ChecksumSpec::xor))
+    }
+
+    // Check whether a signal of this message decodes like a signal of another message, under a
+    // multiplexer that decodes alike too when they're multiplexed
+    pub fn signal_decodes_like(&self, signal: &SignalDefinition, other_message: &MessageDefinition, other: &SignalDefinition) -> bool {
+        if !signal.decodes_like(other) {
+            return false;
+        }
+        let (Some(multiplexer), Some(other_multiplexer)) = (&signal.multiplexer_signal, &other.multiplexer_signal) else {
+            return true;
+        };
+        match (self.get_signal_by_name(multiplexer), other_message.get_signal_by_name(other_multiplexer)) {
+            (Some(multiplexer), Some(other_multiplexer)) => multiplexer.decodes_like(other_multiplexer),
+            _ => multiplexer == other_multiplexer,
+        }
+    }
+
+    // Pair every signal with a signal of another definition decoding the same bits, as indexes into
+    // both, or None when the two don't have the same layout
+    pub fn pair_signals_by_layout(&self, other: &MessageDefinition) -> Option<Vec<(usize, usize)>> {
+        if self.signals.len() != other.signals.len() {
+            return None;
+        }
+        let mut paired = vec![false; other.signals.len()];
+        let mut pairs = Vec::new();
+        for (index, signal) in self.signals.iter().enumerate() {
+            // Prefer the signal of the same name, in case two signals share their bits
+            let other_index = other
+                .signals
+                .iter()
+                .enumerate()
+                .filter(|&(other_index, other_signal)| !paired[other_index] && self.signal_decodes_like(signal, other, other_signal))
+                .min_by_key(|(_, other)| other.name != signal.name)
+                .map(|(other_index, _)| other_index)?;
+            paired[other_index] = true;
+            pairs.push((index, other_index));
+        }
+        Some(pairs)
+    }
+
+    // Rename signals by index, along with the multiplexer and checksum references to them
+    fn rename_signals(&mut self, renames: &[(usize, String)]) {
+        let names: HashMap<String, String> =
+            renames.iter().map(|(index, name)| (self.signals[*index].name.clone(), name.clone())).collect();
+        let rename = |name: &mut String| {
+            if let Some(new_name) = names.get(name.as_str()) {
+                *name = new_name.clone();
+            }
+        };
+
+        for (index, name) in renames.iter() {
+            self.signals[*index].name = name.clone();
+        }
+        for signal in self.signals.iter_mut() {
+            if let Some(multiplexer) = signal.multiplexer_signal.as_mut() {
+                rename(multiplexer);
+            }
+        }
+        if let Some(checksum) = self.checksum_signal.as_mut() {
+            rename(checksum);
+        }
+        if let Some(checksum) = self.checksum.as_mut() {
+            rename(&mut checksum.signal);
+        }
+    }
+
+    // Compare the checksum signal (if one is configured) against the checksum computed over all
+    // other payload bytes
+    pub fn check_checksum(&self, payload: &[u8]) -> anyhow::Result<Option<ChecksumCheck>> {
+        let Some(spec) = self.checksum_spec() else {
+            return Ok(None);
+        };
+
+        let signal = self
+            .get_signal_by_name(&spec.signal)
+            .with_context(|| format!("Checksum signal {} not found in {}", spec.signal, self.name))?;
+        let received = signal
+            .read_raw(payload)
+            .with_context(|| format!("Could not read checksum signal {}", spec.signal))?;
+
+        // The checksum covers every byte except the ones holding the checksum itself
+        let checksum_bytes = signal.byte_range().unwrap_or(0..0);
+        let computed = spec.compute(payload, checksum_bytes)?;
+
+        Ok(Some(ChecksumCheck { received, computed }))
+    }
+}
+
+// A single signal decoded from a message payload. Together with DecodedFrame and DecodedDerived this
+// is the output of the library, its JSON form is covered by tests/decoded_frame_golden.json and only
+// grows new fields.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub struct DecodedSignal {
+    pub name: String,
+    // Written as a decimal string, JSON numbers can't hold every u128
+    #[serde(with = "raw_as_string")]
+    pub raw: u128,
+    // Written as a number, or as "NaN", "inf" or "-inf" which JSON numbers can't hold
+    #[serde(with = "physical_as_number")]
+    pub physical: f64,
+    // More than 64 bits long, so the physical value is approximate
+    pub wide: bool,
+    // Label of the range the raw value is in, shown instead of the physical value
+    pub label: Option<String>,
+    // Label of the raw value in the signal's choices
+    pub choice: Option<String>,
+    pub unit: Option<String>,
+    // Payload bytes the signal spans, from its first byte to its last
+    pub bytes: Option<std::ops::Range<usize>>,
+    pub flags: SignalFlags,
+}
+
+// What's known about a decoded signal's value besides the value itself
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub struct SignalFlags {
+    pub signed: bool,
+    pub float: bool,
+    // The frame's multiplexer values select the signal, always true for signals that aren't multiplexed
+    pub mux_selected: bool,
+    // The value isn't a measurement: it's in a labeled range, or a float that isn't a number
+    pub invalid: bool,
+    // The physical value is outside the signal's minimum to maximum
+    pub out_of_range: bool,
 }
 
-// Defines a top level message definition, and underneath that are all the signals
-// and their definitions.
-#[derive(Serialize, Deserialize, Debug)]
-pub struct MessageDefinition {
-    pub name: String,
-    pub  length: i32,
-    pub id: i32,
-    pub comment: Option<String>,
-    pub signals: Vec<SignalDefinition>,
+mod raw_as_string {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(raw: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(raw)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+mod physical_as_number {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Physical {
+        Number(f64),
+        Text(String),
+        // Written for NaN before non-finite values were written as text
+        Null(()),
+    }
+
+    pub fn serialize<S: Serializer>(physical: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if physical.is_finite() {
+            serializer.serialize_f64(*physical)
+        } else {
+            serializer.collect_str(physical)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        match Physical::deserialize(deserializer)? {
+            Physical::Number(physical) => Ok(physical),
+            Physical::Text(text) => text.parse().map_err(serde::de::Error::custom),
+            Physical::Null(()) => Ok(f64::NAN),
+        }
+    }
+}
+
+// Why a signal wasn't decoded from a payload
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignalSkip {
+    // A signal without any bits carries no value
+    NoBits,
+    // Longer than the 128 bits a raw value holds, with the signal's name
+    TooWide(String),
+    // A big-endian signal the database gives no start bit, with the signal's name
+    NoStartBit(String),
+    // Couldn't be read from the payload, such as running past its end, with the signal's name and why
+    Unreadable(String, String),
+}
+
+impl std::fmt::Display for SignalSkip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignalSkip::NoBits => write!(f, "Signal has no bits"),
+            SignalSkip::TooWide(signal) => write!(f, "Signal {} is too large to fit in a u128", signal),
+            SignalSkip::NoStartBit(signal) => write!(f, "Could not read signal {}: Big-endian signal {} has no start bit", signal, signal),
+            SignalSkip::Unreadable(signal, error) => write!(f, "Could not read signal {}: {}", signal, error),
+        }
+    }
+}
+
+// The value of a derived signal, None when one of its operands wasn't decoded
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub struct DecodedDerived {
+    pub name: String,
+    pub value: Option<f64>,
+}
+
+// All signals decoded from a single frame, along with any signals that failed to decode
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub struct DecodedFrame {
+    pub id: i32,
+    pub name: String,
+    pub signals: Vec<DecodedSignal>,
+    pub derived: Vec<DecodedDerived>,
+    pub errors: Vec<String>,
+}
+
+impl MessageDefinition {
+    // Decode every signal of this message from the given payload
+    pub fn decode(&self, payload: &[u8]) -> DecodedFrame {
+        self.decode_with_trace(payload, None)
+    }
+
+    // Decode every signal of this message from the given payload, recording each step in the trace
+    // when given one. A header already in the trace is kept.
+    pub fn decode_with_trace(&self, payload: &[u8], mut trace: Option<&mut DecodeTrace>) -> DecodedFrame {
+        let mut frame = DecodedFrame {
+            id: self.id,
+            name: self.name.clone(),
+            signals: Vec::new(),
+            derived: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        if let Some(trace) = trace.as_deref_mut() {
+            trace.message = self.name.clone();
+            trace.id = self.id;
+            trace.payload = crate::trace::format_hex(payload);
+            trace.signals.clear();
+            trace.derived.clear();
+        }
+
+        for signal in self.signals.iter() {
+            match signal.read_traced(payload, trace.as_deref_mut()) {
+                Ok(raw) => {
+                    let label = signal.range_label(raw).map(String::from);
+                    frame.signals.push(self.decoded_signal(signal, raw, signal.to_physical(raw), label, payload));
+                }
+                // Signals without any bits carry no value, that's not an error
+                Err(SignalSkip::NoBits) => {}
+                Err(skip) => frame.errors.push(skip.to_string()),
+            }
+        }
+
+        // Wide signals can't be used as operands, their physical values are approximate
+        for derived in self.derived.iter() {
+            let value = self.eval_derived_traced(
+                derived,
+                &|name| {
+                    frame
+                        .signals
+                        .iter()
+                        .find(|signal| signal.name == name && !signal.wide)
+                        .map(|signal| signal.physical)
+                },
+                trace.as_deref_mut(),
+            );
+            frame.derived.push(DecodedDerived {
+                name: derived.name.clone(),
+                value,
+            });
+        }
+
+        frame
+    }
+
+    // Describe a signal's value read from a payload of this message, given its physical value and
+    // range label
+    pub(crate) fn decoded_signal(
+        &self,
+        signal: &SignalDefinition,
+        raw: u128,
+        physical: f64,
+        label: Option<String>,
+        payload: &[u8],
+    ) -> DecodedSignal {
+        let is_float = signal.is_float.unwrap_or(false);
+        DecodedSignal {
+            name: signal.name.clone(),
+            raw,
+            physical,
+            wide: signal.is_wide(),
+            choice: signal.choice_label(raw).map(String::from),
+            unit: signal.unit.clone(),
+            bytes: signal.byte_range(),
+            flags: SignalFlags {
+                signed: signal.is_signed.unwrap_or(false) && !is_float,
+                float: is_float,
+                mux_selected: self.is_selected(signal, payload),
+                invalid: label.is_some() || physical.is_nan(),
+                out_of_range: signal.range_percent(physical).is_some_and(|percent| !(0.0..=100.0).contains(&percent)),
+            },
+            label,
+        }
+    }
+
+    // Check whether the multiplexers above a signal select it in a payload, following nested
+    // multiplexers up. A multiplexer that can't be read or values that can't be parsed don't rule
+    // the signal out.
+    pub fn is_selected(&self, signal: &SignalDefinition, payload: &[u8]) -> bool {
+        let mut current = signal;
+        // Each multiplexer is followed at most once, in case the database loops
+        for _ in 0..self.signals.len() {
+            let Some(multiplexer) = current
+                .multiplexer_signal
+                .as_deref()
+                .and_then(|name| self.signals.iter().find(|signal| signal.name == name))
+            else {
+                return true;
+            };
+            if let (Some(values), Ok(raw)) = (current.multiplexer_values(), multiplexer.read_raw(payload)) {
+                if i64::try_from(raw).map_or(true, |raw| !values.contains(&raw)) {
+                    return false;
+                }
+            }
+            current = multiplexer;
+        }
+        true
+    }
+
+    // Evaluate a derived signal given a lookup of the physical values decoded so far. Operands may
+    // use a signal's alias.
+    pub fn eval_derived(&self, derived: &DerivedSignal, lookup: &dyn Fn(&str) -> Option<f64>) -> Option<f64> {
+        derived.expression.eval(&|name| {
+            let signal = self.get_signal_by_name(name)?;
+            lookup(&signal.name)
+        })
+    }
+
+    // Evaluate a derived signal like eval_derived, recording it in the trace of its frame when given
+    // one. Shared by the dissector and decode like SignalDefinition::read_traced.
+    pub fn eval_derived_traced(
+        &self,
+        derived: &DerivedSignal,
+        lookup: &dyn Fn(&str) -> Option<f64>,
+        trace: Option<&mut DecodeTrace>,
+    ) -> Option<f64> {
+        let value = self.eval_derived(derived, lookup);
+        if let Some(trace) = trace {
+            trace.derived.push(DerivedTrace {
+                name: derived.name.clone(),
+                expression: derived.expression.as_str().to_string(),
+                value,
+            });
+        }
+        value
+    }
+}
+
+// A single inner frame of an ELPIS datagram
+pub struct ElpisFrame<'a> {
+    pub id: i32,
+    // Offset of the frame header within the datagram
+    pub offset: usize,
+    pub payload: &'a [u8],
+}
+
+// Walks the inner frames of an ELPIS datagram: a header with the id and payload length, by default
+// a 4-byte big endian id and a 4-byte big endian payload length, then the payload. Iteration stops
+// after the first malformed frame.
+pub struct FrameIter<'a> {
+    datagram: &'a [u8],
+    variant: HeaderVariant,
+    offset: usize,
+    failed: bool,
+}
+
+impl<'a> FrameIter<'a> {
+    pub fn new(datagram: &'a [u8]) -> Self {
+        Self::with_variant(datagram, HeaderVariant::default())
+    }
+
+    // Walk a datagram whose frames have headers of the given variant
+    pub fn with_variant(datagram: &'a [u8], variant: HeaderVariant) -> Self {
+        Self {
+            datagram,
+            variant,
+            offset: 0,
+            failed: false,
+        }
+    }
+}
+
+impl<'a> Iterator for FrameIter<'a> {
+    type Item = anyhow::Result<ElpisFrame<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let remaining = &self.datagram[self.offset..];
+        if self.failed || remaining.is_empty() {
+            return None;
+        }
+
+        // Any error ends the walk, there's no way to find the next frame header
+        self.failed = true;
+        let Some((packet_id, payload_length)) = self.variant.parse_header(remaining) else {
+            return Some(Err(anyhow::anyhow!("Truncated frame header at offset {}", self.offset)));
+        };
+
+        let header_length = self.variant.header_length() as usize;
+        if payload_length < 0 || payload_length as usize > remaining.len() - header_length {
+            return Some(Err(anyhow::anyhow!("Invalid payload length")));
+        }
+
+        if packet_id < 0 {
+            return Some(Err(anyhow::anyhow!("Invalid packet ID")));
+        }
+
+        let frame = ElpisFrame {
+            id: packet_id,
+            offset: self.offset,
+            payload: &remaining[header_length..header_length + payload_length as usize],
+        };
+        self.offset += header_length + payload_length as usize;
+        self.failed = false;
+
+        Some(Ok(frame))
+    }
+}
+
+// Longest signal a database may define, in bits. Nothing decodes past 128 bits, but wide byte arrays
+// up to this length still load and are reported when decoded; anything longer is a typo or garbage,
+// and would overflow the bit arithmetic of extraction.
+pub const MAX_SIGNAL_BITS: i32 = 2048;
+
+// Longest payload a message may declare, in bytes, a little more than a UDP datagram can carry.
+// Encoding allocates the declared length.
+pub const MAX_MESSAGE_BYTES: i32 = 65536;
+
+// A problem found while loading message definitions that was worked around instead of failing the load
+#[derive(Debug, Clone)]
+pub struct LoadIssue {
+    pub message: String,
+    pub signal: Option<String>,
+    pub description: String,
+    // File the message was loaded from
+    pub source: Option<String>,
+}
+
+impl std::fmt::Display for LoadIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.signal {
+            Some(signal) => write!(f, "{}.{}: {}", self.message, signal, self.description)?,
+            None => write!(f, "{}: {}", self.message, self.description)?,
+        }
+        if let Some(source) = &self.source {
+            write!(f, " (in {})", source)?;
+        }
+        Ok(())
+    }
+}
+
+// Get the text of a message database. Databases exported from DBC files are sometimes Latin-1 rather
+// than UTF-8, with umlauts in comments and names; with the encoding_rs feature those are converted
+// instead of failing the load. Returns whether the text had to be converted.
+#[cfg(all(feature = "encoding_rs", not(target_arch = "wasm32")))]
+fn decode_database_text(bytes: Vec<u8>) -> anyhow::Result<(String, bool)> {
+    match String::from_utf8(bytes) {
+        Ok(contents) => Ok((contents, false)),
+        Err(error) => {
+            // Latin-1 files in the wild are nearly always Windows-1252, which is a superset of it
+            let (contents, _) = encoding_rs::WINDOWS_1252.decode_without_bom_handling(error.as_bytes());
+            Ok((contents.into_owned(), true))
+        }
+    }
+}
+
+#[cfg(all(not(feature = "encoding_rs"), not(target_arch = "wasm32")))]
+fn decode_database_text(bytes: Vec<u8>) -> anyhow::Result<(String, bool)> {
+    let contents = String::from_utf8(bytes)
+        .context("File is not valid UTF-8, build with the encoding_rs feature to load Latin-1 databases")?;
+    Ok((contents, false))
+}
+
+// How to handle definitions that can't be decoded reliably
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadMode {
+    // Fail the load
+    Strict,
+    // Skip the offending definition and record a LoadIssue
+    Permissive,
+}
+
+// Which definition to keep when two merged databases both define an id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePriority {
+    // Keep the definition already in the database being merged into
+    PreferSelf,
+    // Replace it with the definition from the other database
+    PreferOther,
+}
+
+// Which name a signal gets when two merged databases define an id with the same layout but name its
+// signals differently, such as a vendor database and an internal one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SignalNamePolicy {
+    // The name of the database being merged into
+    #[default]
+    PreferFirst,
+    // The longer name, usually the more descriptive one, the first database's on a tie
+    PreferLongestName,
+    // The name of the database being merged into, with the other name as an alias
+    RecordBoth,
+}
+
+pub struct ElpisMessages {
+    // All message definitions as loaded from the JSON file\
+    // Key is the message ID
+    // Value is the message definition
+    messages: HashMap<i32, MessageDefinition>,
+
+    // Problems worked around while loading
+    issues: Vec<LoadIssue>,
+
+    // Messages sharing a numeric id as a standard and an extended CAN id, found before only one of
+    // them was kept
+    id_conflicts: Vec<(i32, bool, i32, bool)>,
+
+    // Ids of the messages with an id_mask, looked up when no message has a frame's exact id
+    masked_ids: Vec<i32>,
+}
+
+impl ElpisMessages {
+    // Load ELPIS messages from the given path to a messages.json file
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_json(json_path: &str) -> anyhow::Result<Self> {
+        Self::load_from_json_with_mode(json_path, LoadMode::Strict)
+    }
+
+    // Load ELPIS messages from the given path to a messages.json file, choosing how to handle
+    // signals that can't be decoded reliably
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_json_with_mode(json_path: &str, mode: LoadMode) -> anyhow::Result<Self> {
+        Self::load_json(json_path, mode, false)
+    }
+
+    // Load ELPIS messages from a messages.json file of CAN FD messages, where every message that
+    // doesn't set is_can_fd is taken to be CAN FD
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_can_fd_json(json_path: &str) -> anyhow::Result<Self> {
+        Self::load_json(json_path, LoadMode::Strict, true)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load_json(json_path: &str, mode: LoadMode, can_fd: bool) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(json_path).with_context(|| format!("Could not open file {}", json_path))?;
+        let (contents, converted) =
+            decode_database_text(bytes).with_context(|| format!("Could not read JSON file {}", json_path))?;
+        let mut jsondec = crate::schema::parse_database(&contents)
+            .with_context(|| format!("Could not parse JSON file {}", json_path))?;
+        if can_fd {
+            for message in jsondec.iter_mut() {
+                message.is_can_fd.get_or_insert(true);
+            }
+        }
+
+        let mut messages = Self::from_definitions(jsondec);
+        messages.set_source(json_path);
+        messages.check_loaded(mode).with_context(|| format!("Invalid message definitions in {}", json_path))?;
+        if converted {
+            messages.issues.push(LoadIssue {
+                message: json_path.to_string(),
+                signal: None,
+                description: String::from("file is not valid UTF-8, names and comments were read as Latin-1"),
+                source: None,
+            });
+        }
+
+        Ok(messages)
+    }
+
+    // Parse ELPIS messages from the contents of a messages.json file, for callers without a
+    // filesystem such as the WebAssembly build
+    pub fn from_json_str(contents: &str, mode: LoadMode) -> anyhow::Result<Self> {
+        let mut messages = Self::from_definitions(crate::schema::parse_database(contents)?);
+        messages.check_loaded(mode).context("Invalid message definitions")?;
+        Ok(messages)
+    }
+
+    // Check freshly loaded definitions, dropping the signals that can't be decoded in permissive mode
+    fn check_loaded(&mut self, mode: LoadMode) -> anyhow::Result<()> {
+        self.check_lengths(mode)?;
+        self.check_signal_starts(mode)?;
+        self.check_signal_ranges(mode)?;
+        self.validate()
+    }
+
+    // Record the file every message without a source yet was loaded from
+    pub fn set_source(&mut self, source: &str) {
+        for message in self.messages.values_mut().filter(|message| message.source.is_none()) {
+            message.source = Some(source.to_string());
+        }
+    }
+
+    // Get the problems worked around while loading
+    pub fn issues(&self) -> &[LoadIssue] {
+        &self.issues
+    }
+
+    // Replace every message definition with those of another database, keeping the issues found
+    // while loading this one
+    pub(crate) fn replace_messages(&mut self, other: ElpisMessages) {
+        self.messages = other.messages;
+        self.masked_ids = other.masked_ids;
+    }
+
+    // Lengths and start bits come straight from the database and end up in bit and byte arithmetic, so
+    // negative or absurd ones are refused before anything else looks at them: signals may have up to
+    // MAX_SIGNAL_BITS bits starting within MAX_MESSAGE_BYTES bytes, and messages up to
+    // MAX_MESSAGE_BYTES bytes. A length of 0 is left alone, a
+    // signal without bits carries no value and a message without one isn't checked against frames.
+    // In strict mode every offender is reported in the error, in permissive mode offending signals
+    // and messages are removed and recorded as load issues.
+    pub fn check_lengths(&mut self, mode: LoadMode) -> anyhow::Result<()> {
+        let mut invalid = Vec::new();
+        for message in self.messages.values() {
+            if !(0..=MAX_MESSAGE_BYTES).contains(&message.length) {
+                invalid.push(LoadIssue {
+                    message: message.name.clone(),
+                    signal: None,
+                    description: format!("length {} is not between 0 and {} bytes", message.length, MAX_MESSAGE_BYTES),
+                    source: message.source.clone(),
+                });
+                continue;
+            }
+            for signal in message.signals.iter() {
+                if let Some(description) = signal.bounds_problem() {
+                    invalid.push(LoadIssue {
+                        message: message.name.clone(),
+                        signal: Some(signal.name.clone()),
+                        description,
+                        source: message.source.clone(),
+                    });
+                }
+            }
+        }
+        invalid.sort_by(|a, b| (&a.message, &a.signal).cmp(&(&b.message, &b.signal)));
+
+        if invalid.is_empty() {
+            return Ok(());
+        }
+
+        if mode == LoadMode::Strict {
+            let lengths = invalid.iter().map(|issue| issue.to_string()).collect::<Vec<String>>().join(", ");
+            return Err(anyhow::anyhow!("Invalid lengths or start bits: {}", lengths));
+        }
+
+        self.messages.retain(|_, message| (0..=MAX_MESSAGE_BYTES).contains(&message.length));
+        self.masked_ids.retain(|id| self.messages.contains_key(id));
+        for message in self.messages.values_mut() {
+            message.signals.retain(|signal| signal.bounds_problem().is_none());
+        }
+        self.issues.extend(invalid);
+
+        Ok(())
+    }
+
+    // Big-endian signals must give an explicit start bit, there's no default that's right for every
+    // database. In strict mode every offending signal is reported in the error, in permissive mode
+    // they're removed and recorded as load issues.
+    pub fn check_signal_starts(&mut self, mode: LoadMode) -> anyhow::Result<()> {
+        let mut missing = Vec::new();
+        for message in self.messages.values() {
+            for signal in message.signals.iter() {
+                if signal.start_bit().is_none() {
+                    missing.push(LoadIssue {
+                        message: message.name.clone(),
+                        signal: Some(signal.name.clone()),
+                        description: String::from(
+                            "big-endian signal has no \"start\", add the bit number of its most significant bit",
+                        ),
+                        source: message.source.clone(),
+                    });
+                }
+            }
+        }
+        missing.sort_by(|a, b| (&a.message, &a.signal).cmp(&(&b.message, &b.signal)));
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        if mode == LoadMode::Strict {
+            let signals = missing
+                .iter()
+                .map(|issue| format!("{}.{}", issue.message, issue.signal.as_deref().unwrap_or_default()))
+                .collect::<Vec<String>>()
+                .join(", ");
+            return Err(anyhow::anyhow!(
+                "Big-endian signals must give an explicit \"start\" (the bit number of their most significant bit), \
+                 add one to these signals: {}",
+                signals
+            ));
+        }
+
+        for message in self.messages.values_mut() {
+            message.signals.retain(|signal| signal.start_bit().is_some());
+        }
+        self.issues.extend(missing);
+
+        Ok(())
+    }
+
+    // Build the decoder from the definitions a loader read out of another database format, refusing
+    // the lengths and start bits it can't have checked
+    pub(crate) fn from_loaded_definitions(definitions: Vec<MessageDefinition>) -> anyhow::Result<Self> {
+        let mut messages = Self::from_definitions(definitions);
+        messages.check_lengths(LoadMode::Strict)?;
+        Ok(messages)
+    }
+
+    // Build the decoder from a list of message definitions
+    pub fn from_definitions(definitions: Vec<MessageDefinition>) -> Self {
+        let id_conflicts = find_id_conflicts(&definitions);
+
+        // Build a hashmap of message IDs to message definitions
+        let messages_map: HashMap<i32, MessageDefinition> = definitions
+            .into_iter()
+            .map(|message| (message.id, message))
+            .collect();
+
+        Self {
+            masked_ids: masked_ids(&messages_map),
+            messages: messages_map,
+            issues: Vec::new(),
+            id_conflicts,
+        }
+    }
+
+    // Get the messages with the same numeric id but a different choice of standard or extended
+    // CAN id, as (id, extended, id, extended). Frames carry no such flag, so only the last of each
+    // pair in the database is used.
+    pub fn compute_id_conflicts(&self) -> Vec<(i32, bool, i32, bool)> {
+        self.id_conflicts.clone()
+    }
+
+    // Get the number of messages defined in this decoder
+    pub fn get_messagedef_count(&self) -> usize {
+        self.messages.len()
+    }
+
+    // Find a message definition by its id, or else by the id family of a message with an id_mask
+    pub fn get_def_by_id(&self, id: i32) -> Option<&MessageDefinition> {
+        self.messages.get(&id).or_else(|| {
+            self.masked_ids
+                .iter()
+                .map(|masked_id| &self.messages[masked_id])
+                .find(|message| message.matches_id(id))
+        })
+    }
+
+    // Check the definitions for mistakes that can't be caught while parsing them
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.check_aliases()?;
+        self.check_checksums()?;
+        self.check_derived()?;
+        self.check_id_masks()?;
+        self.check_value_ranges()?;
+        Ok(())
+    }
+
+    // Ensure every id maps to a single message: no message's id may fall in another message's id
+    // family, and no two families may share an id
+    pub fn check_id_masks(&self) -> anyhow::Result<()> {
+        for family in self.iter_sorted() {
+            let Some(mask) = family.id_mask else {
+                continue;
+            };
+            if mask <= 0 {
+                return Err(anyhow::anyhow!("Message {} has an invalid id_mask {:#x}", family.name, mask));
+            }
+            for other in self.iter_sorted().filter(|other| other.id != family.id) {
+                let ambiguous = match other.id_mask {
+                    Some(other_mask) => (family.id ^ other.id) & mask & other_mask == 0,
+                    None => family.matches_id(other.id),
+                };
+                if ambiguous {
+                    return Err(anyhow::anyhow!(
+                        "Message {} with id {:#x} is ambiguous with the id family of {}, id {:#x} with id_mask {:#x}",
+                        other.name,
+                        other.id,
+                        family.name,
+                        family.id,
+                        mask
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Ensure every value range is the right way round and is either labeled or scaled
+    pub fn check_value_ranges(&self) -> anyhow::Result<()> {
+        for message in self.iter_sorted() {
+            for signal in message.signals.iter() {
+                for range in signal.ranges.iter() {
+                    let problem = if range.from > range.to {
+                        "starts after it ends"
+                    } else if range.label.is_some() == range.scale {
+                        "needs either a \"label\" or \"scale\": true"
+                    } else {
+                        continue;
+                    };
+                    return Err(anyhow::anyhow!(
+                        "Range {} to {} of signal {}.{} {}",
+                        range.from,
+                        range.to,
+                        message.name,
+                        signal.name,
+                        problem
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Ensure every signal fits in the payload of a single bus frame of its message, 8 bytes for
+    // classic CAN and LIN, 64 for CAN FD. In strict mode every offending signal is reported in the
+    // error, in permissive mode they're removed and recorded as load issues.
+    pub fn check_signal_ranges(&mut self, mode: LoadMode) -> anyhow::Result<()> {
+        let mut outside = Vec::new();
+        for message in self.iter_sorted() {
+            for signal in message.signals.iter() {
+                if let Some(description) = message.signal_past_frame(signal) {
+                    outside.push(LoadIssue {
+                        message: message.name.clone(),
+                        signal: Some(signal.name.clone()),
+                        description,
+                        source: message.source.clone(),
+                    });
+                }
+            }
+        }
+
+        if outside.is_empty() {
+            return Ok(());
+        }
+
+        if mode == LoadMode::Strict {
+            let signals = outside
+                .iter()
+                .map(|issue| format!("Signal {}.{} {}", issue.message, issue.signal.as_deref().unwrap_or_default(), issue.description))
+                .collect::<Vec<String>>()
+                .join("; ");
+            return Err(anyhow::anyhow!("{}", signals));
+        }
+
+        for message in self.messages.values_mut() {
+            let kept = message.signals.iter().filter(|signal| message.signal_past_frame(signal).is_none()).cloned().collect();
+            message.signals = kept;
+        }
+        self.issues.extend(outside);
+
+        Ok(())
+    }
+
+    // Ensure every checksum names a known algorithm and a signal of the message as wide as the
+    // checksums it computes
+    pub fn check_checksums(&self) -> anyhow::Result<()> {
+        for message in self.iter_sorted() {
+            let Some(spec) = message.checksum.as_ref() else {
+                continue;
+            };
+            if message.checksum_signal.is_some() {
+                return Err(anyhow::anyhow!(
+                    "Message {} has both a \"checksum\" and a \"checksum_signal\", keep one",
+                    message.name
+                ));
+            }
+
+            let algorithm = spec.algorithm().with_context(|| format!("Invalid checksum of message {}", message.name))?;
+            let signal = message
+                .get_signal_by_name(&spec.signal)
+                .with_context(|| format!("Checksum signal {} not found in {}", spec.signal, message.name))?;
+            if signal.length != algorithm.width() {
+                return Err(anyhow::anyhow!(
+                    "Checksum signal {}.{} is {} bits long, but {} computes {} bit checksums",
+                    message.name,
+                    signal.name,
+                    signal.length,
+                    algorithm.as_str(),
+                    algorithm.width()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Ensure every derived signal only refers to signals in its own message
+    pub fn check_derived(&self) -> anyhow::Result<()> {
+        for message in self.iter_sorted() {
+            for derived in message.derived.iter() {
+                for identifier in derived.expression.identifiers() {
+                    if message.get_signal_by_name(identifier).is_none() {
+                        return Err(anyhow::anyhow!(
+                            "Derived signal {}.{} refers to unknown signal {}",
+                            message.name,
+                            derived.name,
+                            identifier
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Ensure no alias shadows a real signal name, or is used twice within the same message
+    pub fn check_aliases(&self) -> anyhow::Result<()> {
+        let signal_names: HashSet<&str> = self
+            .messages
+            .values()
+            .flat_map(|message| message.signals.iter().map(|signal| signal.name.as_str()))
+            .collect();
+
+        for message in self.iter_sorted() {
+            let mut message_aliases = HashSet::new();
+            for signal in message.signals.iter() {
+                for alias in signal.aliases.iter() {
+                    if signal_names.contains(alias.as_str()) {
+                        return Err(anyhow::anyhow!(
+                            "Alias {} of signal {}.{} collides with a signal of the same name",
+                            alias,
+                            message.name,
+                            signal.name
+                        ));
+                    }
+
+                    if !message_aliases.insert(alias.as_str()) {
+                        return Err(anyhow::anyhow!(
+                            "Alias {} is used more than once in message {}",
+                            alias,
+                            message.name
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Find signal names used by several messages with a different layout in each, which makes
+    // filters on the signal name ambiguous. Signals laid out the same way in every message, e.g. a
+    // shared counter, aren't a collision.
+    pub fn signal_name_collisions(&self) -> Vec<SignalNameCollision> {
+        let mut layouts: BTreeMap<&str, Vec<(&MessageDefinition, &SignalDefinition)>> = BTreeMap::new();
+        for message in self.iter_sorted() {
+            for signal in message.signals.iter() {
+                layouts.entry(signal.name.as_str()).or_default().push((message, signal));
+            }
+        }
+
+        layouts
+            .into_iter()
+            .filter(|(_, signals)| {
+                let layout = |signal: &SignalDefinition| (signal.start_bit(), signal.length, signal.is_big_endian);
+                signals.iter().any(|(_, signal)| layout(signal) != layout(signals[0].1))
+            })
+            .map(|(name, signals)| SignalNameCollision {
+                signal: name.to_string(),
+                messages: signals.iter().map(|(message, _)| message.name.clone()).collect(),
+            })
+            .collect()
+    }
+
+    // Resolve a signal name or alias to the canonical name of the signal
+    pub fn resolve_signal_name(&self, name: &str) -> Option<&str> {
+        self.iter_sorted()
+            .flat_map(|message| message.signals.iter())
+            .find(|signal| signal.matches_name(name))
+            .map(|signal| signal.name.as_str())
+    }
+
+    // Combine two databases, such as a vendor database and local overrides of some of its
+    // messages. Ids defined by both keep the definition chosen by the priority, and the load issues
+    // of both are kept.
+    pub fn merge_with_priority(mut self, other: ElpisMessages, priority: MergePriority) -> ElpisMessages {
+        // A standard id in one database and the same extended id in the other conflict too
+        let mut definitions: Vec<MessageDefinition> = self.messages.values().cloned().collect();
+        definitions.extend(other.messages.values().cloned());
+        let mut id_conflicts = find_id_conflicts(&definitions);
+        id_conflicts.extend(self.id_conflicts.iter().chain(other.id_conflicts.iter()).copied());
+        id_conflicts.sort();
+        id_conflicts.dedup();
+
+        for (id, message) in other.messages {
+            if priority == MergePriority::PreferOther || !self.messages.contains_key(&id) {
+                self.messages.insert(id, message);
+            }
+        }
+        self.issues.extend(other.issues);
+        self.id_conflicts = id_conflicts;
+        self.masked_ids = masked_ids(&self.messages);
+
+        self
+    }
+
+    // Combine two databases like merge_with_priority, naming the signals of ids both define with the
+    // same layout by the policy whichever definition is kept. Every signal named differently by the
+    // two is recorded as a load issue, so the outcome doesn't depend on which file happened to win.
+    // A dropped name still read by a derived signal is kept as an alias.
+    pub fn merge_with_policy(self, other: ElpisMessages, priority: MergePriority, naming: SignalNamePolicy) -> ElpisMessages {
+        let mut shared: Vec<(MessageDefinition, MessageDefinition)> = other
+            .messages
+            .iter()
+            .filter_map(|(id, second)| Some((self.messages.get(id)?.clone(), second.clone())))
+            .collect();
+        shared.sort_by_key(|(first, _)| first.id);
+        let mut merged = self.merge_with_priority(other, priority);
+
+        for (first, second) in shared {
+            let Some(pairs) = first.pair_signals_by_layout(&second) else {
+                continue;
+            };
+            let Some(kept) = merged.messages.get_mut(&first.id) else {
+                continue;
+            };
+
+            let mut renames = Vec::new();
+            let mut issues = Vec::new();
+            for (first_index, second_index) in pairs {
+                let (first_name, second_name) = (&first.signals[first_index].name, &second.signals[second_index].name);
+                if first_name == second_name {
+                    continue;
+                }
+                let (name, other_name) = match naming {
+                    SignalNamePolicy::PreferLongestName if second_name.len() > first_name.len() => (second_name, first_name),
+                    _ => (first_name, second_name),
+                };
+                let index = match priority {
+                    MergePriority::PreferSelf => first_index,
+                    MergePriority::PreferOther => second_index,
+                };
+                renames.push((index, name.clone()));
+
+                let read_by_derived =
+                    kept.derived.iter().any(|derived| derived.expression.identifiers().contains(&other_name.as_str()));
+                let description = if naming == SignalNamePolicy::RecordBoth || read_by_derived {
+                    let aliases = &mut kept.signals[index].aliases;
+                    if !aliases.contains(other_name) {
+                        aliases.push(other_name.clone());
+                    }
+                    format!("Named {} by the other database too, kept as an alias", other_name)
+                } else {
+                    format!("Named {} by the other database too, that name was dropped", other_name)
+                };
+                issues.push(LoadIssue {
+                    message: kept.name.clone(),
+                    signal: Some(name.clone()),
+                    description,
+                    source: kept.source.clone(),
+                });
+            }
+            kept.rename_signals(&renames);
+            merged.issues.extend(issues);
+        }
+
+        merged
+    }
+
+    // Build a new decoder containing only the messages carried in the given frame type
+    pub fn filter_by_frame_type(&self, frame_type: FrameType) -> ElpisMessages {
+        Self::from_definitions(
+            self.messages
+                .values()
+                .filter(|message| message.frame_type == Some(frame_type))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    // Iterate over all message definitions in order of their id
+    pub fn iter_sorted(&self) -> impl Iterator<Item = &MessageDefinition> {
+        let mut messages: Vec<&MessageDefinition> = self.messages.values().collect();
+        messages.sort_by_key(|message| message.id);
+        messages.into_iter()
+    }
+}
+
+// A database as it was when it was read, kept whole for as long as it's held even if the database
+// is replaced in the meantime
+pub type ElpisMessagesSnapshot = Arc<ElpisMessages>;
+
+// A database shared between the dissector and anything replacing it. Readers take a snapshot, so the
+// lock is only held long enough to clone an Arc, and a replacement never waits for a dissection to
+// finish or changes the database under one.
+pub struct SharedMessages {
+    current: RwLock<ElpisMessagesSnapshot>,
+    // Number of times the database was replaced
+    generation: AtomicU64,
+}
+
+impl SharedMessages {
+    pub fn new(messages: ElpisMessages) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(messages)),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    // Get how many times the database was replaced, for state built from it to spot a new one
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    // Get the current database
+    pub fn snapshot(&self) -> ElpisMessagesSnapshot {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    // Replace the database for every snapshot taken from now on
+    pub fn replace(&self, messages: ElpisMessages) {
+        *self.current.write().unwrap() = Arc::new(messages);
+        self.generation.fetch_add(1, Ordering::Release);
+    }
 }
 
-pub struct ElpisMessages {
-    // All message definitions as loaded from the JSON file\
-    // Key is the message ID
-    // Value is the message definition
-    messages: HashMap<i32, MessageDefinition>,
+// Something built from the shared database, such as notes checked against its messages. It's built
+// the first time it's needed and again the first time after the database is replaced.
+pub struct DatabaseDerived<T> {
+    build: fn(&ElpisMessages) -> T,
+    // What was last built and the generation of the database it was built from
+    current: Mutex<Option<(u64, Arc<T>)>>,
+    // What was built from each database given to get_for, while that database is in use
+    by_database: Mutex<Vec<(Weak<ElpisMessages>, Arc<T>)>>,
 }
 
-impl ElpisMessages {
-    // Load ELPIS messages from the given path to a messages.json file
-    pub fn load_from_json(json_path: &str) -> anyhow::Result<Self> {
-        let contents = std::fs::read_to_string(json_path)
-            .with_context(|| format!("Could not open file {}", json_path))?;
-        let jsondec: Vec<MessageDefinition> = serde_json::from_str(&contents)
-            .with_context(|| format!("Could not parse JSON file {}", json_path))?;
+impl<T> DatabaseDerived<T> {
+    pub fn new(build: fn(&ElpisMessages) -> T) -> Self {
+        Self {
+            build,
+            current: Mutex::new(None),
+            by_database: Mutex::new(Vec::new()),
+        }
+    }
 
-        // Build a hashmap of message IDs to message definitions
-        let messages_map: HashMap<i32, MessageDefinition> = jsondec
-            .into_iter()
-            .map(|message| (message.id, message))
-            .collect();
+    // Get what's built from a given database, such as the one a capture or a multicast group is
+    // dissected with, building it the first time. What was built from databases no longer in use
+    // is dropped, so a database that's in use is never mistaken for one that was dropped.
+    pub fn get_for(&self, messages: &ElpisMessagesSnapshot) -> Arc<T> {
+        let mut by_database = self.by_database.lock().unwrap();
+        by_database.retain(|(database, _)| database.strong_count() > 0);
+        if let Some((_, value)) = by_database.iter().find(|(database, _)| database.as_ptr() == Arc::as_ptr(messages)) {
+            return Arc::clone(value);
+        }
 
-        Ok(Self {
-            messages: messages_map,
-        })
+        let value = Arc::new((self.build)(messages));
+        by_database.push((Arc::downgrade(messages), Arc::clone(&value)));
+        value
     }
 
-    // Get the number of messages defined in this decoder
-    pub fn get_messagedef_count(&self) -> usize {
-        self.messages.len()
+    // Get what's built from the current database, building it if the database changed
+    pub fn get(&self, messages: &SharedMessages) -> Arc<T> {
+        let generation = messages.generation();
+        let mut current = self.current.lock().unwrap();
+        if let Some((_, value)) = current.as_ref().filter(|(built, _)| *built == generation) {
+            return Arc::clone(value);
+        }
+
+        let value = Arc::new((self.build)(&messages.snapshot()));
+        *current = Some((generation, Arc::clone(&value)));
+        value
     }
+}
 
-    // Find a message definition by its id
-    pub fn get_def_by_id(&self, id: i32) -> Option<&MessageDefinition> {
-        self.messages.get(&id)
+// A signal name shared by messages that lay the signal out differently
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignalNameCollision {
+    pub signal: String,
+    // Names of every message with a signal of this name, in order of their id
+    pub messages: Vec<String>,
+}
+
+impl std::fmt::Display for SignalNameCollision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Signal {} has a different layout in messages {}, filters on its name are ambiguous",
+            self.signal,
+            self.messages.join(", ")
+        )
+    }
+}
+
+// Get the ids of the messages with an id_mask, in order
+fn masked_ids(messages: &HashMap<i32, MessageDefinition>) -> Vec<i32> {
+    let mut ids: Vec<i32> = messages.values().filter(|message| message.id_mask.is_some()).map(|message| message.id).collect();
+    ids.sort();
+    ids
+}
+
+// Find pairs of definitions whose ids are the same number, one as a standard and one as an
+// extended CAN id, sorted by id
+fn find_id_conflicts(definitions: &[MessageDefinition]) -> Vec<(i32, bool, i32, bool)> {
+    let mut kinds: BTreeMap<i32, BTreeSet<bool>> = BTreeMap::new();
+    for definition in definitions {
+        kinds
+            .entry(definition.id)
+            .or_default()
+            .insert(definition.is_extended_id.unwrap_or(false));
+    }
+
+    kinds
+        .into_iter()
+        .filter(|(_, kinds)| kinds.len() > 1)
+        .map(|(id, _)| (id, false, id, true))
+        .collect()
+}
+
+// Computes the XOR of all bytes in a payload
+pub fn payload_checksum_xor(payload: &[u8]) -> u8 {
+    payload.iter().fold(0, |acc, byte| acc ^ byte)
+}
+
+// Computes the Shannon entropy of a payload's bytes in bits per byte, from 0 for a single repeated
+// byte to 8 for bytes spread evenly over all values. Structured payloads are low, encrypted or
+// compressed ones close to 8.
+pub fn payload_entropy(payload: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for byte in payload.iter() {
+        counts[*byte as usize] += 1;
     }
+    let length = payload.len() as f64;
+    counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let probability = *count as f64 / length;
+            -probability * probability.log2()
+        })
+        .sum()
+}
+
+// Checks whether every byte of a payload is the given byte, as in all-zero heartbeats or frames padded
+// with 0xFF. An empty payload is filled with nothing.
+pub fn payload_filled_with(payload: &[u8], byte: u8) -> bool {
+    !payload.is_empty() && payload.iter().all(|value| *value == byte)
+}
 
+// Reverses the bytes of every complete 4-byte word in a payload, leaving any trailing bytes untouched
+pub fn word_swap_payload(payload: &[u8]) -> Vec<u8> {
+    let mut swapped = payload.to_vec();
+    WordSwap.apply(&mut swapped);
+    swapped
 }
 
 // Reads bits from a CAN buffer in Motorola Big Endian order
@@ -157,4 +1834,947 @@ fn read_big_endian_data() {
     // assert_eq!(read_bits_intel_le(&data, 10, 45), 0xD159E048D15);
     // assert_eq!(read_bits_intel_le(&data, 0, 54), 0x34567812345678);
 
-}
\ No newline at end of file
+}
+
+#[test]
+fn xor_checksum() {
+    assert_eq!(payload_checksum_xor(&[]), 0);
+    assert_eq!(payload_checksum_xor(&[0x12, 0x34, 0x56]), 0x12 ^ 0x34 ^ 0x56);
+
+    // Trailing checksum byte over the first three bytes
+    let message: MessageDefinition = serde_json::from_str(
+        r#"{
+            "name": "Test", "length": 4, "id": 1, "comment": null,
+            "checksum_signal": "Test_Checksum",
+            "signals": [
+                { "name": "Test_Checksum", "start": 24, "length": 8, "is_big_endian": false }
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    let check = message.check_checksum(&[0x12, 0x34, 0x56, 0x70]).unwrap().unwrap();
+    assert!(check.is_valid());
+
+    let check = message.check_checksum(&[0x12, 0x34, 0x57, 0x70]).unwrap().unwrap();
+    assert!(!check.is_valid());
+    assert_eq!(check.computed, 0x71);
+}
+
+#[test]
+fn payload_entropy_bits_per_byte() {
+    assert_eq!(payload_entropy(&[]), 0.0);
+    assert_eq!(payload_entropy(&[0x55; 8]), 0.0);
+    assert_eq!(payload_entropy(&[0x00, 0xff, 0x00, 0xff]), 1.0);
+    let every_byte: Vec<u8> = (0..=255).collect();
+    assert_eq!(payload_entropy(&every_byte), 8.0);
+}
+
+#[test]
+fn payload_filled_with_one_byte() {
+    assert!(payload_filled_with(&[0x00; 8], 0x00));
+    assert!(payload_filled_with(&[0xff; 3], 0xff));
+    assert!(!payload_filled_with(&[0x00, 0x00, 0x01], 0x00));
+    assert!(!payload_filled_with(&[], 0x00));
+}
+
+#[test]
+fn configured_checksum_algorithm() {
+    let json = r#"[{
+        "name": "Wheels", "length": 4, "id": 1, "comment": null,
+        "checksum": { "signal": "Wheels_CRC", "algorithm": "crc8" },
+        "signals": [
+            { "name": "Wheels_CRC", "start": 0, "length": 8, "is_big_endian": false }
+        ]
+    }]"#;
+    let messages = ElpisMessages::from_definitions(serde_json::from_str(json).unwrap());
+    messages.validate().unwrap();
+
+    // CRC-8/SAE-J1850 of "123" is 0x62
+    let message = messages.get_def_by_id(1).unwrap();
+    assert!(message.check_checksum(&[0x62, b'1', b'2', b'3']).unwrap().unwrap().is_valid());
+    let check = message.check_checksum(&[0x62, b'1', b'2', b'4']).unwrap().unwrap();
+    assert!(!check.is_valid());
+
+    // The algorithm, the signal and its width are checked when loading
+    let load = |json: String| ElpisMessages::from_definitions(serde_json::from_str(&json).unwrap()).validate();
+    assert!(load(json.replace("\"crc8\"", "\"crc9\"")).is_err());
+    assert!(load(json.replace("\"signal\": \"Wheels_CRC\"", "\"signal\": \"CRC\"")).is_err());
+    let error = load(json.replace("\"crc8\"", "\"crc16_ccitt\"")).err().unwrap();
+    assert_eq!(error.to_string(), "Checksum signal Wheels.Wheels_CRC is 8 bits long, but crc16_ccitt computes 16 bit checksums");
+    assert!(load(json.replace("\"comment\": null", "\"comment\": null, \"checksum_signal\": \"Wheels_CRC\"")).is_err());
+}
+
+#[test]
+fn big_endian_signals_need_a_start() {
+    let json = r#"[{
+        "name": "Status", "length": 2, "id": 1, "comment": null,
+        "signals": [
+            { "name": "Flag", "length": 1 },
+            { "name": "Counter", "start": 15, "length": 4 },
+            { "name": "Level", "length": 4, "is_big_endian": false }
+        ]
+    }]"#;
+    let path = std::env::temp_dir().join(format!("elpis_be_start_{}.json", std::process::id()));
+    std::fs::write(&path, json).unwrap();
+    let path = path.to_str().unwrap();
+
+    // Strict loading names every signal that needs fixing
+    let error = format!("{:#}", ElpisMessages::load_from_json(path).err().unwrap());
+    assert!(error.contains("Status.Flag"), "{}", error);
+    assert!(!error.contains("Status.Counter"), "{}", error);
+    assert!(!error.contains("Status.Level"), "{}", error);
+
+    // Permissive loading skips the signal and records why
+    let messages = ElpisMessages::load_from_json_with_mode(path, LoadMode::Permissive).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    let status = messages.get_def_by_id(1).unwrap();
+    assert!(status.get_signal_by_name("Flag").is_none());
+    assert!(status.get_signal_by_name("Counter").is_some());
+    assert_eq!(status.source.as_deref(), Some(path));
+    assert_eq!(messages.issues().len(), 1);
+    assert_eq!(messages.issues()[0].signal.as_deref(), Some("Flag"));
+    assert!(messages.issues()[0].to_string().ends_with(&format!("(in {})", path)));
+
+    // Little endian signals still start at bit 0 by default
+    assert_eq!(status.get_signal_by_name("Level").unwrap().start_bit(), Some(0));
+}
+
+#[test]
+fn refuse_absurd_lengths() {
+    let json = r#"[
+        { "name": "Status", "length": 8, "id": 1, "comment": null, "signals": [
+            { "name": "Flag", "start": 7, "length": -1 },
+            { "name": "Blob", "start": 0, "length": 2147483647, "is_big_endian": false },
+            { "name": "Serial", "start": 0, "length": 2048, "is_big_endian": false },
+            { "name": "Unused", "start": 0, "length": 0, "is_big_endian": false }
+        ] },
+        { "name": "Huge", "length": 65537, "id": 2, "comment": null, "signals": [] },
+        { "name": "Backwards", "length": -8, "id": 3, "comment": null, "signals": [] },
+        { "name": "Trigger", "length": 0, "id": 4, "comment": null, "signals": [] },
+        { "name": "Far", "length": 8, "id": 5, "comment": null, "frame_type": "CAN", "signals": [
+            { "name": "Past", "start": 2147483647, "length": 8, "is_big_endian": false },
+            { "name": "Before", "start": -8, "length": 8, "is_big_endian": false }
+        ] }
+    ]"#;
+
+    let error = format!("{:#}", ElpisMessages::from_json_str(json, LoadMode::Strict).err().unwrap());
+    assert!(error.contains("Status.Flag: length -1 is not between 0 and 2048 bits"), "{}", error);
+    assert!(error.contains("Status.Blob: length 2147483647"), "{}", error);
+    assert!(error.contains("Huge: length 65537 is not between 0 and 65536 bytes"), "{}", error);
+    assert!(error.contains("Backwards: length -8"), "{}", error);
+    assert!(error.contains("Far.Past: start 2147483647 is not between 0 and 524287"), "{}", error);
+    assert!(error.contains("Far.Before: start -8"), "{}", error);
+    assert!(!error.contains("Serial") && !error.contains("Unused") && !error.contains("Trigger"), "{}", error);
+
+    let messages = ElpisMessages::from_json_str(json, LoadMode::Permissive).unwrap();
+    let ids: Vec<i32> = messages.iter_sorted().map(|message| message.id).collect();
+    assert_eq!(ids, vec![1, 4, 5]);
+    let names: Vec<&str> = messages.get_def_by_id(1).unwrap().signals.iter().map(|signal| signal.name.as_str()).collect();
+    assert_eq!(names, vec!["Serial", "Unused"]);
+    assert!(messages.get_def_by_id(5).unwrap().signals.is_empty());
+    assert_eq!(messages.issues().len(), 6);
+
+    // Other loaders get the same check
+    let definitions = crate::schema::parse_database(json).unwrap();
+    assert!(ElpisMessages::from_loaded_definitions(definitions).is_err());
+
+    // What's left decodes without tripping over its lengths
+    let decoded = messages.get_def_by_id(1).unwrap().decode(&[0; 8]);
+    assert_eq!(decoded.errors, vec![String::from("Signal Serial is too large to fit in a u128")]);
+    messages.build_codec();
+}
+
+#[test]
+fn bundled_messages_load_strictly() {
+    let messages = ElpisMessages::load_from_json(concat!(env!("CARGO_MANIFEST_DIR"), "/messages.json")).unwrap();
+    assert!(messages.issues().is_empty());
+
+    // Signals that start at bit 0 decode from the bottom of the first byte
+    let proximity = messages.iter_sorted().find(|message| message.name == "VAS_Proximity").unwrap();
+    let near = proximity.get_signal_by_name("VAS_Vehicle_Near_Proximity").unwrap();
+    assert_eq!(near.read_raw(&[0b0000_0001]).unwrap(), 1);
+    assert_eq!(near.read_raw(&[0b1000_0000]).unwrap(), 0);
+}
+
+#[cfg(feature = "encoding_rs")]
+#[test]
+fn load_latin1_database() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/latin1_messages.json");
+    let messages = ElpisMessages::load_from_json(path).unwrap();
+
+    // The conversion is recorded, and the umlauts come out as they were meant
+    assert_eq!(messages.issues().len(), 1);
+    assert!(messages.issues()[0].description.contains("Latin-1"));
+
+    let message = messages.get_def_by_id(784).unwrap();
+    assert_eq!(message.name, "Türsteuerung");
+    assert_eq!(message.comment.as_deref(), Some("Türsteuergerät Fahrerseite, Botschaft für Fensterheber"));
+
+    let decoded = message.decode(&[40, 0b10]);
+    let names: Vec<&str> = decoded.signals.iter().map(|signal| signal.name.as_str()).collect();
+    assert_eq!(names, vec!["Fensterhöhe", "Schlüssel_Status"]);
+}
+
+#[cfg(not(feature = "encoding_rs"))]
+#[test]
+fn reject_latin1_database() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/latin1_messages.json");
+    let error = format!("{:#}", ElpisMessages::load_from_json(path).err().unwrap());
+    assert!(error.contains("encoding_rs"), "{}", error);
+}
+
+#[test]
+fn derived_signals() {
+    let definitions: Vec<MessageDefinition> = serde_json::from_str(
+        r#"[{
+            "name": "Wheels", "length": 4, "id": 1, "comment": null,
+            "signals": [
+                { "name": "WhlFL", "start": 0, "length": 8, "is_big_endian": false },
+                { "name": "WhlFR", "start": 8, "length": 8, "is_big_endian": false },
+                { "name": "WhlRL", "start": 16, "length": 8, "is_big_endian": false },
+                { "name": "WhlRR", "start": 24, "length": 8, "is_big_endian": false, "aliases": ["RearRight"] }
+            ],
+            "derived": [
+                { "name": "AvgWheelSpeed", "expression": "(WhlFL+WhlFR+WhlRL+RearRight)/4" }
+            ]
+        }]"#,
+    )
+    .unwrap();
+    let messages = ElpisMessages::from_definitions(definitions);
+    messages.validate().unwrap();
+
+    let wheels = messages.get_def_by_id(1).unwrap();
+    let frame = wheels.decode(&[10, 12, 11, 13]);
+    assert_eq!(frame.derived.len(), 1);
+    assert_eq!(frame.derived[0].name, "AvgWheelSpeed");
+    assert_eq!(frame.derived[0].value, Some(11.5));
+
+    // An operand that couldn't be decoded leaves the derived signal unavailable
+    let frame = wheels.decode(&[10, 12, 11]);
+    assert_eq!(frame.derived[0].value, None);
+}
+
+#[test]
+fn derived_signal_load_errors() {
+    // Expressions that don't parse are rejected while loading
+    assert!(serde_json::from_str::<MessageDefinition>(
+        r#"{ "name": "A", "length": 1, "id": 1, "comment": null, "signals": [],
+             "derived": [{ "name": "Bad", "expression": "(1 +" }] }"#,
+    )
+    .is_err());
+
+    // As are expressions referring to signals outside of the message
+    let definition: MessageDefinition = serde_json::from_str(
+        r#"{ "name": "A", "length": 1, "id": 1, "comment": null,
+             "signals": [{ "name": "Speed", "start": 0, "length": 8 }],
+             "derived": [{ "name": "Double", "expression": "Speed * 2 + Other" }] }"#,
+    )
+    .unwrap();
+    assert!(ElpisMessages::from_definitions(vec![definition]).validate().is_err());
+}
+
+#[test]
+fn frame_types() {
+    let definitions: Vec<MessageDefinition> = serde_json::from_str(
+        r#"[
+            { "name": "A", "length": 8, "id": 1, "comment": null, "signals": [], "frame_type": "CAN" },
+            { "name": "B", "length": 64, "id": 2, "comment": null, "signals": [], "frame_type": "CANFD" },
+            { "name": "C", "length": 8, "id": 3, "comment": null, "signals": [], "frame_type": "CAN" },
+            { "name": "D", "length": 8, "id": 4, "comment": null, "signals": [] }
+        ]"#,
+    )
+    .unwrap();
+    let messages = ElpisMessages::from_definitions(definitions);
+
+    assert_eq!(messages.get_def_by_id(2).unwrap().frame_type, Some(FrameType::CanFd));
+    assert_eq!(messages.get_def_by_id(4).unwrap().frame_type, None);
+    assert_eq!(FrameType::CanFd.as_str(), "CANFD");
+    assert_eq!(FrameType::Can.max_payload_length(), Some(8));
+    assert_eq!(FrameType::CanFd.max_payload_length(), Some(64));
+
+    let can = messages.filter_by_frame_type(FrameType::Can);
+    assert_eq!(can.get_messagedef_count(), 2);
+    assert!(can.get_def_by_id(1).is_some());
+    assert!(can.get_def_by_id(3).is_some());
+    assert_eq!(messages.filter_by_frame_type(FrameType::Lin).get_messagedef_count(), 0);
+
+    assert!(serde_json::from_str::<FrameType>(r#""FlexRay""#).is_err());
+}
+
+#[test]
+fn word_swapped_payload() {
+    assert_eq!(word_swap_payload(&[1, 2, 3, 4, 5, 6, 7, 8]), vec![4, 3, 2, 1, 8, 7, 6, 5]);
+    assert_eq!(word_swap_payload(&[1, 2, 3, 4, 5, 6]), vec![4, 3, 2, 1, 5, 6]);
+    assert_eq!(word_swap_payload(&[1, 2, 3]), vec![1, 2, 3]);
+
+    // A VAS_Proximity frame as captured through the buggy firmware decodes the same as the original
+    let messages: Vec<MessageDefinition> =
+        serde_json::from_str(&std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/messages.json")).unwrap())
+            .unwrap();
+    let message = messages.iter().find(|message| message.name == "VAS_Proximity").unwrap();
+
+    let known_good = [0b1010_0110, 0x00, 0x00, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55];
+    let captured = [0x00, 0x00, 0x00, 0b1010_0110, 0x44, 0x33, 0x22, 0x11, 0x55];
+
+    let expected = message.decode(&known_good);
+    let decoded = message.decode(&word_swap_payload(&captured));
+    assert_eq!(decoded.signals.len(), expected.signals.len());
+    for (decoded, expected) in decoded.signals.iter().zip(expected.signals.iter()) {
+        assert_eq!(decoded.name, expected.name);
+        assert_eq!(decoded.raw, expected.raw);
+    }
+    assert_eq!(decoded.signals[0].raw, 1);
+}
+
+#[test]
+fn walk_frames() {
+    let datagram = [
+        0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0xAA, 0xBB, // id 1, 2 bytes
+        0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, // id 2, empty
+        0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x10, 0xCC, // id 3, truncated payload
+    ];
+
+    let frames: Vec<_> = FrameIter::new(&datagram).collect();
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[0].as_ref().unwrap().id, 1);
+    assert_eq!(frames[0].as_ref().unwrap().payload, &[0xAA, 0xBB]);
+    assert_eq!(frames[1].as_ref().unwrap().payload.len(), 0);
+    assert_eq!(frames[1].as_ref().unwrap().offset, 10);
+    assert!(frames[2].is_err());
+}
+
+#[test]
+fn walk_frames_of_two_variants() {
+    // The same two frames from a migrated gateway and one still sending v2-le16 headers
+    let v1 = [
+        0x00, 0x00, 0x01, 0x20, 0x00, 0x00, 0x00, 0x02, 0xAA, 0xBB, // id 0x120, 2 bytes
+        0x00, 0x00, 0x00, 0x50, 0x00, 0x00, 0x00, 0x01, 0xCC, // id 0x50, 1 byte
+    ];
+    let v2 = [
+        0x20, 0x01, 0x02, 0x00, 0xAA, 0xBB, // id 0x120, 2 bytes
+        0x50, 0x00, 0x01, 0x00, 0xCC, // id 0x50, 1 byte
+    ];
+
+    let walk = |datagram: &[u8], variant: HeaderVariant| -> Vec<(i32, usize, Vec<u8>)> {
+        FrameIter::with_variant(datagram, variant)
+            .map(|frame| frame.map(|frame| (frame.id, frame.offset, frame.payload.to_vec())).unwrap())
+            .collect()
+    };
+    let v1_frames = walk(&v1, HeaderVariant::V1Be32);
+    let v2_frames = walk(&v2, HeaderVariant::V2Le16);
+    assert_eq!(v1_frames, vec![(0x120, 0, vec![0xAA, 0xBB]), (0x50, 10, vec![0xCC])]);
+    assert_eq!(v2_frames, vec![(0x120, 0, vec![0xAA, 0xBB]), (0x50, 6, vec![0xCC])]);
+
+    // Read with the other variant's headers, neither walks cleanly
+    assert!(FrameIter::with_variant(&v2, HeaderVariant::V1Be32).any(|frame| frame.is_err()));
+    assert!(FrameIter::with_variant(&v1, HeaderVariant::V2Le16).any(|frame| frame.is_err()));
+}
+
+#[test]
+fn decode_physical_values() {
+    let message: MessageDefinition = serde_json::from_str(
+        r#"{
+            "name": "Test", "length": 4, "id": 1, "comment": null,
+            "signals": [
+                { "name": "Speed", "start": 0, "length": 16, "is_big_endian": false, "scale": 0.5, "offset": -10 },
+                { "name": "Temp", "start": 16, "length": 8, "is_big_endian": false, "is_signed": true },
+                { "name": "Empty", "start": 24, "length": 0, "is_big_endian": false },
+                { "name": "Missing", "start": 32, "length": 8, "is_big_endian": false }
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    let frame = message.decode(&[0x64, 0x00, 0xFE, 0x00]);
+    assert_eq!(frame.signals.len(), 2);
+    assert_eq!(frame.signals[0].raw, 100);
+    assert_eq!(frame.signals[0].physical, 40.0);
+    assert_eq!(frame.signals[1].physical, -2.0);
+    assert_eq!(frame.errors.len(), 1);
+}
+
+#[cfg(test)]
+fn aliased_messages(json: &str) -> anyhow::Result<ElpisMessages> {
+    let definitions: Vec<MessageDefinition> = serde_json::from_str(json)?;
+    let messages = ElpisMessages::from_definitions(definitions);
+    messages.validate()?;
+    Ok(messages)
+}
+
+#[test]
+fn signal_aliases() {
+    let messages = aliased_messages(
+        r#"[{
+            "name": "Wheels", "length": 2, "id": 1, "comment": null,
+            "signals": [
+                { "name": "WheelSpeed_FrontLeft", "start": 0, "length": 8, "is_big_endian": false, "aliases": ["WhlSpd_FL"] },
+                { "name": "WheelSpeed_FrontRight", "start": 8, "length": 8, "is_big_endian": false }
+            ]
+        }]"#,
+    )
+    .unwrap();
+
+    // A lookup saved against the old name still finds the renamed signal
+    let wheels = messages.get_def_by_id(1).unwrap();
+    let signal = wheels.get_signal_by_name("WhlSpd_FL").unwrap();
+    assert_eq!(signal.name, "WheelSpeed_FrontLeft");
+    assert_eq!(messages.resolve_signal_name("WhlSpd_FL"), Some("WheelSpeed_FrontLeft"));
+    assert_eq!(messages.resolve_signal_name("WhlSpd_RR"), None);
+
+    // Decoded signals only carry the canonical name, but still match the old one
+    let frame = wheels.decode(&[0x2A, 0x00]);
+    assert_eq!(frame.signals[0].name, "WheelSpeed_FrontLeft");
+    assert_eq!(signal.read_raw(&[0x2A, 0x00]).unwrap(), 0x2A);
+}
+
+#[test]
+fn signal_alias_collisions() {
+    // Alias shadowing a real signal in another message
+    assert!(aliased_messages(
+        r#"[
+            { "name": "A", "length": 1, "id": 1, "comment": null,
+              "signals": [{ "name": "Speed", "start": 0, "length": 8, "aliases": ["Counter"] }] },
+            { "name": "B", "length": 1, "id": 2, "comment": null,
+              "signals": [{ "name": "Counter", "start": 0, "length": 8 }] }
+        ]"#,
+    )
+    .is_err());
+
+    // The same alias on two signals of one message
+    assert!(aliased_messages(
+        r#"[
+            { "name": "A", "length": 2, "id": 1, "comment": null,
+              "signals": [
+                  { "name": "Speed", "start": 0, "length": 8, "aliases": ["Old"] },
+                  { "name": "Counter", "start": 8, "length": 8, "aliases": ["Old"] }
+              ] }
+        ]"#,
+    )
+    .is_err());
+}
+
+#[test]
+fn format_physical_values() {
+    let mut signal = SignalDefinition::new("Speed", Some(7), 16);
+    assert_eq!(signal.format_physical(0.12345678), "0.12");
+    assert_eq!(signal.format_physical(100.0), "100.00");
+    assert_eq!(signal.format_physical(-2.005), "-2.00");
+
+    signal.display_precision = Some(1);
+    assert_eq!(signal.format_physical(87.46), "87.5");
+    signal.display_precision = Some(0);
+    assert_eq!(signal.format_physical(87.46), "87");
+
+    let signal: SignalDefinition =
+        serde_json::from_str(r#"{ "name": "Temp", "start": 7, "length": 8, "display_precision": 3 }"#).unwrap();
+    assert_eq!(signal.format_physical(21.0), "21.000");
+}
+
+#[test]
+fn snapshots_outlive_replacement() {
+    let shared = SharedMessages::new(ElpisMessages::from_definitions(vec![MessageDefinition::new("Old", 1, 8)]));
+    let old = shared.snapshot();
+    assert_eq!(shared.generation(), 0);
+
+    shared.replace(ElpisMessages::from_definitions(vec![MessageDefinition::new("New", 2, 8)]));
+    assert_eq!(old.get_def_by_id(1).unwrap().name, "Old");
+    assert!(old.get_def_by_id(2).is_none());
+    assert_eq!(shared.generation(), 1);
+
+    let new = shared.snapshot();
+    assert_eq!(new.get_def_by_id(2).unwrap().name, "New");
+    assert!(new.get_def_by_id(1).is_none());
+}
+
+#[test]
+fn derived_state_follows_replacement() {
+    let shared = SharedMessages::new(ElpisMessages::from_definitions(vec![MessageDefinition::new("Old", 1, 8)]));
+    let names = DatabaseDerived::new(|messages: &ElpisMessages| -> Vec<String> {
+        messages.iter_sorted().map(|message| message.name.clone()).collect()
+    });
+
+    // Built once per database
+    let first = names.get(&shared);
+    assert_eq!(*first, ["Old"]);
+    assert!(Arc::ptr_eq(&first, &names.get(&shared)));
+
+    shared.replace(ElpisMessages::from_definitions(vec![MessageDefinition::new("New", 2, 8)]));
+    assert_eq!(*names.get(&shared), ["New"]);
+    assert_eq!(*first, ["Old"]);
+}
+
+#[test]
+fn derived_state_per_database() {
+    let names = DatabaseDerived::new(|messages: &ElpisMessages| -> Vec<String> {
+        messages.iter_sorted().map(|message| message.name.clone()).collect()
+    });
+    let chassis: ElpisMessagesSnapshot = Arc::new(ElpisMessages::from_definitions(vec![MessageDefinition::new("Chassis", 1, 8)]));
+    let body: ElpisMessagesSnapshot = Arc::new(ElpisMessages::from_definitions(vec![MessageDefinition::new("Body", 2, 8)]));
+
+    // Each database gets its own, built once while it's in use
+    let first = names.get_for(&chassis);
+    assert_eq!(*first, ["Chassis"]);
+    assert_eq!(*names.get_for(&body), ["Body"]);
+    assert!(Arc::ptr_eq(&first, &names.get_for(&chassis)));
+
+    // A dropped database's is dropped with it
+    drop(body);
+    names.get_for(&chassis);
+    assert_eq!(names.by_database.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn can_fd_signal_ranges() {
+    let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/can_fd_messages.json");
+
+    // Signals past byte 8 are only valid in CAN FD messages
+    let error = ElpisMessages::load_from_json(fixture).err().unwrap();
+    assert!(format!("{:#}", error).contains("Signal BatteryCells.Cell12 reaches payload byte 48, past the 8 bytes of a CAN frame"));
+
+    // Loading permissively drops those signals instead
+    let messages = ElpisMessages::load_from_json_with_mode(fixture, LoadMode::Permissive).unwrap();
+    let cells = messages.get_def_by_id(0x410).unwrap();
+    assert!(cells.get_signal_by_name("Cell12").is_none());
+    assert!(cells.get_signal_by_name("CellCount").is_some());
+    assert!(messages.issues().iter().any(|issue| issue.to_string().starts_with("BatteryCells.Cell12: reaches payload byte 48")));
+
+    let messages = ElpisMessages::load_from_can_fd_json(fixture).unwrap();
+    let cells = messages.get_def_by_id(0x410).unwrap();
+    assert_eq!(cells.is_can_fd, Some(true));
+    assert_eq!(cells.payload_limit(true), Some((64, "CANFD")));
+    assert_eq!(cells.payload_limit(false), Some((8, "CAN")));
+
+    // Messages that say they're classic CAN stay that way
+    let status = messages.get_def_by_id(0x411).unwrap();
+    assert_eq!(status.payload_limit(true), Some((8, "CAN")));
+
+    let mut message = MessageDefinition::new("Cells", 0x410, 64);
+    message.frame_type = Some(FrameType::CanFd);
+    message.signals.push(SignalDefinition::new("Cell32", Some(511), 8));
+    assert!(ElpisMessages::from_definitions(vec![message.clone()]).check_signal_ranges(LoadMode::Strict).is_ok());
+    message.signals.push(SignalDefinition::new("Cell33", Some(519), 8));
+    assert!(ElpisMessages::from_definitions(vec![message]).check_signal_ranges(LoadMode::Strict).is_err());
+    assert_eq!(MessageDefinition::new("Log", 1, 200).payload_limit(true), None);
+}
+
+#[test]
+fn signal_byte_offsets() {
+    let signal = |start: i32, length: i32, is_big_endian: bool| {
+        let mut signal = SignalDefinition::new("S", Some(start), length);
+        signal.is_big_endian = is_big_endian;
+        signal.byte_offsets().unwrap()
+    };
+
+    // Intel signals grow from the LSB towards later bytes
+    assert_eq!(signal(0, 8, false), (0, 0));
+    assert_eq!(signal(4, 16, false), (0, 2));
+
+    // Motorola signals grow from the MSB towards later bytes
+    assert_eq!(signal(7, 8, true), (0, 0));
+    assert_eq!(signal(7, 16, true), (1, 0));
+    assert_eq!(signal(3, 12, true), (1, 0));
+    assert_eq!(signal(3, 4, true), (0, 0));
+    assert_eq!(signal(23, 24, true), (4, 2));
+
+    // Byte ranges cover every byte either way round
+    let range = |start: i32, length: i32, is_big_endian: bool| {
+        let mut signal = SignalDefinition::new("S", Some(start), length);
+        signal.is_big_endian = is_big_endian;
+        signal.byte_range().unwrap()
+    };
+    assert_eq!(range(4, 16, false), 0..3);
+    assert_eq!(range(23, 24, true), 2..5);
+    assert_eq!(range(3, 4, true), 0..1);
+
+    // The LSB is where read_raw finds it
+    let mut be = SignalDefinition::new("S", Some(7), 16);
+    be.is_big_endian = true;
+    assert_eq!(be.read_raw(&[0x00, 0x01]).unwrap(), 1);
+}
+
+#[test]
+fn parse_safety_flag() {
+    let signal: SignalDefinition =
+        serde_json::from_str(r#"{ "name": "BrakeReq", "start": 7, "length": 1, "affects_safety": true }"#).unwrap();
+    assert!(signal.is_safety_critical());
+
+    let signal: SignalDefinition =
+        serde_json::from_str(r#"{ "name": "Radio", "start": 7, "length": 1, "affects_safety": false }"#).unwrap();
+    assert!(!signal.is_safety_critical());
+    assert!(!SignalDefinition::new("Radio", Some(7), 1).is_safety_critical());
+
+    // The flag is only written back out when the database gave it
+    let json = serde_json::to_string(&SignalDefinition::new("Radio", Some(7), 1)).unwrap();
+    assert!(!json.contains("affects_safety"), "{}", json);
+}
+
+#[test]
+fn parse_signal_group() {
+    let signal: SignalDefinition =
+        serde_json::from_str(r#"{ "name": "Latitude", "start": 7, "length": 32, "group": "Position" }"#).unwrap();
+    assert_eq!(signal.group.as_deref(), Some("Position"));
+
+    let json = serde_json::to_string(&SignalDefinition::new("Radio", Some(7), 1)).unwrap();
+    assert!(!json.contains("group"), "{}", json);
+}
+
+#[test]
+fn parse_signal_category() {
+    let signal: SignalDefinition =
+        serde_json::from_str(r#"{ "name": "CoolantTemp", "start": 7, "length": 8, "category": "temperature" }"#).unwrap();
+    assert_eq!(signal.category.as_deref(), Some("temperature"));
+
+    let json = serde_json::to_string(&SignalDefinition::new("Radio", Some(7), 1)).unwrap();
+    assert!(!json.contains("category"), "{}", json);
+}
+
+#[test]
+fn parse_message_sender() {
+    let message: MessageDefinition =
+        serde_json::from_str(r#"{ "name": "CellVoltages", "length": 8, "id": 1040, "sender": "BMS", "signals": [] }"#)
+            .unwrap();
+    assert_eq!(message.sender.as_deref(), Some("BMS"));
+
+    // The sender is only written back out when the database gave it
+    let json = serde_json::to_string(&MessageDefinition::new("CellVoltages", 1040, 8)).unwrap();
+    assert!(!json.contains("sender"), "{}", json);
+}
+
+#[test]
+fn find_id_conflicts_between_standard_and_extended() {
+    let messages = ElpisMessages::from_definitions(
+        serde_json::from_str(
+            r#"[
+                { "name": "Diag", "length": 8, "id": 2047, "comment": null, "signals": [] },
+                { "name": "DiagJ1939", "length": 8, "id": 2047, "comment": null, "signals": [], "is_extended_frame": true },
+                { "name": "Wheels", "length": 8, "id": 288, "comment": null, "signals": [], "is_extended_id": false },
+                { "name": "Status", "length": 8, "id": 16, "comment": null, "signals": [], "is_extended_id": true }
+            ]"#,
+        )
+        .unwrap(),
+    );
+
+    assert_eq!(messages.compute_id_conflicts(), vec![(2047, false, 2047, true)]);
+    assert!(messages.get_def_by_id(16).unwrap().is_extended_id.unwrap());
+}
+
+#[cfg(test)]
+fn id_family_test_messages(extra: &str) -> anyhow::Result<ElpisMessages> {
+    let messages = ElpisMessages::from_definitions(serde_json::from_str(&format!(
+        r#"[
+            {{ "name": "WheelData", "length": 8, "id": 288, "id_mask": 2032, "comment": null, "signals": [] }},
+            {{ "name": "Status", "length": 8, "id": 304, "comment": null, "signals": [] }}
+            {}
+        ]"#,
+        extra
+    ))?);
+    messages.validate()?;
+    Ok(messages)
+}
+
+#[test]
+fn id_family_lookup() {
+    let messages = id_family_test_messages("").unwrap();
+
+    // Every id 0x120 to 0x12f is an instance of WheelData, exact ids come first
+    let wheels = messages.get_def_by_id(0x123).unwrap();
+    assert_eq!((wheels.name.as_str(), wheels.instance(0x123)), ("WheelData", Some(3)));
+    assert_eq!(messages.get_def_by_id(0x120).unwrap().instance(0x120), Some(0));
+    assert_eq!(messages.get_def_by_id(0x12f).unwrap().instance(0x12f), Some(15));
+    assert_eq!(messages.get_def_by_id(0x130).unwrap().name, "Status");
+    assert_eq!(messages.get_def_by_id(0x130).unwrap().instance(0x130), None);
+    assert!(messages.get_def_by_id(0x131).is_none());
+    assert!(messages.get_def_by_id(0x220).is_none());
+
+    let codec = messages.build_codec();
+    assert_eq!(codec.decode(0x125, &[0; 8]).unwrap().name, "WheelData");
+}
+
+#[test]
+fn ambiguous_id_families() {
+    // An exact id inside a family, and families sharing ids, can't be told apart
+    let error = id_family_test_messages(r#", { "name": "Wheel3", "length": 8, "id": 291, "comment": null, "signals": [] }"#)
+        .err()
+        .unwrap();
+    assert_eq!(
+        error.to_string(),
+        "Message Wheel3 with id 0x123 is ambiguous with the id family of WheelData, id 0x120 with id_mask 0x7f0"
+    );
+    let overlapping = r#", { "name": "Pairs", "length": 8, "id": 290, "id_mask": 2046, "comment": null, "signals": [] }"#;
+    assert!(id_family_test_messages(overlapping).is_err());
+    let zero_mask = r#", { "name": "Any", "length": 8, "id": 512, "id_mask": 0, "comment": null, "signals": [] }"#;
+    assert!(id_family_test_messages(zero_mask).is_err());
+
+    // Families that differ in a bit both masks cover are separate
+    let separate = r#", { "name": "Brakes", "length": 8, "id": 320, "id_mask": 2032, "comment": null, "signals": [] }"#;
+    let messages = id_family_test_messages(separate).unwrap();
+    assert_eq!(messages.get_def_by_id(0x14a).unwrap().name, "Brakes");
+}
+
+// A J1939 style vehicle speed: 0 to 250 km/h, three error codes, not installed and not available
+#[cfg(test)]
+pub(crate) fn ranged_test_signal() -> SignalDefinition {
+    serde_json::from_str(
+        r#"{
+            "name": "VehicleSpeed", "start": 0, "length": 8, "is_big_endian": false, "unit": "km/h",
+            "ranges": [
+                { "from": 0, "to": 250, "scale": true },
+                { "from": 251, "to": 253, "label": "Error" },
+                { "from": 254, "to": 254, "label": "Not installed" },
+                { "from": 255, "to": 255, "label": "SNA" }
+            ]
+        }"#,
+    )
+    .unwrap()
+}
+
+#[test]
+fn signal_value_ranges() {
+    let signal = ranged_test_signal();
+    let labels: Vec<Option<&str>> = [0, 250, 251, 253, 254, 255].into_iter().map(|raw| signal.range_label(raw)).collect();
+    assert_eq!(labels, [None, None, Some("Error"), Some("Error"), Some("Not installed"), Some("SNA")]);
+
+    let mut message = MessageDefinition::new("Speed", 0x100, 1);
+    message.signals.push(signal);
+    let frame = message.decode(&[254]);
+    assert_eq!(frame.signals[0].label.as_deref(), Some("Not installed"));
+    assert_eq!(message.decode(&[88]).signals[0].label, None);
+    assert!(ElpisMessages::from_definitions(vec![message.clone()]).validate().is_ok());
+
+    // Ranges must be labeled or scaled, but not both
+    message.signals[0].ranges[0].label = Some(String::from("Speed"));
+    assert!(ElpisMessages::from_definitions(vec![message.clone()]).validate().is_err());
+    message.signals[0].ranges[0] = ValueRange { from: 250, to: 0, label: None, scale: true };
+    assert!(ElpisMessages::from_definitions(vec![message]).validate().is_err());
+}
+
+#[test]
+fn signal_range_percent() {
+    let mut signal = SignalDefinition::new("Battery_Level", Some(0), 8);
+    assert_eq!(signal.range_percent(50.0), None);
+
+    signal.minimum = -40.0;
+    signal.maximum = 60.0;
+    assert_eq!(signal.range_percent(10.0), Some(50.0));
+    assert_eq!(signal.range_percent(-40.0), Some(0.0));
+    assert_eq!(signal.range_percent(160.0), Some(200.0));
+    assert_eq!(signal.range_fraction(35.0), Some(0.75));
+
+    signal.maximum = signal.minimum;
+    assert_eq!(signal.range_percent(-40.0), None);
+}
+
+#[test]
+fn small_messages() {
+    let mut message = MessageDefinition::new("ESP_Alive", 0x50, 2);
+    message.signals.push(SignalDefinition::new("Counter", Some(7), 8));
+    message.signals.push(SignalDefinition::new("Reserved", Some(15), 0));
+    assert!(message.is_small(1));
+
+    message.derived.push(DerivedSignal {
+        name: String::from("Counter_Doubled"),
+        expression: crate::expr::Expression::parse("Counter * 2").unwrap(),
+    });
+    assert!(!message.is_small(1));
+    assert!(message.is_small(2));
+}
+
+#[test]
+fn merge_databases_with_priority() {
+    let database = |names: &[(i32, &str)]| {
+        ElpisMessages::from_definitions(names.iter().map(|&(id, name)| MessageDefinition::new(name, id, 8)).collect())
+    };
+    let vendor = || database(&[(0x100, "Vendor_Speed"), (0x200, "Vendor_Status")]);
+    let local = || database(&[(0x100, "Local_Speed"), (0x300, "Local_Debug")]);
+
+    let merged = vendor().merge_with_priority(local(), MergePriority::PreferOther);
+    let names: Vec<&str> = merged.iter_sorted().map(|message| message.name.as_str()).collect();
+    assert_eq!(names, vec!["Local_Speed", "Vendor_Status", "Local_Debug"]);
+
+    let merged = vendor().merge_with_priority(local(), MergePriority::PreferSelf);
+    let names: Vec<&str> = merged.iter_sorted().map(|message| message.name.as_str()).collect();
+    assert_eq!(names, vec!["Vendor_Speed", "Vendor_Status", "Local_Debug"]);
+
+    // An extended id overriding a standard one is still reported
+    let mut extended = MessageDefinition::new("Local_Extended", 0x200, 8);
+    extended.is_extended_id = Some(true);
+    let merged = vendor().merge_with_priority(ElpisMessages::from_definitions(vec![extended]), MergePriority::PreferOther);
+    assert_eq!(merged.compute_id_conflicts(), vec![(0x200, false, 0x200, true)]);
+}
+
+#[test]
+fn merge_signal_name_policies() {
+    // The vendor's and the internal names for the same bits of one message
+    let database = |json: &str| ElpisMessages::from_definitions(serde_json::from_str(json).unwrap());
+    let vendor = || {
+        database(
+            r#"[{ "name": "Wheels", "id": 288, "length": 3, "comment": null, "checksum_signal": "CRC",
+                  "signals": [
+                      { "name": "WhlSpd_FL", "start": 0, "length": 16, "is_big_endian": false, "scale": 0.01 },
+                      { "name": "CRC", "start": 16, "length": 8, "is_big_endian": false }
+                  ] }]"#,
+        )
+    };
+    let internal = || {
+        database(
+            r#"[{ "name": "Wheels", "id": 288, "length": 3, "comment": null, "checksum_signal": "Wheels_Chk",
+                  "signals": [
+                      { "name": "Wheels_Chk", "start": 16, "length": 8, "is_big_endian": false },
+                      { "name": "Wheel_Speed_Front_Left", "start": 0, "length": 16, "is_big_endian": false, "scale": 0.01 }
+                  ],
+                  "derived": [{ "name": "Speed_Kmh", "expression": "Wheel_Speed_Front_Left * 3.6" }] }]"#,
+        )
+    };
+    let names = |merged: &ElpisMessages| -> Vec<(String, Vec<String>)> {
+        let message = merged.get_def_by_id(288).unwrap();
+        message.signals.iter().map(|signal| (signal.name.clone(), signal.aliases.clone())).collect()
+    };
+    let owned = |name: &str, aliases: &[&str]| (name.to_string(), aliases.iter().map(|alias| alias.to_string()).collect());
+
+    // Whichever definition is kept, the first database's names win
+    let merged = vendor().merge_with_policy(internal(), MergePriority::PreferOther, SignalNamePolicy::PreferFirst);
+    // The internal derived signal still reads its name
+    assert_eq!(names(&merged), vec![owned("CRC", &[]), owned("WhlSpd_FL", &["Wheel_Speed_Front_Left"])]);
+    assert_eq!(merged.get_def_by_id(288).unwrap().checksum_signal.as_deref(), Some("CRC"));
+    let issues: Vec<String> = merged.issues().iter().map(|issue| issue.to_string()).collect();
+    assert_eq!(
+        issues,
+        vec![
+            "Wheels.WhlSpd_FL: Named Wheel_Speed_Front_Left by the other database too, kept as an alias",
+            "Wheels.CRC: Named Wheels_Chk by the other database too, that name was dropped",
+        ]
+    );
+    let merged = vendor().merge_with_policy(internal(), MergePriority::PreferSelf, SignalNamePolicy::PreferFirst);
+    assert_eq!(names(&merged), vec![owned("WhlSpd_FL", &[]), owned("CRC", &[])]);
+    assert!(merged.issues().iter().all(|issue| issue.description.ends_with("that name was dropped")));
+
+    let merged = vendor().merge_with_policy(internal(), MergePriority::PreferSelf, SignalNamePolicy::PreferLongestName);
+    assert_eq!(names(&merged), vec![owned("Wheel_Speed_Front_Left", &[]), owned("Wheels_Chk", &[])]);
+    assert_eq!(merged.get_def_by_id(288).unwrap().checksum_signal.as_deref(), Some("Wheels_Chk"));
+
+    let merged = internal().merge_with_policy(vendor(), MergePriority::PreferOther, SignalNamePolicy::RecordBoth);
+    assert_eq!(names(&merged), vec![owned("Wheel_Speed_Front_Left", &["WhlSpd_FL"]), owned("Wheels_Chk", &["CRC"])]);
+    assert_eq!(merged.get_def_by_id(288).unwrap().get_signal_by_name("WhlSpd_FL").unwrap().name, "Wheel_Speed_Front_Left");
+
+    // Different layouts aren't renamed
+    let mut moved = internal();
+    moved.messages.get_mut(&288).unwrap().signals[1].start = Some(1);
+    let merged = vendor().merge_with_policy(moved, MergePriority::PreferOther, SignalNamePolicy::PreferFirst);
+    assert_eq!(names(&merged)[1].0, "Wheel_Speed_Front_Left");
+    assert!(merged.issues().is_empty());
+
+    // Multiplexed signals sharing bits are paired by their multiplexer values, not their order
+    let vendor = database(
+        r#"[{ "name": "Climate", "id": 300, "length": 2, "comment": null, "signals": [
+              { "name": "Page", "start": 0, "length": 8, "is_big_endian": false, "is_multiplexer": true },
+              { "name": "Temp", "start": 8, "length": 8, "is_big_endian": false, "multiplexer_signal": "Page", "multiplexer_ids": [1] },
+              { "name": "Press", "start": 8, "length": 8, "is_big_endian": false, "multiplexer_signal": "Page", "multiplexer_ids": [2] }
+          ] }]"#,
+    );
+    let internal = database(
+        r#"[{ "name": "Climate", "id": 300, "length": 2, "comment": null, "signals": [
+              { "name": "Climate_Page", "start": 0, "length": 8, "is_big_endian": false, "is_multiplexer": true },
+              { "name": "Cabin_Pressure", "start": 8, "length": 8, "is_big_endian": false, "multiplexer_signal": "Climate_Page", "multiplexer_ids": [2] },
+              { "name": "Cabin_Temperature", "start": 8, "length": 8, "is_big_endian": false, "multiplexer_signal": "Climate_Page", "multiplexer_ids": [1] }
+          ] }]"#,
+    );
+    let merged = vendor.merge_with_policy(internal, MergePriority::PreferOther, SignalNamePolicy::PreferFirst);
+    let climate = merged.get_def_by_id(300).unwrap();
+    let layout: Vec<(&str, Option<BTreeSet<i64>>)> =
+        climate.signals.iter().map(|signal| (signal.name.as_str(), signal.multiplexer_values())).collect();
+    assert_eq!(
+        layout,
+        vec![("Page", None), ("Press", Some(BTreeSet::from([2]))), ("Temp", Some(BTreeSet::from([1])))]
+    );
+    assert_eq!(climate.signals[1].multiplexer_signal.as_deref(), Some("Page"));
+}
+
+#[test]
+fn find_signal_name_collisions() {
+    let messages = ElpisMessages::from_definitions(
+        serde_json::from_str(
+            r#"[
+                { "name": "Front", "length": 2, "id": 1, "comment": null,
+                  "signals": [
+                      { "name": "Counter", "start": 0, "length": 4, "is_big_endian": false },
+                      { "name": "Speed", "start": 7, "length": 8 }
+                  ] },
+                { "name": "Rear", "length": 2, "id": 2, "comment": null,
+                  "signals": [
+                      { "name": "Counter", "length": 4, "is_big_endian": false, "scale": 2.0 },
+                      { "name": "Speed", "start": 15, "length": 8 }
+                  ] },
+                { "name": "Status", "length": 1, "id": 3, "comment": null,
+                  "signals": [{ "name": "Speed", "start": 7, "length": 8 }] }
+            ]"#,
+        )
+        .unwrap(),
+    );
+
+    // The counter only differs in scale and an implied start, so it decodes from the same bits
+    let collisions = messages.signal_name_collisions();
+    assert_eq!(
+        collisions,
+        vec![SignalNameCollision {
+            signal: String::from("Speed"),
+            messages: vec![String::from("Front"), String::from("Rear"), String::from("Status")],
+        }]
+    );
+    assert_eq!(
+        collisions[0].to_string(),
+        "Signal Speed has a different layout in messages Front, Rear, Status, filters on its name are ambiguous"
+    );
+}
+
+// The fixture with signals over 64 bits, shared by every consumer's tests
+#[cfg(test)]
+pub(crate) fn wide_test_messages() -> ElpisMessages {
+    ElpisMessages::load_from_json(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/wide_signals.json")).unwrap()
+}
+
+// Build a WideIds payload: the serial as 12 little endian bytes, the counter, then the digest as
+// 16 big endian bytes
+#[cfg(test)]
+pub(crate) fn wide_test_payload(serial: u128, counter: u8, digest: u128) -> Vec<u8> {
+    let mut payload = vec![0u8; 32];
+    payload[0..12].copy_from_slice(&serial.to_le_bytes()[0..12]);
+    payload[12] = counter;
+    payload[16..32].copy_from_slice(&digest.to_be_bytes());
+    payload
+}
+
+#[test]
+fn decode_wide_signals() {
+    let messages = wide_test_messages();
+    let message = messages.get_def_by_id(0x300).unwrap();
+
+    // Both values need every one of their bits, an f64 would round them
+    let serial = 0xfedc_ba98_7654_3210_0123_4567u128;
+    let digest = 0x0011_2233_4455_6677_8899_aabb_ccdd_eeffu128;
+    let frame = message.decode(&wide_test_payload(serial, 7, digest));
+    assert!(frame.errors.is_empty(), "{:?}", frame.errors);
+
+    let signal = |name: &str| frame.signals.iter().find(|signal| signal.name == name).unwrap();
+    assert_eq!(signal("Serial").raw, serial);
+    assert!(signal("Serial").wide);
+    assert_eq!(signal("Digest").raw, digest);
+    assert!(signal("Digest").wide);
+    assert_eq!(signal("Counter").raw, 7);
+    assert!(!signal("Counter").wide);
+
+    let serial_definition = message.get_signal_by_name("Serial").unwrap();
+    assert_eq!(serial_definition.raw_bytes(serial), serial.to_be_bytes()[4..].to_vec());
+    assert_eq!(message.get_signal_by_name("Digest").unwrap().raw_bytes(digest), digest.to_be_bytes().to_vec());
+
+    // Derived signals only use exact operands
+    let derived = |name: &str| frame.derived.iter().find(|derived| derived.name == name).unwrap().value;
+    assert_eq!(derived("CounterTwice"), Some(14.0));
+    assert_eq!(derived("SerialNext"), None);
+
+    // Over 128 bits still can't be read
+    let mut too_wide = message.clone();
+    too_wide.signals[0].length = 129;
+    assert_eq!(too_wide.decode(&[0u8; 32]).errors, vec!["Signal Serial is too large to fit in a u128"]);
+}
@@ -0,0 +1,87 @@
+// The ett indexes registered for the protocol and which tree item gets which. Wireshark remembers
+// whether a subtree is expanded per ett index, so each frame and signal of a packet has its own,
+// and past the last one of a kind every further item shares it. An index past the registered count
+// trips an epan assertion, so the count and every index handed out come from the same layout.
+
+// Ett indexes in the order they're registered: one for the protocol, one per frame, one per
+// signal, one for interpolated frame entries and one per signal group
+pub struct EttLayout {
+    frames: usize,
+    signals: usize,
+    groups: usize,
+}
+
+// Allow a maximum of 64 frames, 256 signals and 16 signal groups to be opened and closed separately
+pub const ETT_LAYOUT: EttLayout = EttLayout::new(64, 256, 16);
+
+impl EttLayout {
+    pub const fn new(frames: usize, signals: usize, groups: usize) -> Self {
+        Self { frames, signals, groups }
+    }
+
+    // Get the number of ett indexes to register
+    pub fn count(&self) -> i32 {
+        (1 + self.frames + self.signals + 1 + self.groups) as i32
+    }
+
+    // Get the index of a frame's subtree given its position in the packet
+    pub fn frame(&self, index: usize) -> i32 {
+        self.checked(1 + index.min(self.frames - 1))
+    }
+
+    // Get the index of a signal's subtree given its position in the frame, also used for TLV entries
+    pub fn signal(&self, index: usize) -> i32 {
+        self.checked(1 + self.frames + index.min(self.signals - 1))
+    }
+
+    // Get the index of an interpolated frame entry
+    pub fn interpolated(&self) -> i32 {
+        self.checked(1 + self.frames + self.signals)
+    }
+
+    // Get the index of a signal group's subtree given its position in the frame
+    pub fn group(&self, index: usize) -> i32 {
+        self.checked(2 + self.frames + self.signals + index.min(self.groups - 1))
+    }
+
+    fn checked(&self, index: usize) -> i32 {
+        let index = index as i32;
+        debug_assert!(index < self.count(), "ett index {} is past the {} registered", index, self.count());
+        index
+    }
+}
+
+#[test]
+fn ett_layout() {
+    // The indexes registered before the layout was shared keep their place, groups come after them
+    assert_eq!(ETT_LAYOUT.count(), 2 + 64 + 256 + 16);
+    assert_eq!(ETT_LAYOUT.frame(0), 1);
+    assert_eq!(ETT_LAYOUT.frame(63), 64);
+    assert_eq!(ETT_LAYOUT.signal(0), 1 + 64);
+    assert_eq!(ETT_LAYOUT.signal(255), 1 + 64 + 255);
+    assert_eq!(ETT_LAYOUT.interpolated(), 1 + 64 + 256);
+    assert_eq!(ETT_LAYOUT.group(0), 2 + 64 + 256);
+
+    // Past the last frame or signal, every further one shares the last index
+    assert_eq!(ETT_LAYOUT.frame(64), ETT_LAYOUT.frame(63));
+    assert_eq!(ETT_LAYOUT.frame(usize::MAX), 64);
+    assert_eq!(ETT_LAYOUT.signal(1000), ETT_LAYOUT.signal(255));
+
+    // Every index of any layout is registered, and none are shared between kinds
+    let layout = EttLayout::new(3, 2, 2);
+    let mut indexes = vec![
+        layout.frame(0),
+        layout.frame(1),
+        layout.frame(2),
+        layout.signal(0),
+        layout.signal(1),
+        layout.interpolated(),
+        layout.group(0),
+        layout.group(1),
+    ];
+    assert!(indexes.iter().all(|index| (1..layout.count()).contains(index)));
+    indexes.dedup();
+    assert_eq!(indexes.len(), 8);
+    assert_eq!(layout.group(5), layout.group(1));
+    assert_eq!(layout.signal(9), layout.signal(1));
+}
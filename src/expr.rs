@@ -0,0 +1,255 @@
+// A tiny arithmetic expression evaluator for derived signals, e.g. "(WhlFL+WhlFR+WhlRL+WhlRR)/4".
+// Identifiers refer to the physical values of other signals in the same message. Supports
+// + - * /, unary minus, parentheses and numeric constants.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(f64),
+    Ident(String),
+    Neg(Box<Node>),
+    Binary(char, Box<Node>, Box<Node>),
+}
+
+// A parsed expression, kept alongside its source text so it serializes back unchanged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+fn tokenize(source: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(pos, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = pos;
+            while let Some(&(idx, c)) = chars.peek() {
+                if !c.is_ascii_digit() && c != '.' {
+                    break;
+                }
+                end = idx + c.len_utf8();
+                chars.next();
+            }
+            let number = source[pos..end]
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid number {} at position {}", &source[pos..end], pos))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = pos;
+            while let Some(&(idx, c)) = chars.peek() {
+                if !c.is_alphanumeric() && c != '_' {
+                    break;
+                }
+                end = idx + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Ident(source[pos..end].to_string()));
+        } else {
+            tokens.push(match c {
+                '+' | '-' | '*' | '/' => Token::Op(c),
+                '(' => Token::Open,
+                ')' => Token::Close,
+                _ => return Err(anyhow::anyhow!("Unexpected character '{}' at position {}", c, pos)),
+            });
+            chars.next();
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Recursive descent parser over the token stream
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    // expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> anyhow::Result<Node> {
+        let mut node = self.term()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek() {
+            let op = *op;
+            self.next();
+            node = Node::Binary(op, Box::new(node), Box::new(self.term()?));
+        }
+        Ok(node)
+    }
+
+    // term := factor (('*' | '/') factor)*
+    fn term(&mut self) -> anyhow::Result<Node> {
+        let mut node = self.factor()?;
+        while let Some(Token::Op(op @ ('*' | '/'))) = self.peek() {
+            let op = *op;
+            self.next();
+            node = Node::Binary(op, Box::new(node), Box::new(self.factor()?));
+        }
+        Ok(node)
+    }
+
+    // factor := '-' factor | number | identifier | '(' expression ')'
+    fn factor(&mut self) -> anyhow::Result<Node> {
+        match self.next() {
+            Some(Token::Op('-')) => Ok(Node::Neg(Box::new(self.factor()?))),
+            Some(Token::Number(number)) => Ok(Node::Number(number)),
+            Some(Token::Ident(name)) => Ok(Node::Ident(name)),
+            Some(Token::Open) => {
+                let node = self.expression()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(node),
+                    _ => Err(anyhow::anyhow!("Missing closing parenthesis")),
+                }
+            }
+            Some(token) => Err(anyhow::anyhow!("Unexpected {:?}", token)),
+            None => Err(anyhow::anyhow!("Unexpected end of expression")),
+        }
+    }
+}
+
+fn eval_node(node: &Node, lookup: &dyn Fn(&str) -> Option<f64>) -> Option<f64> {
+    match node {
+        Node::Number(number) => Some(*number),
+        Node::Ident(name) => lookup(name),
+        Node::Neg(inner) => Some(-eval_node(inner, lookup)?),
+        Node::Binary(op, left, right) => {
+            let left = eval_node(left, lookup)?;
+            let right = eval_node(right, lookup)?;
+            match op {
+                '+' => Some(left + right),
+                '-' => Some(left - right),
+                '*' => Some(left * right),
+                _ if right == 0.0 => None,
+                _ => Some(left / right),
+            }
+        }
+    }
+}
+
+fn collect_identifiers<'a>(node: &'a Node, identifiers: &mut Vec<&'a str>) {
+    match node {
+        Node::Number(_) => {}
+        Node::Ident(name) => identifiers.push(name),
+        Node::Neg(inner) => collect_identifiers(inner, identifiers),
+        Node::Binary(_, left, right) => {
+            collect_identifiers(left, identifiers);
+            collect_identifiers(right, identifiers);
+        }
+    }
+}
+
+impl Expression {
+    // Parse an expression from its source text
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+        };
+
+        let root = parser.expression()?;
+        if let Some(token) = parser.peek() {
+            return Err(anyhow::anyhow!("Unexpected {:?} after end of expression", token));
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            root,
+        })
+    }
+
+    // Evaluate the expression, looking up identifiers with the given function. Returns None if an
+    // identifier has no value or the expression divides by zero.
+    pub fn eval(&self, lookup: &dyn Fn(&str) -> Option<f64>) -> Option<f64> {
+        eval_node(&self.root, lookup)
+    }
+
+    // Get every identifier referenced by the expression
+    pub fn identifiers(&self) -> Vec<&str> {
+        let mut identifiers = Vec::new();
+        collect_identifiers(&self.root, &mut identifiers);
+        identifiers
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+impl TryFrom<String> for Expression {
+    type Error = anyhow::Error;
+
+    fn try_from(source: String) -> anyhow::Result<Self> {
+        Self::parse(&source).map_err(|e| anyhow::anyhow!("Invalid expression \"{}\": {}", source, e))
+    }
+}
+
+impl From<Expression> for String {
+    fn from(expression: Expression) -> Self {
+        expression.source
+    }
+}
+
+#[cfg(test)]
+fn eval_with(source: &str, values: &[(&str, f64)]) -> Option<f64> {
+    let lookup = |name: &str| values.iter().find(|(key, _)| *key == name).map(|(_, value)| *value);
+    Expression::parse(source).unwrap().eval(&lookup)
+}
+
+#[test]
+fn evaluate_expressions() {
+    assert_eq!(eval_with("1 + 2 * 3", &[]), Some(7.0));
+    assert_eq!(eval_with("(1 + 2) * 3", &[]), Some(9.0));
+    assert_eq!(eval_with("10 - 4 - 3", &[]), Some(3.0));
+    assert_eq!(eval_with("8 / 4 / 2", &[]), Some(1.0));
+    assert_eq!(eval_with("-2 * -(3 + 1)", &[]), Some(8.0));
+    assert_eq!(eval_with("0.5 * 3", &[]), Some(1.5));
+
+    let wheels = [("WhlFL", 10.0), ("WhlFR", 12.0), ("WhlRL", 11.0), ("WhlRR", 13.0)];
+    assert_eq!(eval_with("(WhlFL+WhlFR+WhlRL+WhlRR)/4", &wheels), Some(11.5));
+
+    // Missing operands and division by zero are unavailable rather than an error
+    assert_eq!(eval_with("(WhlFL+WhlFR+WhlRL+WhlXX)/4", &wheels), None);
+    assert_eq!(eval_with("WhlFL / (WhlFR - 12)", &wheels), None);
+}
+
+#[test]
+fn expression_identifiers() {
+    let expression = Expression::parse("(A_1 + B) * A_1 - 3").unwrap();
+    assert_eq!(expression.identifiers(), vec!["A_1", "B", "A_1"]);
+    assert_eq!(expression.as_str(), "(A_1 + B) * A_1 - 3");
+}
+
+#[test]
+fn reject_invalid_expressions() {
+    for source in ["", "1 +", "(1 + 2", "1 + 2)", "1 2", "A $ B", "1..2", "* 3"] {
+        assert!(Expression::parse(source).is_err(), "{} should not parse", source);
+    }
+
+    assert!(serde_json::from_str::<Expression>(r#""(A + B""#).is_err());
+    let expression: Expression = serde_json::from_str(r#""A + B""#).unwrap();
+    assert_eq!(serde_json::to_string(&expression).unwrap(), r#""A + B""#);
+}
@@ -0,0 +1,169 @@
+// Filter fields planned for every message and signal in the database, e.g. elpis.Wheels.Speed_FL.
+//
+// The plan is always in the same order, messages by id and signals by start bit then name, however
+// the database was loaded. Registering fields in this order keeps `tshark -G fields` stable between
+// runs, and since abbreviations are handed out in this order too, any clash in them resolves the
+// same way every time.
+
+use crate::elpis::ElpisMessages;
+use crate::sanitize::AbbrevSanitizer;
+
+// A field to register for a message or one of its signals
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedField {
+    // Full filter name, such as elpis.Wheels.Speed_FL
+    pub abbrev: String,
+    // Name shown in the tree and the column picker, the message name as it is in the database, or
+    // for a signal the message and signal names, e.g. Wheels.Speed_FL, since signal names repeat
+    // across messages
+    pub name: String,
+    pub message_id: i32,
+    // Signal the field is for, None for the field of the message itself
+    pub signal: Option<String>,
+}
+
+// Plan the fields of every message and signal in the database, in canonical order
+pub fn plan_fields(messages: &ElpisMessages) -> Vec<PlannedField> {
+    let mut fields = Vec::new();
+
+    // Signal abbreviations only need to be unique within their message
+    let mut message_abbrevs = AbbrevSanitizer::default();
+    for message in messages.iter_sorted() {
+        let message_abbrev = format!("elpis.{}", message_abbrevs.abbreviate(&message.name));
+        fields.push(PlannedField {
+            abbrev: message_abbrev.clone(),
+            name: message.name.clone(),
+            message_id: message.id,
+            signal: None,
+        });
+
+        let mut signal_abbrevs = AbbrevSanitizer::default();
+        for signal in message.signals_sorted() {
+            fields.push(PlannedField {
+                abbrev: format!("{}.{}", message_abbrev, signal_abbrevs.abbreviate(&signal.name)),
+                name: format!("{}.{}", message.name, signal.name),
+                message_id: message.id,
+                signal: Some(signal.name.clone()),
+            });
+        }
+    }
+
+    fields
+}
+
+#[test]
+fn planned_fields_are_reproducible() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/messages.json");
+    let first = plan_fields(&ElpisMessages::load_from_json(path).unwrap());
+    let second = plan_fields(&ElpisMessages::load_from_json(path).unwrap());
+    assert_eq!(format!("{:?}", first), format!("{:?}", second));
+
+    // Messages by id, each followed by its signals
+    let message_ids: Vec<i32> = first
+        .iter()
+        .filter(|field| field.signal.is_none())
+        .map(|field| field.message_id)
+        .collect();
+    assert!(message_ids.windows(2).all(|ids| ids[0] < ids[1]));
+    assert!(first.windows(2).all(|fields| fields[1].signal.is_none() || fields[0].message_id == fields[1].message_id));
+}
+
+#[test]
+fn signals_in_start_bit_order() {
+    use crate::elpis::{MessageDefinition, SignalDefinition};
+
+    let mut message = MessageDefinition::new("Wheels", 0x120, 8);
+    for (name, start) in [("Speed_RL", 32), ("Speed_FR", 16), ("Status", 16), ("Speed_FL", 0)] {
+        let mut signal = SignalDefinition::new(name, Some(start), 16);
+        signal.is_big_endian = false;
+        message.signals.push(signal);
+    }
+
+    let fields = plan_fields(&ElpisMessages::from_definitions(vec![message]));
+    let abbrevs: Vec<&str> = fields.iter().map(|field| field.abbrev.as_str()).collect();
+    assert_eq!(
+        abbrevs,
+        vec![
+            "elpis.Wheels",
+            "elpis.Wheels.Speed_FL",
+            "elpis.Wheels.Speed_FR",
+            "elpis.Wheels.Status",
+            "elpis.Wheels.Speed_RL"
+        ]
+    );
+}
+
+// Get the (abbrev, label) of every field registered in plugin_register
+#[cfg(test)]
+pub(crate) fn registered_fields() -> Vec<(&'static str, &'static str)> {
+    include_str!("plugin.rs")
+        .split("WiresharkFieldArgs::new(\"")
+        .skip(1)
+        .map(|registration| {
+            let mut quoted = registration.split('"');
+            let abbrev = quoted.next().unwrap();
+            let label = quoted.nth(1).unwrap();
+            (abbrev, label)
+        })
+        .collect()
+}
+
+#[test]
+fn field_labels_are_unique() {
+    use std::collections::HashMap;
+
+    // Every registered field and every field planned for the bundled database, each label may only
+    // be used once or the column picker and PDML can't tell the fields apart
+    let registered = registered_fields();
+    assert!(registered.contains(&("elpis.signal_name", "Signal Name")));
+
+    let planned = plan_fields(&ElpisMessages::load_from_json(concat!(env!("CARGO_MANIFEST_DIR"), "/messages.json")).unwrap());
+    let fields = registered
+        .iter()
+        .copied()
+        .chain(planned.iter().map(|field| (field.abbrev.as_str(), field.name.as_str())));
+
+    let mut labels: HashMap<&str, &str> = HashMap::new();
+    for (abbrev, label) in fields {
+        if let Some(other) = labels.insert(label, abbrev) {
+            panic!("{} and {} are both labeled {}", other, abbrev, label);
+        }
+    }
+}
+
+// Fields of the 1.0 plugin as (abbrev, type). Saved filters, coloring rules and tshark scripts are
+// written against them, so they're never renamed or retyped: a replacement is registered next to
+// the old field, and the old one stays for at least a release with its value as it was.
+#[cfg(test)]
+const FIELDS_1_0: &[(&str, &str)] = &[
+    ("elpis.id", "Uint8"),
+    ("elpis.len", "Uint8"),
+    ("elpis.name", "String"),
+    ("elpis.payload", "Bytes"),
+    ("elpis.signal_formatted", "None"),
+    ("elpis.signal_name", "String"),
+    ("elpis.signal_kv", "String"),
+    ("elpis.frame", ""),
+];
+
+#[test]
+fn fields_of_1_0_are_registered() {
+    // The type of every registered field, empty for fields registered without one
+    let types: Vec<(&str, &str)> = include_str!("plugin.rs")
+        .split("WiresharkFieldArgs::new(\"")
+        .skip(1)
+        .map(|registration| {
+            let abbrev = registration.split('"').next().unwrap();
+            let registration = &registration[..registration.find(';').unwrap()];
+            let field_type = registration
+                .split("FieldType::")
+                .nth(1)
+                .map_or("", |field_type| field_type.split(')').next().unwrap());
+            (abbrev, field_type)
+        })
+        .collect();
+
+    for (abbrev, field_type) in FIELDS_1_0 {
+        assert!(types.contains(&(abbrev, field_type)), "1.0 field {} isn't registered as {:?}", abbrev, field_type);
+    }
+}
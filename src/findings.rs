@@ -0,0 +1,242 @@
+// Expert findings collected over a capture for `-z elpis,findings,out.csv`, so the dissector's checks
+// can be attached to a test report. Every expert item the dissector adds is recorded with its packet
+// and whatever message, signal and value it's about, and written out sorted by packet number once
+// tshark is done with the capture. Findings are kept per packet and a packet dissected again replaces
+// its findings, so the GUI redissecting or tshark's two passes don't repeat them.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// What a finding is about, as far as the check that found it knows
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Subject {
+    pub message: Option<String>,
+    pub signal: Option<String>,
+    pub value: Option<String>,
+}
+
+impl Subject {
+    pub fn message(name: &str) -> Self {
+        Self {
+            message: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub fn signal(message: &str, signal: &str) -> Self {
+        Self {
+            signal: Some(signal.to_string()),
+            ..Self::message(message)
+        }
+    }
+
+    pub fn with_value(self, value: impl ToString) -> Self {
+        Self {
+            value: Some(value.to_string()),
+            ..self
+        }
+    }
+}
+
+// A single expert finding
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    pub packet: u32,
+    // Seconds since the epoch
+    pub timestamp: f64,
+    // "Note" or "Warning"
+    pub severity: &'static str,
+    pub message: Option<String>,
+    pub signal: Option<String>,
+    pub value: Option<String>,
+    pub finding: String,
+    // Whether the expert_suppress preference kept it out of the tree
+    pub suppressed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FindingsFormat {
+    Csv,
+    Json,
+}
+
+// Where and how to write the findings, from the arguments of `-z elpis,findings,<file>[,suppressed]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FindingsExport {
+    pub path: String,
+    // JSON for a .json file, CSV otherwise
+    pub format: FindingsFormat,
+    pub include_suppressed: bool,
+}
+
+impl FindingsExport {
+    // Parse the whole -z argument, e.g. "elpis,findings,out.json,suppressed"
+    pub fn parse(argument: &str) -> anyhow::Result<Self> {
+        let options = argument.strip_prefix("elpis,findings").unwrap_or(argument);
+        let mut options = options.strip_prefix(',').unwrap_or(options).split(',');
+        let path = options
+            .next()
+            .filter(|path| !path.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("-z elpis,findings needs a file to write, e.g. -z elpis,findings,out.csv"))?;
+
+        let mut include_suppressed = false;
+        for option in options {
+            match option {
+                "suppressed" => include_suppressed = true,
+                other => return Err(anyhow::anyhow!("Unknown -z elpis,findings option \"{}\", expected suppressed", other)),
+            }
+        }
+
+        let format = if path.to_ascii_lowercase().ends_with(".json") {
+            FindingsFormat::Json
+        } else {
+            FindingsFormat::Csv
+        };
+        Ok(Self {
+            path: path.to_string(),
+            format,
+            include_suppressed,
+        })
+    }
+}
+
+// Findings of every packet dissected while an export is set up
+#[derive(Default)]
+pub struct FindingsLog {
+    export: Option<FindingsExport>,
+    packets: BTreeMap<u32, Vec<Finding>>,
+}
+
+impl FindingsLog {
+    pub fn set_export(&mut self, export: FindingsExport) {
+        self.export = Some(export);
+    }
+
+    pub fn export(&self) -> Option<&FindingsExport> {
+        self.export.as_ref()
+    }
+
+    // Forget what an earlier dissection of the packet found
+    pub fn begin_packet(&mut self, packet: u32) {
+        if self.export.is_some() {
+            self.packets.remove(&packet);
+        }
+    }
+
+    // Record a finding, only kept when the findings are being exported
+    pub fn record(&mut self, finding: Finding) {
+        if self.export.is_some() {
+            self.packets.entry(finding.packet).or_default().push(finding);
+        }
+    }
+
+    // Get the findings to export in packet order, in the order they were found within a packet
+    pub fn findings(&self, include_suppressed: bool) -> Vec<&Finding> {
+        self.packets
+            .values()
+            .flatten()
+            .filter(|finding| include_suppressed || !finding.suppressed)
+            .collect()
+    }
+
+    // Render the findings in the export's format, None without an export
+    pub fn render(&self) -> Option<String> {
+        let export = self.export.as_ref()?;
+        let findings = self.findings(export.include_suppressed);
+        Some(match export.format {
+            FindingsFormat::Csv => render_csv(&findings),
+            FindingsFormat::Json => serde_json::to_string_pretty(&findings).unwrap_or_default() + "\n",
+        })
+    }
+}
+
+// Quote a CSV cell when it holds a separator, quote or line break
+fn csv_cell(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn render_csv(findings: &[&Finding]) -> String {
+    let mut output = String::from("packet,timestamp,severity,message,signal,value,finding,suppressed\n");
+    for finding in findings {
+        let cells = [
+            finding.packet.to_string(),
+            format!("{:.6}", finding.timestamp),
+            finding.severity.to_string(),
+            csv_cell(finding.message.as_deref().unwrap_or_default()),
+            csv_cell(finding.signal.as_deref().unwrap_or_default()),
+            csv_cell(finding.value.as_deref().unwrap_or_default()),
+            csv_cell(&finding.finding),
+            finding.suppressed.to_string(),
+        ];
+        output.push_str(&cells.join(","));
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+fn test_finding(packet: u32, subject: Subject, text: &str, suppressed: bool) -> Finding {
+    Finding {
+        packet,
+        timestamp: 1700000000.0 + packet as f64 / 10.0,
+        severity: "Warning",
+        message: subject.message,
+        signal: subject.signal,
+        value: subject.value,
+        finding: text.to_string(),
+        suppressed,
+    }
+}
+
+#[test]
+fn parse_findings_export() {
+    let export = FindingsExport::parse("elpis,findings,out.csv").unwrap();
+    assert_eq!((export.path.as_str(), export.format, export.include_suppressed), ("out.csv", FindingsFormat::Csv, false));
+
+    let export = FindingsExport::parse("elpis,findings,/tmp/Report.JSON,suppressed").unwrap();
+    assert_eq!((export.path.as_str(), export.format, export.include_suppressed), ("/tmp/Report.JSON", FindingsFormat::Json, true));
+
+    assert!(FindingsExport::parse("elpis,findings").is_err());
+    assert!(FindingsExport::parse("elpis,findings,").is_err());
+    assert!(FindingsExport::parse("elpis,findings,out.csv,all").is_err());
+}
+
+#[test]
+fn export_findings_by_packet() {
+    let mut log = FindingsLog::default();
+
+    // Nothing is kept until an export is set up
+    log.record(test_finding(1, Subject::default(), "Truncated frame header", false));
+    assert_eq!(log.render(), None);
+
+    log.set_export(FindingsExport::parse("elpis,findings,out.csv").unwrap());
+    log.begin_packet(7);
+    log.record(test_finding(7, Subject::message("Cells").with_value(0x12), "Checksum mismatch", true));
+    log.record(test_finding(7, Subject::signal("Wheels", "Speed").with_value("412.5"), "Speed is outside its range of 0 to 300", false));
+    log.begin_packet(3);
+    log.record(test_finding(3, Subject::default(), "Only 10% of frames had known ids, 8 of 80", false));
+
+    // Dissecting packet 7 again replaces what it found the first time
+    log.begin_packet(7);
+    log.record(test_finding(7, Subject::signal("Wheels", "Speed").with_value("412.5"), "Speed is outside its range of 0 to 300", false));
+    log.record(test_finding(7, Subject::message("Cells").with_value(0x12), "Checksum mismatch", true));
+
+    assert_eq!(
+        log.render().unwrap(),
+        "packet,timestamp,severity,message,signal,value,finding,suppressed\n\
+         3,1700000000.300000,Warning,,,,\"Only 10% of frames had known ids, 8 of 80\",false\n\
+         7,1700000000.700000,Warning,Wheels,Speed,412.5,Speed is outside its range of 0 to 300,false\n"
+    );
+
+    log.set_export(FindingsExport::parse("elpis,findings,out.json,suppressed").unwrap());
+    let json: serde_json::Value = serde_json::from_str(&log.render().unwrap()).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 3);
+    assert_eq!(json[2]["message"], "Cells");
+    assert_eq!(json[2]["value"], "18");
+    assert_eq!(json[2]["suppressed"], true);
+    assert_eq!(json[0]["signal"], serde_json::Value::Null);
+}
@@ -0,0 +1,234 @@
+// Databases per multicast group. Telemetry can multicast a different message set to each group,
+// e.g. chassis messages to 239.1.1.1 and ADAS messages to 239.1.1.2, and the `group_databases`
+// preference names the database for the datagrams sent to each:
+//
+//     "239.1.1.1/32=chassis.json;239.1.1.0/24=body.json;ff15::1=adas.json"
+//
+// A rule is an IPv4 or IPv6 address with an optional prefix length, an address on its own only
+// matches itself. When several rules match a destination the most specific one wins, and of equally
+// specific ones the first listed. Destinations no rule matches use messages.json as before.
+
+use crate::elpis::ElpisMessages;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+// A destination network and the database of datagrams sent to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupRule {
+    network: IpAddr,
+    prefix_length: u8,
+    pub database: String,
+}
+
+impl GroupRule {
+    fn parse(entry: &str) -> anyhow::Result<Self> {
+        let (network, database) = entry
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Expected address=file in group database rule \"{}\"", entry))?;
+        let (address, prefix_length) = match network.trim().split_once('/') {
+            Some((address, prefix_length)) => (address, Some(prefix_length)),
+            None => (network.trim(), None),
+        };
+
+        let network: IpAddr = address
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid address {} in group database rule \"{}\"", address, entry))?;
+        let max_length = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_length = match prefix_length {
+            Some(text) => text
+                .parse::<u8>()
+                .ok()
+                .filter(|length| *length <= max_length)
+                .ok_or_else(|| anyhow::anyhow!("Invalid prefix length {} in group database rule \"{}\"", text, entry))?,
+            None => max_length,
+        };
+
+        let database = database.trim();
+        if database.is_empty() {
+            return Err(anyhow::anyhow!("Empty database file in group database rule \"{}\"", entry));
+        }
+
+        Ok(Self {
+            network,
+            prefix_length,
+            database: database.to_string(),
+        })
+    }
+
+    // Check whether an address is in this rule's network
+    pub fn matches(&self, address: IpAddr) -> bool {
+        let (network, address, bits) = match (self.network, address) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => (u32::from(network) as u128, u32::from(address) as u128, 32),
+            (IpAddr::V6(network), IpAddr::V6(address)) => (u128::from(network), u128::from(address), 128),
+            _ => return false,
+        };
+        let host_bits = bits - self.prefix_length as u32;
+        host_bits == bits || network >> host_bits == address >> host_bits
+    }
+}
+
+impl std::fmt::Display for GroupRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}={}", self.network, self.prefix_length, self.database)
+    }
+}
+
+// The parsed `group_databases` preference
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(try_from = "String")]
+pub struct GroupDatabases {
+    rules: Vec<GroupRule>,
+}
+
+impl TryFrom<String> for GroupDatabases {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> anyhow::Result<Self> {
+        Self::parse(&value)
+    }
+}
+
+impl GroupDatabases {
+    // Parse a list of `address[/prefix]=file` rules separated by semicolons
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let rules = text
+            .split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(GroupRule::parse)
+            .collect::<anyhow::Result<Vec<GroupRule>>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // Get the index of the most specific rule matching a destination
+    fn rule_index(&self, address: IpAddr) -> Option<usize> {
+        let mut best: Option<usize> = None;
+        for (index, rule) in self.rules.iter().enumerate() {
+            if rule.matches(address) && best.is_none_or(|best| rule.prefix_length > self.rules[best].prefix_length) {
+                best = Some(index);
+            }
+        }
+        best
+    }
+}
+
+// Get the IP address of a network address given as 4 or 16 bytes
+pub fn ip_address(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => Some(IpAddr::from(<[u8; 4]>::try_from(bytes).unwrap())),
+        16 => Some(IpAddr::from(<[u8; 16]>::try_from(bytes).unwrap())),
+        _ => None,
+    }
+}
+
+// The database chosen for each destination, resolved once for each and kept for the session.
+// Each rule's database is loaded the first time a destination matches it.
+#[derive(Default)]
+pub struct GroupDatabaseCache {
+    // Index of the rule matching each destination seen, None when none does
+    destinations: HashMap<IpAddr, Option<usize>>,
+    // Database of each rule used so far, None when it couldn't be loaded
+    databases: HashMap<usize, Option<Arc<ElpisMessages>>>,
+}
+
+impl GroupDatabaseCache {
+    // Get the rule matching a destination and its database, loading it if it's the first use of the
+    // rule. A database that fails to load is reported once and the rule is still returned, so the
+    // caller can fall back.
+    pub fn resolve<'a>(
+        &mut self,
+        groups: &'a GroupDatabases,
+        destination: IpAddr,
+        load: impl FnOnce(&GroupRule) -> anyhow::Result<ElpisMessages>,
+    ) -> Option<(&'a GroupRule, Option<Arc<ElpisMessages>>)> {
+        let index = (*self
+            .destinations
+            .entry(destination)
+            .or_insert_with(|| groups.rule_index(destination)))?;
+        let rule = &groups.rules[index];
+        let database = self.databases.entry(index).or_insert_with(|| match load(rule) {
+            Ok(messages) => Some(Arc::new(messages)),
+            Err(e) => {
+                eprintln!("Error loading ELPIS database {} for group {}: {:#}", rule.database, destination, e);
+                None
+            }
+        });
+        Some((rule, database.clone()))
+    }
+}
+
+#[test]
+fn parse_group_rules() {
+    let groups = GroupDatabases::parse("239.1.1.1/32=chassis.json; 239.1.1.0/24 = body.json;ff15::1=adas.json;").unwrap();
+    let rules: Vec<String> = groups.rules.iter().map(|rule| rule.to_string()).collect();
+    assert_eq!(rules, vec!["239.1.1.1/32=chassis.json", "239.1.1.0/24=body.json", "ff15::1/128=adas.json"]);
+    assert!(GroupDatabases::parse("").unwrap().is_empty());
+
+    assert!(GroupDatabases::parse("239.1.1.1").is_err());
+    assert!(GroupDatabases::parse("239.1.1.1/33=chassis.json").is_err());
+    assert!(GroupDatabases::parse("239.1.1/24=chassis.json").is_err());
+    assert!(GroupDatabases::parse("239.1.1.1=").is_err());
+    let error = GroupDatabases::parse("ff15::1/129=adas.json").err().unwrap();
+    assert_eq!(error.to_string(), "Invalid prefix length 129 in group database rule \"ff15::1/129=adas.json\"");
+}
+
+#[cfg(test)]
+impl GroupDatabases {
+    fn database_for(&self, address: &str) -> Option<&str> {
+        self.rule_index(address.parse().unwrap()).map(|index| self.rules[index].database.as_str())
+    }
+}
+
+#[test]
+fn most_specific_group_rule() {
+    let groups = GroupDatabases::parse("239.1.0.0/16=wide.json;239.1.1.1=chassis.json;239.1.1.0/24=body.json;0.0.0.0/0=fallback.json").unwrap();
+    assert_eq!(groups.database_for("239.1.1.1"), Some("chassis.json"));
+    assert_eq!(groups.database_for("239.1.1.2"), Some("body.json"));
+    assert_eq!(groups.database_for("239.1.9.1"), Some("wide.json"));
+    assert_eq!(groups.database_for("10.0.0.1"), Some("fallback.json"));
+
+    // Of equally specific rules the first listed wins
+    let groups = GroupDatabases::parse("239.1.1.0/24=first.json;239.1.1.0/24=second.json").unwrap();
+    assert_eq!(groups.database_for("239.1.1.7"), Some("first.json"));
+
+    // IPv6 multicast groups, which IPv4 rules never match
+    let groups = GroupDatabases::parse("0.0.0.0/0=v4.json;ff15::/16=site.json;ff15::1=adas.json").unwrap();
+    assert_eq!(groups.database_for("ff15::1"), Some("adas.json"));
+    assert_eq!(groups.database_for("ff15::2"), Some("site.json"));
+    assert_eq!(groups.database_for("ff02::1"), None);
+    assert_eq!(ip_address(&[0xff, 0x15, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]), Some("ff15::1".parse().unwrap()));
+    assert_eq!(ip_address(&[239, 1, 1, 1]), Some("239.1.1.1".parse().unwrap()));
+    assert_eq!(ip_address(&[]), None);
+}
+
+#[test]
+fn group_databases_resolved_once() {
+    let groups = GroupDatabases::parse("239.1.1.1=chassis.json;239.1.1.2=missing.json").unwrap();
+    let mut cache = GroupDatabaseCache::default();
+    let mut loads = 0;
+
+    for _ in 0..2 {
+        let (rule, database) = cache
+            .resolve(&groups, "239.1.1.1".parse().unwrap(), |_| {
+                loads += 1;
+                Ok(ElpisMessages::from_definitions(Vec::new()))
+            })
+            .unwrap();
+        assert_eq!(rule.database, "chassis.json");
+        assert!(database.is_some());
+    }
+    assert_eq!(loads, 1);
+
+    // A database that fails to load still reports its rule
+    let (rule, database) = cache
+        .resolve(&groups, "239.1.1.2".parse().unwrap(), |_| Err(anyhow::anyhow!("not found")))
+        .unwrap();
+    assert_eq!((rule.database.as_str(), database.is_none()), ("missing.json", true));
+    assert!(cache.resolve(&groups, "239.1.1.3".parse().unwrap(), |_| unreachable!()).is_none());
+}
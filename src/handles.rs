@@ -0,0 +1,95 @@
+// Field handles looked up by name while dissecting. A field that failed to register, e.g. after a
+// clash of abbreviations, has no valid handle, and adding items with one corrupts the tree. Every
+// lookup goes through CheckedHandles so a missing field is noticed: without a field the dissector
+// needs, packets aren't dissected at all, and without any other field the items it would show are
+// left out. Each missing field is logged once per session.
+
+use std::collections::BTreeSet;
+use std::ffi::c_int;
+
+// Looks up field handles, remembering every field that isn't registered
+pub struct CheckedHandles<F> {
+    lookup: F,
+    missing: Vec<&'static str>,
+    missing_required: bool,
+}
+
+impl<F: Fn(&str) -> c_int> CheckedHandles<F> {
+    pub fn new(lookup: F) -> Self {
+        Self {
+            lookup,
+            missing: Vec::new(),
+            missing_required: false,
+        }
+    }
+
+    // Get the handle of a field, None when it isn't registered
+    pub fn optional(&mut self, name: &'static str) -> Option<c_int> {
+        let handle = (self.lookup)(name);
+        if handle > 0 {
+            return Some(handle);
+        }
+        self.missing.push(name);
+        None
+    }
+
+    // Get the handle of a field the dissector can't do without. A missing field gives an invalid
+    // handle, and has_required tells not to use any of them.
+    pub fn required(&mut self, name: &'static str) -> c_int {
+        self.optional(name).unwrap_or_else(|| {
+            self.missing_required = true;
+            -1
+        })
+    }
+
+    // Whether every field looked up with required is registered
+    pub fn has_required(&self) -> bool {
+        !self.missing_required
+    }
+
+    // Get the fields that aren't registered, in the order they were looked up
+    pub fn missing(&self) -> &[&'static str] {
+        &self.missing
+    }
+}
+
+// Fields already logged as missing this session
+#[derive(Default)]
+pub struct MissingFieldLog {
+    reported: BTreeSet<&'static str>,
+}
+
+impl MissingFieldLog {
+    // Get the fields that haven't been logged yet, taking them as logged
+    pub fn unreported(&mut self, missing: &[&'static str]) -> Vec<&'static str> {
+        missing.iter().copied().filter(|name| self.reported.insert(name)).collect()
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn lookup_without(missing: &'static str) -> impl Fn(&str) -> c_int {
+    move |name| if name == missing { -1 } else { 1 + name.len() as c_int }
+}
+
+#[test]
+fn missing_fields() {
+    let mut handles = CheckedHandles::new(lookup_without("elpis.tlv.tag"));
+    assert_eq!(handles.optional("elpis.tlv"), Some(10));
+    assert_eq!(handles.optional("elpis.tlv.tag"), None);
+    assert_eq!(handles.required("elpis.name"), 11);
+    assert!(handles.has_required());
+    assert_eq!(handles.missing(), ["elpis.tlv.tag"]);
+
+    // A missing required field is listed too, and has_required says not to use the handles
+    let mut handles = CheckedHandles::new(lookup_without("elpis.name"));
+    assert_eq!(handles.required("elpis.name"), -1);
+    assert!(!handles.has_required());
+
+    // Handle 0 is never one of ours either
+    let mut handles = CheckedHandles::new(|_: &str| 0);
+    assert_eq!(handles.optional("elpis.tlv"), None);
+
+    let mut log = MissingFieldLog::default();
+    assert_eq!(log.unreported(&["elpis.tlv.tag", "elpis.name"]), ["elpis.tlv.tag", "elpis.name"]);
+    assert!(log.unreported(&["elpis.tlv.tag"]).is_empty());
+}
@@ -0,0 +1,118 @@
+// Text of the Info column of an ELPIS packet. Scripts read the column with
+// `tshark -T fields -e _ws.col.Info`, so its format is versioned: the `info_column_version`
+// preference picks the version and a version's output never changes once released. Anything that
+// changes the text goes in a new version, and tests/info_column_golden.json pins the output of every
+// version for a fixed set of packets.
+//
+// Version 1 is the names of the messages in the packet, each once, in reverse alphabetical order
+// and joined with " / ". Frames of experimental ids the database doesn't know show as
+// "Experimental 0x..".
+
+use serde::Deserialize;
+use std::collections::BTreeSet;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(try_from = "u32")]
+pub enum InfoColumnVersion {
+    #[default]
+    V1,
+}
+
+impl InfoColumnVersion {
+    const ALL: [InfoColumnVersion; 1] = [InfoColumnVersion::V1];
+
+    pub fn number(&self) -> u32 {
+        match self {
+            InfoColumnVersion::V1 => 1,
+        }
+    }
+}
+
+impl TryFrom<u32> for InfoColumnVersion {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u32) -> anyhow::Result<Self> {
+        Self::ALL.into_iter().find(|version| version.number() == value).ok_or_else(|| {
+            let numbers: Vec<String> = Self::ALL.iter().map(|version| version.number().to_string()).collect();
+            anyhow::anyhow!("Unsupported info_column_version {}, expected one of {}", value, numbers.join(", "))
+        })
+    }
+}
+
+// Collects what a packet's frames contribute to the Info column while they're dissected
+pub struct InfoColumnBuilder {
+    version: InfoColumnVersion,
+    names: BTreeSet<String>,
+}
+
+impl InfoColumnBuilder {
+    pub fn new(version: InfoColumnVersion) -> Self {
+        Self {
+            version,
+            names: BTreeSet::new(),
+        }
+    }
+
+    // Add a frame of a message in the database
+    pub fn add_message(&mut self, name: &str) {
+        self.names.insert(name.to_string());
+    }
+
+    // Add a frame of an experimental id the database doesn't know
+    pub fn add_experimental(&mut self, id: i32) {
+        self.names.insert(format!("Experimental {:#x}", id));
+    }
+
+    pub fn build(&self) -> String {
+        match self.version {
+            InfoColumnVersion::V1 => self.names.iter().rev().map(String::as_str).collect::<Vec<&str>>().join(" / "),
+        }
+    }
+}
+
+// Frames of the packets the golden file pins, message names or experimental ids
+#[cfg(test)]
+const GOLDEN_PACKETS: &[&[Result<&str, i32>]] = &[
+    &[],
+    &[Ok("Wheels")],
+    &[Ok("Wheels"), Ok("Brakes"), Ok("Wheels")],
+    &[Ok("ESP_Alive"), Ok("Brakes"), Ok("body_status"), Ok("Airbag")],
+    &[Err(0x7f00), Ok("Wheels"), Err(0x7f01), Err(0x7f00)],
+    &[Ok("Engine/Torque"), Ok("Engine")],
+];
+
+#[test]
+fn info_column_golden_output() {
+    let golden: std::collections::BTreeMap<String, Vec<String>> = serde_json::from_str(
+        &std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/info_column_golden.json")).unwrap(),
+    )
+    .unwrap();
+
+    // Every version must be pinned, and its output for every packet must match
+    for version in InfoColumnVersion::ALL {
+        let expected = golden.get(&version.number().to_string()).expect("version missing from the golden file");
+        let output: Vec<String> = GOLDEN_PACKETS
+            .iter()
+            .map(|frames| {
+                let mut builder = InfoColumnBuilder::new(version);
+                for frame in frames.iter() {
+                    match frame {
+                        Ok(name) => builder.add_message(name),
+                        Err(id) => builder.add_experimental(*id),
+                    }
+                }
+                builder.build()
+            })
+            .collect();
+        assert_eq!(&output, expected, "Info column version {} changed", version.number());
+    }
+    assert_eq!(golden.len(), InfoColumnVersion::ALL.len());
+}
+
+#[test]
+fn info_column_version_preference() {
+    let version: InfoColumnVersion = serde_json::from_str("1").unwrap();
+    assert_eq!(version, InfoColumnVersion::V1);
+    let error = serde_json::from_str::<InfoColumnVersion>("2").err().unwrap();
+    assert!(error.to_string().starts_with("Unsupported info_column_version 2, expected one of 1"));
+}
@@ -0,0 +1,85 @@
+// Detects a second copy of the plugin loaded into the same Wireshark, e.g. one in the personal and
+// one in the global plugin directory. Registering the same fields twice aborts Wireshark, so only
+// the first copy registers and any later one does nothing.
+//
+// Every copy of the library has its own statics, and the protocol itself is only registered after
+// every plugin_register has run, so the marker is kept in the environment the copies share.
+
+// Environment variable the first copy stores the path it was loaded from in
+pub const INSTANCE_MARKER: &str = "ELPIS_PLUGIN_REGISTERED_FROM";
+
+// Claim registration for the copy loaded from the given path, using the given lookup and store of
+// the marker. Returns the path of the copy that already registered, if there is one.
+pub fn claim_registration(
+    own_path: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+    store: impl FnOnce(&str, &str),
+) -> Option<String> {
+    match lookup(INSTANCE_MARKER) {
+        Some(registered_path) => Some(registered_path),
+        None => {
+            store(INSTANCE_MARKER, own_path);
+            None
+        }
+    }
+}
+
+// Find the path of the mapped file containing an address, from the contents of /proc/self/maps.
+// Each line reads "start-end perms offset dev inode path" with hex addresses.
+pub fn mapping_path_containing(maps: &str, address: usize) -> Option<String> {
+    maps.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let (start, end) = fields.next()?.split_once('-')?;
+        let start = usize::from_str_radix(start, 16).ok()?;
+        let end = usize::from_str_radix(end, 16).ok()?;
+        if address < start || address >= end {
+            return None;
+        }
+
+        // The path is whatever follows the inode, and may contain spaces
+        let path = line.find('/').map(|pos| line[pos..].to_string())?;
+        Some(path)
+    })
+}
+
+#[test]
+fn claim_registration_once() {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    let environment = RefCell::new(HashMap::new());
+    let lookup = |name: &str| environment.borrow().get(name).cloned();
+    let store = |name: &str, value: &str| {
+        environment.borrow_mut().insert(name.to_string(), value.to_string());
+    };
+
+    let personal = "/home/user/.local/lib/wireshark/plugins/4.4/epan/libelpis.so";
+    let global = "/usr/lib/wireshark/plugins/4.4/epan/libelpis.so";
+    assert_eq!(claim_registration(personal, lookup, store), None);
+
+    // The second copy is told where the first came from, and doesn't replace it
+    let lookup = |name: &str| environment.borrow().get(name).cloned();
+    let store = |_: &str, _: &str| panic!("second copy stored the marker");
+    assert_eq!(claim_registration(global, lookup, store), Some(personal.to_string()));
+}
+
+#[test]
+fn find_mapping_of_address() {
+    let maps = "\
+55d4c0a00000-55d4c0a21000 r--p 00000000 fd:01 1312 /usr/bin/wireshark
+7f3a10000000-7f3a10200000 r-xp 00010000 fd:01 4242 /home/user/my plugins/libelpis.so
+7f3a20000000-7f3a20200000 r-xp 00010000 fd:01 4343 /usr/lib/wireshark/plugins/4.4/epan/libelpis.so
+7ffc10000000-7ffc10021000 rw-p 00000000 00:00 0 [stack]
+";
+
+    assert_eq!(
+        mapping_path_containing(maps, 0x7f3a10001234),
+        Some(String::from("/home/user/my plugins/libelpis.so"))
+    );
+    assert_eq!(
+        mapping_path_containing(maps, 0x7f3a20000000),
+        Some(String::from("/usr/lib/wireshark/plugins/4.4/epan/libelpis.so"))
+    );
+    assert_eq!(mapping_path_containing(maps, 0x7f3a10200000), None);
+    assert_eq!(mapping_path_containing(maps, 0x7ffc10000010), None);
+}
@@ -0,0 +1,100 @@
+// Where the value of an item added to the tree comes from, which decides the bytes it claims and
+// whether it's flagged as generated. Wireshark highlights an item's bytes when it's selected, and
+// PDML consumers take an item's span as the bytes it was read from, so every item follows one
+// policy:
+//
+// - read from the wire: the bytes it was read from, not generated
+// - computed from this frame: the bytes it was computed from, generated
+// - computed across frames, such as counts, deltas and pairing with other frames: no bytes,
+//   generated
+//
+// Items computed from the database or the packet's lower layers rather than from bytes of the
+// frame, such as message names and directions, are computed items claiming no bytes. Spans are
+// offsets from the position of the subtree the item is added to, the payload's first byte for
+// signals.
+
+use crate::elpis::{DerivedSignal, MessageDefinition, SignalDefinition};
+use std::ops::Range;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ItemSource {
+    Wire(Range<usize>),
+    Computed(Range<usize>),
+    CrossFrame,
+}
+
+impl ItemSource {
+    // A computed item no bytes of the frame went into
+    pub const UNBACKED: ItemSource = ItemSource::Computed(0..0);
+
+    // The bytes a signal's value is computed from
+    pub fn signal(signal: &SignalDefinition) -> Self {
+        ItemSource::Computed(signal.byte_range().unwrap_or(0..0))
+    }
+
+    // The bytes of a signal as they're shown, which are only the wire's bytes when no payload
+    // transform changed them
+    pub fn signal_bytes(signal: &SignalDefinition, transformed: bool) -> Self {
+        let range = signal.byte_range().unwrap_or(0..0);
+        if transformed {
+            ItemSource::Computed(range)
+        } else {
+            ItemSource::Wire(range)
+        }
+    }
+
+    // The bytes of every signal a derived signal's expression reads, from the first to the last
+    pub fn derived(definition: &MessageDefinition, derived: &DerivedSignal) -> Self {
+        let range = derived
+            .expression
+            .identifiers()
+            .into_iter()
+            .filter_map(|name| definition.get_signal_by_name(name)?.byte_range())
+            .reduce(|span, range| span.start.min(range.start)..span.end.max(range.end));
+        ItemSource::Computed(range.unwrap_or(0..0))
+    }
+
+    pub fn offset(&self) -> i32 {
+        match self {
+            ItemSource::Wire(range) | ItemSource::Computed(range) => range.start as i32,
+            ItemSource::CrossFrame => 0,
+        }
+    }
+
+    pub fn length(&self) -> i32 {
+        match self {
+            ItemSource::Wire(range) | ItemSource::Computed(range) => range.len() as i32,
+            ItemSource::CrossFrame => 0,
+        }
+    }
+
+    pub fn is_generated(&self) -> bool {
+        !matches!(self, ItemSource::Wire(_))
+    }
+}
+
+#[test]
+fn item_source_policy() {
+    let messages: Vec<MessageDefinition> = serde_json::from_str(
+        r#"[{
+            "name": "Wheels", "id": 288, "length": 8,
+            "signals": [
+                { "name": "Speed_FL", "start": 0, "length": 16, "is_big_endian": false },
+                { "name": "Speed_FR", "start": 39, "length": 16, "is_big_endian": true }
+            ],
+            "derived": [{ "name": "Speed_Front", "expression": "(Speed_FL+Speed_FR)/2" }]
+        }]"#,
+    )
+    .unwrap();
+    let message = &messages[0];
+
+    // (offset, length, generated) of one item of each kind
+    let placement = |source: ItemSource| (source.offset(), source.length(), source.is_generated());
+    assert_eq!(placement(ItemSource::Wire(0..4)), (0, 4, false));
+    assert_eq!(placement(ItemSource::signal_bytes(&message.signals[0], false)), (0, 2, false));
+    assert_eq!(placement(ItemSource::signal_bytes(&message.signals[0], true)), (0, 2, true));
+    assert_eq!(placement(ItemSource::signal(&message.signals[1])), (4, 2, true));
+    assert_eq!(placement(ItemSource::derived(message, &message.derived[0])), (0, 6, true));
+    assert_eq!(placement(ItemSource::UNBACKED), (0, 0, true));
+    assert_eq!(placement(ItemSource::CrossFrame), (0, 0, true));
+}
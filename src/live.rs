@@ -0,0 +1,291 @@
+// Decodes ELPIS datagrams as they arrive and renders each frame as a line of output, for watching
+// traffic on a bench without Wireshark.
+
+use crate::elpis::{DecodedFrame, ElpisMessages, FrameIter};
+
+// How each decoded frame is written out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    // One JSON object per frame
+    Jsonl,
+    // One row per signal
+    Csv,
+    // One human readable line per frame
+    Pretty,
+}
+
+// Counts of everything received since the decoder was started
+#[derive(Debug, Default, PartialEq)]
+pub struct LiveStats {
+    pub datagrams: u64,
+    pub frames: u64,
+    pub unknown_ids: u64,
+    pub malformed_datagrams: u64,
+    pub decode_errors: u64,
+}
+
+impl LiveStats {
+    // Render the counts for printing on exit
+    pub fn render(&self) -> String {
+        format!(
+            "datagrams: {}\nframes: {}\nunknown ids: {}\nmalformed datagrams: {}\ndecode errors: {}\n",
+            self.datagrams, self.frames, self.unknown_ids, self.malformed_datagrams, self.decode_errors
+        )
+    }
+}
+
+// A value to output, signals over 64 bits are written as their exact raw value in hex since their
+// physical value would be rounded, and values in a labeled range as the label
+enum LiveValue {
+    Physical(f64),
+    Wide(u128),
+    Label(String),
+}
+
+impl LiveValue {
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            LiveValue::Physical(value) => serde_json::json!(value),
+            LiveValue::Wide(_) | LiveValue::Label(_) => serde_json::json!(self.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for LiveValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LiveValue::Physical(value) => write!(f, "{}", value),
+            LiveValue::Wide(raw) => write!(f, "{:#x}", raw),
+            LiveValue::Label(label) => write!(f, "{}", label),
+        }
+    }
+}
+
+pub struct LiveDecoder<'a> {
+    messages: &'a ElpisMessages,
+    // Signals to output, all of them when empty
+    signals: Vec<String>,
+    format: StreamFormat,
+    stats: LiveStats,
+}
+
+impl<'a> LiveDecoder<'a> {
+    pub fn new(messages: &'a ElpisMessages, signals: Vec<String>, format: StreamFormat) -> Self {
+        Self {
+            messages,
+            signals,
+            format,
+            stats: LiveStats::default(),
+        }
+    }
+
+    // Get the line to output before any frames, if the format has one
+    pub fn header(&self) -> Option<&'static str> {
+        match self.format {
+            StreamFormat::Csv => Some("timestamp,id,message,signal,raw,physical"),
+            _ => None,
+        }
+    }
+
+    pub fn stats(&self) -> &LiveStats {
+        &self.stats
+    }
+
+    fn is_selected(&self, name: &str) -> bool {
+        self.signals.is_empty() || self.signals.iter().any(|signal| signal == name)
+    }
+
+    // Decode a datagram received at the given time, returning the lines to output for it. A
+    // malformed datagram is counted and its frames before the error are still output.
+    pub fn decode_datagram(&mut self, timestamp: f64, datagram: &[u8]) -> String {
+        self.stats.datagrams += 1;
+
+        let mut output = String::new();
+        for frame in FrameIter::new(datagram) {
+            let frame = match frame {
+                Ok(frame) => frame,
+                Err(_) => {
+                    self.stats.malformed_datagrams += 1;
+                    break;
+                }
+            };
+
+            output.push_str(self.decode_frame(timestamp, frame.id, frame.payload).as_str());
+        }
+
+        output
+    }
+
+    // Decode a single frame received at the given time outside of a datagram, e.g. from a simulator
+    // log, returning the lines to output for it
+    pub fn decode_frame(&mut self, timestamp: f64, id: i32, payload: &[u8]) -> String {
+        self.stats.frames += 1;
+        let decoded = match self.messages.get_def_by_id(id) {
+            Some(message_def) => message_def.decode(payload),
+            None => {
+                self.stats.unknown_ids += 1;
+                return String::new();
+            }
+        };
+        self.stats.decode_errors += decoded.errors.len() as u64;
+
+        self.render_frame(timestamp, &decoded)
+    }
+
+    // Render a decoded frame, nothing when none of its signals are selected
+    fn render_frame(&self, timestamp: f64, decoded: &DecodedFrame) -> String {
+        let signals: Vec<(&str, Option<u128>, LiveValue)> = decoded
+            .signals
+            .iter()
+            .map(|signal| {
+                let value = if let Some(label) = &signal.label {
+                    LiveValue::Label(label.clone())
+                } else if signal.wide {
+                    LiveValue::Wide(signal.raw)
+                } else {
+                    LiveValue::Physical(signal.physical)
+                };
+                (signal.name.as_str(), Some(signal.raw), value)
+            })
+            .chain(
+                decoded
+                    .derived
+                    .iter()
+                    .filter_map(|derived| Some((derived.name.as_str(), None, LiveValue::Physical(derived.value?)))),
+            )
+            .filter(|(name, _, _)| self.is_selected(name))
+            .collect();
+
+        if signals.is_empty() && !self.signals.is_empty() {
+            return String::new();
+        }
+
+        match self.format {
+            StreamFormat::Jsonl => {
+                let values: serde_json::Map<String, serde_json::Value> = signals
+                    .iter()
+                    .map(|(name, _, value)| (name.to_string(), value.to_json()))
+                    .collect();
+                let line = serde_json::json!({
+                    "timestamp": timestamp,
+                    "id": decoded.id,
+                    "name": decoded.name,
+                    "signals": values,
+                    "errors": decoded.errors,
+                });
+                format!("{}\n", line)
+            }
+            StreamFormat::Csv => signals
+                .iter()
+                .map(|(name, raw, value)| {
+                    let raw = raw.map(|raw| raw.to_string()).unwrap_or_default();
+                    format!("{:.6},{:#x},{},{},{},{}\n", timestamp, decoded.id, decoded.name, name, raw, value)
+                })
+                .collect(),
+            StreamFormat::Pretty => {
+                let values = signals
+                    .iter()
+                    .map(|(name, _, value)| format!("{}={}", name, value))
+                    .collect::<Vec<String>>()
+                    .join(" ");
+                if values.is_empty() {
+                    format!("{:.6} {:#x} {}\n", timestamp, decoded.id, decoded.name)
+                } else {
+                    format!("{:.6} {:#x} {}: {}\n", timestamp, decoded.id, decoded.name, values)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn live_test_messages() -> ElpisMessages {
+    use crate::elpis::{MessageDefinition, SignalDefinition};
+
+    let mut speed = SignalDefinition::new("Speed", Some(7), 8);
+    speed.scale = Some(0.5);
+    let mut message = MessageDefinition::new("Wheels", 0x120, 2);
+    message.signals.push(speed);
+    message.signals.push(SignalDefinition::new("Gear", Some(15), 8));
+
+    ElpisMessages::from_definitions(vec![message])
+}
+
+#[test]
+fn stream_decoded_frames() {
+    let messages = live_test_messages();
+    // A known frame followed by an unknown one
+    let datagram = [
+        0, 0, 1, 0x20, 0, 0, 0, 2, 10, 3,
+        0, 0, 0, 0x99, 0, 0, 0, 1, 0xff,
+    ];
+
+    let mut decoder = LiveDecoder::new(&messages, Vec::new(), StreamFormat::Jsonl);
+    assert_eq!(decoder.header(), None);
+    let line: serde_json::Value = serde_json::from_str(decoder.decode_datagram(1.5, &datagram).trim()).unwrap();
+    assert_eq!(line["id"], 0x120);
+    assert_eq!(line["name"], "Wheels");
+    assert_eq!(line["signals"]["Speed"], 5.0);
+    assert_eq!(line["signals"]["Gear"], 3.0);
+
+    let mut decoder = LiveDecoder::new(&messages, vec![String::from("Gear")], StreamFormat::Csv);
+    assert_eq!(decoder.header(), Some("timestamp,id,message,signal,raw,physical"));
+    assert_eq!(decoder.decode_datagram(1.5, &datagram), "1.500000,0x120,Wheels,Gear,3,3\n");
+
+    let mut decoder = LiveDecoder::new(&messages, Vec::new(), StreamFormat::Pretty);
+    assert_eq!(decoder.decode_datagram(1.5, &datagram), "1.500000 0x120 Wheels: Speed=5 Gear=3\n");
+    assert_eq!(decoder.stats().unknown_ids, 1);
+
+    // Frames outside of a datagram, as read from a simulator log
+    assert_eq!(decoder.decode_frame(1.6, 0x120, &[10, 3]), "1.600000 0x120 Wheels: Speed=5 Gear=3\n");
+    assert_eq!(decoder.decode_frame(1.6, 0x99, &[0xff]), "");
+    assert_eq!((decoder.stats().datagrams, decoder.stats().frames, decoder.stats().unknown_ids), (1, 4, 2));
+}
+
+#[test]
+fn count_malformed_datagrams() {
+    let messages = live_test_messages();
+    let mut decoder = LiveDecoder::new(&messages, Vec::new(), StreamFormat::Pretty);
+
+    // A good frame followed by one claiming more payload than the datagram holds
+    let truncated = [0, 0, 1, 0x20, 0, 0, 0, 2, 10, 3, 0, 0, 1, 0x20, 0, 0, 0, 9, 1];
+    assert_eq!(decoder.decode_datagram(2.0, &truncated).lines().count(), 1);
+    assert_eq!(decoder.decode_datagram(2.1, &[0, 1, 2]), "");
+
+    // The decoder keeps going after malformed datagrams
+    decoder.decode_datagram(2.2, &[0, 0, 1, 0x20, 0, 0, 0, 2, 10, 3]);
+
+    assert_eq!(
+        decoder.stats(),
+        &LiveStats {
+            datagrams: 3,
+            frames: 2,
+            unknown_ids: 0,
+            malformed_datagrams: 2,
+            decode_errors: 0,
+        }
+    );
+}
+
+#[test]
+fn stream_wide_signals() {
+    use crate::elpis::{wide_test_messages, wide_test_payload};
+
+    let messages = wide_test_messages();
+    let serial = 0xfedc_ba98_7654_3210_0123_4567u128;
+    let mut datagram = vec![0, 0, 3, 0, 0, 0, 0, 32];
+    datagram.extend(wide_test_payload(serial, 7, 1));
+
+    let mut decoder = LiveDecoder::new(&messages, Vec::new(), StreamFormat::Jsonl);
+    let line: serde_json::Value = serde_json::from_str(decoder.decode_datagram(1.0, &datagram).trim()).unwrap();
+    assert_eq!(line["signals"]["Serial"], "0xfedcba987654321001234567");
+    assert_eq!(line["signals"]["Digest"], "0x1");
+    assert_eq!(line["signals"]["Counter"], 7.0);
+    assert!(line["signals"].get("SerialNext").is_none());
+
+    let mut decoder = LiveDecoder::new(&messages, vec![String::from("Serial")], StreamFormat::Csv);
+    assert_eq!(
+        decoder.decode_datagram(1.0, &datagram),
+        format!("1.000000,0x300,WideIds,Serial,{},0xfedcba987654321001234567\n", serial)
+    );
+}
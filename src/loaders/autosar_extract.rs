@@ -0,0 +1,256 @@
+// AUTOSAR extracts, the subset of ARXML that Vector DaVinci Configurator exports with only the COM
+// stack elements. Each PDU carries its signals' layouts directly rather than through mappings:
+//
+//     <SYSTEM-SIGNAL-I-PDU>
+//       <SHORT-NAME>Wheels</SHORT-NAME>
+//       <IDENTIFIER>0x120</IDENTIFIER>
+//       <LENGTH>8</LENGTH>
+//       <I-SIGNALS>
+//         <I-SIGNAL>
+//           <SHORT-NAME>WheelSpeed</SHORT-NAME>
+//           <BIT-POSITION>0</BIT-POSITION>
+//           <DATA-LENGTH>16</DATA-LENGTH>
+//           <BYTE-ORDER>MOST-SIGNIFICANT-BYTE-LAST</BYTE-ORDER>
+//         </I-SIGNAL>
+//       </I-SIGNALS>
+//     </SYSTEM-SIGNAL-I-PDU>
+//
+// Multiplexed PDUs are COMPOSITE-I-PDUs, with a SELECTOR-FIELD laid out like a signal, a STATIC-PART
+// of signals always present and DYNAMIC-PARTs of signals present for one SELECTOR-FIELD-CODE.
+//
+// A PDU without its own IDENTIFIER takes the one of a PDU-TRIGGERING referring to it, and PDUs with
+// neither are skipped. DATA-LENGTH is in bits, LENGTH in bytes.
+
+use super::autosar_system::{check_unique_ids, load_signal_layout, reference_name};
+use super::xml::{parse_document, required_number, Element};
+use crate::elpis::{ElpisMessages, MessageDefinition, SignalDefinition};
+use anyhow::Context;
+use std::collections::HashMap;
+
+fn load_signals(part: &Element, pdu_name: &str) -> anyhow::Result<Vec<SignalDefinition>> {
+    part.find_all("I-SIGNAL")
+        .into_iter()
+        .map(|element| {
+            let name = element
+                .child_text("SHORT-NAME")
+                .with_context(|| format!("I-SIGNAL in {} has no SHORT-NAME", pdu_name))?;
+            load_signal_layout(element, name, "DATA-LENGTH", &format!("signal {}.{}", pdu_name, name))
+        })
+        .collect()
+}
+
+// Add the selector and the static and dynamic parts of a composite PDU to its message
+fn load_composite(pdu: &Element, message: &mut MessageDefinition) -> anyhow::Result<()> {
+    let selector = pdu
+        .child("SELECTOR-FIELD")
+        .with_context(|| format!("COMPOSITE-I-PDU {} has no SELECTOR-FIELD", message.name))?;
+    let selector_name = selector.child_text("SHORT-NAME").unwrap_or("Selector").to_string();
+    let mut selector_signal =
+        load_signal_layout(selector, &selector_name, "DATA-LENGTH", &format!("selector of {}", message.name))?;
+    selector_signal.is_multiplexer = Some(true);
+    message.signals.push(selector_signal);
+
+    if let Some(part) = pdu.child("STATIC-PART") {
+        message.signals.extend(load_signals(part, &message.name)?);
+    }
+
+    // A signal present for several codes is listed once with all of them
+    let mut dynamic: Vec<(SignalDefinition, Vec<i32>)> = Vec::new();
+    for part in pdu.find_all("DYNAMIC-PART") {
+        let code = required_number(part, "SELECTOR-FIELD-CODE", &format!("dynamic part of {}", message.name))?;
+        for signal in load_signals(part, &message.name)? {
+            let layout = |signal: &SignalDefinition| (signal.start, signal.length, signal.is_big_endian);
+            match dynamic.iter_mut().find(|(other, _)| other.name == signal.name) {
+                Some((other, codes)) if layout(other) == layout(&signal) => codes.push(code),
+                Some(_) => {
+                    return Err(anyhow::anyhow!(
+                        "Signal {}.{} is laid out differently for selector code {}",
+                        message.name,
+                        signal.name,
+                        code
+                    ))
+                }
+                None => dynamic.push((signal, vec![code])),
+            }
+        }
+    }
+
+    for (mut signal, codes) in dynamic {
+        signal.multiplexer_signal = Some(selector_name.clone());
+        signal.multiplexer_ids = Some(serde_json::Value::from(codes));
+        message.signals.push(signal);
+    }
+    Ok(())
+}
+
+impl ElpisMessages {
+    // Load ELPIS messages from the PDUs of an AUTOSAR extract exported by DaVinci Configurator
+    pub fn load_from_autosar_extract(xml: &str) -> anyhow::Result<Self> {
+        let document = parse_document(xml).context("Could not parse AUTOSAR extract")?;
+
+        // Ids of PDUs routed by a triggering, keyed by PDU name
+        let mut routed: HashMap<&str, i32> = HashMap::new();
+        for triggering in document.find_all("PDU-TRIGGERING") {
+            let pdu = triggering
+                .child_text("I-PDU-REF")
+                .context("PDU triggering has no I-PDU-REF")?;
+            let id = required_number(triggering, "IDENTIFIER", &format!("triggering of {}", pdu))?;
+            routed.insert(reference_name(pdu), id);
+        }
+
+        let mut definitions = Vec::new();
+        let mut pdus = document.find_all("SYSTEM-SIGNAL-I-PDU");
+        pdus.extend(document.find_all("COMPOSITE-I-PDU"));
+        for pdu in pdus {
+            let name = pdu
+                .child_text("SHORT-NAME")
+                .with_context(|| format!("{} has no SHORT-NAME", pdu.name))?;
+            let id = match pdu.child("IDENTIFIER") {
+                Some(_) => required_number(pdu, "IDENTIFIER", name)?,
+                None => match routed.get(name) {
+                    Some(id) => *id,
+                    None => continue,
+                },
+            };
+
+            let mut message = MessageDefinition::new(name, id, required_number(pdu, "LENGTH", name)?);
+            if pdu.name == "COMPOSITE-I-PDU" {
+                load_composite(pdu, &mut message)?;
+            } else {
+                message.signals = load_signals(pdu, name)?;
+            }
+            definitions.push(message);
+        }
+
+        check_unique_ids(&definitions)?;
+        Self::from_loaded_definitions(definitions)
+    }
+}
+
+#[cfg(test)]
+const TEST_EXTRACT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<AUTOSAR xmlns="http://autosar.org/schema/r4.0">
+  <AR-PACKAGES>
+    <AR-PACKAGE>
+      <SHORT-NAME>ComExtract</SHORT-NAME>
+      <ELEMENTS>
+        <SYSTEM-SIGNAL-I-PDU>
+          <SHORT-NAME>Wheels</SHORT-NAME>
+          <IDENTIFIER>0x120</IDENTIFIER>
+          <LENGTH>4</LENGTH>
+          <I-SIGNALS>
+            <I-SIGNAL>
+              <SHORT-NAME>WheelSpeed</SHORT-NAME>
+              <BIT-POSITION>0</BIT-POSITION>
+              <DATA-LENGTH>16</DATA-LENGTH>
+              <BYTE-ORDER>MOST-SIGNIFICANT-BYTE-LAST</BYTE-ORDER>
+            </I-SIGNAL>
+            <I-SIGNAL>
+              <SHORT-NAME>Torque</SHORT-NAME>
+              <BIT-POSITION>24</BIT-POSITION>
+              <DATA-LENGTH>12</DATA-LENGTH>
+              <BYTE-ORDER>MOST-SIGNIFICANT-BYTE-FIRST</BYTE-ORDER>
+            </I-SIGNAL>
+          </I-SIGNALS>
+        </SYSTEM-SIGNAL-I-PDU>
+        <COMPOSITE-I-PDU>
+          <SHORT-NAME>Battery</SHORT-NAME>
+          <LENGTH>4</LENGTH>
+          <SELECTOR-FIELD>
+            <SHORT-NAME>Page</SHORT-NAME>
+            <BIT-POSITION>0</BIT-POSITION>
+            <DATA-LENGTH>8</DATA-LENGTH>
+          </SELECTOR-FIELD>
+          <STATIC-PART>
+            <I-SIGNALS>
+              <I-SIGNAL>
+                <SHORT-NAME>Alive</SHORT-NAME>
+                <BIT-POSITION>8</BIT-POSITION>
+                <DATA-LENGTH>4</DATA-LENGTH>
+              </I-SIGNAL>
+            </I-SIGNALS>
+          </STATIC-PART>
+          <DYNAMIC-PARTS>
+            <DYNAMIC-PART>
+              <SELECTOR-FIELD-CODE>1</SELECTOR-FIELD-CODE>
+              <I-SIGNALS>
+                <I-SIGNAL>
+                  <SHORT-NAME>CellVoltage</SHORT-NAME>
+                  <BIT-POSITION>16</BIT-POSITION>
+                  <DATA-LENGTH>16</DATA-LENGTH>
+                </I-SIGNAL>
+              </I-SIGNALS>
+            </DYNAMIC-PART>
+            <DYNAMIC-PART>
+              <SELECTOR-FIELD-CODE>2</SELECTOR-FIELD-CODE>
+              <I-SIGNALS>
+                <I-SIGNAL>
+                  <SHORT-NAME>CellVoltage</SHORT-NAME>
+                  <BIT-POSITION>16</BIT-POSITION>
+                  <DATA-LENGTH>16</DATA-LENGTH>
+                </I-SIGNAL>
+                <I-SIGNAL>
+                  <SHORT-NAME>CellTemp</SHORT-NAME>
+                  <BIT-POSITION>12</BIT-POSITION>
+                  <DATA-LENGTH>4</DATA-LENGTH>
+                </I-SIGNAL>
+              </I-SIGNALS>
+            </DYNAMIC-PART>
+          </DYNAMIC-PARTS>
+        </COMPOSITE-I-PDU>
+        <SYSTEM-SIGNAL-I-PDU>
+          <SHORT-NAME>Unrouted</SHORT-NAME>
+          <LENGTH>8</LENGTH>
+        </SYSTEM-SIGNAL-I-PDU>
+        <PDU-TRIGGERING>
+          <I-PDU-REF DEST="COMPOSITE-I-PDU">/ComExtract/Battery</I-PDU-REF>
+          <IDENTIFIER>0x3a0</IDENTIFIER>
+        </PDU-TRIGGERING>
+      </ELEMENTS>
+    </AR-PACKAGE>
+  </AR-PACKAGES>
+</AUTOSAR>"#;
+
+#[test]
+fn autosar_extract_layout() {
+    let messages = ElpisMessages::load_from_autosar_extract(TEST_EXTRACT).unwrap();
+    assert_eq!(messages.get_messagedef_count(), 2);
+
+    let wheels = messages.get_def_by_id(0x120).unwrap();
+    let layout: Vec<(&str, Option<i32>, i32, bool)> = wheels
+        .signals
+        .iter()
+        .map(|signal| (signal.name.as_str(), signal.start, signal.length, signal.is_big_endian))
+        .collect();
+    assert_eq!(layout, vec![("WheelSpeed", Some(0), 16, false), ("Torque", Some(19), 12, true)]);
+    let decoded = wheels.decode(&[0x34, 0x12, 0x0a, 0xbc]);
+    assert_eq!((decoded.signals[0].raw, decoded.signals[1].raw), (0x1234, 0xabc));
+
+    // The composite PDU is routed by its triggering, with the selector, static and dynamic signals
+    let battery = messages.get_def_by_id(0x3a0).unwrap();
+    let names: Vec<&str> = battery.signals.iter().map(|signal| signal.name.as_str()).collect();
+    assert_eq!(names, vec!["Page", "Alive", "CellVoltage", "CellTemp"]);
+    assert_eq!(battery.signals[0].is_multiplexer, Some(true));
+    assert_eq!(battery.signals[1].multiplexer_signal, None);
+    let voltage = battery.get_signal_by_name("CellVoltage").unwrap();
+    assert_eq!(voltage.multiplexer_signal.as_deref(), Some("Page"));
+    assert_eq!(voltage.multiplexer_ids, Some(serde_json::json!([1, 2])));
+    assert_eq!(battery.get_signal_by_name("CellTemp").unwrap().multiplexer_ids, Some(serde_json::json!([2])));
+}
+
+#[test]
+fn autosar_rejects_bad_extracts() {
+    let missing_length = TEST_EXTRACT.replace("<DATA-LENGTH>12</DATA-LENGTH>", "");
+    let error = format!("{:#}", ElpisMessages::load_from_autosar_extract(&missing_length).err().unwrap());
+    assert_eq!(error, "signal Wheels.Torque has no DATA-LENGTH");
+
+    let moved = TEST_EXTRACT.replacen("<BIT-POSITION>16</BIT-POSITION>", "<BIT-POSITION>20</BIT-POSITION>", 1);
+    let error = format!("{:#}", ElpisMessages::load_from_autosar_extract(&moved).err().unwrap());
+    assert_eq!(error, "Signal Battery.CellVoltage is laid out differently for selector code 2");
+
+    let no_selector = TEST_EXTRACT.replace("SELECTOR-FIELD>", "SELECTOR>");
+    assert!(ElpisMessages::load_from_autosar_extract(&no_selector).is_err());
+
+    let duplicate_id = TEST_EXTRACT.replace("0x3a0", "0x120");
+    assert!(ElpisMessages::load_from_autosar_extract(&duplicate_id).is_err());
+}
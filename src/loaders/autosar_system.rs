@@ -0,0 +1,220 @@
+// AUTOSAR system template XML, the communication matrix exported by AUTOSAR toolchains. Only the
+// subset describing PDU layouts is read:
+//
+//     <SYSTEM>
+//       <FIBEX-ELEMENTS>
+//         <I-SIGNAL-I-PDU>
+//           <SHORT-NAME>Wheels</SHORT-NAME>
+//           <LENGTH>8</LENGTH>
+//           <I-SIGNAL-TO-PDU-MAPPINGS>
+//             <I-SIGNAL-TO-I-PDU-MAPPING>
+//               <COM-SIGNAL-REF DEST="I-SIGNAL">/Signals/WheelSpeed</COM-SIGNAL-REF>
+//               <BIT-POSITION>0</BIT-POSITION>
+//               <LENGTH>16</LENGTH>
+//               <BYTE-ORDER>MOST-SIGNIFICANT-BYTE-LAST</BYTE-ORDER>
+//             </I-SIGNAL-TO-I-PDU-MAPPING>
+//           </I-SIGNAL-TO-PDU-MAPPINGS>
+//         </I-SIGNAL-I-PDU>
+//         <PDU-TRIGGERING>
+//           <I-PDU-REF DEST="I-SIGNAL-I-PDU">/Pdus/Wheels</I-PDU-REF>
+//           <IDENTIFIER>0x120</IDENTIFIER>
+//         </PDU-TRIGGERING>
+//       </FIBEX-ELEMENTS>
+//     </SYSTEM>
+//
+// A PDU's ELPIS id is the identifier of the PDU-TRIGGERING or CONTAINED-I-PDU-TRIGGERING routing it,
+// PDUs that aren't routed anywhere are skipped. References are matched by their last path segment.
+// Like AUTOSAR COM, BIT-POSITION is the least significant bit for both byte orders.
+
+use super::xml::{motorola_msb, parse_document, required_number, Element};
+use crate::elpis::{ElpisMessages, MessageDefinition, SignalDefinition};
+use anyhow::Context;
+use std::collections::HashMap;
+
+// Get the last segment of a reference path such as /Signals/WheelSpeed
+pub(super) fn reference_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn load_signal(mapping: &Element, pdu_name: &str) -> anyhow::Result<SignalDefinition> {
+    let name = match mapping.child_text("COM-SIGNAL-REF") {
+        Some(path) => reference_name(path),
+        None => mapping
+            .child_text("SHORT-NAME")
+            .with_context(|| format!("Signal mapping in {} has no COM-SIGNAL-REF or SHORT-NAME", pdu_name))?,
+    };
+    load_signal_layout(mapping, name, "LENGTH", &format!("signal {}.{}", pdu_name, name))
+}
+
+// Build a signal from the BIT-POSITION, BYTE-ORDER and length in bits given by an element, the
+// length under length_tag
+pub(super) fn load_signal_layout(element: &Element, name: &str, length_tag: &str, owner: &str) -> anyhow::Result<SignalDefinition> {
+    let position = required_number(element, "BIT-POSITION", owner)?;
+    let length = required_number(element, length_tag, owner)?;
+    let is_big_endian = match element.child_text("BYTE-ORDER") {
+        Some("MOST-SIGNIFICANT-BYTE-FIRST") => true,
+        Some("MOST-SIGNIFICANT-BYTE-LAST") | Some("OPAQUE") | None => false,
+        Some(other) => return Err(anyhow::anyhow!("Unknown BYTE-ORDER {} of {}", other, owner)),
+    };
+
+    let start = if is_big_endian { motorola_msb(position, length) } else { position };
+    let mut signal = SignalDefinition::new(name, Some(start), length);
+    signal.is_big_endian = is_big_endian;
+    Ok(signal)
+}
+
+// Ensure no two PDUs share an id, only one of them could ever be decoded
+pub(super) fn check_unique_ids(definitions: &[MessageDefinition]) -> anyhow::Result<()> {
+    let mut seen = HashMap::new();
+    for message in definitions.iter() {
+        if let Some(other) = seen.insert(message.id, message.name.as_str()) {
+            return Err(anyhow::anyhow!("PDUs {} and {} are both routed as id {:#x}", other, message.name, message.id));
+        }
+    }
+    Ok(())
+}
+
+impl ElpisMessages {
+    // Load ELPIS messages from the PDUs of an AUTOSAR system template
+    pub fn load_from_autosar_system_template(xml: &str) -> anyhow::Result<Self> {
+        let document = parse_document(xml).context("Could not parse system template")?;
+
+        let mut definitions = Vec::new();
+        for system in document.find_all("SYSTEM") {
+            let Some(elements) = system.child("FIBEX-ELEMENTS") else {
+                continue;
+            };
+
+            // Ids of the PDUs routed by this system, keyed by PDU name
+            let mut ids: HashMap<&str, i32> = HashMap::new();
+            let mut triggerings = elements.find_all("PDU-TRIGGERING");
+            triggerings.extend(elements.find_all("CONTAINED-I-PDU-TRIGGERING"));
+            for triggering in triggerings {
+                let pdu = triggering
+                    .child_text("I-PDU-REF")
+                    .context("PDU triggering has no I-PDU-REF")?;
+                let id = required_number(triggering, "IDENTIFIER", &format!("triggering of {}", pdu))?;
+                ids.insert(reference_name(pdu), id);
+            }
+
+            for pdu in elements.find_all("I-SIGNAL-I-PDU") {
+                let name = pdu.child_text("SHORT-NAME").context("I-SIGNAL-I-PDU has no SHORT-NAME")?;
+                let Some(id) = ids.get(name) else {
+                    continue;
+                };
+
+                let length = required_number(pdu, "LENGTH", name)?;
+                let mut message = MessageDefinition::new(name, *id, length);
+                for mapping in pdu.find_all("I-SIGNAL-TO-I-PDU-MAPPING") {
+                    message.signals.push(load_signal(mapping, name)?);
+                }
+                definitions.push(message);
+            }
+        }
+
+        check_unique_ids(&definitions)?;
+        Self::from_loaded_definitions(definitions)
+    }
+}
+
+#[cfg(test)]
+const TEST_SYSTEM_TEMPLATE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<AUTOSAR xmlns="http://autosar.org/schema/r4.0">
+  <AR-PACKAGES>
+    <AR-PACKAGE>
+      <SHORT-NAME>Vehicle</SHORT-NAME>
+      <ELEMENTS>
+        <SYSTEM>
+          <SHORT-NAME>Chassis</SHORT-NAME>
+          <FIBEX-ELEMENTS>
+            <I-SIGNAL-I-PDU>
+              <SHORT-NAME>Wheels</SHORT-NAME>
+              <LENGTH>4</LENGTH>
+              <I-SIGNAL-TO-PDU-MAPPINGS>
+                <I-SIGNAL-TO-I-PDU-MAPPING>
+                  <SHORT-NAME>WheelSpeed_Mapping</SHORT-NAME>
+                  <COM-SIGNAL-REF DEST="I-SIGNAL">/Vehicle/Signals/WheelSpeed</COM-SIGNAL-REF>
+                  <BIT-POSITION>0</BIT-POSITION>
+                  <LENGTH>16</LENGTH>
+                  <BYTE-ORDER>MOST-SIGNIFICANT-BYTE-LAST</BYTE-ORDER>
+                </I-SIGNAL-TO-I-PDU-MAPPING>
+                <I-SIGNAL-TO-I-PDU-MAPPING>
+                  <COM-SIGNAL-REF DEST="I-SIGNAL">/Vehicle/Signals/Torque</COM-SIGNAL-REF>
+                  <BIT-POSITION>24</BIT-POSITION>
+                  <LENGTH>12</LENGTH>
+                  <BYTE-ORDER>MOST-SIGNIFICANT-BYTE-FIRST</BYTE-ORDER>
+                </I-SIGNAL-TO-I-PDU-MAPPING>
+              </I-SIGNAL-TO-PDU-MAPPINGS>
+            </I-SIGNAL-I-PDU>
+            <I-SIGNAL-I-PDU>
+              <SHORT-NAME>Diag</SHORT-NAME>
+              <LENGTH>1</LENGTH>
+              <I-SIGNAL-TO-PDU-MAPPINGS>
+                <I-SIGNAL-TO-I-PDU-MAPPING>
+                  <SHORT-NAME>Status</SHORT-NAME>
+                  <BIT-POSITION>0</BIT-POSITION>
+                  <LENGTH>8</LENGTH>
+                </I-SIGNAL-TO-I-PDU-MAPPING>
+              </I-SIGNAL-TO-PDU-MAPPINGS>
+            </I-SIGNAL-I-PDU>
+            <I-SIGNAL-I-PDU>
+              <SHORT-NAME>Unrouted</SHORT-NAME>
+              <LENGTH>8</LENGTH>
+            </I-SIGNAL-I-PDU>
+            <PDU-TRIGGERING>
+              <I-PDU-REF DEST="I-SIGNAL-I-PDU">/Vehicle/Pdus/Wheels</I-PDU-REF>
+              <IDENTIFIER>0x120</IDENTIFIER>
+            </PDU-TRIGGERING>
+            <CONTAINED-I-PDU-TRIGGERING>
+              <I-PDU-REF DEST="I-SIGNAL-I-PDU">/Vehicle/Pdus/Diag</I-PDU-REF>
+              <IDENTIFIER>42</IDENTIFIER>
+            </CONTAINED-I-PDU-TRIGGERING>
+          </FIBEX-ELEMENTS>
+        </SYSTEM>
+      </ELEMENTS>
+    </AR-PACKAGE>
+  </AR-PACKAGES>
+</AUTOSAR>"#;
+
+#[test]
+fn autosar_system_template_layout() {
+    let messages = ElpisMessages::load_from_autosar_system_template(TEST_SYSTEM_TEMPLATE).unwrap();
+    assert_eq!(messages.get_messagedef_count(), 2);
+
+    let wheels = messages.get_def_by_id(0x120).unwrap();
+    assert_eq!(wheels.name, "Wheels");
+    assert_eq!(wheels.length, 4);
+    let layout: Vec<(&str, Option<i32>, i32, bool)> = wheels
+        .signals
+        .iter()
+        .map(|signal| (signal.name.as_str(), signal.start, signal.length, signal.is_big_endian))
+        .collect();
+    // The big endian torque has its least significant bit at 24, so its most significant is bit 19
+    assert_eq!(layout, vec![("WheelSpeed", Some(0), 16, false), ("Torque", Some(19), 12, true)]);
+
+    let decoded = wheels.decode(&[0x34, 0x12, 0x0a, 0xbc]);
+    assert!(decoded.errors.is_empty(), "{:?}", decoded.errors);
+    assert_eq!(decoded.signals[0].raw, 0x1234);
+    assert_eq!(decoded.signals[1].raw, 0xabc);
+
+    // Contained PDUs are routed by their own triggering, and mappings without a reference use their name
+    let diag = messages.get_def_by_id(42).unwrap();
+    assert_eq!(diag.signals[0].name, "Status");
+    assert!(!diag.signals[0].is_big_endian);
+}
+
+#[test]
+fn autosar_rejects_bad_templates() {
+    assert!(ElpisMessages::load_from_autosar_system_template("<AUTOSAR><SYSTEM></AUTOSAR>").is_err());
+
+    let bad_byte_order = TEST_SYSTEM_TEMPLATE.replace("MOST-SIGNIFICANT-BYTE-FIRST", "SIDEWAYS");
+    let error = format!("{:#}", ElpisMessages::load_from_autosar_system_template(&bad_byte_order).err().unwrap());
+    assert_eq!(error, "Unknown BYTE-ORDER SIDEWAYS of signal Wheels.Torque");
+
+    let duplicate_id = TEST_SYSTEM_TEMPLATE.replace("<IDENTIFIER>42</IDENTIFIER>", "<IDENTIFIER>0x120</IDENTIFIER>");
+    assert!(ElpisMessages::load_from_autosar_system_template(&duplicate_id).is_err());
+
+    let missing_length = TEST_SYSTEM_TEMPLATE.replace("<LENGTH>12</LENGTH>", "");
+    let error = format!("{:#}", ElpisMessages::load_from_autosar_system_template(&missing_length).err().unwrap());
+    assert_eq!(error, "signal Wheels.Torque has no LENGTH");
+}
@@ -0,0 +1,163 @@
+// JSON exported by python-canmatrix (`canconvert db.dbc db.json`). Messages are keyed by their
+// decimal id:
+//
+//     { "messages": { "288": { "name": "Wheels", "length": 8, "signals": [
+//         { "name": "Speed", "start_bit": 0, "length": 16, "factor": 0.01, "offset": 0,
+//           "min": 0, "max": 655.35, "unit": "km/h", "is_little_endian": true } ] } } }
+//
+// `start_bit` is the DBC start bit, the most significant bit in Motorola numbering for big endian
+// signals. Keys other than the ones below are ignored, the export carries plenty the dissector
+// doesn't need.
+
+use crate::elpis::{ElpisMessages, MessageDefinition, SignalDefinition};
+#[cfg(not(target_arch = "wasm32"))]
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+fn default_factor() -> f64 {
+    1.0
+}
+
+fn default_little_endian() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct CanmatrixSignal {
+    name: String,
+    start_bit: i32,
+    length: i32,
+    #[serde(default = "default_factor")]
+    factor: f64,
+    #[serde(default)]
+    offset: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+    unit: Option<String>,
+    #[serde(default = "default_little_endian")]
+    is_little_endian: bool,
+    #[serde(default)]
+    is_signed: bool,
+    comment: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CanmatrixMessage {
+    name: String,
+    length: i32,
+    comment: Option<String>,
+    #[serde(default)]
+    signals: Vec<CanmatrixSignal>,
+}
+
+#[derive(Deserialize)]
+struct CanmatrixExport {
+    messages: BTreeMap<String, CanmatrixMessage>,
+}
+
+impl CanmatrixSignal {
+    fn into_signal(self) -> SignalDefinition {
+        let mut signal = SignalDefinition::new(&self.name, Some(self.start_bit), self.length);
+        signal.is_big_endian = !self.is_little_endian;
+        signal.is_signed = Some(self.is_signed);
+        signal.scale = Some(self.factor);
+        signal.offset = self.offset;
+        if let Some(min) = self.min {
+            signal.minimum = min;
+        }
+        if let Some(max) = self.max {
+            signal.maximum = max;
+        }
+        signal.unit = self.unit.filter(|unit| !unit.is_empty());
+        signal.comment = self.comment;
+        signal
+    }
+}
+
+impl ElpisMessages {
+    // Load ELPIS messages from the given path to a python-canmatrix JSON export
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_canmatrix_json(path: &str) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("Could not open file {}", path))?;
+        let mut messages = Self::from_canmatrix_json(&contents).with_context(|| format!("Could not parse canmatrix file {}", path))?;
+        messages.set_source(path);
+        Ok(messages)
+    }
+
+    // Parse ELPIS messages from the contents of a python-canmatrix JSON export
+    pub fn from_canmatrix_json(contents: &str) -> anyhow::Result<Self> {
+        let export: CanmatrixExport = serde_json::from_str(contents)?;
+
+        let mut definitions = Vec::new();
+        for (id, message) in export.messages {
+            let id: u32 = id
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Message {} has an invalid id \"{}\"", message.name, id))?;
+
+            let mut definition = MessageDefinition::new(&message.name, id as i32, message.length);
+            definition.comment = message.comment;
+            definition.signals = message.signals.into_iter().map(CanmatrixSignal::into_signal).collect();
+            definitions.push(definition);
+        }
+
+        Self::from_loaded_definitions(definitions)
+    }
+}
+
+#[test]
+fn canmatrix_json_layout() {
+    let contents = r#"{
+        "messages": {
+            "288": {
+                "name": "Wheels",
+                "length": 4,
+                "is_extended_frame": false,
+                "comment": "Wheel speeds",
+                "signals": [
+                    { "name": "Speed", "start_bit": 0, "length": 16, "factor": 0.01, "offset": 0,
+                      "min": 0, "max": 655.35, "unit": "km/h", "is_little_endian": true },
+                    { "name": "Temp", "start_bit": 23, "length": 8, "factor": 1, "offset": -40,
+                      "unit": "", "is_little_endian": false, "is_signed": false },
+                    { "name": "Steering", "start_bit": 24, "length": 8, "is_signed": true }
+                ]
+            },
+            "1": { "name": "Empty", "length": 0, "signals": [] }
+        }
+    }"#;
+
+    let messages = ElpisMessages::from_canmatrix_json(contents).unwrap();
+    assert_eq!(messages.get_messagedef_count(), 2);
+
+    let wheels = messages.get_def_by_id(288).unwrap();
+    assert_eq!(wheels.name, "Wheels");
+    assert_eq!(wheels.length, 4);
+    assert_eq!(wheels.comment.as_deref(), Some("Wheel speeds"));
+
+    let speed = wheels.get_signal_by_name("Speed").unwrap();
+    assert!(!speed.is_big_endian);
+    assert_eq!(speed.scale, Some(0.01));
+    assert_eq!(speed.maximum, 655.35);
+    assert_eq!(speed.unit.as_deref(), Some("km/h"));
+
+    let temp = wheels.get_signal_by_name("Temp").unwrap();
+    assert!(temp.is_big_endian);
+    assert_eq!(temp.unit, None);
+
+    // Values decode with the factor and offset applied
+    let decoded = wheels.decode(&[0x10, 0x27, 60, 0xfe]);
+    assert!(decoded.errors.is_empty(), "{:?}", decoded.errors);
+    let physical: Vec<f64> = decoded.signals.iter().map(|signal| signal.physical).collect();
+    assert_eq!(physical, vec![100.0, 20.0, -2.0]);
+}
+
+#[test]
+fn canmatrix_json_rejects_bad_exports() {
+    assert!(ElpisMessages::from_canmatrix_json(r#"{ "messages": { "0x120": { "name": "A", "length": 1 } } }"#).is_err());
+    assert!(ElpisMessages::from_canmatrix_json(r#"{ "messages": { "-1": { "name": "A", "length": 1 } } }"#).is_err());
+    assert!(ElpisMessages::from_canmatrix_json(r#"{ "frames": [] }"#).is_err());
+    assert!(ElpisMessages::from_canmatrix_json(
+        r#"{ "messages": { "1": { "name": "A", "length": 1, "signals": [{ "name": "S", "length": 8 }] } } }"#
+    )
+    .is_err());
+}
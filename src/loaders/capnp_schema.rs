@@ -0,0 +1,289 @@
+// Cap'n Proto schemas, as the CodeGeneratorRequest that `capnp compile -o-` writes. Structs become
+// messages, and the fields of their data section become little endian signals at the exact bit
+// position Cap'n Proto lays them out at.
+//
+// Only structs annotated with an `elpisId` annotation are loaded, e.g.
+//
+//     annotation elpisId(struct) :UInt32;
+//     struct Wheels $elpisId(0x120) { speed @0 :UInt16; valid @1 :Bool; }
+//
+// Pointer fields (text, data, lists and structs) live outside the data section and are skipped.
+// Fields of groups are named `<group>_<field>`, and a union's discriminant becomes a `<union>_which`
+// signal. Fields with explicit defaults are rejected, Cap'n Proto stores them XORed with the default.
+
+use crate::elpis::{ElpisMessages, MessageDefinition, SignalDefinition};
+use anyhow::Context;
+use capnp::schema_capnp::{code_generator_request, field, node, type_, value};
+use std::collections::HashMap;
+
+// Name of the annotation giving a struct's ELPIS message id
+const ID_ANNOTATION: &str = "elpisId";
+
+// Length in bits, signedness and whether the type is a float, None for types outside the data section
+fn slot_layout(slot_type: type_::Reader) -> anyhow::Result<Option<(i32, bool, bool)>> {
+    Ok(Some(match slot_type.which()? {
+        type_::Which::Bool(()) => (1, false, false),
+        type_::Which::Int8(()) => (8, true, false),
+        type_::Which::Int16(()) => (16, true, false),
+        type_::Which::Int32(()) => (32, true, false),
+        type_::Which::Int64(()) => (64, true, false),
+        type_::Which::Uint8(()) => (8, false, false),
+        type_::Which::Uint16(()) => (16, false, false),
+        type_::Which::Uint32(()) => (32, false, false),
+        type_::Which::Uint64(()) => (64, false, false),
+        type_::Which::Float32(()) => (32, false, true),
+        type_::Which::Float64(()) => (64, false, true),
+        type_::Which::Enum(_) => (16, false, false),
+        _ => return Ok(None),
+    }))
+}
+
+// Get the name of a node without the file and scope prefix
+fn node_name(node: node::Reader) -> anyhow::Result<String> {
+    let display_name = node.get_display_name()?.to_str()?;
+    Ok(display_name[node.get_display_name_prefix_length() as usize..].to_string())
+}
+
+// Add the signals of a struct or group node, prefixing their names for groups
+fn collect_signals(
+    nodes: &HashMap<u64, node::Reader>,
+    node_id: u64,
+    prefix: &str,
+    signals: &mut Vec<SignalDefinition>,
+) -> anyhow::Result<()> {
+    let node = nodes
+        .get(&node_id)
+        .with_context(|| format!("Schema refers to unknown node {:#x}", node_id))?;
+    let node::Which::Struct(struct_node) = node.which()? else {
+        return Err(anyhow::anyhow!("Node {:#x} is not a struct", node_id));
+    };
+
+    // Discriminants are always 16 bits, with the offset counted in 16-bit units
+    if struct_node.get_discriminant_count() > 0 {
+        let start = struct_node.get_discriminant_offset() as i32 * 16;
+        let mut signal = SignalDefinition::new(&format!("{}which", prefix), Some(start), 16);
+        signal.is_big_endian = false;
+        signals.push(signal);
+    }
+
+    for field in struct_node.get_fields()?.iter() {
+        let name = format!("{}{}", prefix, field.get_name()?.to_str()?);
+
+        match field.which()? {
+            field::Which::Slot(slot) => {
+                let Some((length, is_signed, is_float)) = slot_layout(slot.get_type()?)? else {
+                    continue;
+                };
+
+                if slot.get_had_explicit_default() {
+                    return Err(anyhow::anyhow!("Field {} has an explicit default, which can't be decoded", name));
+                }
+
+                // Offsets are counted in multiples of the field's own size
+                let start = slot.get_offset() as i32 * length;
+                let mut signal = SignalDefinition::new(&name, Some(start), length);
+                signal.is_big_endian = false;
+                signal.is_signed = Some(is_signed);
+                signal.is_float = Some(is_float);
+                signals.push(signal);
+            }
+            field::Which::Group(group) => {
+                collect_signals(nodes, group.get_type_id(), &format!("{}_", name), signals)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl ElpisMessages {
+    // Load ELPIS messages from a compiled Cap'n Proto schema
+    pub fn load_from_capnp(schema: &[u8]) -> anyhow::Result<Self> {
+        let reader = capnp::serialize::read_message_from_flat_slice(&mut &schema[..], capnp::message::ReaderOptions::new())
+            .context("Could not read compiled schema")?;
+        let request = reader.get_root::<code_generator_request::Reader>()?;
+
+        let nodes: HashMap<u64, node::Reader> = request.get_nodes()?.iter().map(|node| (node.get_id(), node)).collect();
+
+        // Find the annotation giving message ids, wherever it was declared
+        let mut id_annotations = Vec::new();
+        for node in nodes.values() {
+            if let node::Which::Annotation(_) = node.which()? {
+                if node_name(*node)? == ID_ANNOTATION {
+                    id_annotations.push(node.get_id());
+                }
+            }
+        }
+
+        let mut definitions = Vec::new();
+        for node in nodes.values() {
+            let node::Which::Struct(struct_node) = node.which()? else {
+                continue;
+            };
+
+            let mut id = None;
+            for annotation in node.get_annotations()?.iter() {
+                if id_annotations.contains(&annotation.get_id()) {
+                    let value::Which::Uint32(value) = annotation.get_value()?.which()? else {
+                        return Err(anyhow::anyhow!("{} of {} must be a UInt32", ID_ANNOTATION, node_name(*node)?));
+                    };
+                    id = Some(value as i32);
+                }
+            }
+            let Some(id) = id else {
+                continue;
+            };
+
+            let name = node_name(*node)?;
+            let mut message = MessageDefinition::new(&name, id, struct_node.get_data_word_count() as i32 * 8);
+            collect_signals(&nodes, node.get_id(), "", &mut message.signals)
+                .with_context(|| format!("Could not load struct {}", name))?;
+            definitions.push(message);
+        }
+
+        Self::from_loaded_definitions(definitions)
+    }
+}
+
+// Builds the compiled form of this schema, as `capnp compile -o-` would write it:
+//
+//     annotation elpisId(struct) :UInt32;
+//     struct Wheels $elpisId(0x120) {
+//       speed @0 :UInt16;
+//       valid @1 :Bool;
+//       temp @2 :Int8;
+//       odometer @3 :Float64;
+//       label @4 :Text;
+//       status :union { ok @5 :Void; fault @6 :UInt32; }
+//     }
+//     struct Unannotated { x @0 :UInt8; }
+#[cfg(test)]
+fn compiled_test_schema() -> Vec<u8> {
+    let mut message = capnp::message::Builder::new_default();
+    let request = message.init_root::<code_generator_request::Builder>();
+    let mut nodes = request.init_nodes(4);
+
+    let mut annotation = nodes.reborrow().get(0);
+    annotation.set_id(0xa0);
+    annotation.set_display_name("test.capnp:elpisId");
+    annotation.set_display_name_prefix_length(11);
+    annotation.init_annotation().set_targets_struct(true);
+
+    let mut wheels = nodes.reborrow().get(1);
+    wheels.set_id(0xb0);
+    wheels.set_display_name("test.capnp:Wheels");
+    wheels.set_display_name_prefix_length(11);
+    {
+        let mut annotation = wheels.reborrow().init_annotations(1).get(0);
+        annotation.set_id(0xa0);
+        annotation.init_value().set_uint32(0x120);
+    }
+    {
+        let mut wheels = wheels.init_struct();
+        // speed at bits 0..16, valid at bit 16, temp at bits 24..32, status discriminant at bits
+        // 32..48, odometer in the second word, fault in the third
+        wheels.set_data_word_count(3);
+        wheels.set_pointer_count(1);
+        let mut fields = wheels.init_fields(6);
+        let slots: [(&str, u32, fn(type_::Builder)); 5] = [
+            ("speed", 0, |mut t| t.set_uint16(())),
+            ("valid", 16, |mut t| t.set_bool(())),
+            ("temp", 3, |mut t| t.set_int8(())),
+            ("odometer", 1, |mut t| t.set_float64(())),
+            ("label", 0, |mut t| t.set_text(())),
+        ];
+        for (index, (name, offset, set_type)) in slots.into_iter().enumerate() {
+            let mut field = fields.reborrow().get(index as u32);
+            field.set_name(name);
+            let mut slot = field.init_slot();
+            slot.set_offset(offset);
+            set_type(slot.init_type());
+        }
+        let mut status = fields.reborrow().get(5);
+        status.set_name("status");
+        status.init_group().set_type_id(0xc0);
+    }
+
+    let mut status = nodes.reborrow().get(2);
+    status.set_id(0xc0);
+    status.set_display_name("test.capnp:Wheels.status");
+    status.set_display_name_prefix_length(18);
+    {
+        let mut status = status.init_struct();
+        status.set_is_group(true);
+        status.set_discriminant_count(2);
+        status.set_discriminant_offset(2);
+        let mut fields = status.init_fields(2);
+        let mut ok = fields.reborrow().get(0);
+        ok.set_name("ok");
+        ok.init_slot().init_type().set_void(());
+        let mut fault = fields.reborrow().get(1);
+        fault.set_name("fault");
+        let mut slot = fault.init_slot();
+        slot.set_offset(4);
+        slot.init_type().set_uint32(());
+    }
+
+    let mut unannotated = nodes.reborrow().get(3);
+    unannotated.set_id(0xd0);
+    unannotated.set_display_name("test.capnp:Unannotated");
+    unannotated.set_display_name_prefix_length(11);
+    unannotated.init_struct().set_data_word_count(1);
+
+    capnp::serialize::write_message_to_words(&message)
+}
+
+#[test]
+fn capnp_schema_layout() {
+    let messages = ElpisMessages::load_from_capnp(&compiled_test_schema()).unwrap();
+    assert_eq!(messages.get_messagedef_count(), 1);
+
+    let wheels = messages.get_def_by_id(0x120).unwrap();
+    assert_eq!(wheels.name, "Wheels");
+    assert_eq!(wheels.length, 24);
+
+    let layout: Vec<(&str, Option<i32>, i32)> = wheels
+        .signals
+        .iter()
+        .map(|signal| (signal.name.as_str(), signal.start, signal.length))
+        .collect();
+    assert_eq!(
+        layout,
+        vec![
+            ("speed", Some(0), 16),
+            ("valid", Some(16), 1),
+            ("temp", Some(24), 8),
+            ("odometer", Some(64), 64),
+            ("status_which", Some(32), 16),
+            ("status_fault", Some(128), 32),
+        ]
+    );
+    assert!(wheels.signals.iter().all(|signal| !signal.is_big_endian));
+    assert_eq!(wheels.get_signal_by_name("temp").unwrap().is_signed, Some(true));
+    assert_eq!(wheels.get_signal_by_name("odometer").unwrap().is_float, Some(true));
+
+    // Values decode from Cap'n Proto's little endian data section
+    let mut payload = vec![0u8; 24];
+    payload[0..2].copy_from_slice(&300u16.to_le_bytes());
+    payload[2] = 1;
+    payload[3] = (-5i8) as u8;
+    payload[4..6].copy_from_slice(&1u16.to_le_bytes());
+    payload[8..16].copy_from_slice(&12.5f64.to_le_bytes());
+    payload[16..20].copy_from_slice(&0xdeadu32.to_le_bytes());
+
+    let decoded = wheels.decode(&payload);
+    assert!(decoded.errors.is_empty(), "{:?}", decoded.errors);
+    let value = |name: &str| decoded.signals.iter().find(|signal| signal.name == name).unwrap().physical;
+    assert_eq!(value("speed"), 300.0);
+    assert_eq!(value("valid"), 1.0);
+    assert_eq!(value("temp"), -5.0);
+    assert_eq!(value("odometer"), 12.5);
+    assert_eq!(value("status_which"), 1.0);
+    assert_eq!(value("status_fault"), 0xdead as f64);
+}
+
+#[test]
+fn capnp_rejects_bad_schemas() {
+    assert!(ElpisMessages::load_from_capnp(&[]).is_err());
+    assert!(ElpisMessages::load_from_capnp(&[1, 2, 3, 4, 5, 6, 7, 8]).is_err());
+}
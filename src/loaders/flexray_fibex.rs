@@ -0,0 +1,384 @@
+// FlexRay cluster descriptions in ASAM FIBEX XML, the format FlexRay toolchains exchange schedules
+// and frame layouts in. Only the subset describing frame layouts is read:
+//
+//     <FRAME-TRIGGERING>
+//       <TIMINGS><ABSOLUTELY-SCHEDULED-TIMING><SLOT-ID>12</SLOT-ID></ABSOLUTELY-SCHEDULED-TIMING></TIMINGS>
+//       <FRAME-REF ID-REF="fr_wheels"/>
+//     </FRAME-TRIGGERING>
+//     <FRAME ID="fr_wheels">
+//       <SHORT-NAME>Wheels</SHORT-NAME>
+//       <BYTE-LENGTH>8</BYTE-LENGTH>
+//       <PDU-INSTANCES>
+//         <PDU-INSTANCE><PDU-REF ID-REF="pdu_wheels"/><BIT-POSITION>0</BIT-POSITION></PDU-INSTANCE>
+//       </PDU-INSTANCES>
+//     </FRAME>
+//     <PDU ID="pdu_wheels">
+//       <SIGNAL-INSTANCES>
+//         <SIGNAL-INSTANCE>
+//           <BIT-POSITION>0</BIT-POSITION>
+//           <IS-HIGH-LOW-BYTE-ORDER>false</IS-HIGH-LOW-BYTE-ORDER>
+//           <SIGNAL-REF ID-REF="sig_speed"/>
+//         </SIGNAL-INSTANCE>
+//       </SIGNAL-INSTANCES>
+//     </PDU>
+//     <SIGNAL ID="sig_speed"><SHORT-NAME>WheelSpeed</SHORT-NAME><CODING-REF ID-REF="cod_speed"/></SIGNAL>
+//     <CODING ID="cod_speed">
+//       <CODED-TYPE ENCODING="UNSIGNED"><BIT-LENGTH>16</BIT-LENGTH></CODED-TYPE>
+//       <COMPU-METHODS><COMPU-METHOD>... <COMPU-NUMERATOR><V>0</V><V>0.01</V></COMPU-NUMERATOR> ...
+//     </CODING>
+//
+// Each frame scheduled in a slot becomes a message whose id is the SLOT-ID, with the signals of every
+// PDU it carries placed at the PDU's BIT-POSITION in the frame. Frames that aren't scheduled are
+// skipped, and a frame scheduled on both channels in the same slot becomes a single message. Slots
+// shared by several frames in different cycles can't be told apart by their id and fail the load.
+// Like AUTOSAR, BIT-POSITION is the least significant bit for both byte orders.
+
+use super::xml::{motorola_msb, parse_document, required_number, Element};
+use crate::elpis::{ElpisMessages, MessageDefinition, SignalDefinition};
+use anyhow::Context;
+use std::collections::{BTreeMap, HashMap};
+
+// Index the elements with the given name by their ID attribute
+fn by_id<'a>(document: &'a Element, name: &str) -> HashMap<&'a str, &'a Element> {
+    document
+        .find_all(name)
+        .into_iter()
+        .filter_map(|element| Some((element.attribute("ID")?, element)))
+        .collect()
+}
+
+// Get the element an ID-REF child refers to
+fn resolve<'a>(
+    element: &Element,
+    reference: &str,
+    targets: &HashMap<&str, &'a Element>,
+    owner: &str,
+) -> anyhow::Result<&'a Element> {
+    let id = element
+        .child(reference)
+        .and_then(|child| child.attribute("ID-REF"))
+        .with_context(|| format!("{} has no {}", owner, reference))?;
+    targets
+        .get(id)
+        .copied()
+        .with_context(|| format!("{} of {} refers to unknown {}", reference, owner, id))
+}
+
+fn short_name<'a>(element: &'a Element, kind: &str) -> anyhow::Result<&'a str> {
+    element.child_text("SHORT-NAME").with_context(|| {
+        format!("{} {} has no SHORT-NAME", kind, element.attribute("ID").unwrap_or("without an ID"))
+    })
+}
+
+fn parse_f64(text: &str, owner: &str) -> anyhow::Result<f64> {
+    text.parse().with_context(|| format!("Invalid coefficient {} of {}", text, owner))
+}
+
+// Apply a coding's encoding, length, linear scaling and unit to a signal
+fn apply_coding(signal: &mut SignalDefinition, coding: &Element, units: &HashMap<&str, &Element>, owner: &str) -> anyhow::Result<()> {
+    let coded_type = coding.child("CODED-TYPE").with_context(|| format!("Coding of {} has no CODED-TYPE", owner))?;
+    signal.length = required_number(coded_type, "BIT-LENGTH", &format!("coding of {}", owner))?;
+    match coded_type.attribute("ENCODING") {
+        Some("SIGNED") | Some("2C") => signal.is_signed = Some(true),
+        Some("IEEE-FLOATING-TYPE") => signal.is_float = Some(true),
+        Some("UNSIGNED") | None => {}
+        Some(other) => return Err(anyhow::anyhow!("Unknown ENCODING {} of {}", other, owner)),
+    }
+
+    let Some(method) = coding.find_all("COMPU-METHOD").into_iter().next() else {
+        return Ok(());
+    };
+    match method.child_text("CATEGORY") {
+        Some("IDENTICAL") | None => {}
+        Some("LINEAR") => {
+            // Physical values are (numerator[0] + numerator[1] * raw) / denominator[0]
+            let values = |name: &str| -> anyhow::Result<Vec<f64>> {
+                let Some(part) = method.find_all(name).into_iter().next() else {
+                    return Ok(Vec::new());
+                };
+                part.find_all("V").into_iter().map(|value| parse_f64(value.text.trim(), owner)).collect()
+            };
+            let numerator = values("COMPU-NUMERATOR")?;
+            let denominator = values("COMPU-DENOMINATOR")?.first().copied().unwrap_or(1.0);
+            let [offset, factor] = numerator[..] else {
+                return Err(anyhow::anyhow!("Linear scaling of {} needs two numerator coefficients", owner));
+            };
+            if denominator == 0.0 {
+                return Err(anyhow::anyhow!("Linear scaling of {} divides by zero", owner));
+            }
+            signal.offset = offset / denominator;
+            signal.scale = Some(factor / denominator);
+        }
+        Some(other) => return Err(anyhow::anyhow!("Unsupported COMPU-METHOD category {} of {}", other, owner)),
+    }
+
+    if let Some(unit) = method.child("UNIT-REF").and_then(|unit| units.get(unit.attribute("ID-REF")?)) {
+        signal.unit = unit.child_text("DISPLAY-NAME").or_else(|| unit.child_text("SHORT-NAME")).map(String::from);
+    }
+    Ok(())
+}
+
+impl ElpisMessages {
+    // Load ELPIS messages from the given path to a FIBEX FlexRay cluster description
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_flexray_xml(path: &str) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("Could not open file {}", path))?;
+        let mut messages = Self::from_flexray_xml(&contents).with_context(|| format!("Could not parse FIBEX file {}", path))?;
+        messages.set_source(path);
+        Ok(messages)
+    }
+
+    // Parse ELPIS messages from the contents of a FIBEX FlexRay cluster description
+    pub fn from_flexray_xml(xml: &str) -> anyhow::Result<Self> {
+        let document = parse_document(xml).context("Could not parse FIBEX XML")?;
+        let frames = by_id(&document, "FRAME");
+        let pdus = by_id(&document, "PDU");
+        let signals = by_id(&document, "SIGNAL");
+        let codings = by_id(&document, "CODING");
+        let units = by_id(&document, "UNIT");
+
+        // The frame scheduled in each slot, in slot order
+        let mut slots: BTreeMap<i32, &Element> = BTreeMap::new();
+        for triggering in document.find_all("FRAME-TRIGGERING") {
+            let owner = format!("frame triggering {}", triggering.attribute("ID").unwrap_or("without an ID"));
+            let frame = resolve(triggering, "FRAME-REF", &frames, &owner)?;
+            let timing = triggering
+                .find_all("ABSOLUTELY-SCHEDULED-TIMING")
+                .into_iter()
+                .next()
+                .with_context(|| format!("{} has no ABSOLUTELY-SCHEDULED-TIMING", owner))?;
+            let slot = required_number(timing, "SLOT-ID", &owner)?;
+
+            if let Some(other) = slots.insert(slot, frame).filter(|other| !std::ptr::eq(*other, frame)) {
+                return Err(anyhow::anyhow!(
+                    "Frames {} and {} are both scheduled in slot {}, slots shared by cycle aren't supported",
+                    short_name(other, "FRAME")?,
+                    short_name(frame, "FRAME")?,
+                    slot
+                ));
+            }
+        }
+
+        let mut definitions = Vec::new();
+        for (slot, frame) in slots {
+            let name = short_name(frame, "FRAME")?;
+            let length = required_number(frame, "BYTE-LENGTH", name)?;
+            let mut message = MessageDefinition::new(name, slot, length);
+
+            for instance in frame.find_all("PDU-INSTANCE") {
+                let pdu = resolve(instance, "PDU-REF", &pdus, &format!("PDU instance in {}", name))?;
+                let pdu_position = required_number(instance, "BIT-POSITION", &format!("PDU instance in {}", name))?;
+                let pdu_name = short_name(pdu, "PDU")?;
+
+                for signal_instance in pdu.find_all("SIGNAL-INSTANCE") {
+                    let owner = format!("signal instance in {}", pdu_name);
+                    let signal_element = resolve(signal_instance, "SIGNAL-REF", &signals, &owner)?;
+                    let signal_name = short_name(signal_element, "SIGNAL")?;
+                    let owner = format!("signal {}.{}", pdu_name, signal_name);
+
+                    let mut signal = SignalDefinition::new(signal_name, None, 0);
+                    let coding = resolve(signal_element, "CODING-REF", &codings, &owner)?;
+                    apply_coding(&mut signal, coding, &units, &owner)?;
+
+                    let position = pdu_position + required_number(signal_instance, "BIT-POSITION", &owner)?;
+                    signal.is_big_endian = match signal_instance.child_text("IS-HIGH-LOW-BYTE-ORDER") {
+                        Some("true") => true,
+                        Some("false") | None => false,
+                        Some(other) => return Err(anyhow::anyhow!("Invalid IS-HIGH-LOW-BYTE-ORDER {} of {}", other, owner)),
+                    };
+                    signal.start = Some(if signal.is_big_endian { motorola_msb(position, signal.length) } else { position });
+                    message.signals.push(signal);
+                }
+            }
+            definitions.push(message);
+        }
+
+        Self::from_loaded_definitions(definitions)
+    }
+}
+
+#[cfg(test)]
+const TEST_FIBEX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<fx:FIBEX xmlns:fx="http://www.asam.net/xml/fbx" xmlns:ho="http://www.asam.net/xml" VERSION="3.1.0">
+  <fx:ELEMENTS>
+    <fx:CHANNELS>
+      <fx:CHANNEL ID="ch_a">
+        <ho:SHORT-NAME>A</ho:SHORT-NAME>
+        <fx:FRAME-TRIGGERINGS>
+          <fx:FRAME-TRIGGERING ID="ft_wheels_a">
+            <fx:TIMINGS>
+              <fx:ABSOLUTELY-SCHEDULED-TIMING>
+                <fx:SLOT-ID>12</fx:SLOT-ID>
+                <fx:BASE-CYCLE>0</fx:BASE-CYCLE>
+                <fx:CYCLE-REPETITION>1</fx:CYCLE-REPETITION>
+              </fx:ABSOLUTELY-SCHEDULED-TIMING>
+            </fx:TIMINGS>
+            <fx:FRAME-REF ID-REF="fr_wheels"/>
+          </fx:FRAME-TRIGGERING>
+          <fx:FRAME-TRIGGERING ID="ft_body">
+            <fx:TIMINGS>
+              <fx:ABSOLUTELY-SCHEDULED-TIMING><fx:SLOT-ID>7</fx:SLOT-ID></fx:ABSOLUTELY-SCHEDULED-TIMING>
+            </fx:TIMINGS>
+            <fx:FRAME-REF ID-REF="fr_body"/>
+          </fx:FRAME-TRIGGERING>
+        </fx:FRAME-TRIGGERINGS>
+      </fx:CHANNEL>
+      <fx:CHANNEL ID="ch_b">
+        <ho:SHORT-NAME>B</ho:SHORT-NAME>
+        <fx:FRAME-TRIGGERINGS>
+          <fx:FRAME-TRIGGERING ID="ft_wheels_b">
+            <fx:TIMINGS>
+              <fx:ABSOLUTELY-SCHEDULED-TIMING><fx:SLOT-ID>12</fx:SLOT-ID></fx:ABSOLUTELY-SCHEDULED-TIMING>
+            </fx:TIMINGS>
+            <fx:FRAME-REF ID-REF="fr_wheels"/>
+          </fx:FRAME-TRIGGERING>
+        </fx:FRAME-TRIGGERINGS>
+      </fx:CHANNEL>
+    </fx:CHANNELS>
+    <fx:FRAMES>
+      <fx:FRAME ID="fr_wheels">
+        <ho:SHORT-NAME>Wheels</ho:SHORT-NAME>
+        <fx:BYTE-LENGTH>8</fx:BYTE-LENGTH>
+        <fx:PDU-INSTANCES>
+          <fx:PDU-INSTANCE ID="pi_speed">
+            <fx:PDU-REF ID-REF="pdu_speed"/>
+            <fx:BIT-POSITION>0</fx:BIT-POSITION>
+          </fx:PDU-INSTANCE>
+          <fx:PDU-INSTANCE ID="pi_torque">
+            <fx:PDU-REF ID-REF="pdu_torque"/>
+            <fx:BIT-POSITION>32</fx:BIT-POSITION>
+          </fx:PDU-INSTANCE>
+        </fx:PDU-INSTANCES>
+      </fx:FRAME>
+      <fx:FRAME ID="fr_body">
+        <ho:SHORT-NAME>Body</ho:SHORT-NAME>
+        <fx:BYTE-LENGTH>2</fx:BYTE-LENGTH>
+      </fx:FRAME>
+      <fx:FRAME ID="fr_unscheduled">
+        <ho:SHORT-NAME>Unscheduled</ho:SHORT-NAME>
+        <fx:BYTE-LENGTH>4</fx:BYTE-LENGTH>
+      </fx:FRAME>
+    </fx:FRAMES>
+    <fx:PDUS>
+      <fx:PDU ID="pdu_speed">
+        <ho:SHORT-NAME>Speed_PDU</ho:SHORT-NAME>
+        <fx:BYTE-LENGTH>4</fx:BYTE-LENGTH>
+        <fx:SIGNAL-INSTANCES>
+          <fx:SIGNAL-INSTANCE ID="si_speed">
+            <fx:BIT-POSITION>0</fx:BIT-POSITION>
+            <fx:IS-HIGH-LOW-BYTE-ORDER>false</fx:IS-HIGH-LOW-BYTE-ORDER>
+            <fx:SIGNAL-REF ID-REF="sig_speed"/>
+          </fx:SIGNAL-INSTANCE>
+        </fx:SIGNAL-INSTANCES>
+      </fx:PDU>
+      <fx:PDU ID="pdu_torque">
+        <ho:SHORT-NAME>Torque_PDU</ho:SHORT-NAME>
+        <fx:BYTE-LENGTH>4</fx:BYTE-LENGTH>
+        <fx:SIGNAL-INSTANCES>
+          <fx:SIGNAL-INSTANCE ID="si_torque">
+            <fx:BIT-POSITION>8</fx:BIT-POSITION>
+            <fx:IS-HIGH-LOW-BYTE-ORDER>true</fx:IS-HIGH-LOW-BYTE-ORDER>
+            <fx:SIGNAL-REF ID-REF="sig_torque"/>
+          </fx:SIGNAL-INSTANCE>
+        </fx:SIGNAL-INSTANCES>
+      </fx:PDU>
+    </fx:PDUS>
+    <fx:SIGNALS>
+      <fx:SIGNAL ID="sig_speed">
+        <ho:SHORT-NAME>WheelSpeed</ho:SHORT-NAME>
+        <fx:CODING-REF ID-REF="cod_speed"/>
+      </fx:SIGNAL>
+      <fx:SIGNAL ID="sig_torque">
+        <ho:SHORT-NAME>Torque</ho:SHORT-NAME>
+        <fx:CODING-REF ID-REF="cod_torque"/>
+      </fx:SIGNAL>
+    </fx:SIGNALS>
+  </fx:ELEMENTS>
+  <fx:PROCESSING-INFORMATION>
+    <ho:UNIT-SPEC>
+      <ho:UNITS>
+        <ho:UNIT ID="unit_kmh">
+          <ho:SHORT-NAME>kmh</ho:SHORT-NAME>
+          <ho:DISPLAY-NAME>km/h</ho:DISPLAY-NAME>
+        </ho:UNIT>
+      </ho:UNITS>
+    </ho:UNIT-SPEC>
+    <fx:CODINGS>
+      <fx:CODING ID="cod_speed">
+        <ho:SHORT-NAME>Speed_Coding</ho:SHORT-NAME>
+        <ho:CODED-TYPE ho:BASE-DATA-TYPE="A_UINT16" CATEGORY="STANDARD-LENGTH-TYPE" ENCODING="UNSIGNED">
+          <ho:BIT-LENGTH>16</ho:BIT-LENGTH>
+        </ho:CODED-TYPE>
+        <ho:COMPU-METHODS>
+          <ho:COMPU-METHOD>
+            <ho:SHORT-NAME>Speed_Linear</ho:SHORT-NAME>
+            <ho:CATEGORY>LINEAR</ho:CATEGORY>
+            <ho:UNIT-REF ID-REF="unit_kmh"/>
+            <ho:COMPU-INTERNAL-TO-PHYS>
+              <ho:COMPU-SCALES>
+                <ho:COMPU-SCALE>
+                  <ho:COMPU-RATIONAL-COEFFS>
+                    <ho:COMPU-NUMERATOR><ho:V>-10</ho:V><ho:V>0.02</ho:V></ho:COMPU-NUMERATOR>
+                    <ho:COMPU-DENOMINATOR><ho:V>2</ho:V></ho:COMPU-DENOMINATOR>
+                  </ho:COMPU-RATIONAL-COEFFS>
+                </ho:COMPU-SCALE>
+              </ho:COMPU-SCALES>
+            </ho:COMPU-INTERNAL-TO-PHYS>
+          </ho:COMPU-METHOD>
+        </ho:COMPU-METHODS>
+      </fx:CODING>
+      <fx:CODING ID="cod_torque">
+        <ho:SHORT-NAME>Torque_Coding</ho:SHORT-NAME>
+        <ho:CODED-TYPE CATEGORY="STANDARD-LENGTH-TYPE" ENCODING="SIGNED">
+          <ho:BIT-LENGTH>12</ho:BIT-LENGTH>
+        </ho:CODED-TYPE>
+      </fx:CODING>
+    </fx:CODINGS>
+  </fx:PROCESSING-INFORMATION>
+</fx:FIBEX>"#;
+
+#[test]
+fn flexray_fibex_layout() {
+    let messages = ElpisMessages::from_flexray_xml(TEST_FIBEX).unwrap();
+    let ids: Vec<i32> = messages.iter_sorted().map(|message| message.id).collect();
+    assert_eq!(ids, vec![7, 12]);
+
+    // Scheduled on both channels in slot 12, carrying two PDUs
+    let wheels = messages.get_def_by_id(12).unwrap();
+    assert_eq!((wheels.name.as_str(), wheels.length), ("Wheels", 8));
+    assert_eq!(wheels.frame_type, None);
+    let layout: Vec<(&str, Option<i32>, i32, bool)> = wheels
+        .signals
+        .iter()
+        .map(|signal| (signal.name.as_str(), signal.start, signal.length, signal.is_big_endian))
+        .collect();
+    // The big endian torque has its least significant bit at 32 + 8, so its most significant is bit 35
+    assert_eq!(layout, vec![("WheelSpeed", Some(0), 16, false), ("Torque", Some(35), 12, true)]);
+
+    let speed = &wheels.signals[0];
+    assert_eq!((speed.scale, speed.offset, speed.unit.as_deref()), (Some(0.01), -5.0, Some("km/h")));
+    assert_eq!(wheels.signals[1].is_signed, Some(true));
+
+    let decoded = wheels.decode(&[0xe8, 0x03, 0x00, 0x00, 0x0f, 0xff, 0x00, 0x00]);
+    assert!(decoded.errors.is_empty(), "{:?}", decoded.errors);
+    assert_eq!(decoded.signals[0].physical, 5.0);
+    assert_eq!(decoded.signals[1].raw, 0xfff);
+}
+
+#[test]
+fn flexray_rejects_bad_descriptions() {
+    // A different frame in slot 12 in other cycles
+    let shared_slot = TEST_FIBEX.replacen(r#"<fx:FRAME-REF ID-REF="fr_wheels"/>"#, r#"<fx:FRAME-REF ID-REF="fr_unscheduled"/>"#, 1);
+    let error = format!("{:#}", ElpisMessages::from_flexray_xml(&shared_slot).err().unwrap());
+    assert_eq!(error, "Frames Unscheduled and Wheels are both scheduled in slot 12, slots shared by cycle aren't supported");
+
+    let unknown_signal = TEST_FIBEX.replace(r#"ID-REF="sig_torque""#, r#"ID-REF="sig_missing""#);
+    let error = format!("{:#}", ElpisMessages::from_flexray_xml(&unknown_signal).err().unwrap());
+    assert_eq!(error, "SIGNAL-REF of signal instance in Torque_PDU refers to unknown sig_missing");
+
+    let bad_encoding = TEST_FIBEX.replace(r#"ENCODING="SIGNED""#, r#"ENCODING="BCD-P""#);
+    let error = format!("{:#}", ElpisMessages::from_flexray_xml(&bad_encoding).err().unwrap());
+    assert_eq!(error, "Unknown ENCODING BCD-P of signal Torque_PDU.Torque");
+
+    assert!(ElpisMessages::from_flexray_xml("<fx:FIBEX><fx:FRAME></fx:FIBEX>").is_err());
+}
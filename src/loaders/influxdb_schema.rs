@@ -0,0 +1,315 @@
+// Schema files of CAN to InfluxDB bridges, mapping CAN ids to InfluxDB measurements and the bits
+// of the payload to field keys:
+//
+//     [{ "measurement": "wheels", "can_id": 288,
+//        "fields": [{ "key": "speed_fl", "start_bit": 0, "length": 16 }] }]
+//
+// Each measurement becomes a message and each field a little endian signal, the way the bridges
+// read them. The schema doesn't give a payload length, so the message is as long as its fields need.
+//
+// The explicit schemas of an InfluxDB v2 bucket can be loaded from the server as well, from
+// GET /api/v2/buckets/<bucket id>/schema/measurements. They only give names and data types, so the
+// bridges name each measurement after its message and CAN id, "wheels_0x120", and lay out its
+// field columns one after another in the order they're listed: 64 bits for integer, unsigned and
+// float fields and a byte for boolean ones.
+use crate::elpis::{ElpisMessages, MessageDefinition, SignalDefinition};
+#[cfg(not(target_arch = "wasm32"))]
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::HashSet;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InfluxField {
+    key: String,
+    start_bit: i32,
+    length: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InfluxMeasurement {
+    measurement: String,
+    can_id: u32,
+    #[serde(default)]
+    fields: Vec<InfluxField>,
+}
+
+// A column of a bucket's explicit measurement schema, as the schema API returns it
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SchemaColumn {
+    name: String,
+    #[serde(rename = "type")]
+    column_type: String,
+    #[serde(default)]
+    data_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MeasurementSchema {
+    name: String,
+    columns: Vec<SchemaColumn>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MeasurementSchemaList {
+    measurement_schemas: Vec<MeasurementSchema>,
+}
+
+// Split a measurement name the bridges gave, "wheels_0x120", into the message name and CAN id
+fn split_measurement_name(name: &str) -> Option<(&str, i32)> {
+    let (message, id) = name.rsplit_once("_0x")?;
+    let id = i32::from_str_radix(id, 16).ok().filter(|id| *id >= 0)?;
+    (!message.is_empty()).then_some((message, id))
+}
+
+// GET a path of an InfluxDB server, returning the response body. The token goes in a header, so
+// it's only encrypted for https:// URLs.
+#[cfg(all(feature = "influxdb", not(target_arch = "wasm32")))]
+fn influxdb_get(url: &str, path: &str, token: &str) -> anyhow::Result<String> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(std::time::Duration::from_secs(10))
+        .timeout_read(std::time::Duration::from_secs(30))
+        .build();
+    let response = agent
+        .get(&format!("{}{}", url.trim_end_matches('/'), path))
+        .set("Authorization", &format!("Token {}", token))
+        .set("Accept", "application/json")
+        .call();
+
+    match response {
+        Ok(response) => response.into_string().context("Could not read the response of InfluxDB"),
+        Err(ureq::Error::Status(status, response)) => {
+            let status_text = response.status_text().to_string();
+            let body = response.into_string().unwrap_or_default();
+            Err(anyhow::anyhow!("InfluxDB answered {} {}: {}", status, status_text, body.trim()))
+        }
+        Err(error) => Err(error).with_context(|| format!("Could not reach InfluxDB at {}", url)),
+    }
+}
+
+impl ElpisMessages {
+    // Load ELPIS messages from the given path to an InfluxDB bridge schema file
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_influxdb_line_protocol_schema(path: &str) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("Could not open file {}", path))?;
+        let mut messages = Self::from_influxdb_line_protocol_schema(&contents)
+            .with_context(|| format!("Could not parse InfluxDB schema file {}", path))?;
+        messages.set_source(path);
+        Ok(messages)
+    }
+
+    // Parse ELPIS messages from the contents of an InfluxDB bridge schema file
+    pub fn from_influxdb_line_protocol_schema(contents: &str) -> anyhow::Result<Self> {
+        let measurements: Vec<InfluxMeasurement> = serde_json::from_str(contents)?;
+
+        let mut ids = HashSet::new();
+        let mut definitions = Vec::new();
+        for measurement in measurements {
+            if !ids.insert(measurement.can_id) {
+                return Err(anyhow::anyhow!(
+                    "Measurement {} uses CAN id {:#x} of an earlier measurement",
+                    measurement.measurement,
+                    measurement.can_id
+                ));
+            }
+
+            let mut length_bits = 0;
+            let mut signals = Vec::new();
+            for field in measurement.fields {
+                if field.start_bit < 0 || field.length <= 0 || field.length > 64 {
+                    return Err(anyhow::anyhow!(
+                        "Field {}.{} has an invalid layout, start bit {} and length {}",
+                        measurement.measurement,
+                        field.key,
+                        field.start_bit,
+                        field.length
+                    ));
+                }
+                length_bits = length_bits.max(field.start_bit + field.length);
+
+                let mut signal = SignalDefinition::new(&field.key, Some(field.start_bit), field.length);
+                signal.is_big_endian = false;
+                signals.push(signal);
+            }
+
+            let mut definition =
+                MessageDefinition::new(&measurement.measurement, measurement.can_id as i32, (length_bits + 7) / 8);
+            definition.signals = signals;
+            definitions.push(definition);
+        }
+
+        Self::from_loaded_definitions(definitions)
+    }
+
+    // Load ELPIS messages from the explicit measurement schemas of an InfluxDB v2 bucket, given the
+    // server's URL, the bucket's id and an API token allowed to read it
+    #[cfg(all(feature = "influxdb", not(target_arch = "wasm32")))]
+    pub fn load_from_influxdb_schema_registry(url: &str, bucket: &str, token: &str) -> anyhow::Result<Self> {
+        let path = format!("/api/v2/buckets/{}/schema/measurements", bucket);
+        let contents = influxdb_get(url, &path, token)
+            .with_context(|| format!("Could not get the measurement schemas of bucket {} from {}", bucket, url))?;
+        let mut messages = Self::from_influxdb_schema_registry(&contents)
+            .with_context(|| format!("Could not parse the measurement schemas of bucket {}", bucket))?;
+        messages.set_source(&format!("{}{}", url.trim_end_matches('/'), path));
+        Ok(messages)
+    }
+
+    // Parse ELPIS messages from the measurement schemas the InfluxDB schema API returned
+    pub fn from_influxdb_schema_registry(contents: &str) -> anyhow::Result<Self> {
+        let schemas: MeasurementSchemaList = serde_json::from_str(contents)?;
+
+        let mut ids = HashSet::new();
+        let mut definitions = Vec::new();
+        for schema in schemas.measurement_schemas {
+            let (name, id) = split_measurement_name(&schema.name).ok_or_else(|| {
+                anyhow::anyhow!("Measurement {} isn't named <message>_0x<CAN id>", schema.name)
+            })?;
+            if !ids.insert(id) {
+                return Err(anyhow::anyhow!("Measurement {} uses CAN id {:#x} of an earlier measurement", schema.name, id));
+            }
+
+            // Timestamps and tags are added by the bridge, only fields come from the payload
+            let mut start_bit = 0;
+            let mut signals = Vec::new();
+            for column in schema.columns.iter().filter(|column| column.column_type == "field") {
+                let data_type = column.data_type.as_deref().unwrap_or_default();
+                let mut signal = match data_type {
+                    "integer" | "unsigned" | "float" => SignalDefinition::new(&column.name, Some(start_bit), 64),
+                    "boolean" => SignalDefinition::new(&column.name, Some(start_bit), 8),
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "Field {}.{} has data type \"{}\", which can't be read from a payload",
+                            schema.name,
+                            column.name,
+                            data_type
+                        ))
+                    }
+                };
+                signal.is_big_endian = false;
+                signal.is_signed = Some(data_type == "integer");
+                signal.is_float = Some(data_type == "float");
+                start_bit += signal.length;
+                signals.push(signal);
+            }
+
+            let mut definition = MessageDefinition::new(name, id, start_bit / 8);
+            definition.signals = signals;
+            definitions.push(definition);
+        }
+
+        Self::from_loaded_definitions(definitions)
+    }
+}
+
+#[test]
+fn influxdb_schema_layout() {
+    let contents = r#"[
+        {
+            "measurement": "wheels",
+            "can_id": 288,
+            "fields": [
+                { "key": "speed_fl", "start_bit": 0, "length": 16 },
+                { "key": "speed_fr", "start_bit": 16, "length": 16 },
+                { "key": "abs_active", "start_bit": 36, "length": 1 }
+            ]
+        },
+        { "measurement": "heartbeat", "can_id": 1792 }
+    ]"#;
+
+    let messages = ElpisMessages::from_influxdb_line_protocol_schema(contents).unwrap();
+    assert_eq!(messages.get_messagedef_count(), 2);
+    assert_eq!(messages.get_def_by_id(0x700).unwrap().length, 0);
+
+    let wheels = messages.get_def_by_id(0x120).unwrap();
+    assert_eq!(wheels.name, "wheels");
+    assert_eq!(wheels.length, 5);
+
+    let decoded = wheels.decode(&[0x34, 0x12, 0xcd, 0xab, 0x10]);
+    let raw: Vec<(&str, u128)> = decoded.signals.iter().map(|signal| (signal.name.as_str(), signal.raw)).collect();
+    assert_eq!(raw, vec![("speed_fl", 0x1234), ("speed_fr", 0xabcd), ("abs_active", 1)]);
+}
+
+#[test]
+fn influxdb_schema_rejects_bad_schemas() {
+    let parse = ElpisMessages::from_influxdb_line_protocol_schema;
+
+    assert!(parse(r#"[{ "measurement": "a", "can_id": 1 }, { "measurement": "b", "can_id": 1 }]"#).is_err());
+    assert!(parse(r#"[{ "measurement": "a", "can_id": 1, "fields": [{ "key": "x", "start_bit": 0, "length": 0 }] }]"#).is_err());
+    assert!(parse(r#"[{ "measurement": "a", "can_id": -1 }]"#).is_err());
+    assert!(parse(r#"[{ "measurement": "a", "can_id": 1, "tags": [] }]"#).is_err());
+}
+
+#[test]
+fn influxdb_schema_registry_layout() {
+    let contents = r#"{
+        "measurementSchemas": [{
+            "id": "1a3c5e7f9b0a8642",
+            "name": "wheels_0x120",
+            "columns": [
+                { "name": "time", "type": "timestamp" },
+                { "name": "vehicle", "type": "tag" },
+                { "name": "speed_fl", "type": "field", "dataType": "float" },
+                { "name": "abs_active", "type": "field", "dataType": "boolean" },
+                { "name": "odometer", "type": "field", "dataType": "unsigned" }
+            ],
+            "orgID": "0a1b2c3d4e5f6071",
+            "bucketID": "8192a3b4c5d6e7f8"
+        }]
+    }"#;
+
+    let messages = ElpisMessages::from_influxdb_schema_registry(contents).unwrap();
+    let wheels = messages.get_def_by_id(0x120).unwrap();
+    assert_eq!(wheels.name, "wheels");
+    assert_eq!(wheels.length, 17);
+    let layout: Vec<(&str, Option<i32>, i32)> =
+        wheels.signals.iter().map(|signal| (signal.name.as_str(), signal.start, signal.length)).collect();
+    assert_eq!(layout, vec![("speed_fl", Some(0), 64), ("abs_active", Some(64), 8), ("odometer", Some(72), 64)]);
+
+    let mut payload = 12.5f64.to_le_bytes().to_vec();
+    payload.push(1);
+    payload.extend_from_slice(&7u64.to_le_bytes());
+    let physical: Vec<f64> = wheels.decode(&payload).signals.iter().map(|signal| signal.physical).collect();
+    assert_eq!(physical, vec![12.5, 1.0, 7.0]);
+
+    // Names without a CAN id and string fields can't be mapped
+    let unnamed = contents.replace("wheels_0x120", "wheels");
+    assert!(ElpisMessages::from_influxdb_schema_registry(&unnamed).is_err());
+    let string = contents.replace(r#""dataType": "boolean""#, r#""dataType": "string""#);
+    assert!(ElpisMessages::from_influxdb_schema_registry(&string).is_err());
+}
+
+#[cfg(feature = "influxdb")]
+#[test]
+fn influxdb_schema_registry_request() {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/influx/", listener.local_addr().unwrap());
+
+    // A server answering a single request with an empty schema list, handing back the request
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buffer = [0u8; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let count = stream.read(&mut buffer).unwrap();
+            request.extend_from_slice(&buffer[..count]);
+        }
+        let body = r#"{"measurementSchemas": []}"#;
+        write!(stream, "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{}", body).unwrap();
+        String::from_utf8(request).unwrap()
+    });
+
+    let messages = ElpisMessages::load_from_influxdb_schema_registry(&url, "8192a3b4c5d6e7f8", "secret").unwrap();
+    assert_eq!(messages.get_messagedef_count(), 0);
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("GET /influx/api/v2/buckets/8192a3b4c5d6e7f8/schema/measurements HTTP/1.1\r\n"));
+    assert!(request.to_lowercase().contains("authorization: token secret\r\n"));
+
+    // Nothing listens on the port any more
+    assert!(ElpisMessages::load_from_influxdb_schema_registry(&url, "b", "t").is_err());
+}
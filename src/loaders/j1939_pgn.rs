@@ -0,0 +1,259 @@
+// The SAE J1939 Digital Annex, the committee's Excel export of every PGN and its SPNs. Each row of
+// the sheet is an SPN along with the PGN it belongs to, e.g. for Engine Speed:
+//
+//     PGN: F004                       SPN: 190                  Resolution: 0.125 rpm/bit
+//     PGN Label: Electronic Engine    SPN Name: Engine Speed    Offset: 0
+//       Controller 1                  Start Position: 4-5       Data Range: 0 to 8,031.875 rpm
+//     Transmission Rate: 20 ms        Length: 2 bytes           Unit: rpm
+//
+// Columns are found by their header, and the first sheet with PGN and SPN columns is read. PGNs are
+// hex and become the message id, with an extended CAN id. Start positions are 1-based J1939 byte
+// and bit positions, "4-5" or "1.3", and every signal is little endian. SPNs of variable length are
+// left out. There's no cycle time in messages.json, so the transmission rate goes in the message's
+// comment.
+
+use crate::elpis::{ElpisMessages, FrameType, MessageDefinition, SignalDefinition};
+use anyhow::Context;
+use std::collections::HashMap;
+
+// Columns of the sheet, located by their header
+struct Columns {
+    pgn: usize,
+    label: usize,
+    rate: Option<usize>,
+    spn: usize,
+    spn_name: Option<usize>,
+    start: usize,
+    length: usize,
+    resolution: Option<usize>,
+    offset: Option<usize>,
+    range: Option<usize>,
+    unit: Option<usize>,
+}
+
+impl Columns {
+    // Find the columns in a header row, None when it isn't the header of an SPN table
+    fn from_header(header: &[String]) -> Option<Self> {
+        let find = |name: &str| header.iter().position(|column| column.trim().eq_ignore_ascii_case(name));
+        Some(Self {
+            pgn: find("PGN")?,
+            label: find("PGN Label")?,
+            rate: find("Transmission Rate"),
+            spn: find("SPN")?,
+            spn_name: find("SPN Name"),
+            start: find("Start Position").or_else(|| find("SPN Position in PG"))?,
+            length: find("Length").or_else(|| find("SPN Length"))?,
+            resolution: find("Resolution"),
+            offset: find("Offset"),
+            range: find("Data Range"),
+            unit: find("Unit"),
+        })
+    }
+}
+
+// Get the first number in a text, e.g. 0.125 in "0.125 rpm/bit", or a fraction such as "1/256 %/bit".
+// Thousands separators are left out.
+fn leading_number(text: &str) -> Option<f64> {
+    let text = text.trim().replace(',', "");
+    let number: String = text
+        .chars()
+        .enumerate()
+        .take_while(|(index, c)| c.is_ascii_digit() || *c == '.' || *c == '/' || (*index == 0 && (*c == '-' || *c == '+')))
+        .map(|(_, c)| c)
+        .collect();
+    match number.split_once('/') {
+        Some((numerator, denominator)) => Some(numerator.parse::<f64>().ok()? / denominator.parse::<f64>().ok()?),
+        None => number.parse().ok(),
+    }
+}
+
+// Parse a data range such as "0 to 8,031.875 rpm" or "-125 to 125 %"
+fn parse_range(text: &str) -> Option<(f64, f64)> {
+    let (low, high) = text.split_once(" to ")?;
+    Some((leading_number(low)?, leading_number(high)?))
+}
+
+// Get the start bit of an SPN from its 1-based position: "4" or "4-5" start at bit 1 of byte 4,
+// "1.3" at bit 3 of byte 1
+fn parse_start(text: &str) -> Option<i32> {
+    let first = text.trim().split('-').next()?.trim();
+    let (byte, bit) = match first.split_once('.') {
+        Some((byte, bit)) => (byte.trim().parse::<i32>().ok()?, bit.trim().parse::<i32>().ok()?),
+        None => (first.parse::<i32>().ok()?, 1),
+    };
+    if byte < 1 || !(1..=8).contains(&bit) {
+        return None;
+    }
+    Some((byte - 1) * 8 + bit - 1)
+}
+
+// Get the length in bits of an SPN, e.g. "2 bytes" or "4 bits". None for variable lengths.
+fn parse_length(text: &str) -> Option<i32> {
+    let count = leading_number(text)? as i32;
+    let unit = text.trim().trim_start_matches(|c: char| c.is_ascii_digit() || c == '.').trim().to_ascii_lowercase();
+    if unit.starts_with("byte") {
+        Some(count * 8)
+    } else if unit.starts_with("bit") {
+        Some(count)
+    } else {
+        None
+    }
+}
+
+fn parse_pgn(text: &str) -> Option<i32> {
+    let text = text.trim();
+    let digits = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).unwrap_or(text);
+    i32::from_str_radix(digits, 16).ok().filter(|pgn| (0..=0x3ffff).contains(pgn))
+}
+
+fn load_signal(row: &[String], columns: &Columns, line: usize) -> anyhow::Result<Option<SignalDefinition>> {
+    let cell = |column: usize| row.get(column).map(|text| text.trim()).unwrap_or_default();
+    let optional = |column: Option<usize>| column.map(cell).filter(|text| !text.is_empty());
+
+    let spn = cell(columns.spn);
+    if spn.is_empty() {
+        return Ok(None);
+    }
+    let Some(length) = parse_length(cell(columns.length)) else {
+        return Ok(None);
+    };
+    let start = parse_start(cell(columns.start))
+        .with_context(|| format!("Invalid start position \"{}\" of SPN {} on row {}", cell(columns.start), spn, line))?;
+
+    let name = optional(columns.spn_name).map(str::to_string).unwrap_or_else(|| format!("SPN_{}", spn));
+    let mut signal = SignalDefinition::new(&name, Some(start), length);
+    signal.is_big_endian = false;
+    signal.spn = Some(spn.to_string());
+    // Enumerated SPNs give their resolution as e.g. "4 states/2 bit", their raw value is the state
+    signal.scale = optional(columns.resolution)
+        .filter(|resolution| !resolution.contains("states"))
+        .and_then(leading_number)
+        .filter(|scale| *scale != 0.0);
+    signal.offset = optional(columns.offset).and_then(leading_number).unwrap_or(0.0);
+    if let Some((minimum, maximum)) = optional(columns.range).and_then(parse_range) {
+        signal.minimum = minimum;
+        signal.maximum = maximum;
+    }
+    signal.unit = optional(columns.unit).map(str::to_string);
+    Ok(Some(signal))
+}
+
+impl ElpisMessages {
+    // Load ELPIS messages from the given path to the J1939 Digital Annex spreadsheet
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_j1939_pgn_database(path: &str) -> anyhow::Result<Self> {
+        use calamine::Reader;
+
+        let mut workbook = calamine::open_workbook_auto(path).with_context(|| format!("Could not open file {}", path))?;
+        let sheets: Vec<Vec<Vec<String>>> = workbook
+            .worksheets()
+            .into_iter()
+            .map(|(_, range)| range.rows().map(|row| row.iter().map(|cell| cell.to_string()).collect()).collect())
+            .collect();
+
+        let rows = sheets
+            .iter()
+            .find(|rows| rows.iter().any(|row| Columns::from_header(row).is_some()))
+            .with_context(|| format!("No sheet of {} has PGN and SPN columns", path))?;
+        let mut messages =
+            Self::from_j1939_pgn_rows(rows).with_context(|| format!("Could not read J1939 PGN database {}", path))?;
+        messages.set_source(path);
+        Ok(messages)
+    }
+
+    // Build ELPIS messages from the rows of a J1939 Digital Annex sheet, header row included
+    pub fn from_j1939_pgn_rows(rows: &[Vec<String>]) -> anyhow::Result<Self> {
+        let (header_index, columns) = rows
+            .iter()
+            .enumerate()
+            .find_map(|(index, row)| Some((index, Columns::from_header(row)?)))
+            .context("Sheet has no header row with PGN and SPN columns")?;
+
+        // Messages in the order their PGN first appears
+        let mut definitions: Vec<MessageDefinition> = Vec::new();
+        let mut indexes: HashMap<i32, usize> = HashMap::new();
+        for (index, row) in rows.iter().enumerate().skip(header_index + 1) {
+            let line = index + 1;
+            let cell = |column: usize| row.get(column).map(|text| text.trim()).unwrap_or_default();
+            if cell(columns.pgn).is_empty() {
+                continue;
+            }
+            let pgn = parse_pgn(cell(columns.pgn)).with_context(|| format!("Invalid PGN \"{}\" on row {}", cell(columns.pgn), line))?;
+
+            let message_index = *indexes.entry(pgn).or_insert_with(|| {
+                let label = cell(columns.label);
+                let name = if label.is_empty() { format!("PGN_{:#x}", pgn) } else { label.to_string() };
+                let mut message = MessageDefinition::new(&name, pgn, 8);
+                message.frame_type = Some(FrameType::Can);
+                message.is_extended_id = Some(true);
+                message.comment = columns
+                    .rate
+                    .map(cell)
+                    .filter(|rate| !rate.is_empty())
+                    .map(|rate| format!("Transmission rate: {}", rate));
+                definitions.push(message);
+                definitions.len() - 1
+            });
+
+            if let Some(signal) = load_signal(row, &columns, line)? {
+                definitions[message_index].signals.push(signal);
+            }
+        }
+
+        Self::from_loaded_definitions(definitions)
+    }
+}
+
+#[cfg(test)]
+fn j1939_test_rows(rows: &[&str]) -> Vec<Vec<String>> {
+    rows.iter().map(|row| row.split('|').map(|cell| cell.trim().to_string()).collect()).collect()
+}
+
+#[cfg(test)]
+const J1939_HEADER: &str =
+    "PGN | PGN Label | Transmission Rate | SPN | SPN Name | Start Position | Length | Resolution | Offset | Data Range | Unit";
+
+#[test]
+fn j1939_pgn_rows() {
+    let rows = j1939_test_rows(&[
+        "J1939 Digital Annex",
+        J1939_HEADER,
+        "F004 | Electronic Engine Controller 1 | 20 ms | 190 | Engine Speed | 4-5 | 2 bytes | 0.125 rpm/bit | 0 | 0 to 8,031.875 rpm | rpm",
+        "F004 | Electronic Engine Controller 1 | 20 ms | 899 | Engine Torque Mode | 1.1 | 4 bits | 16 states/4 bit | 0 | 0 to 15 | bit",
+        "F004 | Electronic Engine Controller 1 | 20 ms | 513 | Actual Engine - Percent Torque | 3 | 1 byte | 1 %/bit | -125 % | -125 to 125 % | %",
+        "FEF1 | Cruise Control/Vehicle Speed 1 | 100 ms | 84 | Wheel-Based Vehicle Speed | 2-3 | 2 bytes | 1/256 km/h per bit | 0 | 0 to 250.996 km/h | km/h",
+        "FEF1 | Cruise Control/Vehicle Speed 1 | 100 ms | 1234 | Manufacturer Data | 5 | Variable | | | |",
+        "FECA | DM1 | On request | | | | | | | |",
+    ]);
+    let messages = ElpisMessages::from_j1939_pgn_rows(&rows).unwrap();
+    assert_eq!(messages.get_messagedef_count(), 3);
+
+    let eec1 = messages.get_def_by_id(0xf004).unwrap();
+    assert_eq!(eec1.name, "Electronic Engine Controller 1");
+    assert_eq!((eec1.is_extended_id, eec1.comment.as_deref()), (Some(true), Some("Transmission rate: 20 ms")));
+    let speed = eec1.get_signal_by_name("Engine Speed").unwrap();
+    assert_eq!((speed.start, speed.length, speed.is_big_endian), (Some(24), 16, false));
+    assert_eq!((speed.scale, speed.maximum, speed.unit.as_deref()), (Some(0.125), 8031.875, Some("rpm")));
+    assert_eq!(speed.spn.as_deref(), Some("190"));
+    let mode = eec1.get_signal_by_name("Engine Torque Mode").unwrap();
+    assert_eq!((mode.start, mode.length, mode.scale), (Some(0), 4, None));
+    let torque = eec1.get_signal_by_name("Actual Engine - Percent Torque").unwrap();
+    assert_eq!((torque.start, torque.offset, torque.minimum), (Some(16), -125.0, -125.0));
+
+    // Fractional resolutions, and variable length SPNs are left out
+    let ccvs = messages.get_def_by_id(0xfef1).unwrap();
+    assert_eq!(ccvs.signals.len(), 1);
+    assert_eq!(ccvs.signals[0].scale, Some(1.0 / 256.0));
+    assert_eq!(ccvs.decode(&[0, 0x00, 0x32, 0, 0, 0, 0, 0]).signals[0].physical, 50.0);
+    assert!(messages.get_def_by_id(0xfeca).unwrap().signals.is_empty());
+}
+
+#[test]
+fn j1939_rejects_bad_rows() {
+    let parse = |row: &str| ElpisMessages::from_j1939_pgn_rows(&j1939_test_rows(&[J1939_HEADER, row]));
+    let error = parse("XYZ | EEC1 | 20 ms | 190 | Engine Speed | 4-5 | 2 bytes | | | |").err().unwrap();
+    assert_eq!(error.to_string(), "Invalid PGN \"XYZ\" on row 2");
+    assert!(parse("F004 | EEC1 | 20 ms | 190 | Engine Speed | 0 | 2 bytes | | | |").is_err());
+    assert!(parse("F004 | EEC1 | 20 ms | 190 | Engine Speed | 1.9 | 2 bits | | | |").is_err());
+    assert!(ElpisMessages::from_j1939_pgn_rows(&j1939_test_rows(&["PGN | Label"])).is_err());
+}
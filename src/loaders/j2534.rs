@@ -0,0 +1,139 @@
+// SAE J2534 PassThru configuration XML, the filter files PassThru tools use to say which CAN ids to
+// pass through. Each PASSTHRU_MSG gives one id and its data length:
+//
+//     <PASSTHRU_CONFIG>
+//       <FILTERS>
+//         <PASSTHRU_MSG>
+//           <NAME>EngineRequest</NAME>
+//           <PROTOCOL_ID>ISO15765</PROTOCOL_ID>
+//           <TX_FLAGS>0x00000100</TX_FLAGS>
+//           <ID>0x18DA10F1</ID>
+//           <DLC>8</DLC>
+//         </PASSTHRU_MSG>
+//       </FILTERS>
+//     </PASSTHRU_CONFIG>
+//
+// The files rarely describe signals, so every data byte becomes a signal named byte_N. A message
+// without a NAME is named after its id, and CAN_29BIT_ID (0x100) in TX_FLAGS marks an extended id.
+// CAN and ISO15765 frames carry at most 8 bytes, their CAN FD variants 64.
+
+use super::xml::{parse_document, required_number, Element};
+use crate::elpis::{ElpisMessages, FrameType, MessageDefinition, SignalDefinition};
+use anyhow::Context;
+use std::collections::HashMap;
+
+// TX_FLAGS bit for a 29 bit CAN id
+const CAN_29BIT_ID: i32 = 0x100;
+
+// Get whether a protocol is CAN FD, None when it isn't a CAN protocol
+fn is_can_fd_protocol(protocol: &str) -> Option<bool> {
+    match protocol {
+        "CAN" | "CAN_PS" | "ISO15765" | "ISO15765_PS" => Some(false),
+        "CAN_FD_PS" | "ISO15765_FD_PS" => Some(true),
+        _ => None,
+    }
+}
+
+fn load_message(element: &Element) -> anyhow::Result<MessageDefinition> {
+    let id = required_number(element, "ID", "PASSTHRU_MSG")?;
+    let owner = format!("PASSTHRU_MSG {:#x}", id);
+    let length = required_number(element, "DLC", &owner)?;
+
+    let protocol = element.child_text("PROTOCOL_ID").unwrap_or("CAN");
+    let is_can_fd = is_can_fd_protocol(protocol).with_context(|| format!("Unknown PROTOCOL_ID {} of {}", protocol, owner))?;
+    let max_length = if is_can_fd { 64 } else { 8 };
+    if !(0..=max_length).contains(&length) {
+        return Err(anyhow::anyhow!("DLC {} of {} is over the {} bytes of {}", length, owner, max_length, protocol));
+    }
+
+    let tx_flags = match element.child("TX_FLAGS") {
+        Some(_) => required_number(element, "TX_FLAGS", &owner)?,
+        None => 0,
+    };
+
+    let name = match element.child_text("NAME") {
+        Some(name) => name.to_string(),
+        None => format!("PassThru_{:#x}", id),
+    };
+    let mut message = MessageDefinition::new(&name, id, length);
+    message.frame_type = Some(if is_can_fd { FrameType::CanFd } else { FrameType::Can });
+    message.is_can_fd = Some(is_can_fd);
+    message.is_extended_id = Some(tx_flags & CAN_29BIT_ID != 0);
+    for byte in 0..length {
+        let mut signal = SignalDefinition::new(&format!("byte_{}", byte), Some(byte * 8), 8);
+        signal.is_big_endian = false;
+        message.signals.push(signal);
+    }
+    Ok(message)
+}
+
+impl ElpisMessages {
+    // Load ELPIS messages from the PASSTHRU_MSG filters of a J2534 PassThru configuration
+    pub fn load_from_j2534_passthru_cfg(xml: &str) -> anyhow::Result<Self> {
+        let document = parse_document(xml).context("Could not parse PassThru configuration")?;
+
+        // Filters often list an id more than once, e.g. as a pass filter and a flow control filter
+        let mut definitions: Vec<MessageDefinition> = Vec::new();
+        let mut lengths: HashMap<i32, i32> = HashMap::new();
+        for element in document.find_all("PASSTHRU_MSG") {
+            let message = load_message(element)?;
+            match lengths.insert(message.id, message.length) {
+                Some(length) if length == message.length => continue,
+                Some(length) => {
+                    return Err(anyhow::anyhow!(
+                        "PASSTHRU_MSG {:#x} is given a DLC of both {} and {}",
+                        message.id,
+                        length,
+                        message.length
+                    ))
+                }
+                None => definitions.push(message),
+            }
+        }
+
+        Self::from_loaded_definitions(definitions)
+    }
+}
+
+#[test]
+fn j2534_passthru_filters() {
+    let messages = ElpisMessages::load_from_j2534_passthru_cfg(include_str!("../../tests/j2534_filters.xml")).unwrap();
+    assert_eq!(messages.get_messagedef_count(), 3);
+
+    let request = messages.get_def_by_id(0x7e0).unwrap();
+    assert_eq!(request.name, "EngineRequest");
+    assert_eq!(request.is_extended_id, Some(false));
+    let names: Vec<&str> = request.signals.iter().map(|signal| signal.name.as_str()).collect();
+    assert_eq!(names, vec!["byte_0", "byte_1", "byte_2", "byte_3", "byte_4", "byte_5", "byte_6", "byte_7"]);
+    let decoded = request.decode(&[0x02, 0x01, 0x0c, 0, 0, 0, 0, 0]);
+    let raw: Vec<u128> = decoded.signals.iter().take(3).map(|signal| signal.raw).collect();
+    assert_eq!(raw, vec![0x02, 0x01, 0x0c]);
+
+    // Unnamed messages are named after their id, and TX_FLAGS marks extended ids
+    let response = messages.get_def_by_id(0x18daf110).unwrap();
+    assert_eq!(response.name, "PassThru_0x18daf110");
+    assert_eq!(response.is_extended_id, Some(true));
+    assert_eq!(response.signals.len(), 8);
+
+    let fd = messages.get_def_by_id(0x7e8).unwrap();
+    assert_eq!((fd.is_can_fd, fd.length, fd.signals.len()), (Some(true), 12, 12));
+}
+
+#[test]
+fn j2534_rejects_bad_filters() {
+    let filters = include_str!("../../tests/j2534_filters.xml");
+
+    let too_long = filters.replacen("<DLC>8</DLC>", "<DLC>9</DLC>", 1);
+    let error = format!("{:#}", ElpisMessages::load_from_j2534_passthru_cfg(&too_long).err().unwrap());
+    assert_eq!(error, "DLC 9 of PASSTHRU_MSG 0x7e0 is over the 8 bytes of ISO15765");
+
+    let conflicting = filters.replacen("<DLC>8</DLC>", "<DLC>4</DLC>", 1);
+    let error = format!("{:#}", ElpisMessages::load_from_j2534_passthru_cfg(&conflicting).err().unwrap());
+    assert_eq!(error, "PASSTHRU_MSG 0x7e0 is given a DLC of both 4 and 8");
+
+    let no_id = filters.replacen("<ID>0x7E0</ID>", "", 1);
+    assert!(ElpisMessages::load_from_j2534_passthru_cfg(&no_id).is_err());
+
+    let unknown_protocol = filters.replacen("ISO15765", "J1850VPW", 1);
+    assert!(ElpisMessages::load_from_j2534_passthru_cfg(&unknown_protocol).is_err());
+}
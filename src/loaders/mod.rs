@@ -0,0 +1,22 @@
+// Loaders that build ELPIS message databases from formats other than messages.json
+
+#[cfg(feature = "autosar")]
+mod autosar_extract;
+#[cfg(feature = "autosar")]
+mod autosar_system;
+mod canmatrix_json;
+#[cfg(feature = "flexray")]
+mod flexray_fibex;
+#[cfg(feature = "capnp")]
+mod capnp_schema;
+mod influxdb_schema;
+#[cfg(feature = "j1939")]
+mod j1939_pgn;
+#[cfg(feature = "j2534")]
+mod j2534;
+mod msgdef_binary;
+mod node_red;
+mod peak_csv;
+mod ros_msg;
+#[cfg(any(feature = "autosar", feature = "j2534", feature = "flexray"))]
+mod xml;
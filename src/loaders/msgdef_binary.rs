@@ -0,0 +1,255 @@
+// Compact binary message database format. All integers are big endian, like the ELPIS wire format.
+//
+// Header:     "ELPB" magic, u16 version (1), u32 message count
+// Message:    i32 id, u8 name length + name, u8 signal count, u16 payload length
+// Signal:     u8 name length + name, u16 start (0xFFFF when not given), u16 length,
+//             u8 flags, f32 scale, f32 offset
+//
+// Only the layout of each signal is stored, comments, ranges, choices and the like are dropped.
+
+use crate::elpis::{ElpisMessages, MessageDefinition, SignalDefinition};
+use anyhow::Context;
+
+const MAGIC: &[u8; 4] = b"ELPB";
+const VERSION: u16 = 1;
+
+// Marks a signal without an explicit start
+const NO_START: u16 = 0xFFFF;
+
+const FLAG_BIG_ENDIAN: u8 = 1 << 0;
+const FLAG_SIGNED: u8 = 1 << 1;
+const FLAG_FLOAT: u8 = 1 << 2;
+
+// Reads fields from the binary format, failing on truncation
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, length: usize) -> anyhow::Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.offset..self.offset + length)
+            .with_context(|| format!("Unexpected end of data at offset {}", self.offset))?;
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn f32(&mut self) -> anyhow::Result<f32> {
+        Ok(f32::from_be_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn name(&mut self) -> anyhow::Result<String> {
+        let length = self.u8()? as usize;
+        String::from_utf8(self.bytes(length)?.to_vec()).context("Name is not valid UTF-8")
+    }
+}
+
+fn write_name(output: &mut Vec<u8>, name: &str) -> anyhow::Result<()> {
+    let length: u8 = name
+        .len()
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Name {} is longer than 255 bytes", name))?;
+    output.push(length);
+    output.extend_from_slice(name.as_bytes());
+    Ok(())
+}
+
+impl ElpisMessages {
+    // Load ELPIS messages from the given path to a compact binary message database
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_msgdef_binary(path: &str) -> anyhow::Result<Self> {
+        let data = std::fs::read(path).with_context(|| format!("Could not open file {}", path))?;
+        let mut messages = Self::from_msgdef_binary(&data).with_context(|| format!("Could not parse binary file {}", path))?;
+        messages.set_source(path);
+        Ok(messages)
+    }
+
+    // Parse ELPIS messages from a compact binary message database
+    pub fn from_msgdef_binary(data: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader { data, offset: 0 };
+
+        if reader.bytes(4)? != MAGIC {
+            return Err(anyhow::anyhow!("Missing ELPB magic"));
+        }
+
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(anyhow::anyhow!("Unsupported version {}", version));
+        }
+
+        let message_count = reader.u32()?;
+        let mut definitions = Vec::new();
+        for _ in 0..message_count {
+            let id = reader.u32()? as i32;
+            let name = reader.name()?;
+            let signal_count = reader.u8()?;
+            let length = reader.u16()?;
+
+            let mut message = MessageDefinition::new(&name, id, length as i32);
+            for _ in 0..signal_count {
+                let name = reader.name()?;
+                let start = reader.u16()?;
+                let length = reader.u16()?;
+                let flags = reader.u8()?;
+                let scale = reader.f32()?;
+                let offset = reader.f32()?;
+
+                let start = if start == NO_START { None } else { Some(start as i32) };
+                let mut signal = SignalDefinition::new(&name, start, length as i32);
+                signal.is_big_endian = flags & FLAG_BIG_ENDIAN != 0;
+                signal.is_signed = Some(flags & FLAG_SIGNED != 0);
+                signal.is_float = Some(flags & FLAG_FLOAT != 0);
+                signal.scale = Some(scale as f64);
+                signal.offset = offset as f64;
+                message.signals.push(signal);
+            }
+
+            definitions.push(message);
+        }
+
+        if reader.offset != data.len() {
+            return Err(anyhow::anyhow!("Trailing data after {} messages", message_count));
+        }
+
+        Self::from_loaded_definitions(definitions)
+    }
+
+    // Save these messages to the given path as a compact binary message database
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_to_msgdef_binary(&self, path: &str) -> anyhow::Result<()> {
+        let data = self.to_msgdef_binary()?;
+        std::fs::write(path, data).with_context(|| format!("Could not write file {}", path))
+    }
+
+    // Serialize these messages to a compact binary message database
+    pub fn to_msgdef_binary(&self) -> anyhow::Result<Vec<u8>> {
+        let mut output = Vec::new();
+        output.extend_from_slice(MAGIC);
+        output.extend_from_slice(&VERSION.to_be_bytes());
+        output.extend_from_slice(&(self.get_messagedef_count() as u32).to_be_bytes());
+
+        for message in self.iter_sorted() {
+            let signal_count: u8 = message
+                .signals
+                .len()
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Message {} has more than 255 signals", message.name))?;
+            let length: u16 = message
+                .length
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Message {} has an invalid length", message.name))?;
+
+            output.extend_from_slice(&message.id.to_be_bytes());
+            write_name(&mut output, &message.name)?;
+            output.push(signal_count);
+            output.extend_from_slice(&length.to_be_bytes());
+
+            for signal in message.signals.iter() {
+                let start: u16 = match signal.start {
+                    Some(start) => start
+                        .try_into()
+                        .ok()
+                        .filter(|start| *start != NO_START)
+                        .with_context(|| format!("Signal {} has an invalid start", signal.name))?,
+                    None => NO_START,
+                };
+                let length: u16 = signal
+                    .length
+                    .try_into()
+                    .map_err(|_| anyhow::anyhow!("Signal {} has an invalid length", signal.name))?;
+
+                let mut flags = 0;
+                if signal.is_big_endian {
+                    flags |= FLAG_BIG_ENDIAN;
+                }
+                if signal.is_signed.unwrap_or(false) {
+                    flags |= FLAG_SIGNED;
+                }
+                if signal.is_float.unwrap_or(false) {
+                    flags |= FLAG_FLOAT;
+                }
+
+                write_name(&mut output, &signal.name)?;
+                output.extend_from_slice(&start.to_be_bytes());
+                output.extend_from_slice(&length.to_be_bytes());
+                output.push(flags);
+                output.extend_from_slice(&(signal.scale.unwrap_or(1.0) as f32).to_be_bytes());
+                output.extend_from_slice(&(signal.offset as f32).to_be_bytes());
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+#[test]
+fn msgdef_binary_round_trip() {
+    let mut speed = SignalDefinition::new("Speed", Some(7), 16);
+    speed.scale = Some(0.25);
+    speed.offset = -40.0;
+    speed.is_signed = Some(true);
+
+    let mut counter = SignalDefinition::new("Counter", None, 4);
+    counter.is_big_endian = false;
+
+    let mut flag = SignalDefinition::new("Flag", Some(20), 32);
+    flag.is_float = Some(true);
+
+    let mut first = MessageDefinition::new("Wheels", 0x120, 8);
+    first.signals.push(speed);
+    first.signals.push(counter);
+    first.signals.push(flag);
+    let second = MessageDefinition::new("Empty", 0x7FF, 0);
+
+    let messages = ElpisMessages::from_definitions(vec![first, second]);
+    let data = messages.to_msgdef_binary().unwrap();
+    assert_eq!(&data[0..4], b"ELPB");
+
+    let loaded = ElpisMessages::from_msgdef_binary(&data).unwrap();
+    assert_eq!(loaded.get_messagedef_count(), 2);
+    assert_eq!(loaded.get_def_by_id(0x7FF).unwrap().name, "Empty");
+
+    let wheels = loaded.get_def_by_id(0x120).unwrap();
+    assert_eq!(wheels.name, "Wheels");
+    assert_eq!(wheels.length, 8);
+    assert_eq!(wheels.signals.len(), 3);
+
+    let speed = wheels.get_signal_by_name("Speed").unwrap();
+    assert_eq!(speed.start, Some(7));
+    assert_eq!(speed.length, 16);
+    assert!(speed.is_big_endian);
+    assert_eq!(speed.is_signed, Some(true));
+    assert_eq!(speed.scale, Some(0.25));
+    assert_eq!(speed.offset, -40.0);
+
+    let counter = wheels.get_signal_by_name("Counter").unwrap();
+    assert_eq!(counter.start, None);
+    assert!(!counter.is_big_endian);
+
+    assert_eq!(wheels.get_signal_by_name("Flag").unwrap().is_float, Some(true));
+
+    // Saving the loaded database again produces identical bytes
+    assert_eq!(loaded.to_msgdef_binary().unwrap(), data);
+}
+
+#[test]
+fn msgdef_binary_rejects_bad_data() {
+    assert!(ElpisMessages::from_msgdef_binary(b"ELPX\x00\x01\x00\x00\x00\x00").is_err());
+    assert!(ElpisMessages::from_msgdef_binary(b"ELPB\x00\x02\x00\x00\x00\x00").is_err());
+    assert!(ElpisMessages::from_msgdef_binary(b"ELPB\x00\x01\x00\x00\x00\x01").is_err());
+    assert!(ElpisMessages::from_msgdef_binary(b"ELPB\x00\x01\x00\x00\x00\x00").is_ok());
+}
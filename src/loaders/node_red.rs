@@ -0,0 +1,185 @@
+// Node-RED flow exports (flows.json) from edge deployments that keep their CAN message definitions
+// as configuration nodes of type can-message:
+//
+//     [{ "id": "0x120", "type": "can-message", "name": "Wheels", "dlc": 8,
+//        "signals": [{ "name": "Speed", "start": 0, "length": 16, "scale": 0.01, "unit": "km/h" }] },
+//      { "id": "7f3a.9c1", "type": "inject", "z": "flow1", "wires": [] }]
+//
+// Every other node of the flow is skipped. The node's id is the CAN id, as a number or a decimal or
+// 0x-prefixed hex string. Signals are little endian unless their "byte_order" is "big_endian", with
+// start bits as in messages.json.
+
+use crate::elpis::{ElpisMessages, MessageDefinition, SignalDefinition};
+use anyhow::Context;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashSet;
+
+// Node type holding a CAN message definition
+const CAN_MESSAGE_NODE: &str = "can-message";
+
+#[derive(Deserialize)]
+struct NodeRedSignal {
+    name: String,
+    start: i32,
+    length: i32,
+    #[serde(default)]
+    byte_order: Option<String>,
+    #[serde(default)]
+    signed: bool,
+    #[serde(default)]
+    scale: Option<f64>,
+    #[serde(default)]
+    offset: f64,
+    #[serde(default)]
+    unit: Option<String>,
+}
+
+// A can-message node. The editor adds keys such as "z", "x" and "wires" to every node, so unknown
+// keys are allowed.
+#[derive(Deserialize)]
+struct NodeRedMessage {
+    id: Value,
+    name: String,
+    dlc: i32,
+    #[serde(default)]
+    signals: Vec<NodeRedSignal>,
+}
+
+// Get the CAN id of a node, given as a number or a decimal or hex string
+fn can_id(id: &Value) -> Option<i32> {
+    match id {
+        Value::Number(number) => number.as_i64().and_then(|id| i32::try_from(id).ok()),
+        Value::String(text) => {
+            let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+                Some(hex) => i64::from_str_radix(hex, 16).ok(),
+                None => text.parse::<i64>().ok(),
+            };
+            parsed.and_then(|id| i32::try_from(id).ok())
+        }
+        _ => None,
+    }
+    .filter(|id| *id >= 0)
+}
+
+fn load_signal(signal: NodeRedSignal, message: &str) -> anyhow::Result<SignalDefinition> {
+    if signal.start < 0 || signal.length <= 0 || signal.length > 64 {
+        return Err(anyhow::anyhow!(
+            "Signal {}.{} has an invalid layout, start bit {} and length {}",
+            message,
+            signal.name,
+            signal.start,
+            signal.length
+        ));
+    }
+
+    let mut definition = SignalDefinition::new(&signal.name, Some(signal.start), signal.length);
+    definition.is_big_endian = match signal.byte_order.as_deref() {
+        Some("big_endian") => true,
+        Some("little_endian") | None => false,
+        Some(other) => return Err(anyhow::anyhow!("Unknown byte_order {} of signal {}.{}", other, message, signal.name)),
+    };
+    definition.is_signed = Some(signal.signed);
+    definition.scale = signal.scale;
+    definition.offset = signal.offset;
+    definition.unit = signal.unit;
+    Ok(definition)
+}
+
+impl ElpisMessages {
+    // Load ELPIS messages from the given path to a Node-RED flow export
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_node_red_flows(path: &str) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("Could not open file {}", path))?;
+        let mut messages =
+            Self::from_node_red_flows(&contents).with_context(|| format!("Could not parse Node-RED flows {}", path))?;
+        messages.set_source(path);
+        Ok(messages)
+    }
+
+    // Parse ELPIS messages from the can-message nodes of a Node-RED flow export
+    pub fn from_node_red_flows(contents: &str) -> anyhow::Result<Self> {
+        let nodes: Vec<Value> = serde_json::from_str(contents)?;
+
+        let mut ids = HashSet::new();
+        let mut definitions = Vec::new();
+        for node in nodes {
+            if node.get("type").and_then(Value::as_str) != Some(CAN_MESSAGE_NODE) {
+                continue;
+            }
+
+            let message: NodeRedMessage = serde_json::from_value(node).context("Invalid can-message node")?;
+            let id = can_id(&message.id).with_context(|| format!("Node {} has an invalid CAN id {}", message.name, message.id))?;
+            if !(0..=64).contains(&message.dlc) {
+                return Err(anyhow::anyhow!("Node {} has an invalid dlc {}", message.name, message.dlc));
+            }
+            if !ids.insert(id) {
+                return Err(anyhow::anyhow!("Node {} uses CAN id {:#x} of an earlier node", message.name, id));
+            }
+
+            let mut definition = MessageDefinition::new(&message.name, id, message.dlc);
+            for signal in message.signals {
+                definition.signals.push(load_signal(signal, &message.name)?);
+            }
+            definitions.push(definition);
+        }
+
+        Self::from_loaded_definitions(definitions)
+    }
+}
+
+#[cfg(test)]
+const TEST_FLOWS: &str = r#"[
+    { "id": "f1a2b3c4.d5e6f", "type": "tab", "label": "CAN gateway" },
+    {
+        "id": "0x120",
+        "type": "can-message",
+        "z": "f1a2b3c4.d5e6f",
+        "name": "Wheels",
+        "dlc": 8,
+        "signals": [
+            { "name": "SpeedFL", "start": 0, "length": 16, "scale": 0.01, "unit": "km/h" },
+            { "name": "Torque", "start": 23, "length": 12, "byte_order": "big_endian", "signed": true }
+        ]
+    },
+    { "id": 1792, "type": "can-message", "z": "f1a2b3c4.d5e6f", "name": "Heartbeat", "dlc": 1 },
+    { "id": "9e8d7c6b.5a4f3", "type": "socketcan-in", "z": "f1a2b3c4.d5e6f", "wires": [["0x120"]] }
+]"#;
+
+#[test]
+fn node_red_flows_layout() {
+    let messages = ElpisMessages::from_node_red_flows(TEST_FLOWS).unwrap();
+    assert_eq!(messages.get_messagedef_count(), 2);
+
+    let heartbeat = messages.get_def_by_id(0x700).unwrap();
+    assert_eq!((heartbeat.name.as_str(), heartbeat.length, heartbeat.signals.len()), ("Heartbeat", 1, 0));
+
+    let wheels = messages.get_def_by_id(0x120).unwrap();
+    assert_eq!(wheels.length, 8);
+    let speed = wheels.get_signal_by_name("SpeedFL").unwrap();
+    assert!(!speed.is_big_endian);
+    assert_eq!((speed.scale, speed.unit.as_deref()), (Some(0.01), Some("km/h")));
+    let torque = wheels.get_signal_by_name("Torque").unwrap();
+    assert!(torque.is_big_endian);
+    assert_eq!(torque.is_signed, Some(true));
+
+    let decoded = wheels.decode(&[0x34, 0x12, 0xab, 0xc0, 0, 0, 0, 0]);
+    assert_eq!(decoded.signals[0].raw, 0x1234);
+    assert_eq!(decoded.signals[1].raw, 0xabc);
+}
+
+#[test]
+fn node_red_flows_rejects_bad_nodes() {
+    let parse = ElpisMessages::from_node_red_flows;
+
+    let error = format!("{:#}", parse(&TEST_FLOWS.replace("\"0x120\",\n", "\"f00d.beef\",\n")).err().unwrap());
+    assert_eq!(error, "Node Wheels has an invalid CAN id \"f00d.beef\"");
+    assert!(parse(&TEST_FLOWS.replace("\"id\": 1792", "\"id\": 288")).is_err());
+    assert!(parse(&TEST_FLOWS.replace("\"dlc\": 1", "\"dlc\": 65")).is_err());
+    assert!(parse(&TEST_FLOWS.replace("\"big_endian\"", "\"motorola\"")).is_err());
+    assert!(parse(&TEST_FLOWS.replace("\"length\": 12", "\"length\": 0")).is_err());
+    assert!(parse(r#"{ "type": "can-message" }"#).is_err());
+
+    // Flows without any can-message nodes are an empty database
+    assert_eq!(parse(r#"[{ "id": "1", "type": "tab" }]"#).unwrap().get_messagedef_count(), 0);
+}
@@ -0,0 +1,179 @@
+// Traces exported from PEAK PCAN-View as CSV, for starting a database from captured traffic:
+//
+//     Time,ID,Rx/Tx,DLC,Data[0],Data[1],Data[2],Data[3],Data[4],Data[5],Data[6],Data[7]
+//     0.1254,0123,Rx,2,1A,FF,,,,,,
+//     0.1302,18FEF100,Rx,8,00,7D,7D,00,00,00,F0,FF
+//
+// Every CAN id seen becomes a message named after it, as long as its most common DLC with a signal
+// named byte_N per data byte. Ids and data are hex, with or without a 0x prefix or h suffix, and
+// ids over 0x7FF are extended. Exports made with a comma as the decimal separator use semicolons
+// between columns, which is detected from the header.
+
+use crate::elpis::{ElpisMessages, FrameType, MessageDefinition, SignalDefinition};
+use anyhow::Context;
+use std::collections::{BTreeMap, HashMap};
+
+// Highest 11-bit standard CAN id
+const MAX_STANDARD_ID: i32 = 0x7ff;
+
+// Columns of the export, located by their header
+struct Columns {
+    id: usize,
+    dlc: usize,
+    data: Vec<usize>,
+}
+
+impl Columns {
+    fn from_header(header: &[&str]) -> anyhow::Result<Self> {
+        let find = |name: &str| {
+            header
+                .iter()
+                .position(|column| column.eq_ignore_ascii_case(name))
+                .with_context(|| format!("PCAN-View CSV header has no {} column", name))
+        };
+        let data = (0..8)
+            .map_while(|byte| header.iter().position(|column| column.eq_ignore_ascii_case(&format!("Data[{}]", byte))))
+            .collect();
+        Ok(Self {
+            id: find("ID")?,
+            dlc: find("DLC")?,
+            data,
+        })
+    }
+}
+
+// Parse a hex number written as 1A, 0x1A or 1Ah
+fn parse_hex(text: &str) -> Option<u32> {
+    let text = text.trim();
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .or_else(|| text.strip_suffix('h'))
+        .or_else(|| text.strip_suffix('H'))
+        .unwrap_or(text);
+    u32::from_str_radix(digits, 16).ok()
+}
+
+// Get the DLC seen most often, the longer one of those seen equally often
+fn most_common(dlcs: &BTreeMap<i32, usize>) -> i32 {
+    dlcs.iter()
+        .max_by_key(|(dlc, count)| (**count, **dlc))
+        .map(|(dlc, _)| *dlc)
+        .unwrap_or(0)
+}
+
+impl ElpisMessages {
+    // Load ELPIS messages from the given path to a PCAN-View CSV trace
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_peak_monitor_csv(path: &str) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("Could not open file {}", path))?;
+        let mut messages =
+            Self::from_peak_monitor_csv(&contents).with_context(|| format!("Could not parse PCAN-View trace {}", path))?;
+        messages.set_source(path);
+        Ok(messages)
+    }
+
+    // Build skeleton ELPIS messages from the CAN ids and DLCs in a PCAN-View CSV trace
+    pub fn from_peak_monitor_csv(contents: &str) -> anyhow::Result<Self> {
+        let mut lines = contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let (_, header) = lines.next().context("PCAN-View CSV trace is empty")?;
+        let separator = if header.contains(';') { ';' } else { ',' };
+        let split = |line: &str| -> Vec<String> {
+            line.split(separator).map(|column| column.trim().trim_matches('"').trim().to_string()).collect()
+        };
+        let header = split(header);
+        let columns = Columns::from_header(&header.iter().map(String::as_str).collect::<Vec<&str>>())?;
+
+        // Count of each DLC per id, ids in the order they were first seen
+        let mut dlcs: HashMap<i32, BTreeMap<i32, usize>> = HashMap::new();
+        let mut order = Vec::new();
+        for (index, line) in lines {
+            let row = split(line);
+            let line_number = index + 1;
+            let column = |column: usize| row.get(column).map(String::as_str).unwrap_or_default();
+
+            let id = parse_hex(column(columns.id))
+                .and_then(|id| i32::try_from(id).ok())
+                .filter(|id| *id <= 0x1fff_ffff)
+                .with_context(|| format!("Invalid CAN id \"{}\" on line {}", column(columns.id), line_number))?;
+            let dlc = column(columns.dlc)
+                .parse::<i32>()
+                .ok()
+                .filter(|dlc| (0..=8).contains(dlc))
+                .with_context(|| format!("Invalid DLC \"{}\" on line {}", column(columns.dlc), line_number))?;
+            let data_bytes = columns
+                .data
+                .iter()
+                .take(dlc as usize)
+                .filter(|data| parse_hex(column(**data)).is_some_and(|byte| byte <= 0xff))
+                .count();
+            if data_bytes < dlc as usize {
+                return Err(anyhow::anyhow!("Line {} has fewer than its {} data bytes", line_number, dlc));
+            }
+
+            let counts = dlcs.entry(id).or_insert_with(|| {
+                order.push(id);
+                BTreeMap::new()
+            });
+            *counts.entry(dlc).or_default() += 1;
+        }
+
+        let definitions = order
+            .into_iter()
+            .map(|id| {
+                let length = most_common(&dlcs[&id]);
+                let mut message = MessageDefinition::new(&format!("PCAN_{:#x}", id), id, length);
+                message.frame_type = Some(FrameType::Can);
+                message.is_extended_id = Some(id > MAX_STANDARD_ID);
+                for byte in 0..length {
+                    let mut signal = SignalDefinition::new(&format!("byte_{}", byte), Some(byte * 8), 8);
+                    signal.is_big_endian = false;
+                    message.signals.push(signal);
+                }
+                message
+            })
+            .collect();
+
+        Self::from_loaded_definitions(definitions)
+    }
+}
+
+#[cfg(test)]
+const TEST_TRACE: &str = "Time,ID,Rx/Tx,DLC,Data[0],Data[1],Data[2],Data[3],Data[4],Data[5],Data[6],Data[7]
+0.1254,0123,Rx,2,1A,FF,,,,,,
+0.1302,18FEF100,Rx,8,00,7D,7D,00,00,00,F0,FF
+0.2254,0123,Rx,3,1B,FF,01,,,,,
+0.3254,0123,Tx,2,1C,FF,,,,,,
+";
+
+#[test]
+fn peak_monitor_csv_messages() {
+    let messages = ElpisMessages::from_peak_monitor_csv(TEST_TRACE).unwrap();
+    assert_eq!(messages.get_messagedef_count(), 2);
+
+    // The DLC seen most often decides the length
+    let status = messages.get_def_by_id(0x123).unwrap();
+    assert_eq!((status.name.as_str(), status.length, status.is_extended_id), ("PCAN_0x123", 2, Some(false)));
+    let names: Vec<&str> = status.signals.iter().map(|signal| signal.name.as_str()).collect();
+    assert_eq!(names, vec!["byte_0", "byte_1"]);
+    assert_eq!(status.decode(&[0x1a, 0xff]).signals[1].raw, 0xff);
+
+    let engine = messages.get_def_by_id(0x18fef100).unwrap();
+    assert_eq!((engine.length, engine.is_extended_id, engine.signals.len()), (8, Some(true), 8));
+
+    // Semicolon separated exports, quoted columns and 0x prefixes
+    let trace = "\"Time\";\"ID\";\"Rx/Tx\";\"DLC\";\"Data[0]\"\n1,5;0x7FF;Rx;1;0x42\n";
+    let messages = ElpisMessages::from_peak_monitor_csv(trace).unwrap();
+    assert_eq!(messages.get_def_by_id(0x7ff).unwrap().length, 1);
+}
+
+#[test]
+fn peak_monitor_csv_rejects_bad_rows() {
+    let parse = ElpisMessages::from_peak_monitor_csv;
+    let error = parse(&TEST_TRACE.replace("0.2254,0123", "0.2254,XYZ")).err().unwrap();
+    assert_eq!(error.to_string(), "Invalid CAN id \"XYZ\" on line 4");
+    assert!(parse(&TEST_TRACE.replace("Rx,2,1A", "Rx,9,1A")).is_err());
+    assert!(parse(&TEST_TRACE.replace("Rx,3,1B,FF,01", "Rx,3,1B,FF,")).is_err());
+    assert!(parse("Time,Rx/Tx,DLC\n").is_err());
+    assert!(parse("").is_err());
+}
@@ -0,0 +1,195 @@
+// Robot Operating System message definition files (.msg). Each file defines a single message as
+// one `<type> <field_name>` per line, laid out back to back in little endian like ROS serializes
+// them. The message is named after the file, and its ELPIS id is taken from an `# id: <id>`
+// comment, defaulting to 0.
+//
+// Fixed size arrays such as `uint8[4] data` become one signal per element (data_0 .. data_3).
+// Constants (`uint8 MODE_AUTO=1`) are skipped. Strings, unbounded arrays and nested message types
+// have no fixed layout and are rejected.
+
+use crate::elpis::{ElpisMessages, MessageDefinition, SignalDefinition};
+use anyhow::Context;
+
+// Length in bits, signedness and whether the type is a float
+fn primitive_layout(ros_type: &str) -> Option<(i32, bool, bool)> {
+    Some(match ros_type {
+        "bool" | "uint8" | "byte" | "char" => (8, false, false),
+        "int8" => (8, true, false),
+        "uint16" => (16, false, false),
+        "int16" => (16, true, false),
+        "uint32" => (32, false, false),
+        "int32" => (32, true, false),
+        "uint64" => (64, false, false),
+        "int64" => (64, true, false),
+        "float32" => (32, false, true),
+        "float64" => (64, false, true),
+        _ => return None,
+    })
+}
+
+// Parse an id written in decimal or as 0x-prefixed hex
+fn parse_id(text: &str) -> anyhow::Result<i32> {
+    let id = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse::<u32>(),
+    };
+    id.map(|id| id as i32)
+        .map_err(|_| anyhow::anyhow!("Invalid id {}", text))
+}
+
+impl ElpisMessages {
+    // Load an ELPIS message from the given path to a ROS .msg file
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_ros_msg(path: &str) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("Could not open file {}", path))?;
+        let name = std::path::Path::new(path)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .with_context(|| format!("Could not get a message name from {}", path))?;
+
+        let mut messages =
+            Self::from_ros_msg(name, &contents).with_context(|| format!("Could not parse ROS message file {}", path))?;
+        messages.set_source(path);
+        Ok(messages)
+    }
+
+    // Parse an ELPIS message with the given name from the contents of a ROS .msg file
+    pub fn from_ros_msg(name: &str, contents: &str) -> anyhow::Result<Self> {
+        let mut id = 0;
+        let mut signals = Vec::new();
+        let mut start = 0;
+
+        for (line_number, line) in contents.lines().enumerate() {
+            let line_number = line_number + 1;
+            let (definition, comment) = match line.split_once('#') {
+                Some((definition, comment)) => (definition.trim(), Some(comment.trim())),
+                None => (line.trim(), None),
+            };
+
+            if definition.is_empty() {
+                if let Some(value) = comment.and_then(|comment| comment.strip_prefix("id:")) {
+                    id = parse_id(value.trim()).with_context(|| format!("Line {}", line_number))?;
+                }
+                continue;
+            }
+
+            // Constants don't take up any space in the message
+            if definition.contains('=') {
+                continue;
+            }
+
+            let (ros_type, field_name) = match definition.split_whitespace().collect::<Vec<&str>>()[..] {
+                [ros_type, field_name] => (ros_type, field_name),
+                _ => return Err(anyhow::anyhow!("Line {}: expected \"<type> <field_name>\"", line_number)),
+            };
+
+            let (base_type, count) = match ros_type.split_once('[') {
+                Some((base_type, rest)) => {
+                    let count = rest
+                        .strip_suffix(']')
+                        .and_then(|count| count.parse::<usize>().ok())
+                        .with_context(|| {
+                            format!("Line {}: only fixed size arrays are supported, not {}", line_number, ros_type)
+                        })?;
+                    (base_type, Some(count))
+                }
+                None => (ros_type, None),
+            };
+
+            let (length, is_signed, is_float) = primitive_layout(base_type)
+                .with_context(|| format!("Line {}: unsupported type {}", line_number, base_type))?;
+
+            let names = match count {
+                Some(count) => (0..count).map(|index| format!("{}_{}", field_name, index)).collect(),
+                None => vec![field_name.to_string()],
+            };
+            for name in names {
+                let mut signal = SignalDefinition::new(&name, Some(start), length);
+                signal.is_big_endian = false;
+                signal.is_signed = Some(is_signed);
+                signal.is_float = Some(is_float);
+                signals.push(signal);
+                start += length;
+            }
+        }
+
+        let mut message = MessageDefinition::new(name, id, start / 8);
+        message.signals = signals;
+
+        Self::from_loaded_definitions(vec![message])
+    }
+}
+
+#[test]
+fn ros_msg_layout() {
+    let contents = "
+        # Wheel speeds reported by the drive controller
+        # id: 0x120
+
+        uint8 MODE_AUTO=1   # constants take no space
+        uint8 mode
+        int16 steering      # tenths of a degree
+        float32 speed
+        uint16[2] wheel
+        float64 odometer
+        bool braking
+    ";
+
+    let messages = ElpisMessages::from_ros_msg("DriveStatus", contents).unwrap();
+    let message = messages.get_def_by_id(0x120).unwrap();
+    assert_eq!(message.name, "DriveStatus");
+    assert_eq!(message.length, 1 + 2 + 4 + 4 + 8 + 1);
+
+    let layout: Vec<(&str, Option<i32>, i32)> = message
+        .signals
+        .iter()
+        .map(|signal| (signal.name.as_str(), signal.start, signal.length))
+        .collect();
+    assert_eq!(
+        layout,
+        vec![
+            ("mode", Some(0), 8),
+            ("steering", Some(8), 16),
+            ("speed", Some(24), 32),
+            ("wheel_0", Some(56), 16),
+            ("wheel_1", Some(72), 16),
+            ("odometer", Some(88), 64),
+            ("braking", Some(152), 8),
+        ]
+    );
+    assert!(message.signals.iter().all(|signal| !signal.is_big_endian));
+
+    let steering = message.get_signal_by_name("steering").unwrap();
+    assert_eq!(steering.is_signed, Some(true));
+    let speed = message.get_signal_by_name("speed").unwrap();
+    assert_eq!(speed.is_float, Some(true));
+
+    // Values decode in ROS's little endian byte order
+    let mut payload = vec![2, 0xFE, 0xFF];
+    payload.extend_from_slice(&12.5f32.to_le_bytes());
+    payload.extend_from_slice(&[0x34, 0x12, 0x78, 0x56]);
+    payload.extend_from_slice(&1000.25f64.to_le_bytes());
+    payload.push(1);
+
+    let decoded = message.decode(&payload);
+    assert!(decoded.errors.is_empty(), "{:?}", decoded.errors);
+    let value = |name: &str| decoded.signals.iter().find(|signal| signal.name == name).unwrap().physical;
+    assert_eq!(value("mode"), 2.0);
+    assert_eq!(value("steering"), -2.0);
+    assert_eq!(value("speed"), 12.5);
+    assert_eq!(value("wheel_0"), 0x1234 as f64);
+    assert_eq!(value("wheel_1"), 0x5678 as f64);
+    assert_eq!(value("odometer"), 1000.25);
+    assert_eq!(value("braking"), 1.0);
+}
+
+#[test]
+fn ros_msg_rejects_variable_layouts() {
+    for contents in ["string label", "uint8[] data", "geometry_msgs/Twist twist", "uint8", "# id: twelve"] {
+        assert!(ElpisMessages::from_ros_msg("Bad", contents).is_err(), "{} should not load", contents);
+    }
+
+    // Without an id comment the message gets id 0
+    let messages = ElpisMessages::from_ros_msg("Plain", "uint32 counter").unwrap();
+    assert_eq!(messages.get_def_by_id(0).unwrap().length, 4);
+}
@@ -0,0 +1,132 @@
+// A minimal tree of XML elements, for the loaders of XML formats
+
+use anyhow::Context;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+// An XML element with its text, attributes and child elements. Names of elements and attributes are
+// kept without their namespace prefix.
+pub(super) struct Element {
+    pub(super) name: String,
+    pub(super) text: String,
+    pub(super) attributes: Vec<(String, String)>,
+    pub(super) children: Vec<Element>,
+}
+
+impl Element {
+    fn new(start: &BytesStart) -> anyhow::Result<Self> {
+        let mut attributes = Vec::new();
+        for attribute in start.attributes() {
+            let attribute = attribute?;
+            let name = String::from_utf8_lossy(attribute.key.local_name().as_ref()).into_owned();
+            attributes.push((name, attribute.unescape_value()?.into_owned()));
+        }
+        Ok(Self {
+            name: String::from_utf8_lossy(start.local_name().as_ref()).into_owned(),
+            text: String::new(),
+            attributes,
+            children: Vec::new(),
+        })
+    }
+
+    pub(super) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    pub(super) fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    pub(super) fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|child| child.text.trim())
+    }
+
+    // Find every element with the given name below this one, not looking inside matches
+    fn descendants<'a>(&'a self, name: &str, found: &mut Vec<&'a Element>) {
+        for child in self.children.iter() {
+            if child.name == name {
+                found.push(child);
+            } else {
+                child.descendants(name, found);
+            }
+        }
+    }
+
+    pub(super) fn find_all(&self, name: &str) -> Vec<&Element> {
+        let mut found = Vec::new();
+        self.descendants(name, &mut found);
+        found
+    }
+}
+
+// Read a whole document into a tree of elements
+pub(super) fn parse_document(xml: &str) -> anyhow::Result<Element> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut stack = vec![Element {
+        name: String::new(),
+        text: String::new(),
+        attributes: Vec::new(),
+        children: Vec::new(),
+    }];
+    loop {
+        let event = reader
+            .read_event()
+            .with_context(|| format!("Invalid XML at byte {}", reader.buffer_position()))?;
+        match event {
+            Event::Start(start) => stack.push(Element::new(&start)?),
+            Event::Empty(empty) => stack.last_mut().unwrap().children.push(Element::new(&empty)?),
+            Event::Text(text) => stack.last_mut().unwrap().text.push_str(&text.unescape()?),
+            Event::End(_) => {
+                let element = stack.pop().unwrap();
+                stack
+                    .last_mut()
+                    .context("Closing tag without an opening tag")?
+                    .children
+                    .push(element);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if stack.len() != 1 {
+        return Err(anyhow::anyhow!("Unclosed element {}", stack.last().unwrap().name));
+    }
+    Ok(stack.pop().unwrap())
+}
+
+// Parse a decimal or 0x-prefixed hex number
+pub(super) fn parse_number(text: &str) -> anyhow::Result<i32> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => text.parse::<i64>(),
+    };
+    parsed
+        .ok()
+        .and_then(|number| i32::try_from(number).ok())
+        .with_context(|| format!("Invalid number {}", text))
+}
+
+// Convert the least significant bit of a big endian signal to the most significant bit its start is
+// given as, walking up through the more significant bits in Motorola numbering. AUTOSAR and FIBEX
+// both give the least significant bit.
+pub(super) fn motorola_msb(lsb: i32, length: i32) -> i32 {
+    let mut position = lsb;
+    for _ in 1..length {
+        if position % 8 == 7 {
+            position -= 15;
+        } else {
+            position += 1;
+        }
+    }
+    position
+}
+
+pub(super) fn required_number(element: &Element, name: &str, owner: &str) -> anyhow::Result<i32> {
+    let text = element
+        .child_text(name)
+        .with_context(|| format!("{} has no {}", owner, name))?;
+    parse_number(text).with_context(|| format!("Invalid {} of {}", name, owner))
+}
@@ -0,0 +1,243 @@
+// Small overrides applied on top of a loaded message database, e.g. to try a different scale for
+// one signal while debugging without editing the whole messages.json.
+//
+// A patch is a JSON Merge Patch (RFC 7396) keyed by message name. Signals are keyed by their name
+// instead of being an array, so a single signal can be changed without repeating the others:
+//
+//     { "ESP_WSpeed_Front": { "signals": { "ESP_WSpeed_FL": { "scale": 0.05 } } } }
+//
+// Setting a message or signal to null removes it. Unknown signals are added, unknown messages are
+// an error since a patch can't be relied on to give a complete message definition.
+
+use crate::elpis::{ElpisMessages, LoadMode, MessageDefinition};
+use anyhow::Context;
+use serde_json::{Map, Value};
+
+pub struct ElpisPatch {
+    document: Map<String, Value>,
+    // File the patch was loaded from, recorded in the source of every message it changes
+    source: Option<String>,
+}
+
+impl ElpisPatch {
+    // Load a patch from the given path to a JSON file
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_json(json_path: &str) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(json_path)
+            .with_context(|| format!("Could not open file {}", json_path))?;
+        let document: Value = serde_json::from_str(&contents)
+            .with_context(|| format!("Could not parse JSON file {}", json_path))?;
+
+        let mut patch = Self::from_value(document).with_context(|| format!("Invalid patch in {}", json_path))?;
+        patch.source = Some(json_path.to_string());
+        Ok(patch)
+    }
+
+    // Create a patch from a parsed JSON document
+    pub fn from_value(document: Value) -> anyhow::Result<Self> {
+        match document {
+            Value::Object(document) => Ok(Self { document, source: None }),
+            _ => Err(anyhow::anyhow!("A patch must be an object keyed by message name")),
+        }
+    }
+}
+
+// Apply a JSON Merge Patch to a value as described in RFC 7396
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let target = target.as_object_mut().unwrap();
+
+    for (key, value) in patch.iter() {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.as_str()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+// Turn a message's signal array into an object keyed by signal name, so it can be merged
+fn signals_by_name(message: &mut Map<String, Value>) -> Vec<String> {
+    let signals = match message.remove("signals") {
+        Some(Value::Array(signals)) => signals,
+        _ => Vec::new(),
+    };
+
+    let mut order = Vec::new();
+    let mut by_name = Map::new();
+    for signal in signals {
+        let name = signal["name"].as_str().unwrap_or_default().to_string();
+        order.push(name.clone());
+        by_name.insert(name, signal);
+    }
+    message.insert(String::from("signals"), Value::Object(by_name));
+
+    order
+}
+
+// Turn signals keyed by name back into an array, keeping the original order with added signals last
+fn signals_to_array(message: &mut Map<String, Value>, mut order: Vec<String>) {
+    let Some(Value::Object(mut by_name)) = message.remove("signals") else {
+        message.insert(String::from("signals"), Value::Array(Vec::new()));
+        return;
+    };
+
+    let mut added: Vec<String> = by_name.keys().filter(|name| !order.contains(name)).cloned().collect();
+    added.sort();
+    order.extend(added);
+
+    let signals = order
+        .iter()
+        .filter_map(|name| {
+            let mut signal = by_name.remove(name)?;
+            if let Value::Object(fields) = &mut signal {
+                fields.insert(String::from("name"), Value::String(name.clone()));
+            }
+            Some(signal)
+        })
+        .collect();
+    message.insert(String::from("signals"), Value::Array(signals));
+}
+
+// Apply the patch for a single message to its definition
+fn patch_message(message: &MessageDefinition, patch: &Value) -> anyhow::Result<MessageDefinition> {
+    let mut document = serde_json::to_value(message)?;
+    let fields = document.as_object_mut().unwrap();
+
+    let order = signals_by_name(fields);
+    merge_patch(&mut document, patch);
+    let fields = document
+        .as_object_mut()
+        .with_context(|| format!("Patch for message {} must be an object or null", message.name))?;
+    signals_to_array(fields, order);
+
+    serde_json::from_value(document).with_context(|| format!("Patched message {} is invalid", message.name))
+}
+
+impl ElpisMessages {
+    // Apply a patch to the loaded messages. Nothing is changed if any part of the patch fails.
+    pub fn apply_patch(&mut self, patch: ElpisPatch) -> anyhow::Result<()> {
+        let mut definitions: Vec<MessageDefinition> = self.iter_sorted().cloned().collect();
+
+        for (name, message_patch) in patch.document.iter() {
+            let index = definitions
+                .iter()
+                .position(|message| &message.name == name)
+                .with_context(|| format!("Patch refers to unknown message {}", name))?;
+
+            if message_patch.is_null() {
+                definitions.remove(index);
+            } else {
+                let mut patched = patch_message(&definitions[index], message_patch)?;
+                patched.source = match (&definitions[index].source, &patch.source) {
+                    (Some(source), Some(patch_source)) => Some(format!("{}, patched by {}", source, patch_source)),
+                    (source, patch_source) => source.clone().or_else(|| patch_source.clone()),
+                };
+                definitions[index] = patched;
+            }
+        }
+
+        let mut patched = Self::from_definitions(definitions);
+        patched.check_lengths(LoadMode::Strict)?;
+        patched.check_signal_starts(LoadMode::Strict)?;
+        patched.check_signal_ranges(LoadMode::Strict)?;
+        patched.validate()?;
+
+        self.replace_messages(patched);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn patch_test_messages() -> ElpisMessages {
+    let json = r#"[{
+        "name": "Wheels", "length": 4, "id": 288, "comment": null,
+        "signals": [
+            { "name": "FL", "start": 7, "length": 16, "scale": 0.01 },
+            { "name": "FR", "start": 23, "length": 16, "scale": 0.01, "unit": "km/h" }
+        ],
+        "derived": [ { "name": "Avg", "expression": "(FL + FR) / 2" } ]
+    }, {
+        "name": "Status", "length": 1, "id": 1, "comment": null, "signals": []
+    }]"#;
+
+    ElpisMessages::from_definitions(serde_json::from_str(json).unwrap())
+}
+
+#[test]
+fn apply_merge_patch() {
+    let mut messages = patch_test_messages();
+    let patch = serde_json::json!({
+        "Wheels": {
+            "comment": "patched",
+            "signals": {
+                "FR": { "scale": 0.05, "unit": null },
+                "RL": { "start": 39, "length": 8 }
+            }
+        },
+        "Status": null
+    });
+    messages.apply_patch(ElpisPatch::from_value(patch).unwrap()).unwrap();
+
+    assert_eq!(messages.get_messagedef_count(), 1);
+    let wheels = messages.get_def_by_id(288).unwrap();
+    assert_eq!(wheels.comment.as_deref(), Some("patched"));
+
+    let names: Vec<&str> = wheels.signals.iter().map(|signal| signal.name.as_str()).collect();
+    assert_eq!(names, vec!["FL", "FR", "RL"]);
+
+    // Only the patched fields change
+    let fr = wheels.get_signal_by_name("FR").unwrap();
+    assert_eq!(fr.scale, Some(0.05));
+    assert_eq!(fr.unit, None);
+    assert_eq!(fr.start, Some(23));
+    assert_eq!(wheels.get_signal_by_name("FL").unwrap().scale, Some(0.01));
+    assert_eq!(wheels.derived.len(), 1);
+}
+
+#[test]
+fn track_patched_sources() {
+    let mut messages = patch_test_messages();
+    messages.set_source("chassis_v12.json");
+
+    let mut patch = ElpisPatch::from_value(serde_json::json!({ "Wheels": { "comment": "patched" } })).unwrap();
+    patch.source = Some(String::from("elpis_patch.json"));
+    messages.apply_patch(patch).unwrap();
+
+    let source = |id: i32| messages.get_def_by_id(id).unwrap().source.clone().unwrap();
+    assert_eq!(source(288), "chassis_v12.json, patched by elpis_patch.json");
+    assert_eq!(source(1), "chassis_v12.json");
+}
+
+#[test]
+fn reject_bad_patches() {
+    let bad_patches = [
+        // Unknown message
+        serde_json::json!({ "Brakes": { "length": 2 } }),
+        // Removing a signal a derived signal still needs
+        serde_json::json!({ "Wheels": { "signals": { "FL": null } } }),
+        // Wrong type for a field
+        serde_json::json!({ "Wheels": { "length": "four" } }),
+        // Big-endian signal without a start
+        serde_json::json!({ "Wheels": { "signals": { "FL": { "start": null } } } }),
+    ];
+
+    for patch in bad_patches {
+        let mut messages = patch_test_messages();
+        assert!(messages.apply_patch(ElpisPatch::from_value(patch.clone()).unwrap()).is_err(), "{}", patch);
+
+        // A failed patch leaves the messages untouched
+        assert_eq!(messages.get_messagedef_count(), 2);
+        assert_eq!(messages.get_def_by_id(288).unwrap().signals.len(), 2);
+    }
+
+    assert!(ElpisPatch::from_value(serde_json::json!([1, 2])).is_err());
+}
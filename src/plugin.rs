@@ -0,0 +1,2378 @@
+// The Wireshark plugin: registration of the protocol and its fields, and dissection of ELPIS
+// packets with the loaded database
+
+use crate::annotations::Annotations;
+use bitstream_io::ByteRead;
+use crate::budget::{BudgetLimits, DecodeBudget};
+use crate::capture_db::CaptureDatabase;
+use crate::debug_log::DebugLog;
+use crate::groups::GroupDatabaseCache;
+use crate::handles::{CheckedHandles, MissingFieldLog};
+use crate::info_column::InfoColumnBuilder;
+use crate::item_source::ItemSource;
+use crate::ett::ETT_LAYOUT;
+use crate::findings::{Finding, FindingsExport, FindingsLog, Subject};
+use crate::elpis::{DatabaseDerived, ElpisMessages, ElpisMessagesSnapshot, LoadMode, MessageDefinition, SharedMessages, SignalNameCollision, SignalSkip};
+use crate::patch::ElpisPatch;
+use crate::prefs::ElpisPreferences;
+use crate::self_timing::{PacketTimer, Phase, SelfTiming};
+use crate::series::SeriesCache;
+use crate::state::{CaptureTracker, ConversationVariants, DatabaseMatch, DefinitionNotes, Flow, MessageFirstSeen, ReceiveGap, ReceiveHistory, SignalDecodeCounts, SignalHistory};
+use crate::suppress::{ExpertCheck, SuppressedExperts};
+use crate::trace::{DecodeTrace, FrameHeaderTrace};
+use crate::transform::PayloadTransforms;
+use crate::units::UnitConverter;
+use epan_sys::*;
+use lazy_static::lazy_static;
+use plugshark::*;
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    ffi::*,
+    fs,
+    io::{self, BufRead},
+    path::PathBuf,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+use crate::{coverage, elpis, groups, instance, series, timestamp, tlv};
+
+// Defines a C string in a constant form that's easier to use in Rust.
+macro_rules! cstr {
+    ($s:expr) => {
+        concat!($s, "\0").as_ptr() as *const c_char
+    };
+}
+
+// Adds an item with a value at the span its source claims, flagged as generated unless it's read
+// from the wire, see ItemSource. Every item with a value goes through this, only the fields
+// Wireshark reads itself with add_field are left out as they're always read from the wire, e.g.
+//     add_item!(subtree, ItemSource::CrossFrame, add_field_boolean_value(handle, changed))
+macro_rules! add_item {
+    ($tree:expr, $source:expr, $add:ident($handle:expr, $value:expr)) => {{
+        let source: ItemSource = $source;
+        let mut item = $tree.$add($handle, IndexPosition::Current(source.offset()), source.length(), $value);
+        if source.is_generated() {
+            item.set_generated();
+        }
+        item
+    }};
+}
+
+// Plugin version string
+#[no_mangle]
+#[used]
+pub static plugin_version: &'static CStr = unsafe { CStr::from_ptr(cstr!("1.0.0")) };
+
+// Major version of Wireshark that the plugin is built for
+#[no_mangle]
+#[used]
+pub static plugin_want_major: c_int = 4;
+
+// Minor version of Wireshark that the plugin is built for
+#[no_mangle]
+#[used]
+pub static plugin_want_minor: c_int = 4;
+
+// Load all ELPIS messages from the messages.json file on startup. Each packet is dissected with a
+// snapshot, so replacing the database never waits on a dissection.
+lazy_static! {
+    static ref ELPIS_MESSAGES: SharedMessages = SharedMessages::new(decode_elpis_packets_from_json());
+}
+
+// Spots another capture being opened or the database being replaced, for the state below to start over
+lazy_static! {
+    static ref CAPTURE_TRACKER: Mutex<CaptureTracker> = Mutex::new(CaptureTracker::default());
+}
+
+// Last seen value of every signal in the capture
+lazy_static! {
+    static ref SIGNAL_HISTORY: Mutex<SignalHistory> = Mutex::new(SignalHistory::default());
+}
+
+// First frame of each message in the capture, where problems with its definition are noted
+lazy_static! {
+    static ref DEFINITION_NOTES: Mutex<DefinitionNotes> = Mutex::new(DefinitionNotes::default());
+}
+
+// How many times each signal has been decoded in the capture
+lazy_static! {
+    static ref SIGNAL_DECODE_COUNTS: Mutex<SignalDecodeCounts> = Mutex::new(SignalDecodeCounts::default());
+}
+
+// When each message was last received, for spotting missing frames
+lazy_static! {
+    static ref RECEIVE_HISTORY: Mutex<ReceiveHistory> = Mutex::new(ReceiveHistory::default());
+}
+
+// Frame each message id was first seen in during the capture
+lazy_static! {
+    static ref MESSAGE_FIRST_SEEN: Mutex<MessageFirstSeen> = Mutex::new(MessageFirstSeen::default());
+}
+
+// Header variant of each conversation in the capture
+lazy_static! {
+    static ref CONVERSATION_VARIANTS: Mutex<ConversationVariants> = Mutex::new(ConversationVariants::default());
+}
+
+// Database named by the capture being dissected, used instead of the loaded messages when there is one
+lazy_static! {
+    static ref CAPTURE_DATABASE: Mutex<CaptureDatabase> = Mutex::new(CaptureDatabase::default());
+}
+
+// Database of each multicast group datagrams were sent to, resolved once per destination
+lazy_static! {
+    static ref GROUP_DATABASES: Mutex<GroupDatabaseCache> = Mutex::new(GroupDatabaseCache::default());
+}
+
+// How many frames of the capture the database knows, for spotting the wrong database
+lazy_static! {
+    static ref DATABASE_MATCH: Mutex<DatabaseMatch> = Mutex::new(DatabaseMatch::default());
+}
+
+// Values of the watched signals from the first pass over the capture, read back when IO Graph
+// dissects the packets again
+lazy_static! {
+    static ref SERIES_CACHE: Mutex<SeriesCache> = Mutex::new(SeriesCache::default());
+}
+
+// Fields found missing while dissecting, each logged only once
+lazy_static! {
+    static ref MISSING_FIELDS: Mutex<MissingFieldLog> = Mutex::new(MissingFieldLog::default());
+}
+
+// Load the user's preferences on startup
+lazy_static! {
+    static ref ELPIS_PREFERENCES: ElpisPreferences = load_elpis_preferences();
+}
+
+// Preferences registered with Wireshark, so they're under Edit > Preferences > Protocols > ELPIS and
+// can be set with -o. Each starts out with its value from elpis_prefs.json, and Wireshark writes
+// the value from its own preferences over it when it has one.
+lazy_static! {
+    static ref EXPECT_ORDERED_IDS: AtomicBool = AtomicBool::new(ELPIS_PREFERENCES.expect_ordered_ids);
+    static ref PAYLOAD_WORDSWAP: AtomicBool = AtomicBool::new(ELPIS_PREFERENCES.payload_wordswap);
+}
+
+// Transforms undone on every payload before decoding, as the preferences configure them, without and
+// with the word swap payload_wordswap turns on
+lazy_static! {
+    static ref PAYLOAD_TRANSFORMS: [PayloadTransforms; 2] =
+        [false, true].map(|word_swap| PayloadTransforms::new(&ELPIS_PREFERENCES.payload_key, word_swap));
+}
+
+// Get the transforms undone on payloads with the preferences as they are now
+fn payload_transforms() -> &'static PayloadTransforms {
+    &PAYLOAD_TRANSFORMS[PAYLOAD_WORDSWAP.load(Ordering::Relaxed) as usize]
+}
+
+// Signal name and filter name of the field of each signal on the watch_signals list. Field names are
+// looked up for the rest of the session, so they're never freed.
+lazy_static! {
+    static ref WATCHED_FIELDS: Vec<(&'static str, &'static str)> = ELPIS_PREFERENCES
+        .watch_signals
+        .iter()
+        .zip(series::watched_field_abbrevs(&ELPIS_PREFERENCES.watch_signals))
+        .map(|(signal, abbrev)| (signal.as_str(), &*Box::leak(abbrev.into_boxed_str())))
+        .collect();
+}
+
+// Wireshark's id for the ELPIS protocol, looked up once it's registered
+lazy_static! {
+    static ref ELPIS_PROTOCOL_ID: c_int = unsafe { proto_get_id_by_filter_name(c"elpis".as_ptr()) };
+}
+
+// Per-frame debug output, when the preferences ask for it
+lazy_static! {
+    static ref DEBUG_LOG: Option<DebugLog> = ELPIS_PREFERENCES.debug_logging.then(|| DebugLog::new(1024, io::stderr()));
+}
+
+// Time spent in each phase of dissection over the session, for -z elpis,summary
+lazy_static! {
+    static ref SELF_TIMING: SelfTiming = SelfTiming::default();
+}
+
+// Expert findings of every packet, for -z elpis,findings
+lazy_static! {
+    static ref FINDINGS: Mutex<FindingsLog> = Mutex::new(FindingsLog::default());
+}
+
+// Unit conversions for displaying signal values, including any from the preferences
+lazy_static! {
+    static ref UNIT_CONVERTER: UnitConverter = UnitConverter::new(&ELPIS_PREFERENCES.unit_conversions);
+}
+
+// Expert checks suppressed per message, resolved against the database again whenever it's replaced
+lazy_static! {
+    static ref SUPPRESSED_EXPERTS: DatabaseDerived<SuppressedExperts> =
+        DatabaseDerived::new(|messages| ELPIS_PREFERENCES.expert_suppress.resolve(messages));
+}
+
+// Signal names laid out differently by different messages, keyed by the signal name, for each
+// database frames are dissected with
+lazy_static! {
+    static ref SIGNAL_NAME_COLLISIONS: DatabaseDerived<BTreeMap<String, SignalNameCollision>> =
+        DatabaseDerived::new(|messages| {
+            messages
+                .signal_name_collisions()
+                .into_iter()
+                .map(|collision| (collision.signal.clone(), collision))
+                .collect()
+        });
+}
+
+// Analyst notes on messages and signals, loaded again whenever the database is
+lazy_static! {
+    static ref ELPIS_ANNOTATIONS: DatabaseDerived<Annotations> = DatabaseDerived::new(load_annotations);
+}
+
+// Get the directory this dynamic library is located in
+fn plugin_directory() -> PathBuf {
+    let plugin_path = find_library_path("libelpis.so").unwrap().unwrap();
+    PathBuf::from(plugin_path).parent().unwrap().to_path_buf()
+}
+
+// Loads the preferences from the elpis_prefs.json file, falling back to the defaults on error
+fn load_elpis_preferences() -> ElpisPreferences {
+    let json_path = plugin_directory().join("elpis_prefs.json");
+
+    ElpisPreferences::load_from_json(json_path.to_str().unwrap()).unwrap_or_else(|e| {
+        eprintln!("Error loading ELPIS preferences: {:#}", e);
+        ElpisPreferences::default()
+    })
+}
+
+// Loads the notes file named by the preferences, warning about notes on anything not in the database
+fn load_annotations(messages: &ElpisMessages) -> Annotations {
+    if ELPIS_PREFERENCES.annotations_file.is_empty() {
+        return Annotations::default();
+    }
+
+    let json_path = plugin_directory().join(&ELPIS_PREFERENCES.annotations_file);
+    let annotations = match Annotations::load_from_json(json_path.to_str().unwrap()) {
+        Ok(annotations) => annotations,
+        Err(e) => {
+            eprintln!("Error loading ELPIS annotations: {:#}", e);
+            return Annotations::default();
+        }
+    };
+
+    for target in annotations.unknown_targets(messages) {
+        eprintln!("WARN: Annotation for {} doesn't match anything in the database", target);
+    }
+
+    annotations
+}
+
+// Decodes all ELPIS messages from the messages.json file
+fn decode_elpis_packets_from_json() -> ElpisMessages {
+    // Build a path to a messages.json file in the same directory as the module
+    let json_path = plugin_directory().join("messages.json");
+
+    // Load the ELPIS messages from the JSON file, skipping signals that can't be decoded reliably
+    let mut messages = ElpisMessages::load_from_json_with_mode(json_path.to_str().unwrap(), LoadMode::Permissive).unwrap();
+    for issue in messages.issues() {
+        eprintln!("WARN: Worked around a problem while loading ELPIS messages: {}", issue);
+    }
+    for message in messages.iter_sorted() {
+        for overlap in coverage::signal_overlaps(message) {
+            eprintln!("WARN: ELPIS message {}: {}", message.name, overlap);
+        }
+    }
+    for (id, _, _, _) in messages.compute_id_conflicts() {
+        eprintln!(
+            "WARN: ELPIS messages use {:#x} as both a standard and an extended CAN id, only one of them will be decoded",
+            id
+        );
+    }
+
+    // Apply temporary overrides from an elpis_patch.json file, if there is one
+    let patch_path = plugin_directory().join("elpis_patch.json");
+    if patch_path.exists() {
+        let result = ElpisPatch::load_from_json(patch_path.to_str().unwrap()).and_then(|patch| messages.apply_patch(patch));
+        if let Err(e) = result {
+            eprintln!("Error applying ELPIS patch: {:#}", e);
+        }
+    }
+
+    messages
+}
+
+// Locates the path to the plugin's dynamic library
+fn find_library_path(library_name: &str) -> io::Result<Option<String>> {
+    let file = fs::File::open("/proc/self/maps")?;
+    let reader = io::BufReader::new(file);
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.contains(library_name) {
+            // Assume the path is the last part of the line
+            if let Some(pos) = line.find('/') {
+                return Ok(Some(line[pos..].to_string()));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+// Locates the path of this copy of the plugin's dynamic library, from the mapping containing its code
+fn own_library_path() -> io::Result<Option<String>> {
+    let maps = fs::read_to_string("/proc/self/maps")?;
+    Ok(instance::mapping_path_containing(&maps, plugin_register as *const () as usize))
+}
+
+// Entrypoint of the plugin, registers the plugin, its protocols, and all field type definitions.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_register() {
+    // Only the first copy of the plugin loaded registers, a second one would abort Wireshark
+    let own_path = own_library_path().ok().flatten().unwrap_or_default();
+    let registered = instance::claim_registration(
+        &own_path,
+        |name| std::env::var(name).ok(),
+        |name, value| std::env::set_var(name, value),
+    );
+    if let Some(registered_path) = registered {
+        eprintln!(
+            "WARN: ELPIS plugin is already loaded from {}, ignoring the copy at {}",
+            registered_path, own_path
+        );
+        return;
+    }
+
+    // Warn about ambiguous signal names once per session, this also loads the database before any
+    // packet is dissected
+    for collision in SIGNAL_NAME_COLLISIONS.get(&ELPIS_MESSAGES).values() {
+        eprintln!("WARN: {}", collision);
+    }
+    ELPIS_ANNOTATIONS.get(&ELPIS_MESSAGES);
+
+    WiresharkPlugin::setup(|mut plugin| {
+        let mut protocol =
+            WiresharkProtocolDefinition::new(dissect_callback, "ELPIS Packet", "elpis", "elpis");
+
+        // The packet ID of the packet
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.id", "Message Id")
+                .with_field_type(FieldType::Uint8)
+                .with_display(FieldDisplayType::BaseHex),
+        );
+
+        // Length of the packet
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.len", "Payload Length")
+                .with_field_type(FieldType::Uint8)
+                .with_display(FieldDisplayType::BaseHex),
+        );
+
+        // The name of the packet
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.name", "Message Name")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // The kind of bus frame the message is carried in
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.frame_type", "Frame Type")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Label of the value range a signal's raw value is in, e.g. "SNA"
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_label", "Signal Label")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // The ECU sending the message
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.sender", "Sender")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // The ECU sending the message a signal was decoded from, for filtering signals by ECU
+        // Example: elpis.signal_source_ecu == "BMS" && elpis.signal_name == "CellVoltage"
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_source_ecu", "Source ECU")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // The kind of quantity a signal holds, from its category in the database, for finding the
+        // same kind of signal across messages whatever they're named
+        // Example: elpis.signal_category == "temperature"
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_category", "Signal Category")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Bytes of a datagram left undecoded once its decode budget ran out
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.undecoded", "Undecoded")
+                .with_field_type(FieldType::Bytes)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Generic payload bytes
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.payload", "Payload")
+                .with_field_type(FieldType::Bytes)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // XOR of all payload bytes of the frame, useful for spotting single-bit errors
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.payload_checksum_xor", "Payload XOR")
+                .with_field_type(FieldType::Uint8)
+                .with_display(FieldDisplayType::BaseHex),
+        );
+
+        // Shannon entropy of the frame's payload bytes in bits per byte, close to 8 for encrypted,
+        // compressed or corrupted payloads
+        // Example: elpis.frame_payload_entropy > 7.0
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.frame_payload_entropy", "Payload Entropy")
+                .with_field_type(FieldType::Double)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Whether every byte of the frame's payload is 0x00, as in many heartbeat messages
+        // Example: elpis.frame_all_zeros == 1
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.frame_all_zeros", "All Zeros")
+                .with_field_type(FieldType::Boolean)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Whether every byte of the frame's payload is 0xFF, as in frames padded by safety protocols
+        // Example: elpis.frame_all_ones == 1
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.frame_all_ones", "All Ones")
+                .with_field_type(FieldType::Boolean)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Whether the frame's checksum signal matches the checksum computed over its payload, on
+        // every frame of a message with a checksum
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.checksum_verified", "Checksum Verified")
+                .with_field_type(FieldType::Boolean)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Expert findings raised while dissecting a frame
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.expert", "Expert Info")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Expert finding raised when a payload is longer than its message definition allows
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.payload_length_max_exceeded", "Payload Length Exceeded")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Expert finding suppressed by the expert_suppress preference, holding the name of the check
+        // Example: elpis.expert_suppressed == "checksum"
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.expert_suppressed", "Suppressed Expert Check")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Expert finding raised when a frame's id is lower than the one before it in the datagram,
+        // with the expect_ordered_ids preference enabled
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.frame_sequence_error", "Frame Sequence Error")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // The formatted signal string from a packet
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_formatted", "Signal")
+                .with_field_type(FieldType::None)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Instance of a frame of a message with an id_mask, the bits of its id outside the mask
+        // Example: elpis.instance == 3
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.instance", "Instance")
+                .with_field_type(FieldType::Uint32)
+                .with_display(FieldDisplayType::BaseDec),
+        );
+
+        // Subtree of the signals of a frame sharing a group, shown as the group's name
+        // Example: elpis.signal_group
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_group", "Signal Group")
+                .with_field_type(FieldType::None)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // The name of a signal decoded from the packet, for searching for a packet with a specific signal in it
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_name", "Signal Name")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // The value of a signal decoded from the packet, for searching for a specific signal with a specific value
+        // Example: elpis.signal_kv == "ESP_WSpeed_Front_Message_Counter=2"
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_kv", "Signal Name=Value")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Number of signals marked affects_safety decoded in a frame
+        // Example: elpis.safety_signal_count > 0
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.safety_signal_count", "Safety Critical Signals")
+                .with_field_type(FieldType::Uint32)
+                .with_display(FieldDisplayType::BaseDec),
+        );
+
+        // Number of signals of the definition that couldn't be decoded from a frame, anything but 0 is worth a look
+        // Example: elpis.decode_skipped_signal_count > 0
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.decode_skipped_signal_count", "Skipped Signals")
+                .with_field_type(FieldType::Uint32)
+                .with_display(FieldDisplayType::BaseDec),
+        );
+
+        // How many times a signal has been decoded in the session, up to and including this frame
+        // Example: elpis.signal_decode_count > 1000
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_decode_count", "Decode Count")
+                .with_field_type(FieldType::Uint64)
+                .with_display(FieldDisplayType::BaseDec),
+        );
+
+        // Payload byte offsets of a signal's least and most significant bits, for checking byte order
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_lsb", "LSB Byte Offset")
+                .with_field_type(FieldType::Uint32)
+                .with_display(FieldDisplayType::BaseDec),
+        );
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_msb", "MSB Byte Offset")
+                .with_field_type(FieldType::Uint32)
+                .with_display(FieldDisplayType::BaseDec),
+        );
+
+        // Payload bytes a signal spans, highlighted in the hex dump and copyable for comparing by hand
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_raw_bytes", "Signal Bytes")
+                .with_field_type(FieldType::Bytes)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Expert finding raised on signals whose name other messages use with a different layout
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_name_collision", "Signal Name Collision")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // An analyst's note on a message or signal, from the annotations file
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.annotation", "Note")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Whether a message or signal has a note, for finding them with elpis.annotated
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.annotated", "Annotated")
+                .with_field_type(FieldType::Boolean)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Bytes of the datagram spent on frame headers, 8 for every inner frame
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.protocol_overhead_bytes", "Protocol Overhead")
+                .with_field_type(FieldType::Uint32)
+                .with_display(FieldDisplayType::BaseDec),
+        );
+
+        // File the message definition of a frame was loaded from, with debug_logging enabled
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.definition_source", "Definition Source")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // The group_databases rule that chose the database of a frame's definition
+        // Example: elpis.database_rule contains "chassis.json"
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.database_rule", "Database Rule")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Whether a frame is the first with its message id in the session, for finding when each message first appears
+        // Example: elpis.message_is_new == 1
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.message_is_new", "New Message")
+                .with_field_type(FieldType::Boolean)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Wall-clock time the datagram was captured, in ISO 8601 UTC
+        // Example: elpis.timestamp_absolute contains "2024-03-15T14:32"
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.timestamp_absolute", "Absolute Timestamp")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Which way the datagram travels, A being the endpoint with the lower address and port
+        // Example: elpis.direction == "B→A"
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.direction", "Direction")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Set when the datagram arrived in IP fragments
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.datagram_is_fragmented", "Fragmented Datagram")
+                .with_field_type(FieldType::Boolean)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Set on every datagram, true when a frame couldn't be walked or a signal couldn't be read
+        // from its frame's payload
+        // Example: elpis.datagram_malformed == 1
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.datagram_malformed", "Malformed Datagram")
+                .with_field_type(FieldType::Boolean)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Set to false on a signal whose physical value doesn't encode back to its raw value, only
+        // registered and checked in debug builds
+        // Example: elpis.signal_encode_roundtrip_ok == 0
+        #[cfg(debug_assertions)]
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_encode_roundtrip_ok", "Encode Round Trip OK")
+                .with_field_type(FieldType::Boolean)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Exact raw value of a signal over 64 bits, as big endian bytes since it doesn't fit a 64-bit integer
+        // Example: elpis.signal_raw128 == fe:dc:ba:98:76:54:32:10:01:23:45:67
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_raw128", "Raw Value (wide)")
+                .with_field_type(FieldType::Bytes)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Previous names of a signal decoded from the packet, so filters written against older databases still match
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_alias", "Signal Alias")
+                .with_field_type(FieldType::String)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Whether a signal's value differs from its previous occurrence, always true on the first occurrence
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_value_changed", "Signal Value Changed")
+                .with_field_type(FieldType::Boolean)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // A signal's physical value as a percentage of its minimum to maximum range, clamped to 0-100
+        // Example: elpis.signal_value_percent < 20
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_value_percent", "Percent of Range")
+                .with_field_type(FieldType::Double)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Whether a signal's physical value is above its maximum, for counting over-range values
+        // Example: elpis.signal_above_max == 1
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_above_max", "Above Maximum")
+                .with_field_type(FieldType::Boolean)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Whether a signal's physical value is below its minimum, for counting under-range values
+        // Example: elpis.signal_below_min == 1
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_below_min", "Below Minimum")
+                .with_field_type(FieldType::Boolean)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // A signal's physical value mapped onto its minimum to maximum range as 0 to 1, clamped
+        // Example: elpis.signal_value_normalized > 0.9
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_value_normalized", "Normalized Value")
+                .with_field_type(FieldType::Double)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Whether a signal's value is the last known value standing in for frames that were never received
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_interpolated", "Interpolated Signal")
+                .with_field_type(FieldType::Boolean)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // A missing frame's signal extended along the line through its last two received values
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_interpolated_value", "Linearly Interpolated Value")
+                .with_field_type(FieldType::Double)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Value of each signal on the watch_signals list, kept from the first pass over the capture
+        // Example: AVG(elpis.watched.VehicleSpeed) as an IO Graph Y field
+        for (signal, abbrev) in WATCHED_FIELDS.iter() {
+            protocol.add_field_type(
+                WiresharkFieldArgs::new(abbrev, format!("Watched {}", signal).as_str())
+                    .with_field_type(FieldType::Double)
+                    .with_display(FieldDisplayType::BaseNone),
+            );
+        }
+
+        // Whether a signal was computed from other signals rather than read from the payload
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.signal_derived", "Derived Signal")
+                .with_field_type(FieldType::Boolean)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // A single entry of a self-describing payload in the experimental id range
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.tlv", "TLV")
+                .with_field_type(FieldType::None)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Tag of a TLV entry
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.tlv.tag", "TLV Tag")
+                .with_field_type(FieldType::Uint8)
+                .with_display(FieldDisplayType::BaseHex),
+        );
+
+        // Length of the value of a TLV entry
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.tlv.len", "TLV Length")
+                .with_field_type(FieldType::Uint8)
+                .with_display(FieldDisplayType::BaseDec),
+        );
+
+        // Value of a TLV entry
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.tlv.value", "TLV Value")
+                .with_field_type(FieldType::Bytes)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Complete raw bytes of an inner frame, id and length included, for exporting a single frame
+        protocol.add_field_type(
+            WiresharkFieldArgs::new("elpis.inner_frame_bytes", "Inner Frame Bytes")
+                .with_field_type(FieldType::Bytes)
+                .with_display(FieldDisplayType::BaseNone),
+        );
+
+        // Packet placeholder field
+        protocol.add_field_type(WiresharkFieldArgs::new("elpis.frame", "ELPIS Frame"));
+
+        // ELPIS is sent over port 20000, unless the ports preference lists others
+        for port in ELPIS_PREFERENCES.ports.ports() {
+            protocol.add_match_condition("udp.port", WiresharkMatchType::UInt32(port as u32));
+        }
+
+        // Set the number of ETT fields for this protocol, laid out by ETT_LAYOUT
+        protocol.set_num_ett(ETT_LAYOUT.count());
+
+        plugin.add_protocol(protocol);
+    });
+
+    register_taps();
+    register_preferences();
+
+    // Closing the capture, or dissecting it again after a preference changed, drops the watched
+    // signal values
+    register_cleanup_routine(Some(drop_watched_values));
+}
+
+unsafe extern "C" fn drop_watched_values() {
+    let mut cache = SERIES_CACHE.lock().unwrap();
+    debug_log(|| format!("Dropping {}", cache.describe()));
+    *cache = SeriesCache::default();
+}
+
+// Registers the Wireshark preferences once every protocol is registered, as they belong to the ELPIS
+// protocol Plugshark registers
+unsafe fn register_preferences() {
+    // Wireshark keeps the pointer for the rest of the session
+    static PREFERENCES_PLUGIN: proto_plugin = proto_plugin {
+        register_protoinfo: None,
+        register_handoff: Some(preferences_handoff),
+    };
+    proto_register_plugin(&PREFERENCES_PLUGIN);
+}
+
+unsafe extern "C" fn preferences_handoff() {
+    let module = prefs_register_protocol(*ELPIS_PROTOCOL_ID, None);
+    for (name, title, description, value) in [
+        (
+            cstr!("expect_ordered_ids"),
+            cstr!("Expect ascending frame ids"),
+            cstr!("Note frames whose id is lower than the id before them in the same datagram"),
+            &*EXPECT_ORDERED_IDS,
+        ),
+        (
+            cstr!("payload_wordswap"),
+            cstr!("Undo the payload word swap of gateway firmware 2.3.x"),
+            cstr!("Reverse the bytes of every 4-byte payload word before decoding signals"),
+            &*PAYLOAD_WORDSWAP,
+        ),
+    ] {
+        prefs_register_bool_preference(module, name, title, description, value.as_ptr());
+    }
+}
+
+// Registers the ELPIS tap and its -z options: elpis,summary printing how long dissection spent in
+// each phase and the memory watched signal values use, and elpis,findings writing every expert
+// finding to a file, once tshark is done with the capture
+unsafe fn register_taps() {
+    register_tap(cstr!("elpis"));
+
+    for (title, cli_string, tap_init_cb) in [
+        (cstr!("ELPIS Summary"), cstr!("elpis,summary"), summary_tap_init as unsafe extern "C" fn(*const c_char, *mut c_void)),
+        (cstr!("ELPIS Findings"), cstr!("elpis,findings"), findings_tap_init),
+    ] {
+        // Wireshark keeps the pointer for the rest of the session
+        let ui = Box::leak(Box::new(stat_tap_ui {
+            group: register_stat_group_t_REGISTER_STAT_GROUP_GENERIC,
+            title,
+            cli_string,
+            tap_init_cb: Some(tap_init_cb),
+            nparams: 0,
+            params: std::ptr::null_mut(),
+        }));
+        register_stat_tap_ui(ui, std::ptr::null_mut());
+    }
+}
+
+unsafe extern "C" fn summary_tap_init(_options: *const c_char, _userdata: *mut c_void) {
+    let error = register_tap_listener(
+        cstr!("elpis"),
+        std::ptr::null_mut(),
+        std::ptr::null(),
+        0,
+        None,
+        None,
+        Some(summary_tap_draw),
+        None,
+    );
+    if !error.is_null() {
+        eprintln!("Error: Could not register the ELPIS summary tap");
+    }
+}
+
+unsafe extern "C" fn summary_tap_draw(_tapdata: *mut c_void) {
+    if cfg!(feature = "self_timing") && ELPIS_PREFERENCES.self_timing {
+        print!("{}", SELF_TIMING.render());
+    } else {
+        println!("ELPIS self timing is off, build with the self_timing feature and set the self_timing preference");
+    }
+    if !WATCHED_FIELDS.is_empty() {
+        println!("{}", SERIES_CACHE.lock().unwrap().describe());
+    }
+}
+
+// Gets the whole -z argument, e.g. "elpis,findings,out.csv,suppressed"
+unsafe extern "C" fn findings_tap_init(options: *const c_char, _userdata: *mut c_void) {
+    let argument = if options.is_null() { "" } else { CStr::from_ptr(options).to_str().unwrap_or_default() };
+    let export = match FindingsExport::parse(argument) {
+        Ok(export) => export,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    FINDINGS.lock().unwrap().set_export(export);
+
+    let error = register_tap_listener(
+        cstr!("elpis"),
+        std::ptr::null_mut(),
+        std::ptr::null(),
+        0,
+        None,
+        None,
+        Some(findings_tap_draw),
+        None,
+    );
+    if !error.is_null() {
+        eprintln!("Error: Could not register the ELPIS findings tap");
+    }
+}
+
+unsafe extern "C" fn findings_tap_draw(_tapdata: *mut c_void) {
+    let findings = FINDINGS.lock().unwrap();
+    let (Some(export), Some(rendered)) = (findings.export(), findings.render()) else {
+        return;
+    };
+    match fs::write(&export.path, rendered) {
+        Ok(()) => println!(
+            "ELPIS findings: {} written to {}",
+            findings.findings(export.include_suppressed).len(),
+            export.path
+        ),
+        Err(e) => eprintln!("Error: Could not write ELPIS findings to {}: {}", export.path, e),
+    }
+}
+
+// Severity of an expert finding, mirroring Wireshark's PI_NOTE and PI_WARN levels
+#[derive(Clone, Copy)]
+enum ExpertSeverity {
+    Note,
+    Warn,
+}
+
+impl ExpertSeverity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ExpertSeverity::Note => "Note",
+            ExpertSeverity::Warn => "Warning",
+        }
+    }
+}
+
+// Records an expert finding of the packet being dissected for -z elpis,findings
+unsafe fn record_finding(tree: &mut DissectorSubTree, severity: ExpertSeverity, subject: &Subject, message: &str, suppressed: bool) {
+    let pinfo = tree.get_packet_info();
+    let abs_ts = &(*pinfo).abs_ts;
+    FINDINGS.lock().unwrap().record(Finding {
+        packet: (*pinfo).num,
+        timestamp: abs_ts.secs as f64 + abs_ts.nsecs as f64 / 1e9,
+        severity: severity.as_str(),
+        message: subject.message.clone(),
+        signal: subject.signal.clone(),
+        value: subject.value.clone(),
+        finding: message.to_string(),
+        suppressed,
+    });
+}
+
+// Adds an expert finding to the tree as a generated item of the given field, usually `elpis.expert`,
+// and records it with the message, signal and value it's about
+unsafe fn add_expert_info(
+    tree: &mut DissectorSubTree,
+    expert_handle: c_int,
+    severity: ExpertSeverity,
+    subject: &Subject,
+    message: &str,
+) {
+    let mut item = add_item!(tree, ItemSource::UNBACKED, add_field_string_value(expert_handle, message));
+    item.set_text(format!("Expert Info ({}): {}", severity.as_str(), message).as_str());
+    record_finding(tree, severity, subject, message, false);
+}
+
+// Adds the finding of an expert check unless the check is suppressed for this message, in which
+// case it's only recorded as a hidden `elpis.expert_suppressed` item so it can still be counted.
+// Payload length findings have a field of their own, the others go in `elpis.expert`.
+unsafe fn add_checked_expert_info(
+    tree: &mut DissectorSubTree,
+    handles: &FieldHandles,
+    check: ExpertCheck,
+    message_id: i32,
+    severity: ExpertSeverity,
+    subject: &Subject,
+    message: &str,
+) {
+    if !SUPPRESSED_EXPERTS.get(&ELPIS_MESSAGES).is_suppressed(check, message_id) {
+        let expert_handle = match check {
+            ExpertCheck::Length => handles.payload_length_max_exceeded,
+            ExpertCheck::Checksum | ExpertCheck::FrameType => Some(handles.expert),
+        };
+        if let Some(expert_handle) = expert_handle {
+            add_expert_info(tree, expert_handle, severity, subject, message);
+        }
+        return;
+    }
+    record_finding(tree, severity, subject, message, true);
+
+    let Some(expert_suppressed) = handles.expert_suppressed else {
+        return;
+    };
+    let mut item = add_item!(tree, ItemSource::UNBACKED, add_field_string_value(expert_suppressed, check.as_str()));
+    item.set_text(format!("Suppressed {} expert: {}", check.as_str(), message).as_str());
+    item.set_hidden();
+}
+
+// Handles of the registered fields, looked up once per dissection. Items of a field that isn't
+// registered are left out.
+struct FieldHandles {
+    name: c_int,
+    signal_kv: c_int,
+    signal_name: c_int,
+    signal_formatted: c_int,
+    frame: c_int,
+    expert: c_int,
+    signal_alias: Option<c_int>,
+    annotation: Option<c_int>,
+    annotated: Option<c_int>,
+    signal_name_collision: Option<c_int>,
+    signal_raw128: Option<c_int>,
+    signal_value_changed: Option<c_int>,
+    signal_value_percent: Option<c_int>,
+    signal_value_normalized: Option<c_int>,
+    signal_above_max: Option<c_int>,
+    signal_below_min: Option<c_int>,
+    safety_signal_count: Option<c_int>,
+    decode_skipped_signal_count: Option<c_int>,
+    signal_decode_count: Option<c_int>,
+    signal_lsb: Option<c_int>,
+    signal_msb: Option<c_int>,
+    signal_raw_bytes: Option<c_int>,
+    signal_derived: Option<c_int>,
+    signal_interpolated: Option<c_int>,
+    signal_interpolated_value: Option<c_int>,
+    frame_type: Option<c_int>,
+    sender: Option<c_int>,
+    signal_label: Option<c_int>,
+    signal_source_ecu: Option<c_int>,
+    signal_category: Option<c_int>,
+    payload_checksum_xor: Option<c_int>,
+    frame_payload_entropy: Option<c_int>,
+    frame_all_zeros: Option<c_int>,
+    frame_all_ones: Option<c_int>,
+    checksum_verified: Option<c_int>,
+    payload_length_max_exceeded: Option<c_int>,
+    expert_suppressed: Option<c_int>,
+    frame_sequence_error: Option<c_int>,
+    tlv: Option<c_int>,
+    tlv_tag: Option<c_int>,
+    tlv_len: Option<c_int>,
+    tlv_value: Option<c_int>,
+    inner_frame_bytes: Option<c_int>,
+    protocol_overhead_bytes: Option<c_int>,
+    datagram_is_fragmented: Option<c_int>,
+    datagram_malformed: Option<c_int>,
+    #[cfg(debug_assertions)]
+    signal_encode_roundtrip_ok: Option<c_int>,
+    signal_group: Option<c_int>,
+    instance: Option<c_int>,
+    timestamp_absolute: Option<c_int>,
+    direction: Option<c_int>,
+    message_is_new: Option<c_int>,
+    definition_source: Option<c_int>,
+    database_rule: Option<c_int>,
+    // Field of each signal in WATCHED_FIELDS
+    watched: Vec<Option<c_int>>,
+}
+
+impl FieldHandles {
+    // Look up every handle, None if a field the dissector can't do without isn't registered. Watched
+    // fields are given as in WATCHED_FIELDS.
+    fn new(lookup: &mut CheckedHandles<impl Fn(&str) -> c_int>, watched: &[(&str, &'static str)]) -> Option<Self> {
+        let handles = Self {
+            name: lookup.required("elpis.name"),
+            signal_kv: lookup.required("elpis.signal_kv"),
+            signal_name: lookup.required("elpis.signal_name"),
+            signal_formatted: lookup.required("elpis.signal_formatted"),
+            frame: lookup.required("elpis.frame"),
+            expert: lookup.required("elpis.expert"),
+            signal_alias: lookup.optional("elpis.signal_alias"),
+            annotation: lookup.optional("elpis.annotation"),
+            annotated: lookup.optional("elpis.annotated"),
+            signal_name_collision: lookup.optional("elpis.signal_name_collision"),
+            signal_raw128: lookup.optional("elpis.signal_raw128"),
+            signal_value_changed: lookup.optional("elpis.signal_value_changed"),
+            signal_value_percent: lookup.optional("elpis.signal_value_percent"),
+            signal_value_normalized: lookup.optional("elpis.signal_value_normalized"),
+            signal_above_max: lookup.optional("elpis.signal_above_max"),
+            signal_below_min: lookup.optional("elpis.signal_below_min"),
+            safety_signal_count: lookup.optional("elpis.safety_signal_count"),
+            decode_skipped_signal_count: lookup.optional("elpis.decode_skipped_signal_count"),
+            signal_decode_count: lookup.optional("elpis.signal_decode_count"),
+            signal_lsb: lookup.optional("elpis.signal_lsb"),
+            signal_msb: lookup.optional("elpis.signal_msb"),
+            signal_raw_bytes: lookup.optional("elpis.signal_raw_bytes"),
+            signal_derived: lookup.optional("elpis.signal_derived"),
+            signal_interpolated: lookup.optional("elpis.signal_interpolated"),
+            signal_interpolated_value: lookup.optional("elpis.signal_interpolated_value"),
+            frame_type: lookup.optional("elpis.frame_type"),
+            sender: lookup.optional("elpis.sender"),
+            signal_label: lookup.optional("elpis.signal_label"),
+            signal_source_ecu: lookup.optional("elpis.signal_source_ecu"),
+            signal_category: lookup.optional("elpis.signal_category"),
+            payload_checksum_xor: lookup.optional("elpis.payload_checksum_xor"),
+            frame_payload_entropy: lookup.optional("elpis.frame_payload_entropy"),
+            frame_all_zeros: lookup.optional("elpis.frame_all_zeros"),
+            frame_all_ones: lookup.optional("elpis.frame_all_ones"),
+            checksum_verified: lookup.optional("elpis.checksum_verified"),
+            payload_length_max_exceeded: lookup.optional("elpis.payload_length_max_exceeded"),
+            expert_suppressed: lookup.optional("elpis.expert_suppressed"),
+            frame_sequence_error: lookup.optional("elpis.frame_sequence_error"),
+            tlv: lookup.optional("elpis.tlv"),
+            tlv_tag: lookup.optional("elpis.tlv.tag"),
+            tlv_len: lookup.optional("elpis.tlv.len"),
+            tlv_value: lookup.optional("elpis.tlv.value"),
+            inner_frame_bytes: lookup.optional("elpis.inner_frame_bytes"),
+            protocol_overhead_bytes: lookup.optional("elpis.protocol_overhead_bytes"),
+            datagram_is_fragmented: lookup.optional("elpis.datagram_is_fragmented"),
+            datagram_malformed: lookup.optional("elpis.datagram_malformed"),
+            #[cfg(debug_assertions)]
+            signal_encode_roundtrip_ok: lookup.optional("elpis.signal_encode_roundtrip_ok"),
+            signal_group: lookup.optional("elpis.signal_group"),
+            instance: lookup.optional("elpis.instance"),
+            timestamp_absolute: lookup.optional("elpis.timestamp_absolute"),
+            direction: lookup.optional("elpis.direction"),
+            message_is_new: lookup.optional("elpis.message_is_new"),
+            definition_source: lookup.optional("elpis.definition_source"),
+            database_rule: lookup.optional("elpis.database_rule"),
+            watched: watched.iter().map(|(_, abbrev)| lookup.optional(abbrev)).collect(),
+        };
+
+        // Fields added by name rather than by handle
+        for name in ["elpis.id", "elpis.len", "elpis.payload", "elpis.undecoded"] {
+            lookup.required(name);
+        }
+
+        lookup.has_required().then_some(handles)
+    }
+}
+
+// Adds an analyst's note as a child of the current item, along with the hidden elpis.annotated flag
+unsafe fn add_annotation(tree: &mut DissectorSubTree, handles: &FieldHandles, note: &str) {
+    let (Some(annotation), Some(annotated)) = (handles.annotation, handles.annotated) else {
+        return;
+    };
+    let mut item = add_item!(tree, ItemSource::UNBACKED, add_field_string_value(annotation, note));
+    item.set_text(format!("Note: {}", note).as_str());
+
+    let mut item = add_item!(tree, ItemSource::UNBACKED, add_field_boolean_value(annotated, true));
+    item.set_hidden();
+}
+
+// Adds the hidden ECU a signal comes from, so signals can be filtered by ECU without knowing their message
+unsafe fn add_signal_source_ecu(tree: &mut DissectorSubTree, handles: &FieldHandles, sender: &str) {
+    let Some(signal_source_ecu) = handles.signal_source_ecu else {
+        return;
+    };
+    let mut item = add_item!(tree, ItemSource::UNBACKED, add_field_string_value(signal_source_ecu, sender));
+    item.set_hidden();
+}
+
+// Pushes a subtree at the span its source claims, generated unless it's read from the wire
+unsafe fn push_item_subtree(tree: &mut DissectorSubTree, handle: c_int, source: &ItemSource, ett: i32) -> DissectorSubTree {
+    let position = IndexPosition::Current(source.offset());
+    if source.is_generated() {
+        tree.push_subtree_generated(handle, position, source.length(), ett)
+    } else {
+        tree.push_subtree(handle, position, source.length(), ett)
+    }
+}
+
+// Where a frame is in the capture, for the history kept across packets, and the database it's
+// dissected with
+#[derive(Clone, Copy)]
+struct FrameLocation<'a> {
+    packet_number: u32,
+    frame_index: u32,
+    flow: &'a Flow,
+    // Id the frame was sent with, which differs from its message's id for instances of an id family
+    frame_id: i32,
+    // Offset of the frame header within the datagram
+    frame_offset: usize,
+    // Database of the frame's definition: the loaded one, or the one of its capture or multicast group
+    database: &'a ElpisMessagesSnapshot,
+}
+
+// Signals decoded from a frame's payload by parse_elpis_payload
+struct DecodedPayload {
+    // Physical value of every signal read from the payload
+    signal_values: Vec<(String, f64)>,
+    // Whether any signal couldn't be read from the payload
+    unreadable_signals: bool,
+}
+
+// Start the trace of a frame, with where its header was in the datagram
+fn frame_trace(definition: &MessageDefinition, payload: &[u8], location: &FrameLocation<'_>) -> DecodeTrace {
+    DecodeTrace {
+        message: definition.name.clone(),
+        id: definition.id,
+        header: Some(FrameHeaderTrace {
+            offset: location.frame_offset,
+            id: location.frame_id,
+            length: payload.len() as i32,
+        }),
+        payload: crate::trace::format_hex(payload),
+        ..Default::default()
+    }
+}
+
+// Raw value of every signal of a frame or why it wasn't read, and the value of every derived signal
+struct FrameValues {
+    raw: Vec<Result<u128, SignalSkip>>,
+    derived: Vec<Option<f64>>,
+}
+
+// Read every signal of a frame and compute its derived signals the way decode does, recording each
+// in the frame's trace when it's traced
+fn read_frame_values(definition: &MessageDefinition, payload: &[u8], mut trace: Option<&mut DecodeTrace>) -> FrameValues {
+    let raw: Vec<Result<u128, SignalSkip>> = definition
+        .signals
+        .iter()
+        .map(|signal| signal.read_traced(payload, trace.as_deref_mut()))
+        .collect();
+
+    // Wide signals can't be used as operands, their physical values are approximate
+    let physical = |name: &str| {
+        definition.signals.iter().zip(raw.iter()).find_map(|(signal, raw)| match raw {
+            Ok(raw) if signal.name == name && !signal.is_wide() => Some(signal.to_physical(*raw)),
+            _ => None,
+        })
+    };
+    let derived = definition
+        .derived
+        .iter()
+        .map(|derived| definition.eval_derived_traced(derived, &physical, trace.as_deref_mut()))
+        .collect();
+
+    FrameValues { raw, derived }
+}
+
+// Adds every signal of a message to the tree, returning the physical values of the signals read
+// from the payload. Given a compact summary, each signal's subtree is hidden and its value is added
+// to the summary instead. The frames of the packet trace_frame_number names are traced through the
+// same reads decode_with_trace makes.
+unsafe fn parse_elpis_payload(
+    tree: &mut DissectorSubTree,
+    definition: &MessageDefinition,
+    payload: &[u8],
+    handles: &FieldHandles,
+    location: FrameLocation<'_>,
+    mut compact_summary: Option<&mut Vec<String>>,
+    timer: &mut PacketTimer,
+) -> DecodedPayload {
+    let traced = ELPIS_PREFERENCES.debug_logging && ELPIS_PREFERENCES.trace_frame_number == location.packet_number;
+    let mut trace = traced.then(|| frame_trace(definition, payload, &location));
+
+    let annotations = ELPIS_ANNOTATIONS.get(&ELPIS_MESSAGES);
+    let collisions = SIGNAL_NAME_COLLISIONS.get_for(location.database);
+
+    let mut signal_values = Vec::new();
+
+    // Safety critical signals decoded in this frame
+    let mut safety_signal_count: u32 = 0;
+
+    // Signals of the definition that couldn't be decoded from this frame
+    let mut skipped_signal_count: u32 = 0;
+    let mut unreadable_signals = false;
+
+    // Subtree of each signal group seen so far, in the order of their first signal
+    let mut groups: Vec<(&str, DissectorSubTree)> = Vec::new();
+
+    // Every signal and derived signal, worked out in one go so it's timed once per frame rather than
+    // once per signal
+    let started = timer.start();
+    let values = read_frame_values(definition, payload, trace.as_mut());
+    timer.stop(Phase::SignalExtraction, started);
+
+    let mut current_signal_idx: usize = 0;
+    for (signal_index, signal) in definition.signals.iter().enumerate() {
+        let signal_name = signal.name.as_str();
+
+        // A signal that can't be read is skipped rather than failing the whole frame
+        let data = match &values.raw[signal_index] {
+            Ok(data) => *data,
+            Err(skip) => {
+                if *skip != SignalSkip::NoBits {
+                    debug_log(|| skip.to_string());
+                }
+                unreadable_signals |= matches!(skip, SignalSkip::Unreadable(..));
+                skipped_signal_count += 1;
+                continue;
+            }
+        };
+
+        let byte_length = (signal.length + 7) / 8;
+
+        // Items computed from the signal's value claim the bytes it's read from
+        let source = ItemSource::signal(signal);
+
+        // Grouped signals go under their group's subtree, the others straight under the frame
+        let parent = match signal.group.as_deref().filter(|group| !group.is_empty()).zip(handles.signal_group) {
+            Some((group, handle)) => {
+                let index = groups.iter().position(|(name, _)| *name == group).unwrap_or_else(|| {
+                    let mut group_tree = push_item_subtree(tree, handle, &ItemSource::UNBACKED, ETT_LAYOUT.group(groups.len()));
+                    group_tree.get_top_item().set_text(group);
+                    if compact_summary.is_some() {
+                        group_tree.get_top_item().set_hidden();
+                    }
+                    groups.push((group, group_tree));
+                    groups.len() - 1
+                });
+                &mut groups[index].1
+            }
+            None => &mut *tree,
+        };
+        let mut subtree = push_item_subtree(parent, handles.signal_formatted, &source, ETT_LAYOUT.signal(current_signal_idx));
+        current_signal_idx += 1;
+
+        // Show the physical value when the signal has a unit, converted if the user prefers another unit system.
+        // The filter fields below always keep the database's unit. Signals over 64 bits are shown as
+        // every hex digit of their raw value instead, their physical value would be rounded.
+        let physical = signal.to_physical(data);
+        let mut text = if signal.is_wide() {
+            format!("{}: {:#0width$x}", signal_name, data, width = 2 + byte_length as usize * 2)
+        } else {
+            format!("{}: {} ({:#x})", signal_name, data, data)
+        };
+        if signal.is_safety_critical() {
+            text.insert_str(0, "⚠ ");
+            safety_signal_count += 1;
+        }
+        // Values in a labeled range show the label instead of a physical value
+        let label = signal.range_label(data);
+        let mut converted = None;
+        if let Some(label) = label {
+            text.push_str(format!(" = {}", label).as_str());
+            converted = Some(label.to_string());
+        } else if let Some(unit) = signal.unit.as_deref().filter(|unit| !unit.is_empty() && !signal.is_wide()) {
+            let (value, unit) = UNIT_CONVERTER
+                .convert(physical, unit, ELPIS_PREFERENCES.unit_system)
+                .unwrap_or((physical, unit));
+            text.push_str(format!(" = {} {}", signal.format_physical(value), unit).as_str());
+            converted = Some(format!("{} {}", signal.format_physical(value), unit));
+        }
+        subtree.get_top_item().set_text(text.as_str());
+
+        // A compact frame shows a single value per signal on its own line, the filter fields stay
+        // underneath the hidden subtree
+        if let Some(summary) = compact_summary.as_deref_mut() {
+            let value = if signal.is_wide() {
+                format!("{:#0width$x}", data, width = 2 + byte_length as usize * 2)
+            } else if definition.checksum_spec().is_some_and(|checksum| checksum.signal == signal_name) {
+                format!("0x{:02X}", data)
+            } else {
+                converted.unwrap_or_else(|| data.to_string())
+            };
+            summary.push(format!("{}={}", signal_name, value));
+            subtree.get_top_item().set_hidden();
+        }
+
+        let text = format!("{}={}", signal_name, data);
+        let mut val = add_item!(subtree, source.clone(), add_field_string_value(handles.signal_kv, text.as_str()));
+        val.set_hidden();
+
+        add_item!(subtree, source.clone(), add_field_string_value(handles.signal_name, signal_name));
+
+        if let Some(sender) = definition.sender.as_deref() {
+            add_signal_source_ecu(&mut subtree, handles, sender);
+        }
+
+        if let (Some(category), Some(handle)) = (signal.category.as_deref().filter(|category| !category.is_empty()), handles.signal_category) {
+            add_item!(subtree, ItemSource::UNBACKED, add_field_string_value(handle, category));
+        }
+
+        if let Some(note) = annotations.signal_note(&definition.name, signal_name) {
+            add_annotation(&mut subtree, handles, note);
+        }
+
+        if let (Some(collision), Some(handle)) = (collisions.get(signal_name), handles.signal_name_collision) {
+            let subject = Subject::signal(&definition.name, signal_name);
+            add_expert_info(&mut subtree, handle, ExpertSeverity::Note, &subject, collision.to_string().as_str());
+        }
+
+        if let Some(handle) = handles.signal_decode_count {
+            let count = SIGNAL_DECODE_COUNTS.lock().unwrap().observe(
+                location.packet_number,
+                location.frame_index,
+                definition.id,
+                signal_name,
+            );
+            add_item!(subtree, ItemSource::CrossFrame, add_field_uint64_value(handle, count));
+        }
+
+        if let (Some(label), Some(handle)) = (label, handles.signal_label) {
+            add_item!(subtree, source.clone(), add_field_string_value(handle, label));
+        }
+
+        if let Some(handle) = handles.signal_raw128.filter(|_| signal.is_wide()) {
+            add_item!(subtree, source.clone(), add_field_bytes_value(handle, &signal.raw_bytes(data)));
+        }
+
+        if let Some((lsb, msb)) = signal.byte_offsets() {
+            for (handle, offset) in [(handles.signal_lsb, lsb), (handles.signal_msb, msb)] {
+                let Some(handle) = handle else { continue };
+                let offset_source = ItemSource::Computed(offset as usize..offset as usize + 1);
+                let mut val = add_item!(subtree, offset_source, add_field_uint_value(handle, offset as u32));
+                val.set_hidden();
+            }
+        }
+
+        // The signal's bytes are only the wire's when no payload transform changed them
+        if let (Some(range), Some(handle)) = (signal.byte_range(), handles.signal_raw_bytes) {
+            let bytes_source = ItemSource::signal_bytes(signal, !payload_transforms().is_empty());
+            add_item!(subtree, bytes_source, add_field_bytes_value(handle, &payload[range]));
+        }
+
+        for alias in signal.aliases.iter().filter(|_| handles.signal_alias.is_some()) {
+            let mut val = add_item!(subtree, source.clone(), add_field_string_value(handles.signal_alias.unwrap(), alias));
+            val.set_hidden();
+        }
+
+        // Wide signals are left out of anything working on physical values
+        if !signal.is_wide() {
+            signal_values.push((signal_name.to_string(), physical));
+
+            let watched = WATCHED_FIELDS.iter().position(|(watched, _)| *watched == signal_name);
+            if let Some(handle) = watched.and_then(|index| handles.watched[index]) {
+                let value = SERIES_CACHE.lock().unwrap().observe(
+                    location.packet_number,
+                    location.frame_index,
+                    definition.id,
+                    signal_index as u32,
+                    physical,
+                );
+                add_item!(subtree, source.clone(), add_field_double_value(handle, value as f64));
+            }
+
+            if let Some(fraction) = signal.range_fraction(physical).filter(|_| label.is_none()) {
+                let percent = fraction * 100.0;
+                if let Some(handle) = handles.signal_value_percent {
+                    add_item!(subtree, source.clone(), add_field_double_value(handle, percent.clamp(0.0, 100.0)));
+                }
+
+                if let Some(handle) = handles.signal_value_normalized {
+                    let normalized = fraction.clamp(0.0, 1.0);
+                    add_item!(subtree, source.clone(), add_field_double_value(handle, normalized));
+                    if normalized != fraction {
+                        add_expert_info(
+                            &mut subtree,
+                            handles.expert,
+                            ExpertSeverity::Note,
+                            &Subject::signal(&definition.name, signal_name).with_value(format!("{:.3}", fraction)),
+                            format!("{} normalized value {:.3} clamped to {}", signal_name, fraction, normalized).as_str(),
+                        );
+                    }
+                }
+
+                // Over and under range are told apart, a value can only be one of them
+                for (handle, outside) in [
+                    (handles.signal_above_max, physical > signal.maximum),
+                    (handles.signal_below_min, physical < signal.minimum),
+                ] {
+                    let Some(handle) = handle else { continue };
+                    add_item!(subtree, source.clone(), add_field_boolean_value(handle, outside));
+                }
+
+                if !(0.0..=100.0).contains(&percent) {
+                    add_expert_info(
+                        &mut subtree,
+                        handles.expert,
+                        ExpertSeverity::Warn,
+                        &Subject::signal(&definition.name, signal_name).with_value(signal.format_physical(physical)),
+                        format!(
+                            "{} is outside its range of {} to {}",
+                            signal_name,
+                            signal.format_physical(signal.minimum),
+                            signal.format_physical(signal.maximum)
+                        )
+                        .as_str(),
+                    );
+                }
+            }
+        }
+
+        // Scales and offsets that round, so the value shown isn't the one that was sent
+        #[cfg(debug_assertions)]
+        if !definition.encode_roundtrip_ok(signal, data) {
+            if let Some(handle) = handles.signal_encode_roundtrip_ok {
+                add_item!(subtree, source.clone(), add_field_boolean_value(handle, false));
+            }
+            add_expert_info(
+                &mut subtree,
+                handles.expert,
+                ExpertSeverity::Warn,
+                &Subject::signal(&definition.name, signal_name).with_value(signal.format_physical(physical)),
+                format!("{} = {} doesn't encode back to raw value {:#x}", signal_name, signal.format_physical(physical), data).as_str(),
+            );
+        }
+
+        let change = SIGNAL_HISTORY.lock().unwrap().observe(
+            location.packet_number,
+            location.frame_index,
+            location.flow,
+            location.frame_id,
+            signal_name,
+            data,
+        );
+        if let Some(handle) = handles.signal_value_changed {
+            add_item!(subtree, ItemSource::CrossFrame, add_field_boolean_value(handle, change.changed));
+        }
+    }
+
+    for (derived, value) in definition.derived.iter().zip(values.derived.iter().copied()) {
+        // A derived signal claims the bytes of the signals its expression reads
+        let source = ItemSource::derived(definition, derived);
+        let mut subtree = push_item_subtree(tree, handles.signal_formatted, &source, ETT_LAYOUT.signal(current_signal_idx));
+        current_signal_idx += 1;
+
+        add_item!(subtree, source.clone(), add_field_string_value(handles.signal_name, &derived.name));
+
+        if let Some(sender) = definition.sender.as_deref() {
+            add_signal_source_ecu(&mut subtree, handles, sender);
+        }
+
+        if let Some(handle) = handles.signal_derived {
+            let mut val = add_item!(subtree, source.clone(), add_field_boolean_value(handle, true));
+            val.set_hidden();
+        }
+
+        if let Some(summary) = compact_summary.as_deref_mut() {
+            let value = value.map_or_else(|| String::from("unavailable"), |value| value.to_string());
+            summary.push(format!("{}={}", derived.name, value));
+            subtree.get_top_item().set_hidden();
+        }
+
+        match value {
+            Some(value) => {
+                subtree
+                    .get_top_item()
+                    .set_text(format!("{}: {} [derived: {}]", derived.name, value, derived.expression.as_str()).as_str());
+
+                let text = format!("{}={}", derived.name, value);
+                let mut val = add_item!(subtree, source.clone(), add_field_string_value(handles.signal_kv, text.as_str()));
+                val.set_hidden();
+            }
+            None => {
+                subtree
+                    .get_top_item()
+                    .set_text(format!("{}: unavailable [derived: {}]", derived.name, derived.expression.as_str()).as_str());
+            }
+        }
+    }
+
+    for (handle, count) in [
+        (handles.safety_signal_count, safety_signal_count),
+        (handles.decode_skipped_signal_count, skipped_signal_count),
+    ] {
+        let Some(handle) = handle else { continue };
+        let mut item = add_item!(tree, ItemSource::UNBACKED, add_field_uint_value(handle, count));
+        if compact_summary.is_some() {
+            item.set_hidden();
+        }
+    }
+
+    if let Some(trace) = trace {
+        // Written straight away rather than through the debug log, which could drop it
+        eprintln!("DEBUG: packet {} frame {} trace: {}", location.packet_number, location.frame_index, trace.to_json());
+    }
+
+    DecodedPayload {
+        signal_values,
+        unreadable_signals,
+    }
+}
+
+// Adds an entry standing in for frames of a message that were expected but never received, using
+// the values of the last frame that was
+unsafe fn add_interpolated_frame(tree: &mut DissectorSubTree, gap: &ReceiveGap, handles: &FieldHandles) {
+    let mut subtree = push_item_subtree(tree, handles.signal_formatted, &ItemSource::CrossFrame, ETT_LAYOUT.interpolated());
+    subtree.get_top_item().set_text(
+        format!(
+            "Interpolated: {} missing frame{} in {:.1} ms, showing last known values",
+            gap.missed_frames,
+            if gap.missed_frames == 1 { "" } else { "s" },
+            gap.elapsed_ms
+        )
+        .as_str(),
+    );
+
+    for (name, value) in gap.last_values.iter() {
+        let mut val = add_item!(subtree, ItemSource::CrossFrame, add_field_string_value(handles.signal_name, name));
+        val.set_text(format!("{}: {} [interpolated]", name, value).as_str());
+
+        if let Some(handle) = handles.signal_interpolated {
+            let mut val = add_item!(subtree, ItemSource::CrossFrame, add_field_boolean_value(handle, true));
+            val.set_hidden();
+        }
+
+        let Some(handle) = handles.signal_interpolated_value.filter(|_| ELPIS_PREFERENCES.linear_interpolation) else {
+            continue;
+        };
+        if let Some((_, interpolated)) = gap.interpolated_values.iter().find(|(interpolated_name, _)| interpolated_name == name) {
+            let mut val = add_item!(subtree, ItemSource::CrossFrame, add_field_double_value(handle, *interpolated));
+            val.set_text(format!("{}: {} [linear]", name, interpolated).as_str());
+        }
+    }
+}
+
+// Adds every entry of a self-describing payload from the experimental id range. A malformed chain
+// only gets an expert note, the payload is still shown as raw bytes.
+unsafe fn parse_tlv_payload(tree: &mut DissectorSubTree, payload: &[u8], handles: &FieldHandles) {
+    let (Some(tlv), Some(tlv_tag), Some(tlv_len), Some(tlv_value)) =
+        (handles.tlv, handles.tlv_tag, handles.tlv_len, handles.tlv_value)
+    else {
+        return;
+    };
+
+    let tlvs = match tlv::parse_tlvs(payload) {
+        Ok(tlvs) => tlvs,
+        Err(e) => {
+            add_expert_info(
+                tree,
+                handles.expert,
+                ExpertSeverity::Note,
+                &Subject::default(),
+                format!("Malformed TLV chain, showing raw bytes: {}", e).as_str(),
+            );
+            return;
+        }
+    };
+
+    // Entries are read from the wire, unless a payload transform changed the bytes they're read from
+    let transformed = !payload_transforms().is_empty();
+    let source = |range: std::ops::Range<usize>| {
+        if transformed {
+            ItemSource::Computed(range)
+        } else {
+            ItemSource::Wire(range)
+        }
+    };
+
+    for (index, entry) in tlvs.iter().enumerate() {
+        let offset = entry.offset;
+        let value_length = entry.value.len();
+
+        let mut subtree = push_item_subtree(tree, tlv, &source(offset..offset + 2 + value_length), ETT_LAYOUT.signal(index));
+        subtree.get_top_item().set_text(entry.describe().as_str());
+
+        add_item!(subtree, source(offset..offset + 1), add_field_uint_value(tlv_tag, entry.tag as u32));
+        add_item!(subtree, source(offset + 1..offset + 2), add_field_uint_value(tlv_len, value_length as u32));
+        add_item!(subtree, source(offset + 2..offset + 2 + value_length), add_field_bytes_value(tlv_value, entry.value));
+    }
+}
+
+// Queues a debug message when debug logging is enabled, only formatting it if it is
+fn debug_log(message: impl FnOnce() -> String) {
+    if let Some(log) = DEBUG_LOG.as_ref() {
+        log.log(message());
+    }
+}
+
+// A frame that can't be walked: how many bytes its item covers and why
+#[derive(Debug, PartialEq)]
+struct MalformedFrame {
+    length: i32,
+    reason: String,
+}
+
+// Check the header of a frame with the given number of bytes left in the datagram, its id and
+// payload length if there were enough bytes to read them. The item of a malformed frame only covers
+// the bytes that are really there, not the length the frame claims, so it never highlights past
+// the datagram.
+fn check_frame_header(datagram_remaining: i32, header_length: i32, header: Option<(i32, i32)>) -> Result<(i32, i32), MalformedFrame> {
+    let Some((packet_id, payload_length)) = header else {
+        return Err(MalformedFrame { length: datagram_remaining, reason: String::from("Truncated frame header") });
+    };
+
+    let remaining_size = datagram_remaining - header_length;
+    if payload_length < 0 || payload_length > remaining_size {
+        let reason = format!("Invalid payload length {}, {} bytes left", payload_length, remaining_size);
+        return Err(MalformedFrame { length: datagram_remaining, reason });
+    }
+
+    if packet_id < 0 {
+        return Err(MalformedFrame { length: payload_length + header_length, reason: String::from("Invalid packet ID") });
+    }
+
+    Ok((packet_id, payload_length))
+}
+
+// Adds an item for a frame that couldn't be walked, covering only the given number of bytes
+unsafe fn add_malformed_frame(tree: &mut DissectorSubTree, handles: &FieldHandles, length: i32, ett: i32, reason: &str) {
+    let mut subtree = push_item_subtree(tree, handles.frame, &ItemSource::Wire(0..length as usize), ett);
+    subtree.get_top_item().append_text(" (malformed)");
+    add_expert_info(&mut subtree, handles.expert, ExpertSeverity::Warn, &Subject::default(), reason);
+}
+
+// Get the bytes of a network address, empty when it has none
+unsafe fn address_bytes(address: &address) -> &[u8] {
+    if address.data.is_null() || address.len <= 0 {
+        return &[];
+    }
+    std::slice::from_raw_parts(address.data as *const u8, address.len as usize)
+}
+
+// Get the comment of a packet, if it has one
+unsafe fn packet_comment(pinfo: *const packet_info) -> Option<String> {
+    let rec = (*pinfo).rec;
+    if rec.is_null() || (*rec).block.is_null() {
+        return None;
+    }
+
+    let mut comment: *mut c_char = std::ptr::null_mut();
+    let result = wtap_block_get_nth_string_option_value((*rec).block, OPT_COMMENT, 0, &mut comment);
+    if result != wtap_opttype_return_val_WTAP_OPTTYPE_SUCCESS || comment.is_null() {
+        return None;
+    }
+    Some(CStr::from_ptr(comment).to_string_lossy().into_owned())
+}
+
+// Check if ELPIS is enabled under Analyze > Enabled Protocols. Wireshark doesn't hand packets to
+// a disabled protocol's port, but this is checked anyway so nothing is done for them if it does.
+unsafe fn elpis_protocol_enabled() -> bool {
+    let protocol = find_protocol_by_id(*ELPIS_PROTOCOL_ID);
+    !protocol.is_null() && proto_is_protocol_enabled(protocol) != 0
+}
+
+// Callback for dissection, called when a packet for this protocol is detected and dissected.
+unsafe fn dissect_callback(mut tree: DissectorSubTree) {
+    // Before any per-packet work, including loading the database
+    if !elpis_protocol_enabled() {
+        return;
+    }
+
+    let mut lookup = CheckedHandles::new(|name| tree.get_field_handle(name));
+    let handles = FieldHandles::new(&mut lookup, &WATCHED_FIELDS);
+    for name in MISSING_FIELDS.lock().unwrap().unreported(lookup.missing()) {
+        match handles {
+            Some(_) => eprintln!("Error: ELPIS field {} isn't registered, it's left out of every packet", name),
+            None => eprintln!("Error: ELPIS field {} isn't registered, ELPIS packets won't be dissected", name),
+        }
+    }
+    let Some(handles) = handles else {
+        return;
+    };
+    let mut timer = SELF_TIMING.start_packet(ELPIS_PREFERENCES.self_timing);
+
+    let packet_number = (*tree.get_packet_info()).num;
+    FINDINGS.lock().unwrap().begin_packet(packet_number);
+    let abs_ts = &(*tree.get_packet_info()).abs_ts;
+    let timestamp = abs_ts.secs as f64 + abs_ts.nsecs as f64 / 1e9;
+
+    // State kept across packets starts over with another capture or database
+    if CAPTURE_TRACKER.lock().unwrap().observe(packet_number, timestamp, ELPIS_MESSAGES.generation()) {
+        *SIGNAL_HISTORY.lock().unwrap() = SignalHistory::default();
+        *RECEIVE_HISTORY.lock().unwrap() = ReceiveHistory::default();
+        *SIGNAL_DECODE_COUNTS.lock().unwrap() = SignalDecodeCounts::default();
+        *MESSAGE_FIRST_SEEN.lock().unwrap() = MessageFirstSeen::default();
+        *CONVERSATION_VARIANTS.lock().unwrap() = ConversationVariants::default();
+        *DATABASE_MATCH.lock().unwrap() = DatabaseMatch::default();
+        *DEFINITION_NOTES.lock().unwrap() = DefinitionNotes::default();
+        *SERIES_CACHE.lock().unwrap() = SeriesCache::default();
+    }
+
+    if let Some(handle) = handles.timestamp_absolute {
+        let text = timestamp::format_iso8601(abs_ts.secs, abs_ts.nsecs);
+        add_item!(tree, ItemSource::UNBACKED, add_field_string_value(handle, text.as_str()));
+    }
+
+    // History is kept per flow, so ECUs talking to each other on port 20000 at both ends, or
+    // several conversations at once, aren't mistaken for a single stream
+    let pinfo = tree.get_packet_info();
+    let flow = Flow::new(
+        (address_bytes(&(*pinfo).src), (*pinfo).srcport),
+        (address_bytes(&(*pinfo).dst), (*pinfo).destport),
+    );
+    if let Some(handle) = handles.direction {
+        add_item!(tree, ItemSource::UNBACKED, add_field_string_value(handle, flow.direction().as_str()));
+    }
+
+    // The IP dissector flags datagrams that arrived in fragments. Reassembly may not be complete
+    // when the datagram gets here, so its frames could be cut short.
+    if (*tree.get_packet_info()).fragmented != 0 {
+        if let Some(handle) = handles.datagram_is_fragmented {
+            add_item!(tree, ItemSource::UNBACKED, add_field_boolean_value(handle, true));
+        }
+        add_expert_info(
+            &mut tree,
+            handles.expert,
+            ExpertSeverity::Warn,
+            &Subject::default(),
+            "Datagram was fragmented, frames may be incomplete if it wasn't fully reassembled",
+        );
+    }
+
+    // The first ELPIS packet of a capture can name the database to use for the whole capture
+    if ELPIS_PREFERENCES.auto_db_from_capture && !ELPIS_PREFERENCES.database_search_dir.is_empty() {
+        let comment = packet_comment(tree.get_packet_info());
+        let search_dir = plugin_directory().join(&ELPIS_PREFERENCES.database_search_dir);
+        let outcome = CAPTURE_DATABASE.lock().unwrap().observe(
+            packet_number,
+            timestamp,
+            comment.as_deref(),
+            &search_dir,
+            |path| ElpisMessages::load_from_json_with_mode(path.to_str().unwrap(), LoadMode::Permissive),
+        );
+        if let Some(outcome) = outcome {
+            add_expert_info(&mut tree, handles.expert, ExpertSeverity::Note, &Subject::default(), outcome.to_string().as_str());
+        }
+    }
+
+    // The whole packet is dissected with the database as it is now, even if it's replaced meanwhile
+    let database = ELPIS_MESSAGES.snapshot();
+    let capture_database = CAPTURE_DATABASE.lock().unwrap().messages();
+
+    // Datagrams sent to a multicast group with a database of its own are dissected with that one,
+    // ahead of any database the capture names
+    let group = groups::ip_address(address_bytes(&(*pinfo).dst))
+        .filter(|_| !ELPIS_PREFERENCES.group_databases.is_empty())
+        .and_then(|destination| {
+            GROUP_DATABASES.lock().unwrap().resolve(&ELPIS_PREFERENCES.group_databases, destination, |rule| {
+                let path = plugin_directory().join(&rule.database);
+                ElpisMessages::load_from_json_with_mode(path.to_str().unwrap(), LoadMode::Permissive)
+            })
+        })
+        .and_then(|(rule, messages)| Some((rule, messages?)));
+
+    // The header variant is picked by port before any header bytes are read, the destination port's
+    // first as it's the one ELPIS is sent to. It's picked on the first packet of a conversation and
+    // kept for the rest.
+    let variant = CONVERSATION_VARIANTS.lock().unwrap().resolve(&flow, || {
+        let ports = &ELPIS_PREFERENCES.ports;
+        ports
+            .variant((*pinfo).destport as u16)
+            .or_else(|| ports.variant((*pinfo).srcport as u16))
+            .unwrap_or_default()
+    });
+    let header_length = variant.header_length() as i32;
+    let header_encoding = || if variant.is_big_endian() { FieldEncoding::BigEndian } else { FieldEncoding::LittleEndian };
+
+    // Id of every frame in this packet and whether the database knows it
+    let mut frame_ids: Vec<(i32, bool)> = Vec::new();
+
+    // Whether a signal of any frame couldn't be read from its payload
+    let mut unreadable_signals = false;
+
+    let result = || -> anyhow::Result<()> {
+        // Names of the messages in this packet, for the Info column
+        let mut info_column = InfoColumnBuilder::new(ELPIS_PREFERENCES.info_column_version);
+
+        // Keep current frame idx for ETT indexes.
+        // This makes it so that if a frame is opened, that same index will remain open
+        // on subsequent packets being displayed.
+        let mut current_frame_idx: usize = 0;
+
+        // Index of the frame within this packet
+        let mut frame_index: u32 = 0;
+
+        // Offset of the frame header within the datagram
+        let mut frame_offset: usize = 0;
+
+        // Id of the previous frame within this packet, for the expect_ordered_ids check
+        let mut previous_id: Option<i32> = None;
+
+        let mut budget = DecodeBudget::new(BudgetLimits::scaled(ELPIS_PREFERENCES.decode_budget_scale));
+        loop {
+            let buffer = tree.get_buffer_here(TvBuffByteOrder::BigEndian);
+
+            if buffer.remaining() == 0 {
+                let started = timer.start();
+                tree.set_info_column(info_column.build().as_str());
+                timer.stop(Phase::InfoColumn, started);
+
+                // Every inner frame has a header of the variant's length in front of its payload
+                if let Some(handle) = handles.protocol_overhead_bytes {
+                    let overhead = variant.header_length() * frame_index;
+                    add_item!(tree, ItemSource::UNBACKED, add_field_uint_value(handle, overhead));
+                }
+
+                break;
+            }
+
+            // A frame that can't be walked ends the packet
+            let datagram_remaining: i32 = buffer.remaining().try_into()?;
+            let header = variant.parse_header(tree.get_slice_here(datagram_remaining.min(header_length)));
+            let (packet_id, payload_length) = match check_frame_header(datagram_remaining, header_length, header) {
+                Ok(header) => header,
+                Err(malformed) => {
+                    add_malformed_frame(&mut tree, &handles, malformed.length, ETT_LAYOUT.frame(current_frame_idx), &malformed.reason);
+                    return Err(anyhow::anyhow!(malformed.reason));
+                }
+            };
+
+            debug_log(|| {
+                format!(
+                    "packet {} frame {}: id {:#x}, {} payload bytes",
+                    packet_number, frame_index, packet_id, payload_length
+                )
+            });
+
+            // Find the message definition for this packet, in the database of its multicast group
+            // or the one the capture names if there is one
+            let messages = match group.as_ref() {
+                Some((_, messages)) => messages,
+                None => capture_database.as_ref().unwrap_or(&database),
+            };
+
+            // Locate the message definition for this packet by its id
+            let started = timer.start();
+            let message_def = messages.get_def_by_id(packet_id);
+            timer.stop(Phase::DatabaseLookup, started);
+
+            // Crafted datagrams can hold far more frames and signals than any real one, so stop
+            // once the packet has taken its share of work
+            if let Err(exceeded) = budget.charge_frame(payload_length as usize, message_def) {
+                add_expert_info(
+                    &mut tree,
+                    handles.expert,
+                    ExpertSeverity::Warn,
+                    &Subject::default(),
+                    format!("Decode budget exceeded, {}: {} bytes left undecoded", exceeded, datagram_remaining).as_str(),
+                );
+                tree.add_field("elpis.undecoded", IndexPosition::Current(0), datagram_remaining, FieldEncoding::LittleEndian);
+                continue;
+            }
+
+            // Everything from here to the next frame builds this frame's part of the tree
+            let tree_started = timer.start();
+
+            // Pushing a single field into the dissector
+            let frame_source = ItemSource::Wire(0..(payload_length + header_length) as usize);
+            let mut subtree = push_item_subtree(&mut tree, handles.frame, &frame_source, ETT_LAYOUT.frame(current_frame_idx));
+            current_frame_idx += 1;
+
+            if let Some(handle) = handles.inner_frame_bytes {
+                let frame_bytes = subtree.get_slice_here(payload_length + header_length);
+                let mut item = add_item!(subtree, frame_source, add_field_bytes_value(handle, frame_bytes));
+                item.set_hidden();
+            }
+
+            // Experimental ids describe themselves, they say nothing about the database
+            if message_def.is_some() || !tlv::is_experimental_id(packet_id) {
+                frame_ids.push((packet_id, message_def.is_some()));
+            }
+
+            // Small messages shown as a single line keep every field, hidden, so filters still match
+            let compact = ELPIS_PREFERENCES.compact_small_frames
+                && message_def.is_some_and(|message_def| message_def.is_small(ELPIS_PREFERENCES.compact_max_signals));
+            let mut compact_summary = Vec::new();
+
+            let mut item = subtree.add_field(
+                "elpis.id",
+                IndexPosition::Current(0),
+                variant.field_length() as i32,
+                header_encoding(),
+            );
+            if compact {
+                item.set_hidden();
+            }
+
+            // Senders that transmit in a fixed order send the lowest id first
+            if let Some(handle) = handles.frame_sequence_error.filter(|_| EXPECT_ORDERED_IDS.load(Ordering::Relaxed)) {
+                if let Some(previous_id) = previous_id.filter(|&previous_id| packet_id < previous_id) {
+                    add_expert_info(
+                        &mut subtree,
+                        handle,
+                        ExpertSeverity::Note,
+                        &Subject::default().with_value(format!("{:#x}", packet_id)),
+                        format!("Frame id {:#x} follows {:#x}, ids are expected in ascending order", packet_id, previous_id)
+                            .as_str(),
+                    );
+                }
+            }
+            previous_id = Some(packet_id);
+
+            let is_new = MESSAGE_FIRST_SEEN.lock().unwrap().observe(packet_number, frame_index, packet_id);
+            if let Some(handle) = handles.message_is_new {
+                let mut item = add_item!(subtree, ItemSource::CrossFrame, add_field_boolean_value(handle, is_new));
+                if compact {
+                    item.set_hidden();
+                }
+            }
+
+            // If we found a message definition, add the name of the packet to the Frame item
+            if let Some(message_def) = message_def {
+                // Keep track of all names seen in this packet
+                info_column.add_message(&message_def.name);
+
+                // Add the name of the packet to the Frame item
+                let mut item = add_item!(subtree, ItemSource::UNBACKED, add_field_string_value(handles.name, message_def.name.as_str()));
+                if compact {
+                    item.set_hidden();
+                }
+
+                // Frames of an id family show which instance they are
+                if let (Some(instance), Some(handle)) = (message_def.instance(packet_id), handles.instance) {
+                    let mut item = add_item!(subtree, ItemSource::UNBACKED, add_field_uint_value(handle, instance as u32));
+                    if compact {
+                        item.set_hidden();
+                    }
+                }
+
+                // Append the name to the top level frame
+                subtree
+                    .get_top_item()
+                    .append_text(format!(" ({})", message_def.name).as_str());
+
+                if let Some(note) = ELPIS_ANNOTATIONS.get(&ELPIS_MESSAGES).message_note(&message_def.name) {
+                    add_annotation(&mut subtree, &handles, note);
+                }
+
+                // Which file the definition came from, for tracking down a wrong one
+                let source = message_def.source.as_deref().filter(|_| ELPIS_PREFERENCES.debug_logging);
+                if let (Some(source), Some(handle)) = (source, handles.definition_source) {
+                    let mut item = add_item!(subtree, ItemSource::UNBACKED, add_field_string_value(handle, source));
+                    item.set_text(format!("Definition from {}", source).as_str());
+                }
+
+                // Signals claiming the same bits, noted on the first frame of the message in the capture
+                if DEFINITION_NOTES.lock().unwrap().observe(packet_number, frame_index, message_def.id) {
+                    for overlap in coverage::signal_overlaps(message_def) {
+                        let subject = Subject::message(&message_def.name);
+                        add_expert_info(&mut subtree, handles.expert, ExpertSeverity::Note, &subject, overlap.to_string().as_str());
+                    }
+                }
+
+                if let (Some((rule, _)), Some(handle)) = (group.as_ref(), handles.database_rule) {
+                    let text = rule.to_string();
+                    let mut item = add_item!(subtree, ItemSource::UNBACKED, add_field_string_value(handle, text.as_str()));
+                    item.set_text(format!("Database {} from group rule {}", rule.database, text).as_str());
+                }
+
+                // Payloads longer than the database expects, which can overrun the receiver's buffer
+                if message_def.length > 0 && payload_length > message_def.length {
+                    add_checked_expert_info(
+                        &mut subtree,
+                        &handles,
+                        ExpertCheck::Length,
+                        message_def.id,
+                        ExpertSeverity::Warn,
+                        &Subject::message(&message_def.name).with_value(payload_length),
+                        format!(
+                            "Payload of {} bytes exceeds the {} bytes defined for {}",
+                            payload_length, message_def.length, message_def.name
+                        )
+                        .as_str(),
+                    );
+                }
+
+                if let (Some(sender), Some(handle)) = (message_def.sender.as_deref(), handles.sender) {
+                    let mut item = add_item!(subtree, ItemSource::UNBACKED, add_field_string_value(handle, sender));
+                    if compact {
+                        item.set_hidden();
+                    }
+                }
+
+                if let (Some(frame_type), Some(handle)) = (message_def.frame_type, handles.frame_type) {
+                    let mut item = add_item!(subtree, ItemSource::UNBACKED, add_field_string_value(handle, frame_type.as_str()));
+                    if compact {
+                        item.set_hidden();
+                    }
+                }
+
+                // Payloads that could never fit in a single frame on the bus
+                if let Some((max_length, frame_type)) = message_def.payload_limit(ELPIS_PREFERENCES.allow_can_fd) {
+                    if payload_length as usize > max_length {
+                        add_checked_expert_info(
+                            &mut subtree,
+                            &handles,
+                            ExpertCheck::FrameType,
+                            message_def.id,
+                            ExpertSeverity::Warn,
+                            &Subject::message(&message_def.name).with_value(payload_length),
+                            format!(
+                                "Payload of {} bytes exceeds the {} byte limit of a {} frame",
+                                payload_length, max_length, frame_type
+                            )
+                            .as_str(),
+                        );
+                    }
+                }
+            }
+
+            let mut item = subtree.add_field(
+                "elpis.len",
+                IndexPosition::Current(0),
+                variant.field_length() as i32,
+                header_encoding(),
+            );
+            if compact {
+                item.set_hidden();
+            }
+
+            // XOR of the whole payload, shown whether or not a checksum signal is configured
+            let payload = subtree.get_slice_here(payload_length);
+            if let Some(handle) = handles.payload_checksum_xor {
+                let payload_xor = elpis::payload_checksum_xor(payload);
+                let payload_source = ItemSource::Computed(0..payload.len());
+                let mut item = add_item!(subtree, payload_source, add_field_uint_value(handle, payload_xor as u32));
+                if compact {
+                    item.set_hidden();
+                }
+            }
+            if let Some(handle) = handles.frame_payload_entropy {
+                let payload_source = ItemSource::Computed(0..payload.len());
+                let mut item = add_item!(subtree, payload_source, add_field_double_value(handle, elpis::payload_entropy(payload)));
+                if compact {
+                    item.set_hidden();
+                }
+            }
+            for (handle, byte) in [(handles.frame_all_zeros, 0x00), (handles.frame_all_ones, 0xff)] {
+                if let Some(handle) = handle {
+                    let payload_source = ItemSource::Computed(0..payload.len());
+                    let mut item = add_item!(subtree, payload_source, add_field_boolean_value(handle, elpis::payload_filled_with(payload, byte)));
+                    if compact {
+                        item.set_hidden();
+                    }
+                }
+            }
+
+            // Signals are decoded from a scratch copy with what gateways and loggers did to the payload
+            // undone, such as the word swap of gateway firmware 2.3.x
+            let transforms = payload_transforms();
+            let transformed_payload;
+            let decode_payload = if transforms.is_empty() {
+                payload
+            } else {
+                transformed_payload = transforms.apply(payload);
+                transformed_payload.as_slice()
+            };
+
+            if let Some(message_def) = message_def {
+                let checksum = message_def.check_checksum(decode_payload);
+                if let Some(handle) = handles.checksum_verified {
+                    if message_def.checksum_spec().is_some() {
+                        let verified = matches!(&checksum, Ok(Some(check)) if check.is_valid());
+                        let payload_source = ItemSource::Computed(0..decode_payload.len());
+                        let mut item = add_item!(subtree, payload_source, add_field_boolean_value(handle, verified));
+                        if compact {
+                            item.set_hidden();
+                        }
+                    }
+                }
+
+                let checksum_subject = match message_def.checksum_spec() {
+                    Some(spec) => Subject::signal(&message_def.name, &spec.signal),
+                    None => Subject::message(&message_def.name),
+                };
+                match checksum {
+                    Ok(Some(check)) if !check.is_valid() => add_checked_expert_info(
+                        &mut subtree,
+                        &handles,
+                        ExpertCheck::Checksum,
+                        message_def.id,
+                        ExpertSeverity::Warn,
+                        &checksum_subject.with_value(format!("{:#x}", check.received)),
+                        format!(
+                            "Checksum mismatch: received {:#x}, computed {:#x}",
+                            check.received, check.computed
+                        )
+                        .as_str(),
+                    ),
+                    Err(e) => add_checked_expert_info(
+                        &mut subtree,
+                        &handles,
+                        ExpertCheck::Checksum,
+                        message_def.id,
+                        ExpertSeverity::Note,
+                        &checksum_subject,
+                        format!("Could not verify checksum: {}", e).as_str(),
+                    ),
+                    _ => {}
+                }
+
+                let decoded = parse_elpis_payload(
+                    &mut subtree,
+                    message_def,
+                    decode_payload,
+                    &handles,
+                    FrameLocation {
+                        packet_number,
+                        frame_index,
+                        flow: &flow,
+                        frame_id: packet_id,
+                        frame_offset,
+                        database: messages,
+                    },
+                    compact.then_some(&mut compact_summary),
+                    &mut timer,
+                );
+                unreadable_signals |= decoded.unreadable_signals;
+
+                if let Some(timeout_ms) = message_def.receive_timeout_ms {
+                    let gap = RECEIVE_HISTORY.lock().unwrap().observe(
+                        packet_number,
+                        frame_index,
+                        (flow.clone(), packet_id),
+                        timestamp,
+                        timeout_ms,
+                        decoded.signal_values,
+                    );
+                    if let Some(gap) = gap {
+                        add_interpolated_frame(&mut subtree, &gap, &handles);
+                    }
+                }
+            } else if tlv::is_experimental_id(packet_id) {
+                // Experimental ids describe themselves, unless the database knows better
+                info_column.add_experimental(packet_id);
+                subtree.get_top_item().append_text(" (Experimental TLV)");
+
+                parse_tlv_payload(&mut subtree, decode_payload, &handles);
+            }
+            if let Some(message_def) = message_def.filter(|_| compact) {
+                let text = if compact_summary.is_empty() {
+                    message_def.name.clone()
+                } else {
+                    format!("{}: {}", message_def.name, compact_summary.join(", "))
+                };
+                subtree.get_top_item().set_text(text.as_str());
+            }
+            let mut item = subtree.add_field(
+                "elpis.payload",
+                IndexPosition::Current(0),
+                payload_length,
+                FieldEncoding::LittleEndian,
+            );
+            if compact {
+                item.set_hidden();
+            } else if !transforms.is_empty() {
+                item.append_text(format!(" (decoded after {})", transforms.names()).as_str());
+            }
+            for mark in transforms.frame_marks() {
+                subtree.get_top_item().append_text(format!(" ({})", mark).as_str());
+            }
+
+            timer.stop(Phase::TreeConstruction, tree_started);
+
+            frame_index += 1;
+            frame_offset += (header_length + payload_length) as usize;
+        }
+
+        Ok(())
+    }();
+
+    if let Some(handle) = handles.datagram_malformed {
+        let malformed = result.is_err() || unreadable_signals;
+        add_item!(tree, ItemSource::UNBACKED, add_field_boolean_value(handle, malformed));
+    }
+
+    if let Err(e) = result {
+        eprintln!("Error parsing ELPIS packet: {}", e);
+    }
+
+    // Frames of a malformed packet before the error still count
+    if ELPIS_PREFERENCES.min_known_id_percent > 0.0 {
+        let mismatch = DATABASE_MATCH.lock().unwrap().observe(packet_number, &frame_ids, ELPIS_PREFERENCES.min_known_id_percent);
+        if let Some(mismatch) = mismatch {
+            add_expert_info(&mut tree, handles.expert, ExpertSeverity::Warn, &Subject::default(), mismatch.to_string().as_str());
+            eprintln!(
+                "WARN: {} Most frequent unknown ids: {}",
+                mismatch,
+                mismatch
+                    .frequent_unknown_ids
+                    .iter()
+                    .map(|(id, frames)| format!("{:#x} ({} frames)", id, frames))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            );
+        }
+    }
+
+    if let Some(elapsed) = timer.elapsed() {
+        SELF_TIMING.add(&timer, elapsed);
+        let threshold = Duration::from_secs_f64(ELPIS_PREFERENCES.slow_packet_threshold_ms.max(0.0) / 1e3);
+        if let Some(slow) = timer.slow_packet(elapsed, threshold) {
+            debug_log(|| format!("packet {} {}", packet_number, slow));
+        }
+    }
+}
+
+#[test]
+fn field_handles_with_missing_fields() {
+    // Every field looked up is registered, handed out in registration order
+    let registered = crate::fields::registered_fields();
+    let mut lookup = CheckedHandles::new(|name: &str| {
+        registered.iter().position(|(abbrev, _)| *abbrev == name).map_or(-1, |index| index as c_int + 1)
+    });
+    assert!(FieldHandles::new(&mut lookup, &[]).is_some());
+    assert!(lookup.missing().is_empty(), "{:?}", lookup.missing());
+
+    // Without a field only its own items are left out
+    let mut lookup = CheckedHandles::new(crate::handles::lookup_without("elpis.tlv.tag"));
+    let handles = FieldHandles::new(&mut lookup, &[]).unwrap();
+    assert!(handles.tlv.is_some() && handles.tlv_tag.is_none());
+
+    // Each watched signal has a field of its own
+    let watched = [("VehicleSpeed", "elpis.watched.VehicleSpeed"), ("EngineRpm", "elpis.watched.EngineRpm")];
+    let mut lookup = CheckedHandles::new(crate::handles::lookup_without("elpis.watched.EngineRpm"));
+    let handles = FieldHandles::new(&mut lookup, &watched).unwrap();
+    assert!(handles.watched[0].is_some() && handles.watched[1].is_none());
+
+    // Without one the dissector needs, including those added by name, nothing is dissected
+    for required in ["elpis.frame", "elpis.payload"] {
+        let mut lookup = CheckedHandles::new(crate::handles::lookup_without(required));
+        assert!(FieldHandles::new(&mut lookup, &[]).is_none());
+        assert_eq!(lookup.missing(), [required]);
+    }
+}
+
+#[test]
+fn malformed_frame_lengths() {
+    // Frames that fit are walked
+    assert_eq!(check_frame_header(20, 8, Some((0x120, 12))), Ok((0x120, 12)));
+    assert_eq!(check_frame_header(8, 8, Some((0, 0))), Ok((0, 0)));
+
+    // A truncated header covers what's left of the datagram
+    let truncated = check_frame_header(5, 8, None).unwrap_err();
+    assert_eq!(truncated, MalformedFrame { length: 5, reason: String::from("Truncated frame header") });
+
+    // So does a payload longer than the datagram, or a negative one, never the claimed length
+    let oversized = check_frame_header(20, 8, Some((0x120, 4096))).unwrap_err();
+    assert_eq!(oversized.length, 20);
+    assert_eq!(oversized.reason, "Invalid payload length 4096, 12 bytes left");
+    assert_eq!(check_frame_header(20, 8, Some((0x120, -1))).unwrap_err().length, 20);
+
+    // A bad id only covers its own frame
+    assert_eq!(check_frame_header(40, 8, Some((-1, 12))).unwrap_err().length, 20);
+}
+
+#[test]
+fn plugin_trace_matches_decode_trace() {
+    let database = std::sync::Arc::new(elpis::wide_test_messages());
+    let flow = Flow::new((&[10, 0, 0, 1], 20000), (&[10, 0, 0, 2], 20000));
+    let location = FrameLocation {
+        packet_number: 1,
+        frame_index: 0,
+        flow: &flow,
+        frame_id: 0x300,
+        frame_offset: 8,
+        database: &database,
+    };
+
+    // A signal too wide to read and one without bits are traced with the reason too
+    let mut message = database.get_def_by_id(0x300).unwrap().clone();
+    message.signals.push(serde_json::from_str(r#"{ "name": "Huge", "start": 0, "length": 200, "is_big_endian": false }"#).unwrap());
+    message.signals.push(serde_json::from_str(r#"{ "name": "Empty", "start": 0, "length": 0, "is_big_endian": false }"#).unwrap());
+
+    // The whole payload, and one too short for most signals
+    let payload = elpis::wide_test_payload(1 << 90, 7, 0x1234);
+    for payload in [&payload[..], &payload[..4]] {
+        let mut plugin_trace = frame_trace(&message, payload, &location);
+        read_frame_values(&message, payload, Some(&mut plugin_trace));
+
+        let mut decode_trace = DecodeTrace::default();
+        message.decode_with_trace(payload, Some(&mut decode_trace));
+
+        // Only the plugin knows where the frame's header was
+        assert_eq!(plugin_trace.header.take().unwrap().offset, 8);
+        assert_eq!(plugin_trace.to_json(), decode_trace.to_json());
+    }
+}
@@ -0,0 +1,206 @@
+// UDP ports ELPIS is dissected on, and the header variant of the datagrams on each. The `ports`
+// preference lists ports and port ranges, each optionally with its variant:
+//
+//     "20000;20001=v1-be32;20002-20010"
+//
+// Ports without a variant use v1-be32, the 4-byte big endian id and length header. Gateways that
+// haven't been migrated yet send v2-le16, a 2-byte little endian id and length. Those are the only
+// variants this version decodes, others are rejected by name rather than dissected as garbage.
+
+use serde::Deserialize;
+use std::ops::RangeInclusive;
+
+// Layout of the header in front of every inner frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeaderVariant {
+    // 4-byte big endian id, then 4-byte big endian payload length
+    #[default]
+    V1Be32,
+    // 2-byte little endian id, then 2-byte little endian payload length
+    V2Le16,
+}
+
+impl HeaderVariant {
+    const ALL: [HeaderVariant; 2] = [HeaderVariant::V1Be32, HeaderVariant::V2Le16];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HeaderVariant::V1Be32 => "v1-be32",
+            HeaderVariant::V2Le16 => "v2-le16",
+        }
+    }
+
+    // Get the length of the id field, and of the payload length field after it
+    pub fn field_length(&self) -> u32 {
+        match self {
+            HeaderVariant::V1Be32 => 4,
+            HeaderVariant::V2Le16 => 2,
+        }
+    }
+
+    pub fn is_big_endian(&self) -> bool {
+        match self {
+            HeaderVariant::V1Be32 => true,
+            HeaderVariant::V2Le16 => false,
+        }
+    }
+
+    // Get the length of the header in front of every inner frame
+    pub fn header_length(&self) -> u32 {
+        self.field_length() * 2
+    }
+
+    // Read the id and payload length from the front of a frame, None when there are fewer bytes
+    // than the header needs
+    pub fn parse_header(&self, bytes: &[u8]) -> Option<(i32, i32)> {
+        let field_length = self.field_length() as usize;
+        let header = bytes.get(..field_length * 2)?;
+        let read = |field: &[u8]| match self {
+            HeaderVariant::V1Be32 => i32::from_be_bytes(field.try_into().unwrap()),
+            HeaderVariant::V2Le16 => u16::from_le_bytes(field.try_into().unwrap()) as i32,
+        };
+        Some((read(&header[..field_length]), read(&header[field_length..])))
+    }
+
+    fn from_name(name: &str) -> anyhow::Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|variant| variant.as_str() == name || variant.as_str().split('-').next() == Some(name))
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::ALL.iter().map(|variant| variant.as_str()).collect();
+                anyhow::anyhow!("Unsupported header variant \"{}\", expected one of {}", name, names.join(", "))
+            })
+    }
+}
+
+// A port or range of ports and the header variant used on them
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortRule {
+    pub ports: RangeInclusive<u16>,
+    pub variant: HeaderVariant,
+}
+
+impl PortRule {
+    fn parse(entry: &str) -> anyhow::Result<Self> {
+        let (ports, variant) = match entry.split_once('=') {
+            Some((ports, variant)) => (ports.trim(), HeaderVariant::from_name(variant.trim())?),
+            None => (entry, HeaderVariant::default()),
+        };
+
+        let port = |text: &str| {
+            text.trim()
+                .parse::<u16>()
+                .ok()
+                .filter(|port| *port != 0)
+                .ok_or_else(|| anyhow::anyhow!("Invalid port {} in port rule \"{}\"", text.trim(), entry))
+        };
+        let ports = match ports.split_once('-') {
+            Some((first, last)) => port(first)?..=port(last)?,
+            None => port(ports)?..=port(ports)?,
+        };
+        if ports.is_empty() {
+            return Err(anyhow::anyhow!("Port range ends before it starts in port rule \"{}\"", entry));
+        }
+
+        Ok(Self { ports, variant })
+    }
+}
+
+// The parsed `ports` preference
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String")]
+pub struct PortRules {
+    rules: Vec<PortRule>,
+}
+
+impl Default for PortRules {
+    fn default() -> Self {
+        Self {
+            rules: vec![PortRule {
+                ports: 20000..=20000,
+                variant: HeaderVariant::default(),
+            }],
+        }
+    }
+}
+
+impl TryFrom<String> for PortRules {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> anyhow::Result<Self> {
+        Self::parse(&value)
+    }
+}
+
+impl PortRules {
+    // Parse a list of `port[-port][=variant]` rules separated by semicolons. A port in several
+    // rules is an error, its variant would be ambiguous.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let rules = text
+            .split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(PortRule::parse)
+            .collect::<anyhow::Result<Vec<PortRule>>>()?;
+
+        for (index, rule) in rules.iter().enumerate() {
+            if let Some(other) = rules[..index]
+                .iter()
+                .find(|other| other.ports.start() <= rule.ports.end() && rule.ports.start() <= other.ports.end())
+            {
+                return Err(anyhow::anyhow!(
+                    "Ports {}-{} and {}-{} overlap in the ports preference",
+                    other.ports.start(),
+                    other.ports.end(),
+                    rule.ports.start(),
+                    rule.ports.end()
+                ));
+            }
+        }
+        Ok(Self { rules })
+    }
+
+    // Get every port to dissect ELPIS on
+    pub fn ports(&self) -> impl Iterator<Item = u16> + '_ {
+        self.rules.iter().flat_map(|rule| rule.ports.clone())
+    }
+
+    // Get the header variant of datagrams on a port, None when ELPIS isn't dissected on it
+    pub fn variant(&self, port: u16) -> Option<HeaderVariant> {
+        self.rules.iter().find(|rule| rule.ports.contains(&port)).map(|rule| rule.variant)
+    }
+}
+
+#[test]
+fn parse_port_rules() {
+    let rules = PortRules::parse("20000; 20001=v2-le16;20002-20004=v1").unwrap();
+    assert_eq!(rules.ports().collect::<Vec<u16>>(), vec![20000, 20001, 20002, 20003, 20004]);
+    assert_eq!(rules.variant(20000), Some(HeaderVariant::V1Be32));
+    assert_eq!(rules.variant(20001), Some(HeaderVariant::V2Le16));
+    assert_eq!(rules.variant(20003), Some(HeaderVariant::V1Be32));
+    assert_eq!(rules.variant(20003).unwrap().header_length(), 8);
+    assert_eq!(rules.variant(20001).unwrap().header_length(), 4);
+    assert_eq!(rules.variant(20005), None);
+    assert_eq!(PortRules::default().ports().collect::<Vec<u16>>(), vec![20000]);
+
+    assert!(PortRules::parse("0").is_err());
+    assert!(PortRules::parse("20010-20002").is_err());
+    assert!(PortRules::parse("20000-20010;20005").is_err());
+    assert!(PortRules::parse("70000").is_err());
+    let error = PortRules::parse("20001=v3-ts").err().unwrap();
+    assert_eq!(error.to_string(), "Unsupported header variant \"v3-ts\", expected one of v1-be32, v2-le16");
+}
+
+#[test]
+fn parse_headers() {
+    let header = [0x00, 0x00, 0x01, 0x20, 0x00, 0x00, 0x00, 0x02];
+    assert_eq!(HeaderVariant::V1Be32.parse_header(&header), Some((0x120, 2)));
+    assert_eq!(HeaderVariant::V1Be32.parse_header(&header[..7]), None);
+
+    let header = [0x20, 0x01, 0x02, 0x00];
+    assert_eq!(HeaderVariant::V2Le16.parse_header(&header), Some((0x120, 2)));
+    assert_eq!(HeaderVariant::V2Le16.parse_header(&header[..3]), None);
+
+    // Ids and lengths of v2-le16 are unsigned, they're never negative
+    assert_eq!(HeaderVariant::V2Le16.parse_header(&[0xFF; 4]), Some((0xFFFF, 0xFFFF)));
+}
@@ -0,0 +1,205 @@
+// User preferences for the dissector, loaded from an elpis_prefs.json file next to the plugin.
+// Any key left out keeps its default. The few preferences also registered with Wireshark as
+// `elpis.<key>` only take their default from here.
+//
+// Example elpis_prefs.json:
+//     { "payload_wordswap": true, "unit_system": "imperial" }
+
+use crate::groups::GroupDatabases;
+use crate::info_column::InfoColumnVersion;
+use crate::ports::PortRules;
+use crate::suppress::ExpertSuppression;
+use crate::transform::PayloadKey;
+use crate::units::{UnitConversion, UnitSystem};
+use anyhow::Context;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ElpisPreferences {
+    // Undo the 4-byte word swap applied to payloads by gateway firmware 2.3.x before decoding signals.
+    // Default of the elpis.payload_wordswap Wireshark preference.
+    pub payload_wordswap: bool,
+
+    // 16-byte key test-fleet loggers XOR payloads with, as hex. Empty for payloads that aren't
+    // obfuscated.
+    pub payload_key: PayloadKey,
+
+    // Note frames whose id is lower than the one before them in the same datagram, for senders that
+    // always transmit in ascending id order. Default of the elpis.expect_ordered_ids Wireshark preference.
+    pub expect_ordered_ids: bool,
+
+    // Units signal values are displayed in, "native", "metric" or "imperial"
+    pub unit_system: UnitSystem,
+
+    // Unit conversions on top of the built in ones, e.g. [{ "metric": "Nm", "imperial": "lbf·ft", "scale": 0.737562 }]
+    pub unit_conversions: Vec<UnitConversion>,
+
+    // Expert checks to suppress for matching messages, e.g. "checksum:ProtoECU_*;length:Debug_*"
+    pub expert_suppress: ExpertSuppression,
+
+    // JSON file of analyst notes on messages and signals, relative to the plugin directory. Empty for none.
+    pub annotations_file: String,
+
+    // Warn when fewer than this percentage of frame ids are in the database, after 500 frames.
+    // 0 turns the warning off.
+    pub min_known_id_percent: f64,
+
+    // Write a line to stderr for every frame dissected. Lines are dropped rather than slowing down
+    // a live capture.
+    pub debug_logging: bool,
+
+    // With debug_logging, write a JSON trace of decoding every frame of this packet number to
+    // stderr. 0 for none.
+    pub trace_frame_number: u32,
+
+    // Time the phases of dissecting every packet, reported by -z elpis,summary. Only has an effect
+    // in builds with the self_timing feature.
+    pub self_timing: bool,
+
+    // With debug_logging, write a line for every packet taking longer than this many milliseconds
+    // to dissect, naming its slowest phase. Needs self_timing.
+    pub slow_packet_threshold_ms: f64,
+
+    // Show frames of messages with at most compact_max_signals signals as a single line, e.g.
+    // "ESP_Alive: Counter=7, Checksum=0x3A", with their filter fields hidden underneath
+    pub compact_small_frames: bool,
+
+    // Most signals, derived ones included, a message can have to be shown as a single line
+    pub compact_max_signals: usize,
+
+    // Use the database named by an "elpis-db=<file>" comment on the first ELPIS packet of a
+    // capture, looked up in database_search_dir, instead of messages.json
+    pub auto_db_from_capture: bool,
+
+    // Directory of databases captures can name, relative to the plugin directory
+    pub database_search_dir: String,
+
+    // Multiplies the limits on frames, signals and bytes decoded per packet, for captures with
+    // unusually large datagrams
+    pub decode_budget_scale: u32,
+
+    // Allow CAN FD messages their 64 payload bytes. Without it every CAN message is held to 8.
+    pub allow_can_fd: bool,
+
+    // Also show each signal of a missing frame extended along the line through its last two values,
+    // as elpis.signal_interpolated_value
+    pub linear_interpolation: bool,
+
+    // Databases for datagrams sent to multicast groups, relative to the plugin directory,
+    // e.g. "239.1.1.1/32=chassis.json;ff15::1=adas.json"
+    pub group_databases: GroupDatabases,
+
+    // UDP ports to dissect ELPIS on, with the header variant of each, e.g. "20000;20002-20010=v1-be32"
+    pub ports: PortRules,
+
+    // Format version of the Info column text, raised only to opt in to changes to it
+    pub info_column_version: InfoColumnVersion,
+
+    // Signals to plot in IO Graph, e.g. ["VehicleSpeed", "EngineRpm"]. Each gets a numeric
+    // elpis.watched.<signal> field whose values are kept from the first pass over a capture.
+    pub watch_signals: Vec<String>,
+}
+
+impl Default for ElpisPreferences {
+    fn default() -> Self {
+        Self {
+            payload_wordswap: false,
+            payload_key: PayloadKey::default(),
+            expect_ordered_ids: false,
+            unit_system: UnitSystem::default(),
+            unit_conversions: Vec::new(),
+            expert_suppress: ExpertSuppression::default(),
+            annotations_file: String::new(),
+            min_known_id_percent: 10.0,
+            debug_logging: false,
+            trace_frame_number: 0,
+            self_timing: true,
+            slow_packet_threshold_ms: 50.0,
+            compact_small_frames: false,
+            compact_max_signals: 2,
+            auto_db_from_capture: false,
+            database_search_dir: String::new(),
+            decode_budget_scale: 1,
+            allow_can_fd: true,
+            linear_interpolation: false,
+            group_databases: GroupDatabases::default(),
+            ports: PortRules::default(),
+            info_column_version: InfoColumnVersion::default(),
+            watch_signals: Vec::new(),
+        }
+    }
+}
+
+impl ElpisPreferences {
+    // Load preferences from the given path, using the defaults if the file doesn't exist
+    pub fn load_from_json(json_path: &str) -> anyhow::Result<Self> {
+        if !std::path::Path::new(json_path).exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(json_path)
+            .with_context(|| format!("Could not open file {}", json_path))?;
+        serde_json::from_str(&contents).with_context(|| format!("Could not parse JSON file {}", json_path))
+    }
+}
+
+#[test]
+fn parse_preferences() {
+    let prefs: ElpisPreferences = serde_json::from_str("{}").unwrap();
+    assert!(!prefs.payload_wordswap);
+    assert_eq!(prefs.unit_system, UnitSystem::Native);
+    assert_eq!(prefs.min_known_id_percent, 10.0);
+    assert!(!prefs.debug_logging);
+
+    let prefs: ElpisPreferences = serde_json::from_str(
+        r#"{ "unit_system": "imperial", "unit_conversions": [{ "metric": "l", "imperial": "gal", "scale": 0.264172 }] }"#,
+    )
+    .unwrap();
+    assert_eq!(prefs.unit_system, UnitSystem::Imperial);
+    assert_eq!(prefs.unit_conversions[0].imperial, "gal");
+    assert!(serde_json::from_str::<ElpisPreferences>(r#"{ "unit_system": "furlongs" }"#).is_err());
+
+    let prefs: ElpisPreferences = serde_json::from_str(r#"{ "payload_wordswap": true }"#).unwrap();
+    assert!(prefs.payload_wordswap);
+    assert!(!prefs.expect_ordered_ids);
+
+    let prefs: ElpisPreferences = serde_json::from_str(r#"{ "expect_ordered_ids": true }"#).unwrap();
+    assert!(prefs.expect_ordered_ids);
+
+    let prefs: ElpisPreferences = serde_json::from_str(r#"{ "expert_suppress": "checksum:ProtoECU_*" }"#).unwrap();
+    assert_ne!(prefs.expert_suppress, Default::default());
+    assert!(serde_json::from_str::<ElpisPreferences>(r#"{ "expert_suppress": "range:ProtoECU_*" }"#).is_err());
+
+    let prefs: ElpisPreferences = serde_json::from_str(r#"{ "annotations_file": "notes.json" }"#).unwrap();
+    assert_eq!(prefs.annotations_file, "notes.json");
+
+    let prefs: ElpisPreferences = serde_json::from_str(r#"{ "min_known_id_percent": 0 }"#).unwrap();
+    assert_eq!(prefs.min_known_id_percent, 0.0);
+
+    let prefs: ElpisPreferences = serde_json::from_str(r#"{ "compact_small_frames": true }"#).unwrap();
+    assert!(prefs.compact_small_frames);
+    assert_eq!(prefs.compact_max_signals, 2);
+
+    let prefs: ElpisPreferences =
+        serde_json::from_str(r#"{ "auto_db_from_capture": true, "database_search_dir": "databases" }"#).unwrap();
+    assert!(prefs.auto_db_from_capture);
+    assert_eq!(prefs.database_search_dir, "databases");
+
+    let prefs: ElpisPreferences = serde_json::from_str(r#"{ "decode_budget_scale": 4 }"#).unwrap();
+    assert_eq!(prefs.decode_budget_scale, 4);
+    assert_eq!(ElpisPreferences::default().decode_budget_scale, 1);
+    assert!(ElpisPreferences::default().allow_can_fd);
+    assert!(!ElpisPreferences::default().linear_interpolation);
+
+    let prefs: ElpisPreferences = serde_json::from_str(r#"{ "group_databases": "239.1.1.1/32=chassis.json" }"#).unwrap();
+    assert!(!prefs.group_databases.is_empty());
+    assert!(serde_json::from_str::<ElpisPreferences>(r#"{ "group_databases": "239.1.1.1/40=chassis.json" }"#).is_err());
+
+    let prefs: ElpisPreferences = serde_json::from_str(r#"{ "watch_signals": ["VehicleSpeed", "EngineRpm"] }"#).unwrap();
+    assert_eq!(prefs.watch_signals, ["VehicleSpeed", "EngineRpm"]);
+    assert!(ElpisPreferences::default().watch_signals.is_empty());
+
+    // Misspelled preferences are reported rather than silently ignored
+    assert!(serde_json::from_str::<ElpisPreferences>(r#"{ "payload_wordsawp": true }"#).is_err());
+}
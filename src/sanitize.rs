@@ -0,0 +1,143 @@
+// Turns message and signal names into Wireshark field abbreviations. Abbreviations may only use
+// ASCII letters, digits, '_' and '-', so names with anything else are rewritten, and a rewritten
+// name gets a suffix from a hash of the original so that "Wheel Speed" and "Wheel.Speed" don't
+// both end up as "Wheel_Speed".
+//
+// The suffix only depends on the name itself, never on what else is in the database, so adding or
+// removing signals doesn't change the abbreviations of the others. Anything generating filters
+// (the plugin, elpis-decode) must use this module so the names agree.
+
+use std::collections::HashMap;
+
+// Check if a character can be used in a field abbreviation
+fn is_abbrev_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+// Replace every character that can't be used in an abbreviation with '_'. Names that only use
+// valid characters are returned unchanged.
+pub fn sanitize(name: &str) -> String {
+    if name.is_empty() {
+        return String::from("_");
+    }
+
+    name.chars().map(|c| if is_abbrev_char(c) { c } else { '_' }).collect()
+}
+
+// 32-bit FNV-1a hash of a name, salted with the round so that a clash can be retried
+fn name_hash(name: &str, round: u8) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in std::iter::once(round).chain(name.bytes()) {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+// Abbreviations handed out so far, to make sure no two names share one
+#[derive(Debug, Default)]
+pub struct AbbrevSanitizer {
+    // Name each abbreviation was given to
+    owners: HashMap<String, String>,
+    // Abbreviation given to each name
+    abbrevs: HashMap<String, String>,
+}
+
+impl AbbrevSanitizer {
+    // Get the abbreviation of a name. Names that are valid abbreviations already are used as they
+    // are, others are sanitized and get an 8 hex digit suffix hashed from the original name. In the
+    // unlikely case that's taken by another name, the hash is salted until it isn't.
+    pub fn abbreviate(&mut self, name: &str) -> String {
+        if let Some(abbrev) = self.abbrevs.get(name) {
+            return abbrev.clone();
+        }
+
+        let mut abbrev = sanitize(name);
+        if abbrev != name || self.owners.contains_key(&abbrev) {
+            let sanitized = abbrev;
+            let mut round: u8 = 0;
+            loop {
+                abbrev = format!("{}_{:08x}", sanitized, name_hash(name, round));
+                if !self.owners.contains_key(&abbrev) {
+                    break;
+                }
+                round = round.wrapping_add(1);
+            }
+        }
+
+        self.owners.insert(abbrev.clone(), name.to_string());
+        self.abbrevs.insert(name.to_string(), abbrev.clone());
+        abbrev
+    }
+}
+
+#[test]
+fn sanitize_names() {
+    let mut sanitizer = AbbrevSanitizer::default();
+    assert_eq!(sanitizer.abbreviate("Wheel_Speed"), "Wheel_Speed");
+    assert_eq!(sanitizer.abbreviate("Wheel-Speed"), "Wheel-Speed");
+
+    // Rewritten names never take the plain name, whichever comes first
+    let spaced = sanitizer.abbreviate("Wheel Speed");
+    let dotted = sanitizer.abbreviate("Wheel.Speed");
+    assert!(spaced.starts_with("Wheel_Speed_"), "{}", spaced);
+    assert!(dotted.starts_with("Wheel_Speed_"), "{}", dotted);
+    assert_ne!(spaced, dotted);
+    assert_eq!(sanitizer.abbreviate("Wheel Speed"), spaced);
+
+    assert!(sanitizer.abbreviate("Fensterhöhe").starts_with("Fensterh_he_"));
+    assert!(sanitizer.abbreviate("").starts_with("__"));
+}
+
+// Random names made of characters that are and aren't valid in abbreviations
+#[cfg(test)]
+fn random_names(count: usize, seed: u32) -> Vec<String> {
+    const CHARS: [char; 12] = ['a', 'b', 'A', 'B', '0', '1', '_', '-', ' ', '.', 'ü', '/'];
+
+    let mut state = seed;
+    let mut next = || {
+        // xorshift keeps the names identical between runs
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    };
+
+    let mut names: Vec<String> = (0..count)
+        .map(|_| {
+            let length = 1 + next() as usize % 6;
+            (0..length).map(|_| CHARS[next() as usize % CHARS.len()]).collect()
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[test]
+fn abbreviations_are_unique_and_stable() {
+    let names = random_names(20000, 0x2545_F491);
+    assert!(names.len() > 10000);
+
+    let mut sanitizer = AbbrevSanitizer::default();
+    let abbrevs: Vec<String> = names.iter().map(|name| sanitizer.abbreviate(name)).collect();
+
+    // Every name gets its own valid abbreviation
+    let unique: std::collections::HashSet<&String> = abbrevs.iter().collect();
+    assert_eq!(unique.len(), names.len());
+    assert!(abbrevs.iter().all(|abbrev| abbrev.chars().all(is_abbrev_char)));
+
+    // The same names in another order, mixed with unrelated ones, get the same abbreviations
+    let mut shuffled: Vec<&String> = names.iter().collect();
+    shuffled.reverse();
+    let unrelated = random_names(5000, 0x1234_5678);
+
+    let mut other = AbbrevSanitizer::default();
+    for (name, unrelated) in shuffled.iter().zip(unrelated.iter().cycle()) {
+        other.abbreviate(unrelated);
+        other.abbreviate(name);
+    }
+    for (name, abbrev) in names.iter().zip(abbrevs.iter()) {
+        assert_eq!(&other.abbreviate(name), abbrev, "{:?}", name);
+    }
+}
@@ -0,0 +1,435 @@
+// Versions of the messages.json format. The plain array of messages exported by cantools is schema
+// 1, and every key this crate added on top of it (ranges, aliases, derived signals and the like) is
+// accepted there as best it can: key spellings are normalized and anything unknown is ignored.
+//
+// Schema 2 wraps the messages in an object declaring the version, and is checked strictly:
+//
+//     { "elpis_schema": 2, "messages": [ ... ] }
+//
+// Every key must be one this version knows, so a typo or a key from a newer version is an error
+// rather than silently ignored. elpis-decode migrate upgrades schema 1 files.
+
+use crate::elpis::MessageDefinition;
+use anyhow::Context;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+// Newest schema this version understands
+pub const CURRENT_SCHEMA: u64 = 2;
+
+const SCHEMA_KEY: &str = "elpis_schema";
+const MESSAGES_KEY: &str = "messages";
+
+// Keys of each object in a schema 2 database
+const MESSAGE_KEYS: &[&str] = &[
+    "name",
+    "length",
+    "id",
+    "comment",
+    "signals",
+    "checksum_signal",
+    "checksum",
+    "frame_type",
+    "is_can_fd",
+    "is_extended_id",
+    "id_mask",
+    "receive_timeout_ms",
+    "sender",
+    "derived",
+];
+const SIGNAL_KEYS: &[&str] = &[
+    "name",
+    "start",
+    "length",
+    "is_big_endian",
+    "default",
+    "minimum",
+    "maximum",
+    "offset",
+    "multiplexer_signal",
+    "spn",
+    "choices",
+    "scale",
+    "unit",
+    "comment",
+    "is_signed",
+    "is_multiplexer",
+    "is_float",
+    "multiplexer_ids",
+    "aliases",
+    "display_precision",
+    "affects_safety",
+    "ranges",
+    "group",
+    "category",
+];
+const RANGE_KEYS: &[&str] = &["from", "to", "label", "scale"];
+const DERIVED_KEYS: &[&str] = &["name", "expression"];
+const CHECKSUM_KEYS: &[&str] = &["signal", "algorithm", "data_id", "data_id_list"];
+
+// Old spellings of message keys accepted in schema 1, and the key they became
+const LEGACY_MESSAGE_KEYS: &[(&str, &str)] = &[("is_extended_frame", "is_extended_id")];
+
+// A database wrapped in an object, other keys of the wrapper already checked
+#[derive(Deserialize)]
+struct Wrapper {
+    messages: Vec<MessageDefinition>,
+}
+
+// A database split into its declared version and its messages
+struct Document {
+    version: u64,
+    messages: Vec<Value>,
+    // Keys of the wrapper object other than the version and the messages
+    unknown: Vec<String>,
+}
+
+impl Document {
+    fn split(document: Value) -> anyhow::Result<Self> {
+        let mut wrapper = match document {
+            Value::Array(messages) => {
+                return Ok(Self {
+                    version: 1,
+                    messages,
+                    unknown: Vec::new(),
+                })
+            }
+            Value::Object(wrapper) => wrapper,
+            _ => return Err(anyhow::anyhow!("A database must be an array of messages or an object with \"messages\"")),
+        };
+
+        let version = match wrapper.remove(SCHEMA_KEY) {
+            None => 1,
+            Some(version) => version
+                .as_u64()
+                .filter(|version| *version >= 1)
+                .with_context(|| format!("\"{}\" must be a whole number of at least 1, not {}", SCHEMA_KEY, version))?,
+        };
+        let messages = match wrapper.remove(MESSAGES_KEY) {
+            Some(Value::Array(messages)) => messages,
+            Some(_) => return Err(anyhow::anyhow!("\"{}\" must be an array", MESSAGES_KEY)),
+            None => return Err(anyhow::anyhow!("Database has no \"{}\"", MESSAGES_KEY)),
+        };
+        let unknown = wrapper.keys().map(|key| format!("{} at the top level", key)).collect();
+
+        Ok(Self { version, messages, unknown })
+    }
+
+    // Get every key the current schema doesn't know, and where it is
+    fn unknown_keys(&self) -> Vec<String> {
+        let mut unknown = self.unknown.clone();
+        for message in self.messages.iter() {
+            let message_name = object_name(message);
+            unknown.extend(unknown_in(message, MESSAGE_KEYS, &message_name));
+
+            for signal in array_items(message, "signals") {
+                let signal_name = format!("{}.{}", message_name, object_name(signal));
+                unknown.extend(unknown_in(signal, SIGNAL_KEYS, &signal_name));
+                for range in array_items(signal, "ranges") {
+                    unknown.extend(unknown_in(range, RANGE_KEYS, &format!("{} ranges", signal_name)));
+                }
+            }
+            for derived in array_items(message, "derived") {
+                unknown.extend(unknown_in(derived, DERIVED_KEYS, &format!("{}.{}", message_name, object_name(derived))));
+            }
+            if let Some(checksum) = message.get("checksum") {
+                unknown.extend(unknown_in(checksum, CHECKSUM_KEYS, &format!("{} checksum", message_name)));
+            }
+        }
+        unknown
+    }
+
+    // Bring the spelling of every key in line with the current schema, returning what was changed
+    fn normalize(&mut self) -> Vec<String> {
+        let mut changes = Vec::new();
+        for message in self.messages.iter_mut() {
+            let message_name = object_name(message);
+            normalize_keys(message, MESSAGE_KEYS, LEGACY_MESSAGE_KEYS, &message_name, &mut changes);
+
+            for signal in array_items_mut(message, "signals") {
+                let signal_name = format!("{}.{}", message_name, object_name(signal));
+                normalize_keys(signal, SIGNAL_KEYS, &[], &signal_name, &mut changes);
+                for range in array_items_mut(signal, "ranges") {
+                    normalize_keys(range, RANGE_KEYS, &[], &format!("{} ranges", signal_name), &mut changes);
+                }
+            }
+            for derived in array_items_mut(message, "derived") {
+                let derived_name = format!("{}.{}", message_name, object_name(derived));
+                normalize_keys(derived, DERIVED_KEYS, &[], &derived_name, &mut changes);
+            }
+            if let Some(checksum) = message.get_mut("checksum") {
+                normalize_keys(checksum, CHECKSUM_KEYS, &[], &format!("{} checksum", message_name), &mut changes);
+            }
+        }
+        changes
+    }
+}
+
+// Parse the message definitions of a database of any schema this version understands
+pub fn parse_database(contents: &str) -> anyhow::Result<Vec<MessageDefinition>> {
+    let mut document = Document::split(serde_json::from_str(contents)?)?;
+
+    if document.version > CURRENT_SCHEMA {
+        return Err(anyhow::anyhow!(
+            "Database declares {} {}, but only schema {} and older are understood. Unknown keys: {}",
+            SCHEMA_KEY,
+            document.version,
+            CURRENT_SCHEMA,
+            list_or_none(&document.unknown_keys())
+        ));
+    }
+
+    if document.version == CURRENT_SCHEMA {
+        let unknown = document.unknown_keys();
+        if !unknown.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Unknown keys in an {} {} database: {}",
+                SCHEMA_KEY,
+                document.version,
+                unknown.join(", ")
+            ));
+        }
+    } else if !document.normalize().is_empty() {
+        // Parsed from the normalized document, which loses the line numbers of any error
+        return Ok(serde_json::from_value(Value::Array(document.messages))?);
+    }
+
+    // Parsed again from the text, so errors point at the line they're on
+    if contents.trim_start().starts_with('[') {
+        Ok(serde_json::from_str(contents)?)
+    } else {
+        Ok(serde_json::from_str::<Wrapper>(contents)?.messages)
+    }
+}
+
+// Upgrade a database to the current schema, normalizing key spellings and dropping keys that no
+// schema knows. Returns the upgraded database and a line for each change made.
+pub fn migrate(contents: &str) -> anyhow::Result<(Value, Vec<String>)> {
+    let mut document = Document::split(serde_json::from_str(contents).context("Could not parse JSON")?)?;
+    if document.version > CURRENT_SCHEMA {
+        return Err(anyhow::anyhow!(
+            "Database declares {} {}, which is newer than schema {}",
+            SCHEMA_KEY,
+            document.version,
+            CURRENT_SCHEMA
+        ));
+    }
+
+    let mut changes = document.normalize();
+    let unknown = document.unknown_keys();
+    for message in document.messages.iter_mut() {
+        drop_unknown(message);
+    }
+    changes.extend(unknown.into_iter().map(|key| format!("dropped unknown key {}", key)));
+
+    // Check the result loads before handing it back
+    serde_json::from_value::<Vec<MessageDefinition>>(Value::Array(document.messages.clone()))
+        .context("Migrated database is still invalid")?;
+
+    let mut wrapper = Map::new();
+    wrapper.insert(SCHEMA_KEY.to_string(), Value::from(CURRENT_SCHEMA));
+    wrapper.insert(MESSAGES_KEY.to_string(), Value::Array(document.messages));
+    Ok((Value::Object(wrapper), changes))
+}
+
+// Get the name of a message, signal or derived signal, for pointing at it in errors
+fn object_name(object: &Value) -> String {
+    object.get("name").and_then(Value::as_str).unwrap_or("<unnamed>").to_string()
+}
+
+fn array_items<'a>(object: &'a Value, key: &str) -> impl Iterator<Item = &'a Value> {
+    object.get(key).and_then(Value::as_array).into_iter().flatten()
+}
+
+fn array_items_mut<'a>(object: &'a mut Value, key: &str) -> impl Iterator<Item = &'a mut Value> {
+    object.get_mut(key).and_then(Value::as_array_mut).into_iter().flatten()
+}
+
+fn unknown_in(object: &Value, known: &[&str], location: &str) -> Vec<String> {
+    let Some(object) = object.as_object() else {
+        return Vec::new();
+    };
+    object
+        .keys()
+        .filter(|key| !known.contains(&key.as_str()))
+        .map(|key| format!("{} in {}", key, location))
+        .collect()
+}
+
+// Rename keys spelled in camelCase, with dashes or in an older spelling to the key the schema
+// knows. A key already present under the right spelling is never overwritten, the other spelling
+// is dropped instead.
+fn normalize_keys(object: &mut Value, known: &[&str], legacy: &[(&str, &str)], location: &str, changes: &mut Vec<String>) {
+    let Some(object) = object.as_object_mut() else {
+        return;
+    };
+
+    let keys: Vec<String> = object.keys().filter(|key| !known.contains(&key.as_str())).cloned().collect();
+    for key in keys {
+        let spelled = snake_case(&key);
+        let renamed = legacy
+            .iter()
+            .find(|(old, _)| *old == spelled)
+            .map(|(_, new)| *new)
+            .or_else(|| known.iter().copied().find(|known| *known == spelled));
+        let Some(renamed) = renamed else { continue };
+        if object.contains_key(renamed) {
+            object.remove(&key);
+            changes.push(format!("dropped {} in {}, {} is given too", key, location, renamed));
+            continue;
+        }
+
+        let value = object.remove(&key).unwrap();
+        object.insert(renamed.to_string(), value);
+        changes.push(format!("renamed {} to {} in {}", key, renamed, location));
+    }
+}
+
+fn drop_unknown(message: &mut Value) {
+    retain_known(message, MESSAGE_KEYS);
+    for signal in array_items_mut(message, "signals") {
+        retain_known(signal, SIGNAL_KEYS);
+        for range in array_items_mut(signal, "ranges") {
+            retain_known(range, RANGE_KEYS);
+        }
+    }
+    for derived in array_items_mut(message, "derived") {
+        retain_known(derived, DERIVED_KEYS);
+    }
+    if let Some(checksum) = message.get_mut("checksum") {
+        retain_known(checksum, CHECKSUM_KEYS);
+    }
+}
+
+fn retain_known(object: &mut Value, known: &[&str]) {
+    if let Some(object) = object.as_object_mut() {
+        object.retain(|key, _| known.contains(&key.as_str()));
+    }
+}
+
+// Convert a key such as "receiveTimeoutMs" or "Is-Big-Endian" to snake case
+fn snake_case(key: &str) -> String {
+    let mut snake = String::new();
+    let mut previous: Option<char> = None;
+    for c in key.chars() {
+        if c.is_ascii_uppercase() && previous.is_some_and(|p| p.is_ascii_lowercase() || p.is_ascii_digit()) {
+            snake.push('_');
+        }
+        snake.push(if c == '-' { '_' } else { c.to_ascii_lowercase() });
+        previous = Some(c);
+    }
+    snake
+}
+
+fn list_or_none(keys: &[String]) -> String {
+    if keys.is_empty() {
+        String::from("none found")
+    } else {
+        keys.join(", ")
+    }
+}
+
+#[test]
+fn snake_case_keys() {
+    assert_eq!(snake_case("receiveTimeoutMs"), "receive_timeout_ms");
+    assert_eq!(snake_case("Is-Big-Endian"), "is_big_endian");
+    assert_eq!(snake_case("ID"), "id");
+    assert_eq!(snake_case("is_signed"), "is_signed");
+}
+
+#[test]
+fn schema_1_databases() {
+    // Plain arrays and wrappers without a version are schema 1, with spellings normalized and
+    // unknown keys ignored
+    let messages = parse_database(
+        r#"[{ "name": "Brake", "id": 16, "length": 8, "is_extended_frame": true, "crc": "xor",
+              "signals": [{ "name": "Pressure", "start": 7, "length": 8, "isSigned": true, "transform": "x" }] }]"#,
+    )
+    .unwrap();
+    assert_eq!(messages[0].is_extended_id, Some(true));
+    assert_eq!(messages[0].signals[0].is_signed, Some(true));
+
+    let messages = parse_database(r#"{ "messages": [{ "name": "Brake", "id": 16, "length": 8, "signals": [] }], "vendor": 1 }"#).unwrap();
+    assert_eq!(messages[0].name, "Brake");
+
+    // A key under its right spelling wins over another spelling of it
+    let messages = parse_database(
+        r#"[{ "name": "Brake", "id": 16, "length": 8, "is_extended_id": false, "is_extended_frame": true, "signals": [] }]"#,
+    )
+    .unwrap();
+    assert_eq!(messages[0].is_extended_id, Some(false));
+}
+
+#[test]
+fn schema_2_databases() {
+    let fixture = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/schema2_messages.json")).unwrap();
+    let messages = parse_database(&fixture).unwrap();
+    assert_eq!(messages.len(), 2);
+
+    // Older spellings and unknown keys are errors, each one named
+    let error = parse_database(
+        r#"{ "elpis_schema": 2, "messages": [{ "name": "Brake", "id": 16, "length": 8, "is_extended_frame": true,
+              "signals": [{ "name": "Pressure", "start": 7, "length": 8, "ranges": [{ "from": 0, "to": 1, "scale": true, "step": 1 }] }] }] }"#,
+    )
+    .err()
+    .unwrap()
+    .to_string();
+    assert!(error.contains("is_extended_frame in Brake"), "{}", error);
+    assert!(error.contains("step in Brake.Pressure ranges"), "{}", error);
+
+    let error = parse_database(
+        r#"{ "elpis_schema": 2, "messages": [{ "name": "Brake", "id": 16, "length": 8,
+              "checksum": { "signal": "Crc", "algorithm": "crc8", "polynomial": 29 }, "signals": [] }] }"#,
+    )
+    .err()
+    .unwrap()
+    .to_string();
+    assert!(error.contains("polynomial in Brake checksum"), "{}", error);
+
+    assert!(parse_database(r#"{ "elpis_schema": 0, "messages": [] }"#).is_err());
+    assert!(parse_database(r#"{ "elpis_schema": 2 }"#).is_err());
+}
+
+#[test]
+fn newer_schema_databases() {
+    let error = parse_database(
+        r#"{ "elpis_schema": 3, "messages": [{ "name": "Brake", "id": 16, "length": 8, "cycle_time_ms": 10,
+              "signals": [{ "name": "Pressure", "start": 7, "length": 8, "transform": "x" }] }] }"#,
+    )
+    .err()
+    .unwrap()
+    .to_string();
+    assert!(error.contains("elpis_schema 3"), "{}", error);
+    assert!(error.contains("cycle_time_ms in Brake, transform in Brake.Pressure"), "{}", error);
+
+    // Without any unknown keys a newer schema still can't be trusted to mean the same thing
+    let error = parse_database(r#"{ "elpis_schema": 3, "messages": [] }"#).err().unwrap().to_string();
+    assert!(error.contains("none found"), "{}", error);
+}
+
+#[test]
+fn migrate_to_current_schema() {
+    let (migrated, changes) = migrate(
+        r#"[{ "name": "Brake", "id": 16, "length": 8, "is_extended_frame": true, "crc": "xor",
+              "signals": [{ "name": "Pressure", "start": 7, "length": 8, "Is-Signed": true }] }]"#,
+    )
+    .unwrap();
+    assert_eq!(
+        changes,
+        vec![
+            "renamed is_extended_frame to is_extended_id in Brake",
+            "renamed Is-Signed to is_signed in Brake.Pressure",
+            "dropped unknown key crc in Brake",
+        ]
+    );
+    assert_eq!(migrated["elpis_schema"], CURRENT_SCHEMA);
+
+    // The result loads strictly, and migrating it again changes nothing
+    let migrated = serde_json::to_string(&migrated).unwrap();
+    let messages = parse_database(&migrated).unwrap();
+    assert_eq!(messages[0].is_extended_id, Some(true));
+    assert_eq!(messages[0].signals[0].is_signed, Some(true));
+    assert!(migrate(&migrated).unwrap().1.is_empty());
+
+    assert!(migrate(r#"{ "elpis_schema": 3, "messages": [] }"#).is_err());
+}
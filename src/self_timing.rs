@@ -0,0 +1,255 @@
+// Time the plugin spends in each phase of dissection, for finding out where it goes when a capture
+// is slow to open. The counters are cumulative over the session and cheap enough to stay on: every
+// packet's whole time is taken, but its phases only on one packet in PHASE_SAMPLING, as reading
+// the monotonic clock around each frame's phases costs about as much as the cheaper phases
+// themselves. A packet adds its totals to the shared counters once, when it's done. Built with the
+// self_timing feature, on by default, and turned off at runtime with the self_timing preference.
+// Without the feature every timer is a no-op.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// Phases are timed on one packet in this many
+pub const PHASE_SAMPLING: u64 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    // Finding the message definition of a frame's id
+    DatabaseLookup,
+    // Reading the raw values of signals from the payload
+    SignalExtraction,
+    // Adding a frame's items to the tree, not counting the signal extraction done meanwhile
+    TreeConstruction,
+    // Building and setting the Info column text
+    InfoColumn,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 4] = [
+        Phase::DatabaseLookup,
+        Phase::SignalExtraction,
+        Phase::TreeConstruction,
+        Phase::InfoColumn,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::DatabaseLookup => "database lookup",
+            Phase::SignalExtraction => "signal extraction",
+            Phase::TreeConstruction => "tree construction",
+            Phase::InfoColumn => "info column",
+        }
+    }
+
+    // The phase this one is timed within, whose time it's taken out of
+    fn within(&self) -> Option<Phase> {
+        match self {
+            Phase::SignalExtraction => Some(Phase::TreeConstruction),
+            _ => None,
+        }
+    }
+}
+
+// Times the phases of a single packet
+pub struct PacketTimer {
+    // When the packet's dissection started, None when timing is off
+    started: Option<Instant>,
+    // Whether this packet's phases are timed
+    sampled: bool,
+    nanos: [u64; 4],
+    // Time of the phases timed within each phase
+    nested_nanos: [u64; 4],
+    calls: [u64; 4],
+}
+
+impl PacketTimer {
+    fn new(enabled: bool, sampled: bool) -> Self {
+        let started = (cfg!(feature = "self_timing") && enabled).then(Instant::now);
+        Self {
+            sampled: started.is_some() && sampled,
+            started,
+            nanos: [0; 4],
+            nested_nanos: [0; 4],
+            calls: [0; 4],
+        }
+    }
+
+    // Start timing a phase, to be passed to stop. None when this packet's phases aren't timed.
+    pub fn start(&self) -> Option<Instant> {
+        self.sampled.then(Instant::now)
+    }
+
+    pub fn stop(&mut self, phase: Phase, started: Option<Instant>) {
+        if let Some(started) = started {
+            self.record(phase, started.elapsed());
+        }
+    }
+
+    fn record(&mut self, phase: Phase, elapsed: Duration) {
+        let nanos = elapsed.as_nanos() as u64;
+        self.nanos[phase as usize] += nanos;
+        self.calls[phase as usize] += 1;
+        if let Some(outer) = phase.within() {
+            self.nested_nanos[outer as usize] += nanos;
+        }
+    }
+
+    // Time spent in a phase of this packet, without the phases timed within it
+    fn phase_nanos(&self, phase: Phase) -> u64 {
+        self.nanos[phase as usize].saturating_sub(self.nested_nanos[phase as usize])
+    }
+
+    // Time since the packet's dissection started, None when timing is off
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.started.map(|started| started.elapsed())
+    }
+
+    // Describe a packet that took longer than the threshold, naming the phase it spent the most in if
+    // its phases were timed
+    pub fn slow_packet(&self, elapsed: Duration, threshold: Duration) -> Option<String> {
+        if elapsed <= threshold {
+            return None;
+        }
+        if !self.sampled {
+            return Some(format!("took {:.1} ms, phases not timed on this packet", elapsed.as_secs_f64() * 1e3));
+        }
+        let slowest = Phase::ALL.into_iter().max_by_key(|phase| self.phase_nanos(*phase))?;
+        Some(format!(
+            "took {:.1} ms, slowest phase {} with {:.1} ms",
+            elapsed.as_secs_f64() * 1e3,
+            slowest.name(),
+            self.phase_nanos(slowest) as f64 / 1e6
+        ))
+    }
+}
+
+// Time of every packet and time and calls of every phase over the session
+#[derive(Default)]
+pub struct SelfTiming {
+    nanos: [AtomicU64; 4],
+    calls: [AtomicU64; 4],
+    // Packets started, which picks the ones whose phases are timed
+    started_packets: AtomicU64,
+    packets: AtomicU64,
+    sampled_packets: AtomicU64,
+    packet_nanos: AtomicU64,
+}
+
+impl SelfTiming {
+    // Start timing a packet, timing its phases too if it's one of the sampled packets
+    pub fn start_packet(&self, enabled: bool) -> PacketTimer {
+        let sampled = enabled && self.started_packets.fetch_add(1, Ordering::Relaxed).is_multiple_of(PHASE_SAMPLING);
+        PacketTimer::new(enabled, sampled)
+    }
+
+    // Add a packet that's done, with its whole time
+    pub fn add(&self, timer: &PacketTimer, elapsed: Duration) {
+        if timer.sampled {
+            for phase in Phase::ALL {
+                self.nanos[phase as usize].fetch_add(timer.phase_nanos(phase), Ordering::Relaxed);
+                self.calls[phase as usize].fetch_add(timer.calls[phase as usize], Ordering::Relaxed);
+            }
+            self.sampled_packets.fetch_add(1, Ordering::Relaxed);
+        }
+        self.packets.fetch_add(1, Ordering::Relaxed);
+        self.packet_nanos.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    // Get the time and number of calls of a phase over the sampled packets so far
+    pub fn phase(&self, phase: Phase) -> (Duration, u64) {
+        (
+            Duration::from_nanos(self.nanos[phase as usize].load(Ordering::Relaxed)),
+            self.calls[phase as usize].load(Ordering::Relaxed),
+        )
+    }
+
+    // Render the calls, time and time per call of every phase, after the packets and their whole time
+    pub fn render(&self) -> String {
+        let mut output = format!(
+            "ELPIS self timing: {} packets, {:.3} ms, phases timed on {} of them\n",
+            self.packets.load(Ordering::Relaxed),
+            self.packet_nanos.load(Ordering::Relaxed) as f64 / 1e6,
+            self.sampled_packets.load(Ordering::Relaxed)
+        );
+        for phase in Phase::ALL {
+            let (time, calls) = self.phase(phase);
+            let per_call = (time.as_nanos() as u64).checked_div(calls).unwrap_or(0);
+            output.push_str(
+                format!(
+                    "{:<18} {:>10} calls {:>12.3} ms {:>10} ns/call\n",
+                    phase.name(),
+                    calls,
+                    time.as_secs_f64() * 1e3,
+                    per_call
+                )
+                .as_str(),
+            );
+        }
+        output
+    }
+}
+
+#[cfg(feature = "self_timing")]
+#[test]
+fn nested_phases_and_slow_packets() {
+    let mut timer = PacketTimer::new(true, true);
+    timer.record(Phase::DatabaseLookup, Duration::from_millis(2));
+    timer.record(Phase::TreeConstruction, Duration::from_millis(60));
+    timer.record(Phase::SignalExtraction, Duration::from_millis(25));
+    timer.record(Phase::SignalExtraction, Duration::from_millis(15));
+
+    // Tree construction is left with 20 ms once signal extraction is taken out
+    assert_eq!(
+        timer.slow_packet(Duration::from_millis(70), Duration::from_millis(50)).as_deref(),
+        Some("took 70.0 ms, slowest phase signal extraction with 40.0 ms")
+    );
+    assert_eq!(timer.slow_packet(Duration::from_millis(50), Duration::from_millis(50)), None);
+
+    let totals = SelfTiming::default();
+    totals.add(&timer, Duration::from_millis(70));
+    totals.add(&timer, Duration::from_millis(70));
+    assert_eq!(totals.phase(Phase::SignalExtraction), (Duration::from_millis(80), 4));
+    assert_eq!(totals.phase(Phase::TreeConstruction), (Duration::from_millis(40), 2));
+    assert_eq!(totals.phase(Phase::InfoColumn), (Duration::ZERO, 0));
+
+    // Packets whose phases aren't timed only count towards the whole time
+    let unsampled = PacketTimer::new(true, false);
+    assert!(unsampled.start().is_none());
+    assert_eq!(
+        unsampled.slow_packet(Duration::from_millis(70), Duration::from_millis(50)).as_deref(),
+        Some("took 70.0 ms, phases not timed on this packet")
+    );
+    totals.add(&unsampled, Duration::from_millis(10));
+    assert_eq!(totals.phase(Phase::SignalExtraction), (Duration::from_millis(80), 4));
+    let rendered = totals.render();
+    assert!(rendered.starts_with("ELPIS self timing: 3 packets, 150.000 ms, phases timed on 2 of them\n"));
+    assert_eq!(rendered.lines().count(), 1 + Phase::ALL.len());
+
+    // One packet in PHASE_SAMPLING has its phases timed
+    let totals = SelfTiming::default();
+    let sampled = (0..PHASE_SAMPLING * 2).filter(|_| totals.start_packet(true).start().is_some()).count();
+    assert_eq!(sampled, 2);
+
+    // Nothing is timed when it's off
+    let mut timer = totals.start_packet(false);
+    let started = timer.start();
+    timer.stop(Phase::DatabaseLookup, started);
+    assert!(started.is_none());
+    assert!(timer.elapsed().is_none());
+    assert_eq!(timer.calls, [0; 4]);
+}
+
+#[cfg(not(feature = "self_timing"))]
+#[test]
+fn no_timing_without_feature() {
+    let totals = SelfTiming::default();
+    let mut timer = totals.start_packet(true);
+    let started = timer.start();
+    timer.stop(Phase::DatabaseLookup, started);
+    assert!(started.is_none());
+    assert!(timer.elapsed().is_none());
+    assert_eq!(timer.slow_packet(Duration::from_millis(70), Duration::from_millis(50)).as_deref(), Some("took 70.0 ms, phases not timed on this packet"));
+
+    totals.add(&timer, Duration::ZERO);
+    assert_eq!(totals.phase(Phase::DatabaseLookup), (Duration::ZERO, 0));
+}
@@ -0,0 +1,155 @@
+// Values of the signals on the watch_signals list, kept per signal as columns from the first pass
+// over a capture. IO Graph dissects every packet again each time a graph is redrawn, and a watched
+// signal's elpis.watched.<signal> field is then read back from here. Values are held as 32-bit
+// floats, and the first pass shows the rounded value too so that every pass agrees.
+
+use crate::sanitize::AbbrevSanitizer;
+
+// Every value recorded for one signal of one message, in packet order
+struct Series {
+    message_id: i32,
+    signal_index: u32,
+    // Packet and frame within it of each value
+    packets: Vec<u32>,
+    frames: Vec<u32>,
+    values: Vec<f32>,
+    // Position after the last value read back. Redraws go through the packets in order, so the
+    // value wanted is nearly always the one here and the columns are only searched when it isn't.
+    next: usize,
+}
+
+impl Series {
+    // Get the value recorded for a frame
+    fn value(&mut self, packet_number: u32, frame_index: u32) -> Option<f32> {
+        let at = |index: usize| self.packets.get(index) == Some(&packet_number) && self.frames[index] == frame_index;
+        let index = if at(self.next) {
+            self.next
+        } else {
+            let start = self.packets.partition_point(|packet| *packet < packet_number);
+            let count = self.packets[start..].iter().take_while(|packet| **packet == packet_number).count();
+            start + self.frames[start..start + count].iter().position(|frame| *frame == frame_index)?
+        };
+        self.next = index + 1;
+        Some(self.values[index])
+    }
+
+    // Whether a frame comes after every frame recorded so far
+    fn is_new(&self, packet_number: u32, frame_index: u32) -> bool {
+        match (self.packets.last(), self.frames.last()) {
+            (Some(packet), Some(frame)) => (packet_number, frame_index) > (*packet, *frame),
+            _ => true,
+        }
+    }
+
+    fn memory_bytes(&self) -> usize {
+        (self.packets.capacity() + self.frames.capacity()) * std::mem::size_of::<u32>()
+            + self.values.capacity() * std::mem::size_of::<f32>()
+    }
+}
+
+// Watched signal values of the capture, a series for each signal of each message. There are only
+// as many as signals are watched, so they're looked up one after the other.
+#[derive(Default)]
+pub struct SeriesCache {
+    series: Vec<Series>,
+}
+
+impl SeriesCache {
+    // Get the value of a watched signal in a frame. The value given is recorded when the frame comes
+    // after every one recorded for the signal, as on the first pass, and the recorded one is
+    // returned whenever the frame is seen again.
+    pub fn observe(&mut self, packet_number: u32, frame_index: u32, message_id: i32, signal_index: u32, value: f64) -> f32 {
+        let index = match self
+            .series
+            .iter()
+            .position(|series| series.message_id == message_id && series.signal_index == signal_index)
+        {
+            Some(index) => index,
+            None => {
+                self.series.push(Series {
+                    message_id,
+                    signal_index,
+                    packets: Vec::new(),
+                    frames: Vec::new(),
+                    values: Vec::new(),
+                    next: 0,
+                });
+                self.series.len() - 1
+            }
+        };
+        let series = &mut self.series[index];
+        if series.is_new(packet_number, frame_index) {
+            series.packets.push(packet_number);
+            series.frames.push(frame_index);
+            series.values.push(value as f32);
+            return value as f32;
+        }
+        series.value(packet_number, frame_index).unwrap_or(value as f32)
+    }
+
+    // Get the number of signals with values kept
+    pub fn series_count(&self) -> usize {
+        self.series.len()
+    }
+
+    // Get the number of values kept, across every signal
+    pub fn value_count(&self) -> usize {
+        self.series.iter().map(|series| series.values.len()).sum()
+    }
+
+    // Get the memory used by the values kept, in bytes
+    pub fn memory_bytes(&self) -> usize {
+        self.series.iter().map(Series::memory_bytes).sum::<usize>() + self.series.capacity() * std::mem::size_of::<Series>()
+    }
+
+    // Describe what's kept, for -z elpis,summary
+    pub fn describe(&self) -> String {
+        format!(
+            "ELPIS watched signals: {} values of {} signals in {} KiB",
+            self.value_count(),
+            self.series_count(),
+            self.memory_bytes().div_ceil(1024)
+        )
+    }
+}
+
+// Get the filter name of each watched signal's field, e.g. elpis.watched.VehicleSpeed
+pub fn watched_field_abbrevs(signals: &[String]) -> Vec<String> {
+    let mut abbrevs = AbbrevSanitizer::default();
+    signals
+        .iter()
+        .map(|signal| format!("elpis.watched.{}", abbrevs.abbreviate(signal)))
+        .collect()
+}
+
+#[test]
+fn series_cache() {
+    let mut cache = SeriesCache::default();
+
+    // Values are recorded on the first pass and read back after
+    assert_eq!(cache.observe(1, 0, 0x100, 2, 12.5), 12.5);
+    assert_eq!(cache.observe(1, 1, 0x100, 2, 13.0), 13.0);
+    assert_eq!(cache.observe(2, 0, 0x200, 0, 40.0), 40.0);
+    assert_eq!(cache.observe(3, 0, 0x100, 2, 14.0), 14.0);
+    assert_eq!(cache.observe(1, 1, 0x100, 2, 99.0), 13.0);
+    assert_eq!(cache.observe(1, 0, 0x100, 2, 99.0), 12.5);
+    assert_eq!(cache.observe(3, 0, 0x100, 2, 99.0), 14.0);
+    assert_eq!((cache.series_count(), cache.value_count()), (2, 4));
+    assert!(cache.memory_bytes() >= 4 * 12);
+
+    // A frame the first pass didn't record isn't added out of order
+    assert_eq!(cache.observe(2, 0, 0x100, 2, 20.0), 20.0);
+    assert_eq!(cache.value_count(), 4);
+
+    // Values are rounded to 32 bits on every pass
+    assert_eq!(cache.observe(4, 0, 0x100, 2, 0.1), 0.1f32);
+    assert!(cache.describe().starts_with("ELPIS watched signals: 5 values of 2 signals in "));
+}
+
+#[test]
+fn watched_fields() {
+    let signals = ["VehicleSpeed".to_string(), "Wheel Speed".to_string()];
+    let abbrevs = watched_field_abbrevs(&signals);
+    assert_eq!(abbrevs[0], "elpis.watched.VehicleSpeed");
+    assert!(abbrevs[1].starts_with("elpis.watched.Wheel_Speed_"));
+}
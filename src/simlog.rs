@@ -0,0 +1,93 @@
+// Reads frames out of the text logs our HIL simulator writes instead of pcaps, one frame per line:
+//
+//     12.3456 TX 0x123 8 DE AD BE EF 00 11 22 33
+//
+// with the timestamp in seconds, the direction, the id in hex, the payload length and the payload
+// bytes in hex. Fields are separated by any whitespace and hex is read in either case.
+
+use anyhow::Context;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Tx,
+    Rx,
+}
+
+// A single frame logged by the simulator
+#[derive(Debug, PartialEq)]
+pub struct SimlogFrame {
+    // Seconds, as logged by the simulator
+    pub timestamp: f64,
+    pub direction: Direction,
+    pub id: i32,
+    pub payload: Vec<u8>,
+}
+
+// Parse a line of a simulator log, None for blank lines
+pub fn parse_line(line: &str) -> anyhow::Result<Option<SimlogFrame>> {
+    let mut fields = line.split_whitespace();
+    let Some(timestamp) = fields.next() else {
+        return Ok(None);
+    };
+    let mut next_field = |name: &str| fields.next().with_context(|| format!("Missing {}", name));
+
+    let timestamp: f64 = timestamp.parse().with_context(|| format!("Invalid timestamp {}", timestamp))?;
+    let direction = match next_field("direction")? {
+        direction if direction.eq_ignore_ascii_case("tx") => Direction::Tx,
+        direction if direction.eq_ignore_ascii_case("rx") => Direction::Rx,
+        direction => return Err(anyhow::anyhow!("Invalid direction {}, expected TX or RX", direction)),
+    };
+
+    let id_text = next_field("id")?;
+    let hex = id_text.strip_prefix("0x").or_else(|| id_text.strip_prefix("0X")).unwrap_or(id_text);
+    let id = i32::from_str_radix(hex, 16).with_context(|| format!("Invalid id {}", id_text))?;
+
+    let dlc_text = next_field("length")?;
+    let dlc: usize = dlc_text.parse().with_context(|| format!("Invalid length {}", dlc_text))?;
+
+    let payload = fields
+        .map(|byte| {
+            if byte.len() != 2 {
+                return Err(anyhow::anyhow!("Invalid payload byte {}", byte));
+            }
+            u8::from_str_radix(byte, 16).with_context(|| format!("Invalid payload byte {}", byte))
+        })
+        .collect::<anyhow::Result<Vec<u8>>>()?;
+    if payload.len() != dlc {
+        return Err(anyhow::anyhow!("Length {} but {} payload bytes", dlc, payload.len()));
+    }
+
+    Ok(Some(SimlogFrame {
+        timestamp,
+        direction,
+        id,
+        payload,
+    }))
+}
+
+#[test]
+fn parse_simlog_lines() {
+    assert_eq!(
+        parse_line("12.3456 TX 0x123 8 DE AD BE EF 00 11 22 33").unwrap(),
+        Some(SimlogFrame {
+            timestamp: 12.3456,
+            direction: Direction::Tx,
+            id: 0x123,
+            payload: vec![0xde, 0xad, 0xbe, 0xef, 0x00, 0x11, 0x22, 0x33],
+        })
+    );
+
+    // Lowercase hex, tabs and runs of spaces
+    let frame = parse_line("  0.5\trx   0X1aB 2  de\tad ").unwrap().unwrap();
+    assert_eq!((frame.direction, frame.id, frame.payload), (Direction::Rx, 0x1ab, vec![0xde, 0xad]));
+    assert_eq!(parse_line("1.0 TX 7ff 0").unwrap().unwrap().id, 0x7ff);
+    assert_eq!(parse_line(" \t").unwrap(), None);
+
+    let error = |line: &str| format!("{:#}", parse_line(line).err().unwrap());
+    assert_eq!(error("12.3 TX 0x123 2 DE"), "Length 2 but 1 payload bytes");
+    assert_eq!(error("12.3 TX 0x123 1 DEAD"), "Invalid payload byte DEAD");
+    assert_eq!(error("12.3 XX 0x123 1 DE"), "Invalid direction XX, expected TX or RX");
+    assert_eq!(error("12.3 TX 0x12g 1 DE"), "Invalid id 0x12g: invalid digit found in string");
+    assert_eq!(error("12.3 TX"), "Missing id");
+    assert!(parse_line("TX 0x123 1 DE").is_err());
+}
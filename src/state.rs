@@ -0,0 +1,750 @@
+// State kept across packets of a capture, such as the last seen value of each signal.
+//
+// Wireshark dissects every packet once in order, then again whenever a packet is selected. Results
+// are remembered per packet so a revisited packet shows exactly what it showed the first time.
+//
+// A stateful field must show the same value whether the capture is read in a single pass
+// (`tshark` without `-2`, where only the first pass is ever output) or in two passes (`tshark -2`
+// and the GUI, where only revisits are shown). Fields only look at earlier packets, and manage this
+// by remembering their results.
+
+use crate::ports::HeaderVariant;
+use std::collections::HashMap;
+
+// Which way a datagram travels between the two endpoints of its conversation, with A the endpoint
+// with the lower address, or the lower port on the same address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    AToB,
+    BToA,
+}
+
+impl Direction {
+    // Get the direction from the source and destination (address, port)
+    pub fn from_endpoints(source: (&[u8], u32), destination: (&[u8], u32)) -> Self {
+        if source <= destination {
+            Direction::AToB
+        } else {
+            Direction::BToA
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::AToB => "A→B",
+            Direction::BToA => "B→A",
+        }
+    }
+}
+
+// One direction of one conversation, from the source to the destination (address, port) of its
+// datagrams. ECUs talking to each other on port 20000 at both ends interleave two flows in one
+// conversation, and other ECUs talk at the same time, so history kept per flow keeps them from
+// being mistaken for one stream.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Flow {
+    source: (Vec<u8>, u32),
+    destination: (Vec<u8>, u32),
+}
+
+impl Flow {
+    pub fn new(source: (&[u8], u32), destination: (&[u8], u32)) -> Self {
+        Flow {
+            source: (source.0.to_vec(), source.1),
+            destination: (destination.0.to_vec(), destination.1),
+        }
+    }
+
+    // Get which way the flow goes within its conversation
+    pub fn direction(&self) -> Direction {
+        Direction::from_endpoints((&self.source.0, self.source.1), (&self.destination.0, self.destination.1))
+    }
+
+    // Get the flow's conversation, the same for both directions: the A→B flow of it
+    pub fn conversation(&self) -> Flow {
+        match self.direction() {
+            Direction::AToB => self.clone(),
+            Direction::BToA => Flow {
+                source: self.destination.clone(),
+                destination: self.source.clone(),
+            },
+        }
+    }
+}
+
+// A message id in one flow, each has its own history
+pub type MessageStream = (Flow, i32);
+
+// A flow from the ECU at 10.0.0.<source> to the one at 10.0.0.<destination>, both on port 20000
+#[cfg(test)]
+fn flow(source: u8, destination: u8) -> Flow {
+    Flow::new((&[10, 0, 0, source], 20000), (&[10, 0, 0, destination], 20000))
+}
+
+#[test]
+fn directions() {
+    let ecu = [10, 0, 0, 1];
+    let gateway = [10, 0, 0, 2];
+    assert_eq!(Direction::from_endpoints((&ecu, 20000), (&gateway, 20000)), Direction::AToB);
+    assert_eq!(Direction::from_endpoints((&gateway, 20000), (&ecu, 20000)), Direction::BToA);
+
+    // The same address at both ends is ordered by port
+    assert_eq!(Direction::from_endpoints((&ecu, 20000), (&ecu, 20001)), Direction::AToB);
+    assert_eq!(Direction::from_endpoints((&ecu, 20001), (&ecu, 20000)).as_str(), "B→A");
+
+    assert_eq!(flow(1, 2).direction(), Direction::AToB);
+    assert_eq!(flow(2, 1).direction(), Direction::BToA);
+    assert_ne!(flow(1, 2), flow(3, 4));
+    assert_eq!(flow(2, 1).conversation(), flow(1, 2));
+    assert_eq!(flow(1, 2).conversation(), flow(1, 2));
+}
+
+// The header variant of each conversation, picked by port on its first packet and kept for the
+// rest, so both directions of a conversation are always parsed alike
+#[derive(Default)]
+pub struct ConversationVariants {
+    variants: HashMap<Flow, HeaderVariant>,
+}
+
+impl ConversationVariants {
+    // Get the variant of a datagram's conversation, picking it if this is the conversation's first
+    pub fn resolve(&mut self, flow: &Flow, pick: impl FnOnce() -> HeaderVariant) -> HeaderVariant {
+        *self.variants.entry(flow.conversation()).or_insert_with(pick)
+    }
+}
+
+#[test]
+fn conversation_variants() {
+    let mut variants = ConversationVariants::default();
+    assert_eq!(variants.resolve(&flow(1, 2), || HeaderVariant::V2Le16), HeaderVariant::V2Le16);
+    assert_eq!(variants.resolve(&flow(3, 4), || HeaderVariant::V1Be32), HeaderVariant::V1Be32);
+
+    // Picked once per conversation, whichever way its datagrams go
+    assert_eq!(variants.resolve(&flow(2, 1), || unreachable!()), HeaderVariant::V2Le16);
+    assert_eq!(variants.resolve(&flow(4, 3), || unreachable!()), HeaderVariant::V1Be32);
+}
+
+// Spots when the state kept across packets must start over: another capture was opened, or the
+// database was replaced. Nothing tells the plugin a capture was closed, so a packet dissected again
+// with another timestamp, or not dissected at all despite an earlier number, means a new capture.
+#[derive(Default)]
+pub struct CaptureTracker {
+    // Timestamp of every packet seen so far, indexed by packet number
+    timestamps: Vec<Option<f64>>,
+    // Generation of the database the packets were dissected with
+    database_generation: u64,
+}
+
+impl CaptureTracker {
+    // Record a packet, returning true when it starts a new capture or the database was replaced
+    // since the previous packet
+    pub fn observe(&mut self, packet_number: u32, timestamp: f64, database_generation: u64) -> bool {
+        let index = packet_number as usize;
+        let restart = match self.timestamps.get(index) {
+            Some(Some(seen)) => *seen != timestamp,
+            _ => index < self.timestamps.len(),
+        } || database_generation != self.database_generation;
+
+        if restart {
+            self.timestamps.clear();
+            self.database_generation = database_generation;
+        }
+        if self.timestamps.len() <= index {
+            self.timestamps.resize(index + 1, None);
+        }
+        self.timestamps[index] = Some(timestamp);
+
+        restart
+    }
+}
+
+#[test]
+fn capture_changes() {
+    let mut tracker = CaptureTracker::default();
+    assert!(!tracker.observe(1, 1.0, 0));
+    assert!(!tracker.observe(2, 2.0, 0));
+    assert!(!tracker.observe(4, 4.0, 0));
+
+    // Revisits are the same capture
+    assert!(!tracker.observe(1, 1.0, 0));
+    assert!(!tracker.observe(4, 4.0, 0));
+
+    // A packet seen with another timestamp, or skipped before, is from another capture
+    assert!(tracker.observe(2, 7.0, 0));
+    assert!(!tracker.observe(3, 8.0, 0));
+    assert!(tracker.observe(1, 1.0, 0));
+    assert!(!tracker.observe(2, 2.0, 0));
+    assert!(!tracker.observe(3, 3.0, 0));
+
+    // So is replacing the database
+    assert!(tracker.observe(3, 3.0, 1));
+    assert!(!tracker.observe(3, 3.0, 1));
+}
+
+// Identifies a signal within a specific frame of a specific packet
+#[derive(Hash, PartialEq, Eq, Clone)]
+struct SignalOccurrence {
+    packet_number: u32,
+    frame_index: u32,
+    signal_name: String,
+}
+
+// How a signal's value compares to its previous occurrence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignalChange {
+    // Raw value of the previous occurrence, if there was one
+    pub previous: Option<u128>,
+    // True when the value differs from the previous occurrence, or this is the first occurrence
+    pub changed: bool,
+}
+
+// Whether each signal's value changed since its previous occurrence. Only needs earlier packets.
+// Raw values are compared, as physical values of signals over 64 bits lose their low bits.
+#[derive(Default)]
+pub struct SignalHistory {
+    // Last raw value of each signal, keyed by flow, message id and signal name
+    last_values: HashMap<(Flow, i32, String), u128>,
+    // Results already handed out for each signal occurrence
+    results: HashMap<SignalOccurrence, SignalChange>,
+}
+
+impl SignalHistory {
+    // Record a signal's raw value, returning how it changed since its previous occurrence
+    pub fn observe(
+        &mut self,
+        packet_number: u32,
+        frame_index: u32,
+        flow: &Flow,
+        message_id: i32,
+        signal_name: &str,
+        value: u128,
+    ) -> SignalChange {
+        let occurrence = SignalOccurrence {
+            packet_number,
+            frame_index,
+            signal_name: signal_name.to_string(),
+        };
+
+        if let Some(change) = self.results.get(&occurrence) {
+            return *change;
+        }
+
+        let previous = self
+            .last_values
+            .insert((flow.clone(), message_id, signal_name.to_string()), value);
+        let change = SignalChange {
+            previous,
+            changed: previous != Some(value),
+        };
+        self.results.insert(occurrence, change);
+
+        change
+    }
+}
+
+#[test]
+fn signal_changes() {
+    let mut history = SignalHistory::default();
+
+    let first = history.observe(1, 0, &flow(1, 2), 0x10, "Speed", 5);
+    assert_eq!(first, SignalChange { previous: None, changed: true });
+
+    assert!(!history.observe(2, 0, &flow(1, 2), 0x10, "Speed", 5).changed);
+    assert!(history.observe(3, 0, &flow(1, 2), 0x10, "Speed", 6).changed);
+
+    // The same signal name in a different message is tracked separately
+    assert!(history.observe(3, 1, &flow(1, 2), 0x20, "Speed", 1).changed);
+
+    // Revisiting a packet gives the same answer as the first pass
+    assert_eq!(history.observe(1, 0, &flow(1, 2), 0x10, "Speed", 5), first);
+    assert!(history.observe(3, 0, &flow(1, 2), 0x10, "Speed", 6).changed);
+    assert!(!history.observe(4, 0, &flow(1, 2), 0x10, "Speed", 6).changed);
+
+    // Serials one apart are the same f64, but a different raw value
+    let messages = crate::elpis::wide_test_messages();
+    let message = messages.get_def_by_id(0x300).unwrap();
+    let serial = 1u128 << 90;
+    assert_eq!(serial as f64, (serial + 1) as f64);
+    for (packet_number, serial) in [(5, serial), (6, serial + 1)] {
+        let frame = message.decode(&crate::elpis::wide_test_payload(serial, 0, 0));
+        let change = history.observe(packet_number, 0, &flow(1, 2), 0x300, "Serial", frame.signals[0].raw);
+        assert!(change.changed, "packet {}", packet_number);
+    }
+}
+
+// How many times each signal has been decoded in the capture. Only needs earlier packets, and
+// each occurrence is given the count up to and including itself, so revisits don't add to it.
+#[derive(Default)]
+pub struct SignalDecodeCounts {
+    // Decodes of each signal so far, keyed by message id and signal name
+    counts: HashMap<(i32, String), u64>,
+    // Counts already handed out for each signal occurrence
+    results: HashMap<SignalOccurrence, u64>,
+}
+
+impl SignalDecodeCounts {
+    // Record a decode of a signal, returning how many times it has been decoded so far
+    pub fn observe(&mut self, packet_number: u32, frame_index: u32, message_id: i32, signal_name: &str) -> u64 {
+        let occurrence = SignalOccurrence {
+            packet_number,
+            frame_index,
+            signal_name: signal_name.to_string(),
+        };
+
+        if let Some(count) = self.results.get(&occurrence) {
+            return *count;
+        }
+
+        let count = self.counts.entry((message_id, signal_name.to_string())).or_default();
+        *count += 1;
+        self.results.insert(occurrence, *count);
+        *count
+    }
+}
+
+#[test]
+fn signal_decode_counts() {
+    let mut counts = SignalDecodeCounts::default();
+
+    assert_eq!(counts.observe(1, 0, 0x10, "Speed"), 1);
+    assert_eq!(counts.observe(1, 1, 0x10, "Speed"), 2);
+    assert_eq!(counts.observe(2, 0, 0x10, "Speed"), 3);
+
+    // The same signal name in a different message is counted separately
+    assert_eq!(counts.observe(2, 1, 0x20, "Speed"), 1);
+
+    // Revisiting a packet gives the same count as the first pass
+    assert_eq!(counts.observe(1, 1, 0x10, "Speed"), 2);
+    assert_eq!(counts.observe(3, 0, 0x10, "Speed"), 4);
+}
+
+// Frames of a message that should have been received between two that were
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReceiveGap {
+    // Milliseconds since the previous frame of the message
+    pub elapsed_ms: f64,
+    // How many frames were expected in the gap
+    pub missed_frames: u32,
+    // Physical values from the previous frame, standing in for the missing ones
+    pub last_values: Vec<(String, f64)>,
+    // Values extrapolated to the time of the late frame along the line through the two frames
+    // before it, for signals in both. Empty when only one frame came before.
+    pub interpolated_values: Vec<(String, f64)>,
+}
+
+// The time a frame was received in seconds and the physical values it carried
+type ReceivedFrame = (f64, Vec<(String, f64)>);
+
+// Extend the line through two frames' values to a later time, a first-order hold
+fn extrapolate(previous: &ReceivedFrame, last: &ReceivedFrame, timestamp: f64) -> Vec<(String, f64)> {
+    let (previous_timestamp, previous_values) = previous;
+    let (last_timestamp, last_values) = last;
+    if last_timestamp <= previous_timestamp {
+        return Vec::new();
+    }
+
+    last_values
+        .iter()
+        .filter_map(|(name, last)| {
+            let (_, previous) = previous_values.iter().find(|(previous_name, _)| previous_name == name)?;
+            let slope = (last - previous) / (last_timestamp - previous_timestamp);
+            Some((name.clone(), last + (timestamp - last_timestamp) * slope))
+        })
+        .collect()
+}
+
+// When each message was last received and what it carried, to spot frames that went missing.
+// Only needs earlier packets.
+#[derive(Default)]
+pub struct ReceiveHistory {
+    // Time and signal values of the last two frames of each message, keyed by flow and message
+    // id, the latest last
+    last_frames: HashMap<MessageStream, Vec<ReceivedFrame>>,
+    // Results already handed out for each frame, keyed by packet number and frame index
+    results: HashMap<(u32, u32), Option<ReceiveGap>>,
+}
+
+impl ReceiveHistory {
+    // Record a frame received at the given time in seconds, returning the gap before it when it
+    // arrived later than the message's receive timeout
+    pub fn observe(
+        &mut self,
+        packet_number: u32,
+        frame_index: u32,
+        stream: MessageStream,
+        timestamp: f64,
+        timeout_ms: u32,
+        values: Vec<(String, f64)>,
+    ) -> Option<ReceiveGap> {
+        if let Some(gap) = self.results.get(&(packet_number, frame_index)) {
+            return gap.clone();
+        }
+
+        let frames = self.last_frames.entry(stream).or_default();
+        let gap = match frames.as_slice() {
+            [.., (last_timestamp, last_values)] => {
+                let elapsed_ms = (timestamp - last_timestamp) * 1000.0;
+                let timeout_ms = timeout_ms as f64;
+                (timeout_ms > 0.0 && elapsed_ms > timeout_ms).then(|| ReceiveGap {
+                    elapsed_ms,
+                    // A frame arriving 2.5 timeouts after the last means two were missed
+                    missed_frames: ((elapsed_ms / timeout_ms).ceil() as u32 - 1).max(1),
+                    last_values: last_values.clone(),
+                    interpolated_values: match frames.as_slice() {
+                        [previous, last] => extrapolate(previous, last, timestamp),
+                        _ => Vec::new(),
+                    },
+                })
+            }
+            [] => None,
+        };
+        if frames.len() == 2 {
+            frames.remove(0);
+        }
+        frames.push((timestamp, values));
+        self.results.insert((packet_number, frame_index), gap.clone());
+
+        gap
+    }
+}
+
+#[test]
+fn receive_gaps() {
+    let mut history = ReceiveHistory::default();
+    let values = |speed: f64| vec![(String::from("Speed"), speed)];
+
+    assert_eq!(history.observe(1, 0, (flow(1, 2), 0x10), 10.0, 100, values(1.0)), None);
+    assert_eq!(history.observe(2, 0, (flow(1, 2), 0x10), 10.1, 100, values(2.0)), None);
+
+    let gap = history.observe(3, 0, (flow(1, 2), 0x10), 10.35, 100, values(3.0)).unwrap();
+    assert_eq!(gap.missed_frames, 2);
+    assert_eq!(gap.last_values, values(2.0));
+    assert!((gap.elapsed_ms - 250.0).abs() < 1e-6);
+
+    // Speed rose by 10 a second over the two frames before, so 250 ms on it would be 4.5
+    assert_eq!(gap.interpolated_values.len(), 1);
+    assert!((gap.interpolated_values[0].1 - 4.5).abs() < 1e-9);
+
+    // Other messages have their own timing, and a single frame before a gap gives no line to follow
+    assert_eq!(history.observe(3, 1, (flow(1, 2), 0x20), 10.35, 100, values(9.0)), None);
+    let gap = history.observe(4, 1, (flow(1, 2), 0x20), 11.0, 100, values(9.0)).unwrap();
+    assert_eq!(gap.last_values, values(9.0));
+    assert!(gap.interpolated_values.is_empty());
+    let gap = history.observe(3, 0, (flow(1, 2), 0x10), 10.35, 100, values(3.0)).unwrap();
+
+    // Revisiting gives the same answer, even though later frames have been seen since
+    assert_eq!(history.observe(4, 0, (flow(1, 2), 0x10), 11.0, 100, values(4.0)).unwrap().missed_frames, 6);
+    assert_eq!(history.observe(3, 0, (flow(1, 2), 0x10), 10.35, 100, values(3.0)), Some(gap));
+}
+
+#[test]
+fn bidirectional_traffic() {
+    // Two ECUs both sending Status from port 20000 to port 20000, interleaved 50 ms apart. Each
+    // sends its own unchanging mode every 100 ms, with a receive timeout of 150 ms.
+    let mut signal_history = SignalHistory::default();
+    let mut receive_history = ReceiveHistory::default();
+    for packet_number in 1..=20u32 {
+        let (sender, mode) = if packet_number % 2 == 1 { (flow(1, 2), 1) } else { (flow(2, 1), 2) };
+        let timestamp = packet_number as f64 * 0.05;
+
+        let change = signal_history.observe(packet_number, 0, &sender, 0x10, "Mode", mode);
+        assert_eq!(change.changed, packet_number <= 2, "packet {}", packet_number);
+
+        let values = vec![(String::from("Mode"), mode as f64)];
+        let gap = receive_history.observe(packet_number, 0, (sender, 0x10), timestamp, 150, values);
+        assert_eq!(gap, None, "packet {}", packet_number);
+    }
+
+    // Only the stream of the ECU that went quiet has a gap
+    let values = |mode: f64| vec![(String::from("Mode"), mode)];
+    assert_eq!(receive_history.observe(21, 0, (flow(2, 1), 0x10), 1.05, 150, values(2.0)), None);
+    let gap = receive_history.observe(22, 0, (flow(1, 2), 0x10), 1.35, 150, values(1.0)).unwrap();
+    assert_eq!(gap.missed_frames, 2);
+    assert_eq!(gap.last_values, values(1.0));
+}
+
+#[test]
+fn concurrent_conversations() {
+    // Two ECU pairs talking at the same time, both A→B in their own conversation, one sending mode
+    // 1 and the other mode 2 every 100 ms
+    let mut signal_history = SignalHistory::default();
+    let mut receive_history = ReceiveHistory::default();
+    for packet_number in 1..=20u32 {
+        let (sender, mode) = if packet_number % 2 == 1 { (flow(1, 2), 1) } else { (flow(3, 4), 2) };
+        assert_eq!(sender.direction(), Direction::AToB);
+        let timestamp = packet_number as f64 * 0.05;
+
+        let change = signal_history.observe(packet_number, 0, &sender, 0x10, "Mode", mode);
+        assert_eq!(change.changed, packet_number <= 2, "packet {}", packet_number);
+
+        let values = vec![(String::from("Mode"), mode as f64)];
+        assert_eq!(receive_history.observe(packet_number, 0, (sender, 0x10), timestamp, 150, values), None);
+    }
+}
+
+// The first frame of each message id in the capture, where problems with the message's definition
+// are noted once rather than on every frame. Revisiting that frame notes them again, and it starts
+// over when the CaptureTracker says so, as the definition may have changed.
+#[derive(Default)]
+pub struct DefinitionNotes {
+    // Packet number and frame index of the first frame of each message id
+    first_frames: HashMap<i32, (u32, u32)>,
+}
+
+impl DefinitionNotes {
+    // Record a frame of a message, returning true if it's the frame to note its definition's problems on
+    pub fn observe(&mut self, packet_number: u32, frame_index: u32, message_id: i32) -> bool {
+        let first = *self
+            .first_frames
+            .entry(message_id)
+            .or_insert((packet_number, frame_index));
+        first == (packet_number, frame_index)
+    }
+}
+
+#[test]
+fn definition_notes() {
+    let mut notes = DefinitionNotes::default();
+
+    assert!(notes.observe(1, 0, 0x10));
+    assert!(!notes.observe(1, 1, 0x10));
+    assert!(!notes.observe(2, 0, 0x10));
+    assert!(notes.observe(2, 1, 0x20));
+
+    // Revisiting gives the same answer as the first pass
+    assert!(notes.observe(1, 0, 0x10));
+    assert!(!notes.observe(2, 0, 0x10));
+}
+
+// Which frame each message id was first seen in, for finding when each message first appears.
+// Only needs earlier packets. Kept for the whole capture rather than per conversation.
+#[derive(Default)]
+pub struct MessageFirstSeen {
+    // Packet number and frame index of the first frame of each message id
+    first_frames: HashMap<i32, (u32, u32)>,
+}
+
+impl MessageFirstSeen {
+    // Record a frame of a message, returning true if it's the first frame with that id. Revisiting
+    // the first frame returns true again, since it's compared against the frame that was recorded.
+    pub fn observe(&mut self, packet_number: u32, frame_index: u32, message_id: i32) -> bool {
+        let first = *self
+            .first_frames
+            .entry(message_id)
+            .or_insert((packet_number, frame_index));
+        first == (packet_number, frame_index)
+    }
+}
+
+#[test]
+fn message_first_seen() {
+    let mut first_seen = MessageFirstSeen::default();
+
+    assert!(first_seen.observe(1, 0, 0x10));
+    assert!(first_seen.observe(1, 1, 0x20));
+    assert!(!first_seen.observe(1, 2, 0x10));
+    assert!(!first_seen.observe(2, 0, 0x20));
+
+    // Revisiting gives the same answer as the first pass
+    assert!(first_seen.observe(1, 0, 0x10));
+    assert!(!first_seen.observe(1, 2, 0x10));
+}
+
+// Frames that must be seen before judging whether the database matches the traffic
+pub const DATABASE_MATCH_MIN_FRAMES: u32 = 500;
+
+// Unknown ids listed when the database doesn't match the traffic
+const FREQUENT_UNKNOWN_IDS: usize = 5;
+
+// Raised once per capture when too few frame ids are in the loaded database
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatabaseMismatch {
+    // Percentage of frames whose id is in the database
+    pub known_percent: f64,
+    // The most frequent ids that aren't, with how many frames had each
+    pub frequent_unknown_ids: Vec<(i32, u32)>,
+}
+
+impl std::fmt::Display for DatabaseMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Only {:.0}% of ELPIS message ids matched the loaded database, wrong messages.json?",
+            self.known_percent
+        )
+    }
+}
+
+// How many frames of the capture had an id the database knows, to spot a database loaded for the
+// wrong vehicle. Only needs earlier packets, and starts over when the CaptureTracker says so.
+#[derive(Default)]
+pub struct DatabaseMatch {
+    // Highest packet number counted, packets up to it are revisits and aren't counted again
+    counted_through: u32,
+    known_frames: u32,
+    // Frames per id not in the database
+    unknown_frames: HashMap<i32, u32>,
+    // The packet the mismatch was raised on, it's only raised once
+    mismatch: Option<(u32, DatabaseMismatch)>,
+}
+
+impl DatabaseMatch {
+    // Record whether the id of each frame of a packet is in the database, returning the mismatch
+    // if this is the packet it's raised on
+    pub fn observe(&mut self, packet_number: u32, frames: &[(i32, bool)], min_known_percent: f64) -> Option<DatabaseMismatch> {
+        if packet_number <= self.counted_through {
+            return self
+                .mismatch
+                .as_ref()
+                .filter(|(raised_on, _)| *raised_on == packet_number)
+                .map(|(_, mismatch)| mismatch.clone());
+        }
+
+        self.counted_through = packet_number;
+        for (id, known) in frames.iter() {
+            if *known {
+                self.known_frames += 1;
+            } else {
+                *self.unknown_frames.entry(*id).or_default() += 1;
+            }
+        }
+
+        let total = self.known_frames + self.unknown_frames.values().sum::<u32>();
+        if self.mismatch.is_some() || total < DATABASE_MATCH_MIN_FRAMES {
+            return None;
+        }
+
+        let known_percent = self.known_frames as f64 * 100.0 / total as f64;
+        if known_percent >= min_known_percent {
+            return None;
+        }
+
+        let mut frequent_unknown_ids: Vec<(i32, u32)> = self.unknown_frames.iter().map(|(id, count)| (*id, *count)).collect();
+        frequent_unknown_ids.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        frequent_unknown_ids.truncate(FREQUENT_UNKNOWN_IDS);
+
+        let mismatch = DatabaseMismatch {
+            known_percent,
+            frequent_unknown_ids,
+        };
+        self.mismatch = Some((packet_number, mismatch.clone()));
+        Some(mismatch)
+    }
+}
+
+#[test]
+fn database_mismatch() {
+    let mut tracker = DatabaseMatch::default();
+
+    // 10 frames per packet, one of them known: 10% is below the 20% asked for
+    let frames: Vec<(i32, bool)> =
+        (0..10).map(|frame| if frame == 0 { (0x10, true) } else { (0x100 + frame % 7, false) }).collect();
+    for packet in 1..50 {
+        assert_eq!(tracker.observe(packet, &frames, 20.0), None, "packet {}", packet);
+    }
+
+    // The 500th frame raises it, once
+    let mismatch = tracker.observe(50, &frames, 20.0).unwrap();
+    assert_eq!(mismatch.known_percent, 10.0);
+    assert_eq!(mismatch.frequent_unknown_ids, vec![(0x101, 100), (0x102, 100), (0x100, 50), (0x103, 50), (0x104, 50)]);
+    assert_eq!(
+        mismatch.to_string(),
+        "Only 10% of ELPIS message ids matched the loaded database, wrong messages.json?"
+    );
+    assert_eq!(tracker.observe(51, &frames, 20.0), None);
+
+    // Revisits show it on the same packet only, and aren't counted again
+    assert_eq!(tracker.observe(50, &frames, 20.0), Some(mismatch));
+    assert_eq!(tracker.observe(12, &frames, 20.0), None);
+    assert_eq!(tracker.known_frames, 51);
+}
+
+// A frame of a synthetic capture: its message id and the physical values of its signals
+#[cfg(test)]
+type SyntheticFrame = (i32, &'static [(&'static str, f64)]);
+
+// Emits the stateful fields of a packet as "<frame>.<signal> <field>=<value>", the way the
+// dissector does
+#[cfg(test)]
+fn emit_stateful_fields(
+    history: &mut (SignalHistory, ReceiveHistory),
+    packet_number: u32,
+    frames: &[SyntheticFrame],
+) -> Vec<String> {
+    let (history, receive_history) = history;
+    let mut fields = Vec::new();
+    for (frame_index, (message_id, signals)) in frames.iter().enumerate() {
+        // Packets are a second apart, and every message is expected at least every 1.5 seconds
+        let values = signals.iter().map(|(name, value)| (name.to_string(), *value)).collect();
+        let gap = receive_history.observe(packet_number, frame_index as u32, (flow(1, 2), *message_id), packet_number as f64, 1500, values);
+        if let Some(gap) = gap {
+            for (name, value) in gap.last_values.iter() {
+                fields.push(format!("{}.{} elpis.signal_interpolated={}", frame_index, name, value));
+            }
+        }
+
+        for (name, value) in signals.iter() {
+            let change = history.observe(packet_number, frame_index as u32, &flow(1, 2), *message_id, name, *value as u128);
+            fields.push(format!("{}.{} elpis.signal_value_changed={}", frame_index, name, change.changed));
+        }
+    }
+
+    fields
+}
+
+// Dissects a synthetic capture like a single-pass read, returning the fields shown for each packet
+#[cfg(test)]
+fn replay_single_pass(capture: &[Vec<SyntheticFrame>]) -> Vec<Vec<String>> {
+    let mut history = Default::default();
+    capture
+        .iter()
+        .enumerate()
+        .map(|(index, frames)| emit_stateful_fields(&mut history, index as u32 + 1, frames))
+        .collect()
+}
+
+// Dissects a synthetic capture like a two-pass read: a first pass in order whose output is never
+// shown, then packets revisited in the given order, some more than once
+#[cfg(test)]
+fn replay_two_pass(capture: &[Vec<SyntheticFrame>], visit_order: &[usize]) -> Vec<Vec<String>> {
+    let mut history = Default::default();
+    for (index, frames) in capture.iter().enumerate() {
+        emit_stateful_fields(&mut history, index as u32 + 1, frames);
+    }
+
+    let mut shown: Vec<Option<Vec<String>>> = vec![None; capture.len()];
+    for index in visit_order.iter() {
+        let fields = emit_stateful_fields(&mut history, *index as u32 + 1, &capture[*index]);
+        if let Some(previous) = &shown[*index] {
+            assert_eq!(previous, &fields, "packet {} changed between visits", index + 1);
+        }
+        shown[*index] = Some(fields);
+    }
+
+    shown.into_iter().map(|fields| fields.unwrap()).collect()
+}
+
+#[test]
+fn single_and_two_pass_agree() {
+    let capture: Vec<Vec<SyntheticFrame>> = vec![
+        vec![(0x10, &[("Speed", 5.0), ("Gear", 1.0)])],
+        vec![(0x10, &[("Speed", 5.0), ("Gear", 2.0)]), (0x20, &[("Speed", 1.0)])],
+        vec![],
+        vec![(0x10, &[("Speed", 6.0), ("Gear", 2.0)]), (0x10, &[("Speed", 6.0), ("Gear", 3.0)])],
+        vec![(0x20, &[("Speed", 1.0)])],
+    ];
+
+    let single = replay_single_pass(&capture);
+    let two_pass = replay_two_pass(&capture, &[4, 0, 3, 1, 3, 2, 0, 4]);
+
+    for (packet, (single, two_pass)) in single.iter().zip(two_pass.iter()).enumerate() {
+        assert_eq!(single, two_pass, "packet {}", packet + 1);
+    }
+
+    assert!(single[1].contains(&String::from("0.Speed elpis.signal_value_changed=false")));
+    assert!(single[3].contains(&String::from("1.Speed elpis.signal_value_changed=false")));
+    assert!(single[4].contains(&String::from("0.Speed elpis.signal_value_changed=false")));
+
+    // Message 0x20 skipped packets 3 and 4, so packet 5 fills the gap with its last values
+    assert!(single[4].contains(&String::from("0.Speed elpis.signal_interpolated=1")));
+}
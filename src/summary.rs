@@ -0,0 +1,343 @@
+// Aggregates decoded ELPIS traffic into fixed time windows, for quick triage of a capture
+// without opening it in Wireshark.
+
+use crate::elpis::DecodedFrame;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+// Minimum and maximum physical value of a signal seen within a window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignalRange {
+    pub min: f64,
+    pub max: f64,
+}
+
+// Everything seen within a single time window
+#[derive(Debug, Default)]
+pub struct WindowStats {
+    // Seconds since the epoch at the start of this window
+    pub start: f64,
+    pub datagrams: u64,
+    pub frames: u64,
+    pub message_ids: HashSet<i32>,
+    pub unknown_ids: u64,
+    pub decode_errors: u64,
+    // Ranges of the signals being tracked, keyed by signal name
+    pub signals: BTreeMap<String, SignalRange>,
+    // Tracked signals over 64 bits seen in this window, which have no meaningful range
+    pub wide_signals: BTreeSet<String>,
+}
+
+pub struct WindowAggregator {
+    window: f64,
+    tracked_signals: Vec<String>,
+    origin: Option<f64>,
+    windows: Vec<WindowStats>,
+}
+
+impl WindowAggregator {
+    // Create an aggregator using windows of the given length in seconds, tracking the
+    // minimum and maximum of the named signals
+    pub fn new(window: f64, tracked_signals: Vec<String>) -> Self {
+        Self {
+            window,
+            tracked_signals,
+            origin: None,
+            windows: Vec::new(),
+        }
+    }
+
+    // Find the window a timestamp falls in, creating it and any empty windows before it
+    fn window_index(&mut self, timestamp: f64) -> usize {
+        let origin = *self.origin.get_or_insert(timestamp);
+
+        // Out of order timestamps before the first packet are counted in the first window
+        let index = ((timestamp - origin) / self.window).floor().max(0.0) as usize;
+        while self.windows.len() <= index {
+            let start = origin + self.windows.len() as f64 * self.window;
+            self.windows.push(WindowStats {
+                start,
+                ..Default::default()
+            });
+        }
+
+        index
+    }
+
+    // Count a datagram received at the given time
+    pub fn add_datagram(&mut self, timestamp: f64) {
+        let index = self.window_index(timestamp);
+        self.windows[index].datagrams += 1;
+    }
+
+    // Count a frame with the given id, along with its decoded signals if a definition was found
+    pub fn add_frame(&mut self, timestamp: f64, id: i32, decoded: Option<&DecodedFrame>) {
+        let index = self.window_index(timestamp);
+        let window = &mut self.windows[index];
+
+        window.frames += 1;
+        window.message_ids.insert(id);
+
+        match decoded {
+            Some(decoded) => {
+                window.decode_errors += decoded.errors.len() as u64;
+
+                for signal in decoded.signals.iter() {
+                    if !self.tracked_signals.contains(&signal.name) {
+                        continue;
+                    }
+
+                    if signal.wide {
+                        window.wide_signals.insert(signal.name.clone());
+                        continue;
+                    }
+
+                    let range = window.signals.entry(signal.name.clone()).or_insert(SignalRange {
+                        min: signal.physical,
+                        max: signal.physical,
+                    });
+                    range.min = range.min.min(signal.physical);
+                    range.max = range.max.max(signal.physical);
+                }
+            }
+            None => window.unknown_ids += 1,
+        }
+    }
+
+    // Count a datagram whose frames could not be walked
+    pub fn add_decode_error(&mut self, timestamp: f64) {
+        let index = self.window_index(timestamp);
+        self.windows[index].decode_errors += 1;
+    }
+
+    // Get the names of the signals being tracked
+    pub fn tracked_signals(&self) -> &[String] {
+        &self.tracked_signals
+    }
+
+    // Finish aggregating, returning every window from the first packet to the last
+    pub fn finish(self) -> Vec<WindowStats> {
+        self.windows
+    }
+}
+
+// Parse a window length such as "1s", "250ms" or "2m" into seconds
+pub fn parse_window(text: &str) -> anyhow::Result<f64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid window length {}", text))?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" | "" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(anyhow::anyhow!("Unknown unit in window length {}", text)),
+    };
+
+    if seconds <= 0.0 {
+        return Err(anyhow::anyhow!("Window length must be greater than zero"));
+    }
+
+    Ok(seconds)
+}
+
+// Build the header and rows of the summary table
+fn table_rows(windows: &[WindowStats], signals: &[String]) -> Vec<Vec<String>> {
+    let mut header: Vec<String> = ["start", "datagrams", "frames", "ids", "unknown_ids", "decode_errors"]
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+    for signal in signals.iter() {
+        header.push(format!("{}_min", signal));
+        header.push(format!("{}_max", signal));
+    }
+
+    let mut rows = vec![header];
+    for window in windows.iter() {
+        let mut row = vec![
+            format!("{:.6}", window.start),
+            window.datagrams.to_string(),
+            window.frames.to_string(),
+            window.message_ids.len().to_string(),
+            window.unknown_ids.to_string(),
+            window.decode_errors.to_string(),
+        ];
+        for signal in signals.iter() {
+            match window.signals.get(signal) {
+                _ if window.wide_signals.contains(signal) => {
+                    row.push(String::from("wide"));
+                    row.push(String::from("wide"));
+                }
+                Some(range) => {
+                    row.push(range.min.to_string());
+                    row.push(range.max.to_string());
+                }
+                None => {
+                    row.push(String::new());
+                    row.push(String::new());
+                }
+            }
+        }
+        rows.push(row);
+    }
+
+    rows
+}
+
+// Render the windows as a table with aligned columns
+pub fn render_text(windows: &[WindowStats], signals: &[String]) -> String {
+    let rows = table_rows(windows, signals);
+
+    let mut widths = vec![0; rows[0].len()];
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let mut output = String::new();
+    for row in rows.iter() {
+        let line = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:>width$}", cell, width = width))
+            .collect::<Vec<String>>()
+            .join("  ");
+        output.push_str(line.as_str());
+        output.push('\n');
+    }
+
+    output
+}
+
+// Render the windows as CSV
+pub fn render_csv(windows: &[WindowStats], signals: &[String]) -> String {
+    let mut output = String::new();
+    for row in table_rows(windows, signals).iter() {
+        output.push_str(row.join(",").as_str());
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+fn decoded_frame(id: i32, signals: &[(&str, f64)], errors: usize) -> DecodedFrame {
+    use crate::elpis::DecodedSignal;
+
+    DecodedFrame {
+        id,
+        name: format!("Message_{}", id),
+        signals: signals
+            .iter()
+            .map(|(name, physical)| DecodedSignal {
+                name: name.to_string(),
+                raw: *physical as u128,
+                physical: *physical,
+                wide: false,
+                label: None,
+                choice: None,
+                unit: None,
+                bytes: None,
+                flags: Default::default(),
+            })
+            .collect(),
+        derived: Vec::new(),
+        errors: vec![String::from("error"); errors],
+    }
+}
+
+#[test]
+fn aggregate_windows() {
+    let mut aggregator = WindowAggregator::new(1.0, vec![String::from("Speed")]);
+
+    aggregator.add_datagram(100.0);
+    aggregator.add_frame(100.0, 1, Some(&decoded_frame(1, &[("Speed", 10.0), ("Other", 99.0)], 0)));
+    aggregator.add_frame(100.0, 2, None);
+    aggregator.add_datagram(100.5);
+    aggregator.add_frame(100.5, 1, Some(&decoded_frame(1, &[("Speed", 4.0)], 1)));
+
+    // Nothing in the second and third windows
+    aggregator.add_datagram(103.2);
+    aggregator.add_decode_error(103.2);
+
+    let windows = aggregator.finish();
+    assert_eq!(windows.len(), 4);
+
+    assert_eq!(windows[0].datagrams, 2);
+    assert_eq!(windows[0].frames, 3);
+    assert_eq!(windows[0].message_ids.len(), 2);
+    assert_eq!(windows[0].unknown_ids, 1);
+    assert_eq!(windows[0].decode_errors, 1);
+    assert_eq!(windows[0].signals["Speed"], SignalRange { min: 4.0, max: 10.0 });
+    assert!(!windows[0].signals.contains_key("Other"));
+
+    for window in windows[1..3].iter() {
+        assert_eq!(window.datagrams, 0);
+        assert_eq!(window.frames, 0);
+        assert!(window.signals.is_empty());
+    }
+    assert_eq!(windows[2].start, 102.0);
+
+    assert_eq!(windows[3].datagrams, 1);
+    assert_eq!(windows[3].decode_errors, 1);
+}
+
+#[test]
+fn render_summary_tables() {
+    let mut aggregator = WindowAggregator::new(0.5, vec![String::from("Speed")]);
+    aggregator.add_datagram(1.0);
+    aggregator.add_frame(1.0, 1, Some(&decoded_frame(1, &[("Speed", 2.5)], 0)));
+    aggregator.add_datagram(2.0);
+    let windows = aggregator.finish();
+    let signals = [String::from("Speed")];
+
+    let csv = render_csv(&windows, &signals);
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], "start,datagrams,frames,ids,unknown_ids,decode_errors,Speed_min,Speed_max");
+    assert_eq!(lines[1], "1.000000,1,1,1,0,0,2.5,2.5");
+    assert_eq!(lines[2], "1.500000,0,0,0,0,0,,");
+
+    let text = render_text(&windows, &signals);
+    let widths: HashSet<usize> = text.lines().map(|line| line.len()).collect();
+    assert_eq!(widths.len(), 1);
+}
+
+#[test]
+fn summarize_wide_signals() {
+    use crate::elpis::{wide_test_messages, wide_test_payload};
+
+    let messages = wide_test_messages();
+    let message = messages.get_def_by_id(0x300).unwrap();
+    let signals = vec![String::from("Serial"), String::from("Counter")];
+    let mut aggregator = WindowAggregator::new(1.0, signals.clone());
+    aggregator.add_frame(1.0, 0x300, Some(&message.decode(&wide_test_payload(u128::MAX >> 32, 3, 0))));
+    aggregator.add_frame(1.5, 0x300, Some(&message.decode(&wide_test_payload(1, 9, 0))));
+    let windows = aggregator.finish();
+
+    // The serial is marked instead of getting a range rounded to f64
+    assert!(!windows[0].signals.contains_key("Serial"));
+    assert!(windows[0].wide_signals.contains("Serial"));
+    assert_eq!(windows[0].signals["Counter"], SignalRange { min: 3.0, max: 9.0 });
+    assert_eq!(
+        render_csv(&windows, &signals).lines().nth(1),
+        Some("1.000000,0,2,1,0,0,wide,wide,3,9")
+    );
+}
+
+#[test]
+fn parse_window_lengths() {
+    assert_eq!(parse_window("1s").unwrap(), 1.0);
+    assert_eq!(parse_window("250ms").unwrap(), 0.25);
+    assert_eq!(parse_window("2m").unwrap(), 120.0);
+    assert_eq!(parse_window("1.5").unwrap(), 1.5);
+    assert!(parse_window("0s").is_err());
+    assert!(parse_window("1d").is_err());
+    assert!(parse_window("fast").is_err());
+}
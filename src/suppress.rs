@@ -0,0 +1,175 @@
+// Suppression of expert findings that are known and expected for some messages, e.g. prototype
+// ECUs that never fill in their checksum. The `expert_suppress` preference lists message name
+// globs per check:
+//
+//     "checksum:ProtoECU_*,Bench_?;length:Debug_*"
+//
+// Suppressed findings aren't shown as expert items, but still get a hidden
+// `elpis.expert_suppressed` item so they can be counted and nothing is silently lost.
+
+use crate::elpis::ElpisMessages;
+use serde::Deserialize;
+use std::collections::HashSet;
+
+// The expert checks that can be suppressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExpertCheck {
+    // Checksum signal doesn't match the checksum computed over the payload
+    Checksum,
+    // Payload longer than the message definition
+    Length,
+    // Payload longer than the message's frame type can carry
+    FrameType,
+}
+
+impl ExpertCheck {
+    const ALL: [ExpertCheck; 3] = [ExpertCheck::Checksum, ExpertCheck::Length, ExpertCheck::FrameType];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExpertCheck::Checksum => "checksum",
+            ExpertCheck::Length => "length",
+            ExpertCheck::FrameType => "frame_type",
+        }
+    }
+
+    fn from_name(name: &str) -> anyhow::Result<Self> {
+        Self::ALL.into_iter().find(|check| check.as_str() == name).ok_or_else(|| {
+            let names: Vec<&str> = Self::ALL.iter().map(|check| check.as_str()).collect();
+            anyhow::anyhow!("Unknown expert check \"{}\", expected one of {}", name, names.join(", "))
+        })
+    }
+}
+
+// Match a name against a glob where `*` matches any run of characters and `?` any single one
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // Position in the pattern and name to resume from after the last `*`
+    let mut backtrack: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // Let the last `*` swallow one more character
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+// The parsed `expert_suppress` preference, message name globs for each check
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(try_from = "String")]
+pub struct ExpertSuppression {
+    rules: Vec<(ExpertCheck, String)>,
+}
+
+impl TryFrom<String> for ExpertSuppression {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> anyhow::Result<Self> {
+        Self::parse(&value)
+    }
+}
+
+impl ExpertSuppression {
+    // Parse a list of `check:glob,glob` entries separated by semicolons
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut rules = Vec::new();
+        for entry in text.split(';').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (check, globs) = entry
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("Expected check:glob in expert suppression \"{}\"", entry))?;
+            let check = ExpertCheck::from_name(check.trim())?;
+
+            for glob in globs.split(',').map(str::trim) {
+                if glob.is_empty() {
+                    return Err(anyhow::anyhow!("Empty message glob in expert suppression \"{}\"", entry));
+                }
+                rules.push((check, glob.to_string()));
+            }
+        }
+
+        Ok(Self { rules })
+    }
+
+    // Resolve the globs against the messages of a database, when the preference is applied and again
+    // whenever the database is replaced
+    pub fn resolve(&self, messages: &ElpisMessages) -> SuppressedExperts {
+        let mut suppressed = HashSet::new();
+        for (check, glob) in self.rules.iter() {
+            for message in messages.iter_sorted() {
+                if glob_matches(glob, &message.name) {
+                    suppressed.insert((*check, message.id));
+                }
+            }
+        }
+
+        SuppressedExperts { suppressed }
+    }
+}
+
+// Which checks are suppressed for which message ids
+#[derive(Debug, Default)]
+pub struct SuppressedExperts {
+    suppressed: HashSet<(ExpertCheck, i32)>,
+}
+
+impl SuppressedExperts {
+    pub fn is_suppressed(&self, check: ExpertCheck, message_id: i32) -> bool {
+        self.suppressed.contains(&(check, message_id))
+    }
+}
+
+#[test]
+fn match_globs() {
+    assert!(glob_matches("ProtoECU_*", "ProtoECU_Status"));
+    assert!(glob_matches("ProtoECU_*", "ProtoECU_"));
+    assert!(!glob_matches("ProtoECU_*", "ECU_Status"));
+    assert!(glob_matches("*_Debug", "Inverter_Debug"));
+    assert!(glob_matches("Bench_?", "Bench_1"));
+    assert!(!glob_matches("Bench_?", "Bench_12"));
+    assert!(glob_matches("*a*b", "xxaxxbxb"));
+    assert!(glob_matches("Exact", "Exact"));
+    assert!(!glob_matches("Exact", "Exactly"));
+}
+
+#[test]
+fn resolve_suppressions() {
+    use crate::elpis::MessageDefinition;
+
+    let messages = ElpisMessages::from_definitions(vec![
+        MessageDefinition::new("ProtoECU_Status", 1, 8),
+        MessageDefinition::new("ProtoECU_Temps", 2, 8),
+        MessageDefinition::new("Debug_Trace", 3, 8),
+        MessageDefinition::new("Wheels", 4, 8),
+    ]);
+
+    let suppression = ExpertSuppression::parse("checksum:ProtoECU_*; length:Debug_*,Wheels").unwrap();
+    let suppressed = suppression.resolve(&messages);
+    assert!(suppressed.is_suppressed(ExpertCheck::Checksum, 1));
+    assert!(suppressed.is_suppressed(ExpertCheck::Checksum, 2));
+    assert!(!suppressed.is_suppressed(ExpertCheck::Length, 1));
+    assert!(suppressed.is_suppressed(ExpertCheck::Length, 3));
+    assert!(suppressed.is_suppressed(ExpertCheck::Length, 4));
+    assert!(!suppressed.is_suppressed(ExpertCheck::Checksum, 4));
+    assert!(!suppressed.is_suppressed(ExpertCheck::FrameType, 3));
+
+    assert_eq!(ExpertSuppression::parse("").unwrap(), ExpertSuppression::default());
+    let error = ExpertSuppression::parse("range:ProtoECU_*").unwrap_err().to_string();
+    assert_eq!(error, "Unknown expert check \"range\", expected one of checksum, length, frame_type");
+    assert!(ExpertSuppression::parse("checksum").is_err());
+    assert!(ExpertSuppression::parse("checksum:A,").is_err());
+}
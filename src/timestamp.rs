@@ -0,0 +1,55 @@
+// Wall-clock times formatted for copying out of Wireshark, so they can be matched against vehicle
+// logs and ECU debug output without converting them first.
+
+// Format seconds and nanoseconds since the Unix epoch as an ISO 8601 UTC time, e.g.
+// "2024-03-15T14:32:10.123456Z". Microseconds are enough for most captures, nanoseconds are only
+// written when the capture has them.
+pub fn format_iso8601(secs: i64, nsecs: i32) -> String {
+    let days = secs.div_euclid(86400);
+    let seconds_of_day = secs.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+
+    let fraction = if nsecs % 1000 == 0 {
+        format!("{:06}", nsecs / 1000)
+    } else {
+        format!("{:09}", nsecs)
+    };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60,
+        fraction
+    )
+}
+
+// Get the proleptic Gregorian (year, month, day) of a number of days since 1970-01-01, counting in
+// 400 year eras that start on March 1st so leap days fall at the end of each year
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[test]
+fn format_iso8601_timestamps() {
+    assert_eq!(format_iso8601(0, 0), "1970-01-01T00:00:00.000000Z");
+    assert_eq!(format_iso8601(1_710_513_130, 123_456_000), "2024-03-15T14:32:10.123456Z");
+    assert_eq!(format_iso8601(1_710_513_130, 123_456_789), "2024-03-15T14:32:10.123456789Z");
+
+    // Leap days, the end of a year and times before the epoch
+    assert_eq!(format_iso8601(951_782_400, 0), "2000-02-29T00:00:00.000000Z");
+    assert_eq!(format_iso8601(1_735_689_599, 999_999_000), "2024-12-31T23:59:59.999999Z");
+    assert_eq!(format_iso8601(-1, 0), "1969-12-31T23:59:59.000000Z");
+}
@@ -0,0 +1,113 @@
+// Self-describing payloads of the experimental id range. These payloads need no database entry,
+// they're a chain of TLVs: a 1-byte tag, a 1-byte length and that many bytes of value, repeated
+// until the end of the payload.
+
+use std::ops::RangeInclusive;
+
+// Ids reserved for experimental messages with TLV payloads
+pub const EXPERIMENTAL_IDS: RangeInclusive<i32> = 0x700..=0x77F;
+
+// Check if an id falls in the experimental range
+pub fn is_experimental_id(id: i32) -> bool {
+    EXPERIMENTAL_IDS.contains(&id)
+}
+
+// A single tag, length, value entry
+#[derive(Debug, PartialEq)]
+pub struct Tlv<'a> {
+    // Offset of the tag byte within the payload
+    pub offset: usize,
+    pub tag: u8,
+    pub value: &'a [u8],
+}
+
+impl Tlv<'_> {
+    // Describe the entry like "Tag 0x12 (3 bytes): a1 b2 c3"
+    pub fn describe(&self) -> String {
+        let value = self
+            .value
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let unit = if self.value.len() == 1 { "byte" } else { "bytes" };
+
+        format!("Tag {:#04x} ({} {}): {}", self.tag, self.value.len(), unit, value)
+            .trim_end()
+            .to_string()
+    }
+}
+
+// Walk the TLV chain of a payload. Values are never parsed further, even when they look like TLVs
+// themselves. Fails if the chain doesn't end exactly at the end of the payload.
+pub fn parse_tlvs(payload: &[u8]) -> anyhow::Result<Vec<Tlv<'_>>> {
+    let mut tlvs = Vec::new();
+    let mut offset = 0;
+
+    while offset < payload.len() {
+        if offset + 2 > payload.len() {
+            return Err(anyhow::anyhow!("Truncated TLV header at offset {}", offset));
+        }
+
+        let tag = payload[offset];
+        let length = payload[offset + 1] as usize;
+        let value = payload
+            .get(offset + 2..offset + 2 + length)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "TLV with tag {:#04x} at offset {} needs {} bytes but only {} remain",
+                    tag,
+                    offset,
+                    length,
+                    payload.len() - offset - 2
+                )
+            })?;
+
+        tlvs.push(Tlv { offset, tag, value });
+        offset += 2 + length;
+    }
+
+    Ok(tlvs)
+}
+
+#[test]
+fn walk_tlv_chains() {
+    let payload = [0x12, 3, 0xa1, 0xb2, 0xc3, 0x01, 0, 0x7f, 1, 0xff];
+    let tlvs = parse_tlvs(&payload).unwrap();
+    assert_eq!(tlvs.len(), 3);
+    assert_eq!(tlvs[0].describe(), "Tag 0x12 (3 bytes): a1 b2 c3");
+    assert_eq!(tlvs[1], Tlv { offset: 5, tag: 0x01, value: &[] });
+    assert_eq!(tlvs[1].describe(), "Tag 0x01 (0 bytes):");
+    assert_eq!(tlvs[2].describe(), "Tag 0x7f (1 byte): ff");
+
+    // An empty payload is an empty chain
+    assert!(parse_tlvs(&[]).unwrap().is_empty());
+
+    // Values that look like TLVs themselves are left alone
+    let nested = [0x20, 4, 0x21, 2, 0xaa, 0xbb];
+    let tlvs = parse_tlvs(&nested).unwrap();
+    assert_eq!(tlvs.len(), 1);
+    assert_eq!(tlvs[0].value, &[0x21, 2, 0xaa, 0xbb]);
+
+    assert!(is_experimental_id(0x700));
+    assert!(is_experimental_id(0x77F));
+    assert!(!is_experimental_id(0x780));
+}
+
+#[test]
+fn reject_truncated_tlvs() {
+    // Corpus of chains cut short in the header or the value
+    let corpus: [&[u8]; 4] = [
+        &[0x12],
+        &[0x12, 3, 0xa1, 0xb2],
+        &[0x12, 1, 0xa1, 0x13],
+        &[0x20, 4, 0x21, 2, 0xaa],
+    ];
+
+    for payload in corpus {
+        assert!(parse_tlvs(payload).is_err(), "{:x?} should not parse", payload);
+    }
+
+    let error = parse_tlvs(&[0x12, 1, 0xa1, 0x13, 5, 0x01]).unwrap_err().to_string();
+    assert_eq!(error, "TLV with tag 0x13 at offset 3 needs 5 bytes but only 1 remain");
+}
@@ -0,0 +1,180 @@
+// A record of every step of decoding a single frame, for working out why a decode looks wrong:
+// the payload, each signal's layout as the database gives it, the raw bits read, the value before
+// and after scaling, and why any signal wasn't decoded. MessageDefinition::decode_with_trace
+// fills it in for elpis-decode trace, and the plugin for the trace_frame_number dump, both through
+// SignalDefinition::read_traced and MessageDefinition::eval_derived_traced so the two can't differ.
+
+use crate::elpis::SignalDefinition;
+use serde::Serialize;
+
+// Where a frame was found in its datagram
+#[derive(Debug, Clone, Serialize)]
+pub struct FrameHeaderTrace {
+    // Offset of the frame header within the datagram
+    pub offset: usize,
+    pub id: i32,
+    pub length: i32,
+}
+
+// A signal's layout and every value worked out from it
+#[derive(Debug, Clone, Serialize)]
+pub struct SignalTrace {
+    pub name: String,
+    pub start: Option<i32>,
+    pub length: i32,
+    pub byte_order: &'static str,
+    pub signed: bool,
+    pub float: bool,
+    pub scale: f64,
+    pub offset: f64,
+    // Raw value in hex, and as bits from the most significant one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_bits: Option<String>,
+    // Value after sign extension or float conversion, before scale and offset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unscaled: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub physical: Option<f64>,
+    // Label of the value range the raw value is in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    // Why the signal wasn't decoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SignalTrace {
+    // Record a signal's layout, before anything is read
+    pub fn new(signal: &SignalDefinition) -> Self {
+        Self {
+            name: signal.name.clone(),
+            start: signal.start,
+            length: signal.length,
+            byte_order: if signal.is_big_endian { "big_endian" } else { "little_endian" },
+            signed: signal.is_signed.unwrap_or(false),
+            float: signal.is_float.unwrap_or(false),
+            scale: signal.scale.unwrap_or(1.0),
+            offset: signal.offset,
+            raw: None,
+            raw_bits: None,
+            unscaled: None,
+            physical: None,
+            label: None,
+            error: None,
+        }
+    }
+
+    // Record the values worked out from a signal's raw value
+    pub fn decoded(&mut self, signal: &SignalDefinition, raw: u128) {
+        let length = signal.length.clamp(1, 128) as usize;
+        self.raw = Some(format!("{:#x}", raw));
+        self.raw_bits = Some(format!("{:0length$b}", raw, length = length));
+        self.unscaled = Some(signal.to_unscaled(raw));
+        self.physical = Some(signal.to_physical(raw));
+        self.label = signal.range_label(raw).map(String::from);
+    }
+}
+
+// A derived signal's expression and its value
+#[derive(Debug, Clone, Serialize)]
+pub struct DerivedTrace {
+    pub name: String,
+    pub expression: String,
+    pub value: Option<f64>,
+}
+
+// Everything done to decode a single frame
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DecodeTrace {
+    pub message: String,
+    pub id: i32,
+    // Only known when the frame was decoded out of a datagram
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header: Option<FrameHeaderTrace>,
+    // Payload bytes in hex
+    pub payload: String,
+    pub signals: Vec<SignalTrace>,
+    pub derived: Vec<DerivedTrace>,
+}
+
+impl DecodeTrace {
+    // Render the trace as pretty printed JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+// Format bytes as space separated hex
+pub fn format_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<String>>().join(" ")
+}
+
+// Parse a payload written as hex, with or without spaces, colons or a 0x prefix between bytes
+pub fn parse_hex(text: &str) -> anyhow::Result<Vec<u8>> {
+    let text = text.trim();
+    let digits: String = text
+        .strip_prefix("0x")
+        .unwrap_or(text)
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ':')
+        .collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(anyhow::anyhow!("Hex payload {} has an odd number of digits", text));
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|index| {
+            u8::from_str_radix(&digits[index..index + 2], 16)
+                .map_err(|_| anyhow::anyhow!("Hex payload {} has an invalid byte {}", text, &digits[index..index + 2]))
+        })
+        .collect()
+}
+
+#[test]
+fn parse_hex_payloads() {
+    assert_eq!(parse_hex("0x12345678").unwrap(), vec![0x12, 0x34, 0x56, 0x78]);
+    assert_eq!(parse_hex("12 34:ab").unwrap(), vec![0x12, 0x34, 0xab]);
+    assert_eq!(parse_hex("").unwrap(), Vec::<u8>::new());
+    assert!(parse_hex("123").is_err());
+    assert!(parse_hex("12zz").is_err());
+    assert_eq!(format_hex(&[0x12, 0x34, 0xab]), "12 34 ab");
+}
+
+#[test]
+fn trace_matches_decoded_frame() {
+    let messages = crate::elpis::wide_test_messages();
+    let message = messages.get_def_by_id(0x300).unwrap();
+    let payload = crate::elpis::wide_test_payload(1 << 90, 7, 0x1234);
+
+    let mut trace = DecodeTrace::default();
+    let frame = message.decode_with_trace(&payload, Some(&mut trace));
+    assert_eq!(trace.message, message.name);
+    assert_eq!(trace.payload, format_hex(&payload));
+
+    // Every signal of the definition is in the trace, in order, with the values decode returned
+    let names: Vec<&str> = trace.signals.iter().map(|signal| signal.name.as_str()).collect();
+    let defined: Vec<&str> = message.signals.iter().map(|signal| signal.name.as_str()).collect();
+    assert_eq!(names, defined);
+    for decoded in frame.signals.iter() {
+        let traced = trace.signals.iter().find(|signal| signal.name == decoded.name).unwrap();
+        assert_eq!(traced.raw, Some(format!("{:#x}", decoded.raw)));
+        assert_eq!(u128::from_str_radix(traced.raw_bits.as_ref().unwrap(), 2).unwrap(), decoded.raw);
+        assert_eq!(traced.physical, Some(decoded.physical));
+        assert_eq!(traced.error, None);
+    }
+
+    let derived: Vec<(&str, Option<f64>)> = trace.derived.iter().map(|derived| (derived.name.as_str(), derived.value)).collect();
+    let expected: Vec<(&str, Option<f64>)> = frame.derived.iter().map(|derived| (derived.name.as_str(), derived.value)).collect();
+    assert_eq!(derived, expected);
+
+    // Signals that don't fit the payload are traced with the reason
+    let mut trace = DecodeTrace::default();
+    let frame = message.decode_with_trace(&payload[..4], Some(&mut trace));
+    let failed: Vec<&SignalTrace> = trace.signals.iter().filter(|signal| signal.error.is_some()).collect();
+    assert_eq!(failed.len(), frame.errors.len());
+    assert!(failed.iter().all(|signal| signal.raw.is_none()));
+    assert!(trace.to_json().contains("\"error\""));
+}
@@ -0,0 +1,177 @@
+// Transforms undoing what gateways and loggers do to payloads on the way, applied to a scratch copy
+// of each frame's payload before its signals are decoded. The bytes on the wire stay as they are in
+// elpis.payload.
+//
+// - word swap: gateway firmware 2.3.x reverses the bytes of every 4-byte word, the
+//   payload_wordswap preference
+// - XOR key: test-fleet loggers obfuscate payloads by XOR with a per-vehicle 16-byte key repeated
+//   from the start of each payload, the payload_key preference
+
+use serde::Deserialize;
+
+// Length of the key test-fleet loggers obfuscate payloads with
+pub const PAYLOAD_KEY_LENGTH: usize = 16;
+
+pub trait PayloadTransform: Send + Sync {
+    // What the transform undoes, e.g. "word swap", shown on the payload of every frame it's applied to
+    fn name(&self) -> &'static str;
+
+    // Mark added to the frame item itself, for transforms analysts must not miss
+    fn frame_mark(&self) -> Option<&'static str> {
+        None
+    }
+
+    // Undo the transform in place
+    fn apply(&self, payload: &mut [u8]);
+}
+
+// Reverses the bytes of every complete 4-byte word, leaving any trailing bytes untouched
+pub struct WordSwap;
+
+impl PayloadTransform for WordSwap {
+    fn name(&self) -> &'static str {
+        "word swap"
+    }
+
+    fn apply(&self, payload: &mut [u8]) {
+        for word in payload.chunks_exact_mut(4) {
+            word.reverse();
+        }
+    }
+}
+
+// The payload_key preference, a hex string of 16 bytes or empty for none
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(try_from = "String")]
+pub struct PayloadKey(Option<[u8; PAYLOAD_KEY_LENGTH]>);
+
+impl TryFrom<String> for PayloadKey {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> anyhow::Result<Self> {
+        Self::parse(&value)
+    }
+}
+
+impl PayloadKey {
+    // Parse a key of 32 hex digits, optionally separated by spaces or colons
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let digits: String = text.chars().filter(|c| !c.is_whitespace() && *c != ':').collect();
+        if digits.is_empty() {
+            return Ok(Self(None));
+        }
+        if digits.len() != PAYLOAD_KEY_LENGTH * 2 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(anyhow::anyhow!("payload_key must be {} hex digits, not \"{}\"", PAYLOAD_KEY_LENGTH * 2, text));
+        }
+
+        let mut key = [0u8; PAYLOAD_KEY_LENGTH];
+        for (index, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&digits[index * 2..index * 2 + 2], 16)?;
+        }
+        Ok(Self(Some(key)))
+    }
+}
+
+// XOR with a key repeated from the start of the payload, its own inverse
+pub struct XorKey {
+    key: [u8; PAYLOAD_KEY_LENGTH],
+}
+
+impl PayloadTransform for XorKey {
+    fn name(&self) -> &'static str {
+        "deobfuscation"
+    }
+
+    fn frame_mark(&self) -> Option<&'static str> {
+        Some("deobfuscated")
+    }
+
+    fn apply(&self, payload: &mut [u8]) {
+        for (byte, key) in payload.iter_mut().zip(self.key.iter().cycle()) {
+            *byte ^= key;
+        }
+    }
+}
+
+// The transforms of the preferences, in the order they're undone
+#[derive(Default)]
+pub struct PayloadTransforms {
+    transforms: Vec<Box<dyn PayloadTransform>>,
+}
+
+impl PayloadTransforms {
+    // Loggers obfuscate what the gateway sent them, so the key is undone before the word swap
+    pub fn new(key: &PayloadKey, word_swap: bool) -> Self {
+        let mut transforms: Vec<Box<dyn PayloadTransform>> = Vec::new();
+        if let PayloadKey(Some(key)) = key {
+            transforms.push(Box::new(XorKey { key: *key }));
+        }
+        if word_swap {
+            transforms.push(Box::new(WordSwap));
+        }
+        Self { transforms }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+
+    // Get a copy of the payload with every transform undone
+    pub fn apply(&self, payload: &[u8]) -> Vec<u8> {
+        let mut scratch = payload.to_vec();
+        for transform in self.transforms.iter() {
+            transform.apply(&mut scratch);
+        }
+        scratch
+    }
+
+    // Get the names of the transforms, e.g. "deobfuscation, word swap"
+    pub fn names(&self) -> String {
+        self.transforms.iter().map(|transform| transform.name()).collect::<Vec<&str>>().join(", ")
+    }
+
+    pub fn frame_marks(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.transforms.iter().filter_map(|transform| transform.frame_mark())
+    }
+}
+
+#[test]
+fn parse_payload_key() {
+    assert_eq!(PayloadKey::parse("").unwrap(), PayloadKey(None));
+    let key = PayloadKey::parse("00 11 22 33 44 55 66 77 88 99 aa bb cc dd ee FF").unwrap();
+    assert_eq!(key.0.unwrap()[15], 0xff);
+    assert_eq!(PayloadKey::parse("00:11:22:33:44:55:66:77:88:99:aa:bb:cc:dd:ee:ff").unwrap(), key);
+    assert!(PayloadKey::parse("0011").is_err());
+    assert!(PayloadKey::parse("zz112233445566778899aabbccddeeff").is_err());
+}
+
+#[test]
+fn deobfuscated_payload_decodes_like_clear_twin() {
+    let messages: Vec<crate::elpis::MessageDefinition> =
+        serde_json::from_str(&std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/messages.json")).unwrap())
+            .unwrap();
+    let message = messages.iter().find(|message| message.name == "VAS_Proximity").unwrap();
+
+    // A frame as the logger wrote it with key 5a a5 0f f0 ..., and the frame it was made from
+    let key = PayloadKey::parse("5aa50ff0123456789abcdef001020304").unwrap();
+    let clear = [0b1010_0110, 0x00, 0x00, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55];
+    let obfuscated = [0xfc, 0xa5, 0x0f, 0xf0, 0x03, 0x16, 0x65, 0x3c, 0xcf];
+
+    let transforms = PayloadTransforms::new(&key, false);
+    assert_eq!(transforms.apply(&obfuscated), clear);
+    let expected = message.decode(&clear);
+    let decoded = message.decode(&transforms.apply(&obfuscated));
+    assert_eq!(decoded.signals, expected.signals);
+    assert_eq!(decoded.signals[0].raw, 1);
+    assert_eq!(transforms.frame_marks().collect::<Vec<&str>>(), vec!["deobfuscated"]);
+
+    // Without a key nothing changes
+    let transforms = PayloadTransforms::new(&PayloadKey::default(), false);
+    assert!(transforms.is_empty());
+    assert_eq!(transforms.apply(&obfuscated), obfuscated);
+
+    // The key is undone before the word swap
+    let transforms = PayloadTransforms::new(&key, true);
+    assert_eq!(transforms.names(), "deobfuscation, word swap");
+    assert_eq!(transforms.apply(&obfuscated)[..4], [0x00, 0x00, 0x00, 0b1010_0110]);
+}
@@ -0,0 +1,129 @@
+// Converts physical values between metric and imperial units for display. Only what's shown in the
+// tree changes, filter fields always keep the database's own unit so filters work for everyone.
+
+use serde::Deserialize;
+
+// Which units signal values are displayed in
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitSystem {
+    // Whatever unit the database gives
+    #[default]
+    Native,
+    Metric,
+    Imperial,
+}
+
+// A pair of units where imperial = metric * scale + offset
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct UnitConversion {
+    pub metric: String,
+    pub imperial: String,
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+}
+
+impl UnitConversion {
+    fn new(metric: &str, imperial: &str, scale: f64, offset: f64) -> Self {
+        Self {
+            metric: metric.to_string(),
+            imperial: imperial.to_string(),
+            scale,
+            offset,
+        }
+    }
+}
+
+// Conversions that are always available, several spellings of the same unit are listed separately
+fn builtin_conversions() -> Vec<UnitConversion> {
+    vec![
+        UnitConversion::new("km/h", "mph", 0.621371, 0.0),
+        UnitConversion::new("kph", "mph", 0.621371, 0.0),
+        UnitConversion::new("m/s", "mph", 2.236936, 0.0),
+        UnitConversion::new("°C", "°F", 1.8, 32.0),
+        UnitConversion::new("degC", "degF", 1.8, 32.0),
+        UnitConversion::new("bar", "psi", 14.503774, 0.0),
+    ]
+}
+
+pub struct UnitConverter {
+    conversions: Vec<UnitConversion>,
+}
+
+impl UnitConverter {
+    // Create a converter with the built in conversions, plus additional ones that take precedence
+    pub fn new(additional: &[UnitConversion]) -> Self {
+        let mut conversions = additional.to_vec();
+        conversions.extend(builtin_conversions());
+        Self { conversions }
+    }
+
+    // Convert a value in the given unit for display in a unit system. Returns None when the unit
+    // is already right or there's no conversion for it, in which case it's shown unchanged.
+    pub fn convert(&self, value: f64, unit: &str, system: UnitSystem) -> Option<(f64, &str)> {
+        match system {
+            UnitSystem::Native => None,
+            UnitSystem::Imperial => self
+                .conversions
+                .iter()
+                .find(|conversion| conversion.metric == unit)
+                .map(|conversion| (value * conversion.scale + conversion.offset, conversion.imperial.as_str())),
+            UnitSystem::Metric => self
+                .conversions
+                .iter()
+                .find(|conversion| conversion.imperial == unit)
+                .map(|conversion| ((value - conversion.offset) / conversion.scale, conversion.metric.as_str())),
+        }
+    }
+}
+
+#[cfg(test)]
+fn assert_converts(converted: Option<(f64, &str)>, value: f64, unit: &str) {
+    let (converted_value, converted_unit) = converted.unwrap();
+    assert!((converted_value - value).abs() < 1e-3, "{} != {}", converted_value, value);
+    assert_eq!(converted_unit, unit);
+}
+
+#[test]
+fn convert_units() {
+    let converter = UnitConverter::new(&[]);
+
+    // Pure scaling
+    assert_converts(converter.convert(100.0, "km/h", UnitSystem::Imperial), 62.137, "mph");
+    assert_converts(converter.convert(62.1371, "mph", UnitSystem::Metric), 100.0, "km/h");
+    assert_converts(converter.convert(10.0, "m/s", UnitSystem::Imperial), 22.369, "mph");
+    assert_converts(converter.convert(2.0, "bar", UnitSystem::Imperial), 29.008, "psi");
+
+    // Temperatures have an offset as well as a scale, so 0 doesn't stay 0 and doubling isn't doubling
+    assert_converts(converter.convert(0.0, "°C", UnitSystem::Imperial), 32.0, "°F");
+    assert_converts(converter.convert(100.0, "°C", UnitSystem::Imperial), 212.0, "°F");
+    assert_converts(converter.convert(-40.0, "°C", UnitSystem::Imperial), -40.0, "°F");
+    assert_converts(converter.convert(212.0, "°F", UnitSystem::Metric), 100.0, "°C");
+    assert_converts(converter.convert(50.0, "degF", UnitSystem::Metric), 10.0, "degC");
+
+    // Unknown units, units already in the right system and native display pass through
+    assert_eq!(converter.convert(1.0, "rpm", UnitSystem::Imperial), None);
+    assert_eq!(converter.convert(1.0, "mph", UnitSystem::Imperial), None);
+    assert_eq!(converter.convert(1.0, "km/h", UnitSystem::Native), None);
+}
+
+#[test]
+fn additional_conversions() {
+    let additional: Vec<UnitConversion> = serde_json::from_str(
+        r#"[
+            { "metric": "Nm", "imperial": "lbf·ft", "scale": 0.737562 },
+            { "metric": "km/h", "imperial": "kn", "scale": 0.539957 }
+        ]"#,
+    )
+    .unwrap();
+    let converter = UnitConverter::new(&additional);
+
+    assert_converts(converter.convert(100.0, "Nm", UnitSystem::Imperial), 73.756, "lbf·ft");
+    assert_converts(converter.convert(73.7562, "lbf·ft", UnitSystem::Metric), 100.0, "Nm");
+
+    // Additional conversions take precedence over the built in ones
+    assert_converts(converter.convert(100.0, "km/h", UnitSystem::Imperial), 53.996, "kn");
+    assert_converts(converter.convert(62.1371, "mph", UnitSystem::Metric), 100.0, "km/h");
+}
@@ -0,0 +1,58 @@
+// WebAssembly exports for decoding ELPIS payloads in a browser, for web based log viewers. A
+// database is loaded once from the text of a messages.json file and referred to by the handle
+// load_database returns:
+//
+//     const db = load_database(await (await fetch("messages.json")).text());
+//     const frame = decode_frame(db, 0x120, new Uint8Array([0x34, 0x12, 0, 0, 0, 0, 0, 0]));
+//
+// Frames are returned as the same objects elpis-decode prints as JSON. A database no longer needed
+// is released with free_database(db), after which its handle is invalid. Build with
+// `cargo build --lib --target wasm32-unknown-unknown --features wasm` and wasm-bindgen.
+
+use crate::codec::MessageCodec;
+use crate::elpis::{ElpisMessages, LoadMode};
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use wasm_bindgen::prelude::*;
+
+lazy_static! {
+    // Every database loaded, indexed by handle, None once freed. Handles aren't reused, so a stale
+    // one is an error rather than another database.
+    static ref DATABASES: Mutex<Vec<Option<MessageCodec>>> = Mutex::new(Vec::new());
+}
+
+// Load a database from the contents of a messages.json file, returning its handle
+#[wasm_bindgen]
+pub fn load_database(json: &str) -> Result<u32, JsError> {
+    let messages = ElpisMessages::from_json_str(json, LoadMode::Strict).map_err(|error| JsError::new(&format!("{:#}", error)))?;
+    let mut databases = DATABASES.lock().unwrap();
+    databases.push(Some(messages.build_codec()));
+    Ok((databases.len() - 1) as u32)
+}
+
+// Release a loaded database
+#[wasm_bindgen]
+pub fn free_database(handle: u32) -> Result<(), JsError> {
+    DATABASES
+        .lock()
+        .unwrap()
+        .get_mut(handle as usize)
+        .and_then(Option::take)
+        .map(|_| ())
+        .ok_or_else(|| JsError::new(&format!("No database with handle {}", handle)))
+}
+
+// Decode the payload of a message of a loaded database
+#[wasm_bindgen]
+pub fn decode_frame(handle: u32, id: u32, payload: &[u8]) -> Result<JsValue, JsError> {
+    let databases = DATABASES.lock().unwrap();
+    let codec = databases
+        .get(handle as usize)
+        .and_then(Option::as_ref)
+        .ok_or_else(|| JsError::new(&format!("No database with handle {}", handle)))?;
+    let id = i32::try_from(id).map_err(|_| JsError::new(&format!("Invalid message id {:#x}", id)))?;
+    let frame = codec.decode(id, payload).map_err(|error| JsError::new(&format!("{:#}", error)))?;
+
+    let json = serde_json::to_string(&frame)?;
+    js_sys::JSON::parse(&json).map_err(|_| JsError::new("Could not convert the decoded frame"))
+}
@@ -0,0 +1,75 @@
+// Applications embedding the library store and exchange decoded frames as JSON, so the shape of
+// DecodedFrame's JSON is pinned by tests/decoded_frame_golden.json. When this test fails the output
+// changed for them: new fields go in the golden file too, but renamed or removed ones break users.
+
+use elpis::elpis::{DecodedFrame, ElpisMessages};
+use elpis::schema::parse_database;
+use std::path::Path;
+
+const MESSAGES: &str = r#"[{
+    "name": "Gearbox", "id": 288, "length": 8, "comment": null,
+    "signals": [
+        { "name": "Mode", "start": 0, "length": 8, "is_big_endian": false, "is_multiplexer": true,
+          "choices": { "Drive": 1, "Reverse": 2 } },
+        { "name": "OilTemp", "start": 8, "length": 8, "is_big_endian": false, "is_signed": true,
+          "scale": 0.5, "minimum": -40, "maximum": 50, "unit": "degC",
+          "multiplexer_signal": "Mode", "multiplexer_ids": [1] },
+        { "name": "Pressure", "start": 8, "length": 16, "is_big_endian": false, "unit": "kPa",
+          "multiplexer_signal": "Mode", "multiplexer_ids": [2] },
+        { "name": "Speed", "start": 24, "length": 16, "is_big_endian": false, "scale": 0.25, "unit": "km/h",
+          "ranges": [{ "from": 65535, "to": 65535, "label": "SNA" }] }
+    ],
+    "derived": [{ "name": "OilTempX2", "expression": "OilTemp * 2" }]
+}]"#;
+
+fn golden() -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/decoded_frame_golden.json");
+    std::fs::read_to_string(path).unwrap()
+}
+
+fn decode() -> DecodedFrame {
+    let messages = ElpisMessages::from_definitions(parse_database(MESSAGES).unwrap());
+    let message = messages.get_def_by_id(288).unwrap();
+    message.decode(&[0x01, 0x7f, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00])
+}
+
+#[test]
+fn decoded_frame_json_shape() {
+    let expected: serde_json::Value = serde_json::from_str(&golden()).unwrap();
+    assert_eq!(serde_json::to_value(decode()).unwrap(), expected);
+}
+
+#[test]
+fn decoded_frame_round_trip() {
+    let frame = decode();
+    let json = serde_json::to_string(&frame).unwrap();
+    assert_eq!(serde_json::from_str::<DecodedFrame>(&json).unwrap(), frame);
+
+    // Raw values are strings, every u128 survives
+    let mut frame = frame;
+    frame.signals[0].raw = u128::MAX;
+    let json = serde_json::to_value(&frame).unwrap();
+    assert_eq!(json["signals"][0]["raw"], "340282366920938463463374607431768211455");
+    assert_eq!(serde_json::from_value::<DecodedFrame>(json).unwrap().signals[0].raw, u128::MAX);
+}
+
+#[test]
+fn non_finite_physical_round_trip() {
+    let mut frame = decode();
+    frame.signals[0].physical = f64::NAN;
+    frame.signals[1].physical = f64::INFINITY;
+    frame.signals[2].physical = f64::NEG_INFINITY;
+    let json = serde_json::to_value(&frame).unwrap();
+    assert_eq!(json["signals"][0]["physical"], "NaN");
+    assert_eq!(json["signals"][1]["physical"], "inf");
+    assert_eq!(json["signals"][2]["physical"], "-inf");
+
+    let read = serde_json::from_value::<DecodedFrame>(json).unwrap();
+    assert!(read.signals[0].physical.is_nan());
+    assert_eq!((read.signals[1].physical, read.signals[2].physical), (f64::INFINITY, f64::NEG_INFINITY));
+
+    // Frames written when NaN came out as null still read
+    let mut json = serde_json::to_value(decode()).unwrap();
+    json["signals"][0]["physical"] = serde_json::Value::Null;
+    assert!(serde_json::from_value::<DecodedFrame>(json).unwrap().signals[0].physical.is_nan());
+}
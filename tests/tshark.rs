@@ -0,0 +1,277 @@
+// Runs the built plugin inside a real tshark over a capture generated here, and compares selected
+// fields against tests/tshark_golden.json, the bytes items claim against
+// tests/tshark_spans_golden.json and the packets 1.0 filters and filters on signal aliases match
+// against tests/tshark_filters_1_0.json and tests/tshark_alias_filters.json. Plugin loading, field
+// and preference registration, item spans and everything else that needs epan only break under
+// real Wireshark, which the unit tests never touch.
+//
+// Needs tshark 4.4 on the PATH, so it's ignored by default:
+//     cargo test --test tshark -- --ignored
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Minor version of Wireshark the plugin is built for, plugins are loaded from <dir>/4.<minor>/epan
+const WIRESHARK_MINOR: u32 = 4;
+
+const ELPIS_PORT: u16 = 20000;
+
+// Build an ELPIS datagram from (id, payload) frames
+fn elpis_datagram(frames: &[(i32, &[u8])]) -> Vec<u8> {
+    let mut datagram = Vec::new();
+    for (id, payload) in frames {
+        datagram.extend_from_slice(&id.to_be_bytes());
+        datagram.extend_from_slice(&(payload.len() as i32).to_be_bytes());
+        datagram.extend_from_slice(payload);
+    }
+    datagram
+}
+
+// Wrap a UDP payload in IPv4 and UDP headers, from 10.0.0.1 to 10.0.0.2
+fn ipv4_udp_packet(payload: &[u8]) -> Vec<u8> {
+    let total_length = (20 + 8 + payload.len()) as u16;
+
+    let mut ip = vec![0x45, 0x00];
+    ip.extend_from_slice(&total_length.to_be_bytes());
+    ip.extend_from_slice(&[0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00]);
+    ip.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+
+    // Ones' complement sum of the header's 16-bit words
+    let mut sum: u32 = ip.chunks(2).map(|word| u16::from_be_bytes([word[0], word[1]]) as u32).sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    ip[10..12].copy_from_slice(&(!(sum as u16)).to_be_bytes());
+
+    // A zero UDP checksum means none was computed
+    let mut packet = ip;
+    packet.extend_from_slice(&ELPIS_PORT.to_be_bytes());
+    packet.extend_from_slice(&ELPIS_PORT.to_be_bytes());
+    packet.extend_from_slice(&(total_length - 20).to_be_bytes());
+    packet.extend_from_slice(&[0x00, 0x00]);
+    packet.extend_from_slice(payload);
+    packet
+}
+
+// Write a classic little endian pcap file of raw IPv4 packets, one second apart
+fn write_capture(path: &Path, packets: &[Vec<u8>]) {
+    const LINKTYPE_RAW: u32 = 101;
+
+    let mut capture = Vec::new();
+    capture.extend_from_slice(&0xa1b2_c3d4u32.to_le_bytes());
+    capture.extend_from_slice(&2u16.to_le_bytes());
+    capture.extend_from_slice(&4u16.to_le_bytes());
+    capture.extend_from_slice(&[0; 8]);
+    capture.extend_from_slice(&65535u32.to_le_bytes());
+    capture.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+
+    for (index, packet) in packets.iter().enumerate() {
+        capture.extend_from_slice(&(1_700_000_000 + index as u32).to_le_bytes());
+        capture.extend_from_slice(&0u32.to_le_bytes());
+        capture.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        capture.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        capture.extend_from_slice(packet);
+    }
+
+    fs::write(path, capture).unwrap();
+}
+
+// Find the plugin library cargo built alongside this test, in target/<profile>
+fn plugin_library() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    let profile_dir = exe.parent().unwrap().parent().unwrap();
+    let library = profile_dir.join("libelpis.so");
+    assert!(library.exists(), "{} not found, build the plugin first", library.display());
+    library
+}
+
+// A directory holding the plugin with the sample database next to it and a capture of two ELPIS
+// datagrams, returning the directory and the capture's path
+fn prepare_sample(fixtures: &Path) -> (PathBuf, PathBuf) {
+    let work_dir = std::env::temp_dir().join(format!("elpis-tshark-{}-{:?}", std::process::id(), std::thread::current().id()));
+
+    // The database is read from next to the plugin, and HOME keeps any personally installed copy
+    // of the plugin from being loaded as well
+    let epan_dir = work_dir.join(format!("plugins/4.{}/epan", WIRESHARK_MINOR));
+    fs::create_dir_all(&epan_dir).unwrap();
+    fs::copy(plugin_library(), epan_dir.join("libelpis.so")).unwrap();
+    fs::copy(fixtures.join("tshark_messages.json"), epan_dir.join("messages.json")).unwrap();
+
+    let capture_path = work_dir.join("elpis_sample.pcap");
+    write_capture(
+        &capture_path,
+        &[
+            ipv4_udp_packet(&elpis_datagram(&[(0x120, &[0x34, 0x12, 0, 0, 0, 0, 0, 0]), (0x50, &[0x07, 0x3a])])),
+            ipv4_udp_packet(&elpis_datagram(&[(0x50, &[0x08, 0x3b])])),
+        ],
+    );
+    (work_dir, capture_path)
+}
+
+// Run tshark over the sample capture with the plugin loaded, returning what it printed
+fn run_tshark(work_dir: &Path, capture_path: &Path, args: &[&str]) -> String {
+    let output = Command::new("tshark")
+        .env("WIRESHARK_PLUGIN_DIR", work_dir.join("plugins"))
+        .env("HOME", work_dir)
+        .arg("-r")
+        .arg(capture_path)
+        .args(args)
+        .output()
+        .expect("Could not run tshark");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+// Get an attribute of the first PDML field element with the given name
+fn pdml_attribute(pdml: &str, field: &str, attribute: &str) -> Option<i64> {
+    let element = pdml.lines().find(|line| line.contains(&format!("<field name=\"{}\"", field)))?;
+    let value = element.split(&format!(" {}=\"", attribute)).nth(1)?;
+    value[..value.find('"')?].parse().ok()
+}
+
+#[test]
+#[ignore = "needs tshark, run with --ignored"]
+fn tshark_decodes_sample_capture() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let (work_dir, capture_path) = prepare_sample(&fixtures);
+
+    let output = run_tshark(
+        &work_dir,
+        &capture_path,
+        &["-T", "json", "-e", "elpis.name", "-e", "elpis.signal_kv", "-e", "_ws.col.info"],
+    );
+
+    let packets: serde_json::Value = serde_json::from_str(&output).unwrap();
+    let golden: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(fixtures.join("tshark_golden.json")).unwrap()).unwrap();
+
+    let packets = packets.as_array().unwrap();
+    let golden = golden.as_array().unwrap();
+    assert_eq!(packets.len(), golden.len());
+    for (index, (packet, expected)) in packets.iter().zip(golden).enumerate() {
+        let layers = &packet["_source"]["layers"];
+        for (field, value) in expected.as_object().unwrap() {
+            assert_eq!(&layers[field], value, "packet {} field {}", index + 1, field);
+        }
+    }
+
+    let _ = fs::remove_dir_all(&work_dir);
+}
+
+// One item of each kind in tests/tshark_spans_golden.json, with the bytes it must claim and whether
+// it must be generated: read from the wire, computed from its frame and computed across frames
+#[test]
+#[ignore = "needs tshark, run with --ignored"]
+fn tshark_item_spans() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let (work_dir, capture_path) = prepare_sample(&fixtures);
+
+    // PDML has every item's position in the frame, the text output brackets generated items
+    let pdml = run_tshark(&work_dir, &capture_path, &["-T", "pdml", "-Y", "frame.number == 1"]);
+    let text = run_tshark(&work_dir, &capture_path, &["-V", "-O", "elpis", "-Y", "frame.number == 1"]);
+
+    let golden: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(fixtures.join("tshark_spans_golden.json")).unwrap()).unwrap();
+    for expected in golden.as_array().unwrap() {
+        let field = expected["field"].as_str().unwrap();
+        assert_eq!(pdml_attribute(&pdml, field, "size"), expected["size"].as_i64(), "size of {}", field);
+        if let Some(pos) = expected["pos"].as_i64() {
+            assert_eq!(pdml_attribute(&pdml, field, "pos"), Some(pos), "pos of {}", field);
+        }
+
+        let label = format!("{}:", expected["label"].as_str().unwrap());
+        let line = text
+            .lines()
+            .map(str::trim)
+            .find(|line| line.trim_start_matches('[').starts_with(&label))
+            .unwrap_or_else(|| panic!("{} not in the text output", field));
+        assert_eq!(line.starts_with('['), expected["generated"].as_bool().unwrap(), "generated flag of {}", field);
+    }
+
+    let _ = fs::remove_dir_all(&work_dir);
+}
+
+// Check each display filter of a golden file in tests matches the packets it lists
+fn assert_filters(fixtures: &Path, golden_name: &str, work_dir: &Path, capture_path: &Path) {
+    let golden: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(fixtures.join(golden_name)).unwrap()).unwrap();
+    for expected in golden.as_array().unwrap() {
+        let filter = expected["filter"].as_str().unwrap();
+        let output = run_tshark(work_dir, capture_path, &["-Y", filter, "-T", "fields", "-e", "frame.number"]);
+        let packets: Vec<u64> = output.lines().map(|line| line.trim().parse().unwrap()).collect();
+        let expected: Vec<u64> = serde_json::from_value(expected["packets"].clone()).unwrap();
+        assert_eq!(packets, expected, "packets matching {}", filter);
+    }
+}
+
+// Display filters written against the 1.0 plugin in tests/tshark_filters_1_0.json, with the packets
+// of the sample capture each must still match
+#[test]
+#[ignore = "needs tshark, run with --ignored"]
+fn tshark_filters_of_1_0() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let (work_dir, capture_path) = prepare_sample(&fixtures);
+
+    assert_filters(&fixtures, "tshark_filters_1_0.json", &work_dir, &capture_path);
+
+    let _ = fs::remove_dir_all(&work_dir);
+}
+
+// The same filters, elpis.signal_kv and elpis.payload among them, with every sample message shown
+// as a single line. Compact frames hide their items but must keep them for filters.
+#[test]
+#[ignore = "needs tshark, run with --ignored"]
+fn tshark_filters_of_1_0_compact() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let (work_dir, capture_path) = prepare_sample(&fixtures);
+    let epan_dir = work_dir.join(format!("plugins/4.{}/epan", WIRESHARK_MINOR));
+    fs::write(epan_dir.join("elpis_prefs.json"), r#"{ "compact_small_frames": true }"#).unwrap();
+
+    // Both sample messages have a single signal, so both are compact
+    let text = run_tshark(&work_dir, &capture_path, &["-V", "-O", "elpis", "-Y", "frame.number == 2"]);
+    assert!(text.contains("ESP_Alive: Counter="), "{}", text);
+
+    assert_filters(&fixtures, "tshark_filters_1_0.json", &work_dir, &capture_path);
+
+    let _ = fs::remove_dir_all(&work_dir);
+}
+
+// Filters on a signal's old name in tests/tshark_alias_filters.json, with the sample database's
+// Speed_FL renamed and its old name kept as an alias
+#[test]
+#[ignore = "needs tshark, run with --ignored"]
+fn tshark_alias_filters() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let (work_dir, capture_path) = prepare_sample(&fixtures);
+
+    let database = fs::read_to_string(fixtures.join("tshark_messages.json")).unwrap();
+    let renamed = database.replace(r#""name": "Speed_FL","#, r#""name": "WheelSpeed_FrontLeft", "aliases": ["Speed_FL"],"#);
+    assert_ne!(renamed, database);
+    let epan_dir = work_dir.join(format!("plugins/4.{}/epan", WIRESHARK_MINOR));
+    fs::write(epan_dir.join("messages.json"), renamed).unwrap();
+
+    assert_filters(&fixtures, "tshark_alias_filters.json", &work_dir, &capture_path);
+
+    let _ = fs::remove_dir_all(&work_dir);
+}
+
+// Preferences registered with Wireshark are set with -o, the sample's first datagram has a frame
+// with id 0x50 after one with 0x120
+#[test]
+#[ignore = "needs tshark, run with --ignored"]
+fn tshark_preferences() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let (work_dir, capture_path) = prepare_sample(&fixtures);
+
+    let fields = ["-Y", "elpis.frame_sequence_error", "-T", "fields", "-e", "frame.number"];
+    assert_eq!(run_tshark(&work_dir, &capture_path, &fields).trim(), "");
+
+    let ordered = [&["-o", "elpis.expect_ordered_ids:TRUE"][..], &fields[..]].concat();
+    assert_eq!(run_tshark(&work_dir, &capture_path, &ordered).trim(), "1");
+
+    let text = run_tshark(&work_dir, &capture_path, &["-o", "elpis.payload_wordswap:TRUE", "-V", "-O", "elpis"]);
+    assert!(text.contains("(decoded after word swap)"), "{}", text);
+
+    let _ = fs::remove_dir_all(&work_dir);
+}
@@ -0,0 +1,34 @@
+// Smoke test of the WebAssembly build, run by .github/workflows/wasm.yml once wasm-bindgen has
+// generated Node.js bindings into tests/wasm/pkg:
+//
+//     node tests/wasm/smoke.mjs
+
+import assert from "node:assert/strict";
+import { createRequire } from "node:module";
+
+const { load_database, decode_frame, free_database } = createRequire(import.meta.url)("./pkg/elpis.js");
+
+const db = load_database(`[{
+    "name": "Wheels", "id": 288, "length": 8, "comment": null,
+    "signals": [
+        { "name": "SpeedFL", "start": 0, "length": 16, "is_big_endian": false, "scale": 0.01, "unit": "km/h" }
+    ]
+}]`);
+
+const frame = decode_frame(db, 288, new Uint8Array([0x34, 0x12, 0, 0, 0, 0, 0, 0]));
+assert.equal(frame.name, "Wheels");
+assert.equal(frame.signals[0].name, "SpeedFL");
+assert.equal(frame.signals[0].raw, "4660");
+assert.equal(frame.signals[0].unit, "km/h");
+
+assert.throws(() => decode_frame(db, 289, new Uint8Array(8)), /Unknown message id 0x121/);
+assert.throws(() => decode_frame(db + 1, 288, new Uint8Array(8)), /No database with handle/);
+assert.throws(() => load_database("[{"));
+
+// A freed database's handle is invalid from then on, and isn't given to the next one
+free_database(db);
+assert.throws(() => decode_frame(db, 288, new Uint8Array(8)), /No database with handle/);
+assert.throws(() => free_database(db), /No database with handle/);
+assert.notEqual(load_database("[]"), db);
+
+console.log("wasm smoke test passed");